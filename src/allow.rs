@@ -0,0 +1,31 @@
+use regex::Regex;
+
+/// Has a lint been turned off for this chapter with a comment like
+/// `<!-- linkcheck-allow: missing-alt-text -->`?
+///
+/// Several lints can be listed in the same comment, separated by commas.
+pub(crate) fn is_allowed(src: &str, lint: &str) -> bool {
+    let comment = Regex::new(r"<!--\s*linkcheck-allow:([^>]*?)-->").unwrap();
+
+    let allowed = comment.captures_iter(src).any(|caps| {
+        caps[1].split(',').any(|allowed| allowed.trim() == lint)
+    });
+
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lints_can_be_allowed_with_a_comment() {
+        let src = "# Chapter 1\n\n\
+                   <!-- linkcheck-allow: something-else, missing-alt-text -->\n";
+
+        assert!(is_allowed(src, "missing-alt-text"));
+        assert!(is_allowed(src, "something-else"));
+        assert!(!is_allowed(src, "missing"));
+        assert!(!is_allowed("missing-alt-text", "missing-alt-text"));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::Config;
+use codespan::{FileId, Files, Span};
+use linkcheck::Link;
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// The name used to turn off [`Config::missing_alt_text`] for a single
+/// chapter (see [`crate::allow::is_allowed()`]).
+pub(crate) const LINT: &str = "missing-alt-text";
+
+/// Find the markdown images (e.g. `![](./diagram.png)`) without any alt
+/// text, skipping chapters which allow them.
+pub(crate) fn missing_alt_text(
+    file_ids: &[FileId],
+    files: &Files<String>,
+    cfg: &Config,
+) -> Vec<Link> {
+    if cfg.missing_alt_text.is_none() {
+        return Vec::new();
+    }
+
+    let mut images = Vec::new();
+
+    for &file in file_ids {
+        let src = files.source(file);
+        if crate::allow::is_allowed(src, LINT) {
+            continue;
+        }
+
+        // the image being looked at, and its alt text so far
+        let mut current: Option<(Link, String)> = None;
+
+        for (event, range) in
+            Parser::new_ext(src, cfg.markdown.options()).into_offset_iter()
+        {
+            match event {
+                Event::Start(Tag::Image(_, dest, _)) if current.is_none() => {
+                    let span = Span::new(range.start as u32, range.end as u32);
+                    let link = Link::new(dest.into_string(), span, file);
+                    current = Some((link, String::new()));
+                },
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some((_, ref mut alt)) = current {
+                        alt.push_str(&text);
+                    }
+                },
+                Event::End(Tag::Image(..)) => {
+                    if let Some((link, alt)) = current.take() {
+                        if alt.trim().is_empty() {
+                            images.push(link);
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WarningPolicy;
+
+    #[test]
+    fn find_images_without_alt_text() {
+        let mut files = Files::new();
+        let first = files.add(
+            "chapter_1.md",
+            String::from(
+                "![A diagram](./diagram.png)\n\n![](./empty.png)\n\n\
+                 ![  ](./blank.png) ![`code`](./code.png)\n",
+            ),
+        );
+        let second = files.add(
+            "chapter_2.md",
+            String::from(
+                "<!-- linkcheck-allow: missing-alt-text -->\n\n\
+                 ![](./decorative.png)\n",
+            ),
+        );
+        let cfg = Config {
+            missing_alt_text: Some(WarningPolicy::Warn),
+            ..Default::default()
+        };
+
+        let got = missing_alt_text(&[first, second], &files, &cfg);
+
+        let hrefs: Vec<_> = got.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(hrefs, vec!["./empty.png", "./blank.png"]);
+    }
+}
@@ -0,0 +1,428 @@
+use crate::{FileProvider, SlugConfig, SlugStyle};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+static HEADING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap());
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+static ID_ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\s(?:id|name)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+
+/// A file's content hash and anchors, or `None` if we couldn't find any (see
+/// [`AnchorIndex::read()`]), which is filled in the first time it is needed.
+type Entry = Arc<OnceLock<Option<(u64, Arc<HashSet<String>>)>>>;
+
+/// The anchors in every file that has been linked to during a run, so each
+/// file is read and parsed at most once no matter how many links point into
+/// it.
+///
+/// The anchors found in a previous run can be provided (keyed by a hash of
+/// the file's contents) so unchanged files don't need to be parsed at all.
+#[derive(Debug, Default)]
+pub(crate) struct AnchorIndex {
+    files: Mutex<HashMap<PathBuf, Entry>>,
+    known: HashMap<u64, Arc<HashSet<String>>>,
+    slugs: SlugConfig,
+}
+
+impl AnchorIndex {
+    /// Create an index which generates heading IDs using these settings.
+    pub(crate) fn new(slugs: SlugConfig) -> Self {
+        AnchorIndex {
+            slugs,
+            ..Default::default()
+        }
+    }
+
+    /// Create an index which reuses the anchors from a previous run (see
+    /// [`AnchorIndex::into_known()`]).
+    pub(crate) fn with_known(
+        known: HashMap<u64, HashSet<String>>,
+        slugs: SlugConfig,
+    ) -> Self {
+        AnchorIndex {
+            files: Mutex::default(),
+            known: known
+                .into_iter()
+                .map(|(hash, anchors)| (hash, Arc::new(anchors)))
+                .collect(),
+            slugs,
+        }
+    }
+
+    /// The anchors in every file used during this run, keyed by a hash of
+    /// its contents.
+    pub(crate) fn into_known(self) -> HashMap<u64, HashSet<String>> {
+        self.files
+            .into_inner()
+            .expect("The lock was poisoned")
+            .into_values()
+            .filter_map(|entry| entry.get().cloned().flatten())
+            .map(|(hash, anchors)| (hash, HashSet::clone(&anchors)))
+            .collect()
+    }
+
+    /// Get the anchors in a file, parsing it if this is the first time it has
+    /// been asked for.
+    pub(crate) fn anchors(
+        &self,
+        path: &Path,
+        files: &dyn FileProvider,
+    ) -> Option<Arc<HashSet<String>>> {
+        // the lock isn't held while parsing, so other files can be parsed in
+        // parallel while anyone else asking for this one waits
+        self.entry(path, files)
+            .get_or_init(|| self.read(path, files))
+            .as_ref()
+            .map(|(_, anchors)| Arc::clone(anchors))
+    }
+
+    /// Parse a file on `tokio`'s blocking thread pool, so the anchors are
+    /// already there when [`AnchorIndex::anchors()`] asks for them.
+    pub(crate) async fn prefetch(&self, path: &Path, files: &dyn FileProvider) {
+        let entry = self.entry(path, files);
+        if entry.get().is_some() {
+            return;
+        }
+
+        let parsed = match self.source(path, files) {
+            Some(Source::Known(hash, anchors)) => Some((hash, anchors)),
+            Some(Source::Unparsed(hash, src, is_markdown)) => {
+                let slugs = self.slugs.clone();
+                let parse = move || parse(&src, is_markdown, &slugs);
+                match tokio::task::spawn_blocking(parse).await {
+                    Ok(anchors) => Some((hash, Arc::new(anchors))),
+                    Err(e) => std::panic::resume_unwind(e.into_panic()),
+                }
+            },
+            None => None,
+        };
+
+        // someone else may have parsed it in the meantime, which is fine
+        let _ = entry.set(parsed);
+    }
+
+    fn entry(&self, path: &Path, files: &dyn FileProvider) -> Entry {
+        let key = files
+            .canonicalize(path)
+            .unwrap_or_else(|_| path.to_path_buf());
+
+        Arc::clone(
+            self.files
+                .lock()
+                .expect("The lock was poisoned")
+                .entry(key)
+                .or_default(),
+        )
+    }
+
+    /// Read a file and find all the anchors it contains, returning `None` if
+    /// we don't know how to find anchors in this type of file.
+    fn read(
+        &self,
+        path: &Path,
+        files: &dyn FileProvider,
+    ) -> Option<(u64, Arc<HashSet<String>>)> {
+        match self.source(path, files)? {
+            Source::Known(hash, anchors) => Some((hash, anchors)),
+            Source::Unparsed(hash, src, is_markdown) => {
+                let anchors = parse(&src, is_markdown, &self.slugs);
+                Some((hash, Arc::new(anchors)))
+            },
+        }
+    }
+
+    /// Read a file, reusing the anchors from a previous run if its contents
+    /// haven't changed.
+    fn source(&self, path: &Path, files: &dyn FileProvider) -> Option<Source> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        let is_markdown = match ext.as_str() {
+            "md" => true,
+            "html" | "htm" => false,
+            _ => return None,
+        };
+
+        let src = files.read(path).and_then(|bytes| {
+            String::from_utf8(bytes)
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+        });
+        let src = match src {
+            // headings pulled in with {{#include}} become part of the page
+            Ok(src) if is_markdown => crate::includes::expand_includes(
+                &src,
+                path.parent().unwrap_or_else(|| Path::new(".")),
+                files,
+            ),
+            Ok(src) => src,
+            Err(e) => {
+                tracing::debug!(
+                    "Unable to read \"{}\" to check its anchors: {}",
+                    path.display(),
+                    e
+                );
+                return None;
+            },
+        };
+
+        // the same text has different anchors in markdown and HTML, and
+        // markdown headings depend on how they are slugified
+        let slugs = if is_markdown {
+            format!("{:?}", self.slugs)
+        } else {
+            String::new()
+        };
+        let hash = crate::incremental::hash(
+            [ext.as_bytes(), b"\0", slugs.as_bytes(), b"\0", src.as_bytes()]
+                .concat()
+                .as_slice(),
+        );
+
+        match self.known.get(&hash) {
+            Some(anchors) => Some(Source::Known(hash, Arc::clone(anchors))),
+            None => Some(Source::Unparsed(hash, src, is_markdown)),
+        }
+    }
+}
+
+/// A file's content hash, along with its anchors or the text they need to be
+/// parsed from.
+enum Source {
+    Known(u64, Arc<HashSet<String>>),
+    /// The file's text, and whether it is markdown (rather than HTML).
+    Unparsed(u64, String, bool),
+}
+
+fn parse(src: &str, is_markdown: bool, slugs: &SlugConfig) -> HashSet<String> {
+    if is_markdown {
+        anchors_in_markdown(src, slugs)
+    } else {
+        anchors_in_html(src)
+    }
+}
+
+/// Find all the anchors (e.g. heading IDs or explicit `id="..."` attributes)
+/// in a markdown document, as they would be generated by the book's renderer
+/// (see [`SlugConfig`]).
+pub(crate) fn anchors_in_markdown(
+    src: &str,
+    slugs: &SlugConfig,
+) -> HashSet<String> {
+    let html = mdbook::utils::render_markdown(src, false);
+    let mut anchors = anchors_in_html(&html);
+
+    // mdbook generates heading IDs by post-processing the rendered HTML, so
+    // we need to do the same thing to make sure duplicate headings get the
+    // same "-1", "-2", etc. suffixes.
+    let mut id_counter = HashMap::new();
+
+    for caps in HEADING.captures_iter(&html) {
+        anchors.insert(heading_id(&caps[2], slugs, &mut id_counter));
+    }
+
+    anchors
+}
+
+/// Generate the ID for a heading, given its rendered HTML.
+fn heading_id(
+    html: &str,
+    slugs: &SlugConfig,
+    id_counter: &mut HashMap<String, usize>,
+) -> String {
+    let mut html = html.to_string();
+
+    if slugs.heading_attributes {
+        if let Some((heading, attributes)) = heading_attributes(&html) {
+            let id = attributes
+                .split_whitespace()
+                .find_map(|attribute| attribute.strip_prefix('#'));
+            if let Some(id) = id {
+                return id.to_string();
+            }
+            html = heading.to_string();
+        }
+    }
+
+    for (from, to) in &slugs.replacements {
+        html = html.replace(&escape_html(from), &escape_html(to));
+    }
+
+    match slugs.style {
+        SlugStyle::Mdbook => {
+            mdbook::utils::unique_id_from_content(&html, id_counter)
+        },
+        SlugStyle::Github => {
+            let id = github_slug(&html);
+            let count = id_counter.entry(id.clone()).or_insert(0);
+            let unique = match *count {
+                0 => id,
+                n => format!("{}-{}", id, n),
+            };
+            *count += 1;
+            unique
+        },
+    }
+}
+
+/// Split a heading like `Heading {#custom .class}` into the heading and its
+/// attributes.
+fn heading_attributes(html: &str) -> Option<(&str, &str)> {
+    let html = html.trim_end();
+    let start = html.strip_suffix('}')?.rfind('{')?;
+
+    Some((html[..start].trim_end(), &html[start + 1..html.len() - 1]))
+}
+
+/// The ID GitHub gives a heading: its text, lowercased, with punctuation
+/// removed and spaces turned into dashes.
+fn github_slug(html: &str) -> String {
+    let text = TAG.replace_all(html, "");
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Escape text the same way `pulldown_cmark` does when rendering it.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Find all the explicit anchors (`id="..."` and `<a name="...">`) in a HTML
+/// document.
+pub(crate) fn anchors_in_html(src: &str) -> HashSet<String> {
+    ID_ATTRIBUTE
+        .captures_iter(src)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryFiles;
+
+    #[test]
+    fn headings_get_the_same_ids_as_mdbook() {
+        let src = r#"
+# Chapter 1
+
+## Some `code` and **bold** text
+
+## Duplicate
+
+## Duplicate
+
+<a name="custom-anchor"></a>
+<div id='another-one'></div>
+"#;
+        let should_be: HashSet<String> = vec![
+            "chapter-1",
+            "some-code-and-bold-text",
+            "duplicate",
+            "duplicate-1",
+            "custom-anchor",
+            "another-one",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let got = anchors_in_markdown(src, &SlugConfig::default());
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn headings_can_be_slugified_like_other_renderers() {
+        let src = r#"
+# Überblick
+
+## Tips & Tricks
+
+## Custom {#my-id .class}
+
+## `Vec<T>` {.class}
+"#;
+        let slugs = SlugConfig {
+            style: SlugStyle::Github,
+            heading_attributes: true,
+            replacements: vec![(String::from("&"), String::from("and"))]
+                .into_iter()
+                .collect(),
+        };
+
+        let got = anchors_in_markdown(src, &slugs);
+        let mdbook = anchors_in_markdown(src, &SlugConfig::default());
+
+        let should_be: HashSet<String> =
+            vec!["überblick", "tips-and-tricks", "my-id", "vect"]
+                .into_iter()
+                .map(String::from)
+                .collect();
+        assert_eq!(got, should_be);
+        // mdbook only lowercases ASCII letters
+        assert!(mdbook.contains("Überblick"));
+        assert!(mdbook.contains("custom-my-id-class"));
+    }
+
+    #[test]
+    fn the_index_only_parses_each_file_once() {
+        let chapter = Path::new("/book/src/chapter_1.md");
+        let mut files = InMemoryFiles::new();
+        files.insert(chapter, "# Chapter 1");
+        let index = AnchorIndex::default();
+
+        let first = index.anchors(chapter, &files).unwrap();
+        files.insert(chapter, "# Something Else");
+        let second =
+            index.anchors(Path::new("/book/src/./chapter_1.md"), &files);
+
+        assert!(first.contains("chapter-1"));
+        assert!(Arc::ptr_eq(&first, &second.unwrap()));
+        assert!(index
+            .anchors(Path::new("/book/src/image.png"), &files)
+            .is_none());
+
+        // the next run reuses the anchors for unchanged files
+        let known = index.into_known();
+        assert_eq!(known.len(), 1);
+        let index = AnchorIndex::with_known(known, SlugConfig::default());
+        files.insert(chapter, "# Chapter 1");
+        let third = index.anchors(chapter, &files).unwrap();
+        assert!(Arc::ptr_eq(&third, index.known.values().next().unwrap()));
+    }
+
+    #[test]
+    fn anchors_are_case_sensitive() {
+        let anchors =
+            anchors_in_markdown("## Subheading", &SlugConfig::default());
+
+        assert!(anchors.contains("subheading"));
+        // browsers won't scroll to the heading if the case is different
+        assert!(!anchors.contains("Subheading"));
+    }
+}
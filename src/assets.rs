@@ -0,0 +1,305 @@
+use crate::{
+    config::is_web_link, web::Prober, Config, FileProvider, HttpClient,
+};
+use codespan::Files;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use linkcheck::Link;
+use reqwest::Url;
+use serde_derive::Serialize;
+use std::path::{Path, PathBuf};
+
+/// File extensions for downloads (documents, archives, installers, etc.)
+/// rather than pages.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "bin", "bz2", "deb", "dmg", "epub", "exe", "gz", "iso",
+    "jar", "mobi", "msi", "pdf", "pkg", "rar", "rpm", "tar", "tgz", "whl",
+    "xz", "zip",
+];
+
+/// A problem with a link to a downloadable asset (e.g. a PDF or zip file).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AssetProblem {
+    /// The link to the asset.
+    pub link: Link,
+    /// What is wrong with it.
+    pub kind: AssetProblemKind,
+}
+
+/// The ways an [`AssetProblem`] can go wrong.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum AssetProblemKind {
+    /// The asset is bigger than [`Config::max_asset_size`].
+    TooLarge {
+        /// The asset's size, in bytes.
+        size: u64,
+    },
+    /// A remote asset was served as a HTML page (usually an error or login
+    /// page), so readers won't get the file they expect. These are only
+    /// looked for when [`crate::WebConfig::asset_content_type`] is set.
+    WrongContentType {
+        /// The `Content-Type` the server responded with.
+        content_type: String,
+    },
+}
+
+/// Does this link point at a downloadable asset, going by its extension?
+pub(crate) fn is_asset(href: &str) -> bool {
+    let path = match href.parse::<Url>() {
+        Ok(url) => url.path().to_string(),
+        Err(_) => href.split(['#', '?']).next().unwrap_or_default().into(),
+    };
+    if path.ends_with('/') {
+        return false;
+    }
+
+    Path::new(&path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ASSET_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+        })
+}
+
+/// Look for problems with the (valid) links to assets.
+///
+/// Local assets are only checked against [`Config::max_asset_size`]. Remote
+/// ones are sent a `HEAD` request, and given the benefit of the doubt when
+/// the request fails or the server doesn't say how big they are.
+pub(crate) async fn asset_problems(
+    valid_links: &[Link],
+    cfg: &Config,
+    src_dir: &Path,
+    files: &Files<String>,
+    file_provider: &dyn FileProvider,
+    http: &dyn HttpClient,
+) -> Vec<AssetProblem> {
+    let check_content_type = cfg.web.asset_content_type.is_some();
+    if cfg.max_asset_size.is_none() && !check_content_type {
+        return Vec::new();
+    }
+
+    let (web, local): (Vec<&Link>, Vec<&Link>) = valid_links
+        .iter()
+        .filter(|link| is_asset(&link.href))
+        .partition(|link| is_web_link(&link.href));
+
+    let mut problems: Vec<AssetProblem> = local
+        .into_iter()
+        .filter_map(|link| {
+            let size = local_size(link, src_dir, files, file_provider)?;
+            too_large(link, size, cfg)
+        })
+        .collect();
+
+    let prober = Prober::new(cfg, http);
+    let remote = prober
+        .head_each(web, |link, response| {
+            let response = response.headers();
+            let mut problems = Vec::new();
+
+            if let Some(content_type) = response.get(CONTENT_TYPE) {
+                let content_type = content_type.to_str().ok()?;
+                if check_content_type && is_html(content_type) {
+                    problems.push(AssetProblem {
+                        link: link.clone(),
+                        kind: AssetProblemKind::WrongContentType {
+                            content_type: content_type.to_string(),
+                        },
+                    });
+                }
+            }
+
+            let size = response
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            problems.extend(size.and_then(|size| too_large(link, size, cfg)));
+
+            Some(problems)
+        })
+        .await;
+    problems.extend(remote.into_iter().flatten());
+
+    problems.sort_by_key(|p| (p.link.file, p.link.span));
+    problems
+}
+
+fn too_large(link: &Link, size: u64, cfg: &Config) -> Option<AssetProblem> {
+    match cfg.max_asset_size {
+        Some(max_size) if size > max_size => Some(AssetProblem {
+            link: link.clone(),
+            kind: AssetProblemKind::TooLarge { size },
+        }),
+        _ => None,
+    }
+}
+
+/// The size of the file a local link points to.
+fn local_size(
+    link: &Link,
+    src_dir: &Path,
+    files: &Files<String>,
+    file_provider: &dyn FileProvider,
+) -> Option<u64> {
+    let path = crate::normalize::local_path(&link.href);
+    let path = path.to_str()?;
+    let path: PathBuf = match path.strip_prefix('/') {
+        Some(from_root) => src_dir.join(from_root),
+        None => {
+            let chapter = src_dir.join(files.name(link.file));
+            chapter.parent()?.join(path)
+        },
+    };
+
+    file_provider.file_size(&path).ok()
+}
+
+fn is_html(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.eq_ignore_ascii_case("text/html")
+}
+
+/// Describe a number of bytes the way a person would (e.g. `12.3 MB`).
+pub(crate) fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = "bytes";
+    for next in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next;
+    }
+
+    format!("{:.1} {}", size, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http_client::Canned, InMemoryFiles, WarningPolicy, WebConfig};
+    use codespan::Span;
+
+    #[test]
+    fn recognise_assets() {
+        let inputs = vec![
+            ("./downloads/manual.pdf", true),
+            ("../release.tar.gz#sha256", true),
+            ("https://example.com/setup.EXE?version=2", true),
+            ("https://example.com/manual.pdf/", false),
+            ("https://example.com/", false),
+            ("./chapter_1.md", false),
+            ("./images/logo.png", false),
+        ];
+
+        for (href, should_be) in inputs {
+            assert_eq!(is_asset(href), should_be, "{}", href);
+        }
+    }
+
+    #[test]
+    fn huge_local_assets_are_reported() {
+        let src_dir = Path::new("/book");
+        let mut files = Files::new();
+        let id = files.add("src/chapter_1.md", String::new());
+        let mut file_provider = InMemoryFiles::new();
+        file_provider.insert(src_dir.join("download.zip"), vec![0; 42]);
+        let size = 42;
+        let link = Link::new("../download.zip#L1", Span::new(0, 1), id);
+        let cfg = Config {
+            max_asset_size: Some(size - 1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            local_size(&link, src_dir, &files, &file_provider),
+            Some(size)
+        );
+        assert_eq!(
+            too_large(&link, size, &cfg),
+            Some(AssetProblem {
+                link: link.clone(),
+                kind: AssetProblemKind::TooLarge { size },
+            })
+        );
+        assert_eq!(too_large(&link, size - 1, &cfg), None);
+    }
+
+    #[test]
+    fn remote_assets_are_checked_with_a_head_request() {
+        let client = Canned::default()
+            .respond("https://example.com/old.zip", 301, &[(
+                "Location",
+                "/login",
+            )])
+            .respond("https://example.com/login", 200, &[(
+                "Content-Type",
+                "text/html; charset=utf-8",
+            )])
+            .respond("https://example.com/huge.iso", 200, &[
+                ("Content-Type", "application/octet-stream"),
+                ("Content-Length", "4096"),
+            ]);
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let old = Link::new("https://example.com/old.zip", Span::new(0, 1), id);
+        let huge =
+            Link::new("https://example.com/huge.iso", Span::new(2, 3), id);
+        let cfg = Config {
+            max_asset_size: Some(1024),
+            web: WebConfig {
+                asset_content_type: Some(WarningPolicy::Warn),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let got = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(asset_problems(
+                &[old.clone(), huge.clone()],
+                &cfg,
+                Path::new("/book/src"),
+                &files,
+                &InMemoryFiles::new(),
+                &client,
+            ));
+
+        assert_eq!(got, vec![
+            AssetProblem {
+                link: old,
+                kind: AssetProblemKind::WrongContentType {
+                    content_type: String::from("text/html; charset=utf-8"),
+                },
+            },
+            AssetProblem {
+                link: huge,
+                kind: AssetProblemKind::TooLarge { size: 4096 },
+            },
+        ]);
+    }
+
+    #[test]
+    fn human_readable_sizes() {
+        let inputs = vec![
+            (0, "0 bytes"),
+            (999, "999 bytes"),
+            (1500, "1.5 KB"),
+            (12_345_678, "12.3 MB"),
+            (5_000_000_000, "5.0 GB"),
+        ];
+
+        for (bytes, should_be) in inputs {
+            assert_eq!(human_size(bytes), should_be);
+        }
+    }
+}
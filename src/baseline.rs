@@ -0,0 +1,129 @@
+use crate::ValidationOutcome;
+use anyhow::{Context as _, Error};
+use codespan::Files;
+use linkcheck::Link;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::BTreeSet, io::ErrorKind, path::Path};
+
+/// Broken links which are already known about (see
+/// [`crate::Config::baseline_file`]), so only new ones get reported.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Baseline {
+    entries: BTreeSet<BaselineEntry>,
+}
+
+/// A single broken link in the [`Baseline`].
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub(crate) struct BaselineEntry {
+    /// The chapter the link is in, relative to the source directory.
+    pub(crate) chapter: String,
+    pub(crate) href: String,
+}
+
+impl BaselineEntry {
+    pub(crate) fn for_link(link: &Link, files: &Files<String>) -> Self {
+        BaselineEntry {
+            chapter: files
+                .name(link.file)
+                .to_string_lossy()
+                .replace('\\', "/"),
+            href: link.href.clone(),
+        }
+    }
+}
+
+impl Baseline {
+    /// Read the baseline, treating a missing file as an empty one.
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(Baseline::default())
+            },
+            Err(e) => {
+                return Err(Error::from(e).context(format!(
+                    "Unable to read \"{}\"",
+                    path.display()
+                )))
+            },
+        };
+
+        serde_json::from_str(&json).with_context(|| {
+            format!("Unable to parse the baseline in \"{}\"", path.display())
+        })
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json + "\n").with_context(|| {
+            format!("Unable to write the baseline to \"{}\"", path.display())
+        })
+    }
+
+    pub(crate) fn insert(&mut self, entry: BaselineEntry) {
+        self.entries.insert(entry);
+    }
+
+    pub(crate) fn contains(&self, link: &Link, files: &Files<String>) -> bool {
+        self.entries.contains(&BaselineEntry::for_link(link, files))
+    }
+
+    /// Move any broken links which are in the baseline over to
+    /// [`ValidationOutcome::ignored`].
+    pub(crate) fn apply(
+        &self,
+        outcome: &mut ValidationOutcome,
+        files: &Files<String>,
+    ) {
+        let (known, new): (Vec<_>, Vec<_>) = outcome
+            .invalid_links
+            .drain(..)
+            .partition(|invalid| self.contains(&invalid.link, files));
+
+        if !known.is_empty() {
+            tracing::info!("Ignoring {} links in the baseline", known.len());
+        }
+        outcome.invalid_links = new;
+        outcome
+            .ignored
+            .extend(known.into_iter().map(|invalid| invalid.link));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+    use linkcheck::validation::{InvalidLink, Reason};
+
+    #[test]
+    fn known_broken_links_are_ignored() {
+        let mut files = Files::new();
+        let file = files.add("chapter_1.md", String::new());
+        let broken = |href: &str| InvalidLink {
+            link: Link::new(href, Span::new(0, 0), file),
+            reason: Reason::TraversesParentDirectories,
+        };
+        let mut outcome = ValidationOutcome {
+            invalid_links: vec![broken("./known.md"), broken("./new.md")],
+            ..Default::default()
+        };
+        let mut baseline = Baseline::default();
+        baseline.insert(BaselineEntry {
+            chapter: String::from("chapter_1.md"),
+            href: String::from("./known.md"),
+        });
+        let json = serde_json::to_string(&baseline).unwrap();
+        let baseline: Baseline = serde_json::from_str(&json).unwrap();
+
+        baseline.apply(&mut outcome, &files);
+
+        assert_eq!(outcome.invalid_links.len(), 1);
+        assert_eq!(outcome.invalid_links[0].link.href, "./new.md");
+        assert_eq!(outcome.ignored.len(), 1);
+        assert_eq!(outcome.ignored[0].href, "./known.md");
+    }
+}
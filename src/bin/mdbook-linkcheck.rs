@@ -1,32 +1,369 @@
 use anyhow::{Context, Error};
 use codespan_reporting::term::termcolor::ColorChoice;
-use mdbook::{renderer::RenderContext, MDBook};
-use std::{io, path::PathBuf};
-use structopt::StructOpt;
+use mdbook::{
+    book::Book,
+    preprocess::{CmdPreprocessor, Preprocessor},
+    renderer::RenderContext,
+    MDBook,
+};
+use mdbook_linkcheck::{
+    Checker, CheckerBuilder, Config, DeadlineExceeded, GithubReview,
+    LinkcheckPreprocessor,
+};
+use std::{
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use structopt::{clap::AppSettings, StructOpt};
 
-fn main() -> Result<(), Error> {
-    env_logger::init();
+/// The logging used by `check-url` when `$RUST_LOG` isn't set.
+const CHECK_URL_LOG_FILTER: &str =
+    "mdbook_linkcheck=debug,linkcheck=debug,reqwest=debug";
+
+fn main() {
     let args = Args::from_args();
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(Command::CheckUrl { .. }) = args.command {
+        // explain what the link checker is doing unless told otherwise
+        if std::env::var_os("RUST_LOG").is_none() {
+            logger.parse_filters(CHECK_URL_LOG_FILTER);
+        }
+    }
+    logger.init();
+
+    if let Err(e) = run(args) {
+        eprintln!("Error: {:?}", e);
+
+        // let people tell an incomplete run apart from actual broken links
+        let code = if e.is::<DeadlineExceeded>() {
+            DeadlineExceeded::EXIT_CODE
+        } else {
+            1
+        };
+        std::process::exit(code);
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    match args.command {
+        Some(Command::ConfigSchema) => {
+            let schema = schemars::schema_for!(Config);
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        },
+        Some(Command::Watch { ref root, interval }) => {
+            return mdbook_linkcheck::watch(
+                root,
+                args.colour,
+                Duration::from_millis(interval),
+            );
+        },
+        Some(Command::Preprocess { supports }) => {
+            return preprocess(supports);
+        },
+        Some(Command::Lsp) => {
+            let stdin = io::stdin();
+            return mdbook_linkcheck::serve_language_server(
+                stdin.lock(),
+                io::stdout(),
+            );
+        },
+        Some(Command::CheckUrl {
+            ref link,
+            ref from,
+            ref root,
+        }) => {
+            let root = dunce::canonicalize(root)?;
+            let cache_file = if args.no_cache {
+                None
+            } else {
+                standalone_cache_file(&root)?
+            };
+            let valid = mdbook_linkcheck::check_url(
+                &root,
+                link,
+                from.as_deref(),
+                cache_file.as_deref(),
+                io::stdout(),
+            )?;
+            if !valid {
+                std::process::exit(1);
+            }
+            return Ok(());
+        },
+        Some(Command::Doctor { ref root }) => {
+            let root = dunce::canonicalize(root)?;
+            let cache_file = standalone_cache_file(&root)?;
+            let healthy = mdbook_linkcheck::doctor(
+                &root,
+                cache_file.as_deref(),
+                io::stdout(),
+            )?;
+            if !healthy {
+                std::process::exit(1);
+            }
+            return Ok(());
+        },
+        Some(Command::CheckRendered { ref root, ref dir }) => {
+            let root = dunce::canonicalize(root)?;
+            let cache_file = if args.no_cache {
+                None
+            } else {
+                standalone_cache_file(&root)?
+            };
+            return mdbook_linkcheck::check_rendered(
+                &root,
+                dir.as_deref(),
+                cache_file.as_deref(),
+                args.colour,
+            );
+        },
+        Some(Command::Serve { ref root }) => {
+            let stdin = io::stdin();
+            return mdbook_linkcheck::serve(
+                &dunce::canonicalize(root)?,
+                stdin.lock(),
+                io::stdout(),
+            );
+        },
+        None => {},
+    }
+
+    if args.standalone && !args.root.join("book.toml").exists() {
+        // there's no book here, so just check the markdown files directly
+        let root = dunce::canonicalize(&args.root)?;
+        let cfg = directory_config(&args)?;
+        let selected_files = match (&args.changed_since, &args.selected_files) {
+            (Some(revision), _) => {
+                let checker = Checker::builder().source_dir(&root).build()?;
+                Some(changed_chapters(checker.book(), &root, revision)?)
+            },
+            (None, Some(patterns)) => {
+                let checker = Checker::builder().source_dir(&root).build()?;
+                Some(selected_chapters(checker.book(), &root, &root, patterns)?)
+            },
+            (None, None) => None,
+        };
+        if args.dry_run {
+            let checker = Checker::builder().source_dir(&root).config(cfg);
+            return list_links(checker, selected_files);
+        }
+        if args.fix || args.fix_dry_run || args.interactive {
+            let checker =
+                Checker::builder().source_dir(&root).config(cfg).build()?;
+            if args.interactive {
+                return triage(&checker, None);
+            }
+            return mdbook_linkcheck::fix(&checker, args.fix_dry_run);
+        }
+        let cache_file = if args.no_cache {
+            None
+        } else {
+            cfg.resolve_cache_file(&root)?
+        };
+        return mdbook_linkcheck::run_directory(
+            &root,
+            cfg,
+            cache_file.as_deref(),
+            args.colour,
+            selected_files,
+        );
+    }
+
+    if args.interactive && !args.standalone {
+        return Err(Error::msg(
+            "Interactive mode can only be used when running standalone",
+        ));
+    }
 
     // get a `RenderContext`, either from stdin (because we're used as a plugin)
     // or by instrumenting MDBook directly (in standalone mode).
-    let ctx: RenderContext = if args.standalone {
+    let mut ctx: RenderContext = if args.standalone {
         let md =
             MDBook::load(dunce::canonicalize(&args.root)?).map_err(to_sync)?;
-        let destination = md.build_dir_for("linkcheck");
-        RenderContext::new(md.root, md.book, md.config, destination)
+        if mdbook_linkcheck::get_config(&md.config)?.raw_sources {
+            let destination = md.build_dir_for("linkcheck");
+            RenderContext::new(md.root, md.book, md.config, destination)
+        } else {
+            mdbook_linkcheck::preprocess(&md)?
+        }
     } else {
         serde_json::from_reader(io::stdin())
             .context("Unable to parse RenderContext")?
     };
 
-    let cache_file = ctx.destination.join("cache.json");
+    apply_cli_config(&mut ctx.config, &args)?;
+    let cfg = mdbook_linkcheck::get_config(&ctx.config)?;
+    let selected_files = match (&args.changed_since, &args.selected_files) {
+        (Some(revision), _) => {
+            Some(changed_chapters(&ctx.book, &ctx.source_dir(), revision)?)
+        },
+        (None, Some(patterns)) => Some(selected_chapters(
+            &ctx.book,
+            &ctx.source_dir(),
+            &ctx.root,
+            patterns,
+        )?),
+        (None, None) => None,
+    };
+    if args.dry_run {
+        let checker = Checker::builder()
+            .source_dir(ctx.source_dir())
+            .config(cfg)
+            .book(ctx.book)
+            .book_toml(ctx.root.join("book.toml"));
+        return list_links(checker, selected_files);
+    }
+    if args.fix || args.fix_dry_run || args.interactive {
+        let book_toml = ctx.root.join("book.toml");
+        let checker = Checker::builder()
+            .source_dir(ctx.source_dir())
+            .config(cfg)
+            .book(ctx.book)
+            .build()?;
+        if args.interactive {
+            return triage(&checker, Some(&book_toml));
+        }
+        return mdbook_linkcheck::fix(&checker, args.fix_dry_run);
+    }
+    let cache_file = match cfg.resolve_cache_file(&ctx.root)? {
+        Some(cache_file) => cache_file,
+        None => ctx.destination.join("cache.json"),
+    };
     let cache_file = if args.no_cache {
         None
     } else {
         Some(cache_file.as_path())
     };
-    mdbook_linkcheck::run(cache_file, args.colour, &ctx, args.selected_files)
+    mdbook_linkcheck::run(cache_file, args.colour, &ctx, selected_files)
+}
+
+/// Override the `[output.linkcheck]` table with any settings given on the
+/// command line.
+fn apply_cli_config(
+    book_cfg: &mut mdbook::Config,
+    args: &Args,
+) -> Result<(), Error> {
+    if args.fail_fast {
+        book_cfg
+            .set("output.linkcheck.fail-fast", true)
+            .map_err(to_sync)?;
+    }
+    if args.trace_http {
+        book_cfg
+            .set("output.linkcheck.trace-http", true)
+            .map_err(to_sync)?;
+    }
+    // the library doesn't stream failures by default, but people running the
+    // command want to see progress
+    if args.no_stream
+        || book_cfg.get("output.linkcheck.stream-failures").is_none()
+    {
+        book_cfg
+            .set("output.linkcheck.stream-failures", !args.no_stream)
+            .map_err(to_sync)?;
+    }
+    if let Some(ref review) = args.github_review {
+        book_cfg
+            .set("output.linkcheck.github-review", review)
+            .map_err(to_sync)?;
+    }
+
+    Ok(())
+}
+
+/// The [`Config`] for checking a plain directory of markdown files, worked
+/// out the same way as a book's, just without a `book.toml`.
+fn directory_config(args: &Args) -> Result<Config, Error> {
+    let mut book_cfg = mdbook::Config::default();
+    apply_cli_config(&mut book_cfg, args)?;
+
+    mdbook_linkcheck::get_config(&book_cfg)
+}
+
+/// Where a normal run would keep its cache, if anywhere.
+fn standalone_cache_file(root: &Path) -> Result<Option<PathBuf>, Error> {
+    if !root.join("book.toml").exists() {
+        let cfg = mdbook_linkcheck::get_config(&mdbook::Config::default())?;
+        return cfg.resolve_cache_file(root);
+    }
+
+    let md = MDBook::load(root).map_err(to_sync)?;
+    let cfg = mdbook_linkcheck::get_config(&md.config)?;
+    match cfg.resolve_cache_file(&md.root)? {
+        Some(cache_file) => Ok(Some(cache_file)),
+        None => Ok(Some(md.build_dir_for("linkcheck").join("cache.json"))),
+    }
+}
+
+fn triage(checker: &Checker, book_toml: Option<&Path>) -> Result<(), Error> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::msg("Interactive mode needs a terminal"));
+    }
+
+    let stdin = io::stdin();
+    mdbook_linkcheck::triage(checker, book_toml, stdin.lock(), io::stdout())
+}
+
+fn list_links(
+    builder: CheckerBuilder,
+    selected_files: Option<Vec<String>>,
+) -> Result<(), Error> {
+    let builder = match selected_files {
+        Some(selected_files) => builder.selected_files(selected_files),
+        None => builder,
+    };
+
+    mdbook_linkcheck::list_links(&builder.build()?, io::stdout())
+}
+
+/// Find the chapters affected by any changes since a git revision, in the
+/// form expected by `--files`.
+fn changed_chapters(
+    book: &Book,
+    src_dir: &Path,
+    revision: &str,
+) -> Result<Vec<String>, Error> {
+    let chapters = mdbook_linkcheck::changed_chapters(book, src_dir, revision)?;
+    tracing::info!(
+        "Only checking the {} chapters affected by changes since {}",
+        chapters.len(),
+        revision
+    );
+
+    Ok(chapters
+        .iter()
+        .map(|chapter| chapter.display().to_string())
+        .collect())
+}
+
+/// Find the chapters matching the `--files` patterns (and the chapters
+/// linking to them), in the form expected by `--files`.
+fn selected_chapters(
+    book: &Book,
+    src_dir: &Path,
+    root: &Path,
+    patterns: &[String],
+) -> Result<Vec<String>, Error> {
+    let chapters =
+        mdbook_linkcheck::selected_chapters(book, src_dir, root, patterns)?;
+    if chapters.is_empty() {
+        return Err(Error::msg(format!(
+            "No chapters match {}",
+            patterns.join(", ")
+        )));
+    }
+    tracing::info!(
+        "Only checking the {} chapters matching (or linking to) {}",
+        chapters.len(),
+        patterns.join(", ")
+    );
+
+    Ok(chapters
+        .iter()
+        .map(|chapter| chapter.display().to_string())
+        .collect())
 }
 
 #[derive(Debug, Clone, StructOpt)]
@@ -38,7 +375,8 @@ struct Args {
     )]
     standalone: bool,
     #[structopt(
-        help = "The book to render.",
+        help = "The book to render (or a directory of markdown files when \
+                running standalone).",
         parse(from_os_str),
         default_value = "."
     )]
@@ -55,14 +393,197 @@ struct Args {
     #[structopt(
         short = "f",
         long = "files",
-        help = "Check only the given files (check all files if omitted)."
+        help = "Check only the chapters matching these patterns (e.g. 'src/ch03/**'), and the chapters linking to them (check all files if omitted)."
     )]
     selected_files: Option<Vec<String>>,
+    #[structopt(
+        long = "changed-since",
+        help = "Only check chapters affected by changes since this git revision (e.g. HEAD).",
+        conflicts_with = "selected-files"
+    )]
+    changed_since: Option<String>,
     #[structopt(
         long = "no-cache",
         help = "Ignore any existing cache, neither using nor updating it."
     )]
     no_cache: bool,
+    #[structopt(
+        long = "fail-fast",
+        help = "Stop at the first broken link which would be reported as an error."
+    )]
+    fail_fast: bool,
+    #[structopt(
+        long = "trace-http",
+        help = "Print each web request and response (with credentials redacted) to stderr."
+    )]
+    trace_http: bool,
+    #[structopt(
+        long = "no-stream",
+        help = "Only report broken links at the end, instead of also printing each one as soon as it is found."
+    )]
+    no_stream: bool,
+    #[structopt(
+        long = "github-review",
+        help = "Post problems on lines changed by this pull request (e.g. owner/repo#123) as review comments, using the token in $GITHUB_TOKEN."
+    )]
+    github_review: Option<GithubReview>,
+    #[structopt(
+        long = "fix",
+        help = "Rewrite links which permanently redirect or point to a file that was moved."
+    )]
+    fix: bool,
+    #[structopt(
+        long = "fix-dry-run",
+        help = "Print a diff of the changes --fix would make, without touching any files.",
+        conflicts_with = "fix"
+    )]
+    fix_dry_run: bool,
+    #[structopt(
+        short = "i",
+        long = "interactive",
+        help = "Step through each broken link, choosing whether to fix, exclude, or skip it.",
+        conflicts_with_all = &["fix", "fix-dry-run"]
+    )]
+    interactive: bool,
+    #[structopt(
+        long = "dry-run",
+        help = "List every link and whether it would be checked (and why not), without checking anything.",
+        conflicts_with_all = &["fix", "fix-dry-run", "interactive"]
+    )]
+    dry_run: bool,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+enum Command {
+    #[structopt(
+        name = "config-schema",
+        about = "Print a JSON Schema for the `[output.linkcheck]` table",
+        setting = AppSettings::Hidden
+    )]
+    ConfigSchema,
+    #[structopt(
+        name = "watch",
+        about = "Re-check a book's links every time a file changes"
+    )]
+    Watch {
+        #[structopt(
+            help = "The book (or directory of markdown files) to watch.",
+            parse(from_os_str),
+            default_value = "."
+        )]
+        root: PathBuf,
+        #[structopt(
+            long = "interval",
+            help = "How often to check for changes, in milliseconds",
+            default_value = "500"
+        )]
+        interval: u64,
+    },
+    #[structopt(
+        name = "lsp",
+        about = "Run a language server which reports broken links to your editor"
+    )]
+    Lsp,
+    #[structopt(
+        name = "check-url",
+        about = "Check a single link, explaining each step along the way"
+    )]
+    CheckUrl {
+        #[structopt(help = "The link to check.")]
+        link: String,
+        #[structopt(
+            long = "from",
+            help = "The chapter the link is in, relative to the source directory (e.g. chapter_1.md)."
+        )]
+        from: Option<String>,
+        #[structopt(
+            help = "The book (or directory of markdown files) the link is in.",
+            parse(from_os_str),
+            default_value = "."
+        )]
+        root: PathBuf,
+    },
+    #[structopt(
+        name = "doctor",
+        about = "Look for problems with the configuration and environment, then print the effective settings"
+    )]
+    Doctor {
+        #[structopt(
+            help = "The book (or directory of markdown files) to check.",
+            parse(from_os_str),
+            default_value = "."
+        )]
+        root: PathBuf,
+    },
+    #[structopt(
+        name = "check-rendered",
+        about = "Check the links in the HTML generated by mdbook instead of the markdown source"
+    )]
+    CheckRendered {
+        #[structopt(
+            help = "The book (or directory of HTML files) to check.",
+            parse(from_os_str),
+            default_value = "."
+        )]
+        root: PathBuf,
+        #[structopt(
+            long = "dir",
+            help = "Where the rendered HTML is, if it isn't in the book's build directory.",
+            parse(from_os_str)
+        )]
+        dir: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "serve",
+        about = "Answer JSON-RPC requests to check files or links, one per line on stdin"
+    )]
+    Serve {
+        #[structopt(
+            help = "The book (or directory of markdown files) to check.",
+            parse(from_os_str),
+            default_value = "."
+        )]
+        root: PathBuf,
+    },
+    #[structopt(
+        name = "preprocess",
+        about = "Run as a mdbook preprocessor which annotates broken links in the rendered book"
+    )]
+    Preprocess {
+        #[structopt(subcommand)]
+        supports: Option<Supports>,
+    },
+}
+
+#[derive(Debug, Clone, StructOpt)]
+enum Supports {
+    #[structopt(
+        name = "supports",
+        about = "Check whether a renderer is supported by this preprocessor"
+    )]
+    Supports { renderer: String },
+}
+
+fn preprocess(supports: Option<Supports>) -> Result<(), Error> {
+    let preprocessor = LinkcheckPreprocessor;
+
+    if let Some(Supports::Supports { renderer }) = supports {
+        // mdbook uses the exit code to decide whether to run us
+        if preprocessor.supports_renderer(&renderer) {
+            return Ok(());
+        }
+        std::process::exit(1);
+    }
+
+    let (ctx, book) =
+        CmdPreprocessor::parse_input(io::stdin()).map_err(to_sync)?;
+    mdbook_linkcheck::version_check(&ctx.mdbook_version)?;
+    let book = preprocessor.run(&ctx, book).map_err(to_sync)?;
+    serde_json::to_writer(io::stdout(), &book)?;
+
+    Ok(())
 }
 
 fn parse_colour(raw: &str) -> Result<ColorChoice, Error> {
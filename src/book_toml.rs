@@ -0,0 +1,350 @@
+use crate::{changes::local_target, config::is_web_link};
+use codespan::{Files, Span};
+use linkcheck::{
+    validation::{InvalidLink, Reason},
+    Link,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+use toml::Spanned;
+
+/// The links found in a `book.toml`.
+#[derive(Debug, Default)]
+pub(crate) struct BookTomlLinks {
+    /// Web links which should be checked like any other link.
+    pub(crate) links: Vec<Link>,
+    /// Entries in the `[output.html.redirect]` table which point at a page
+    /// that doesn't exist.
+    pub(crate) broken_redirects: Vec<InvalidLink>,
+}
+
+/// Find the web links in a `book.toml`'s `[output.html]` table (e.g.
+/// `git-repository-url` and `edit-url-template`), so they can be checked like
+/// any other link, and make sure each `[output.html.redirect]` points
+/// somewhere that exists.
+///
+/// The `book.toml` is added to `files` (named relative to `src_dir`) so
+/// diagnostics can point at the offending line. The `{path}` in
+/// `edit-url-template` is filled in with `chapter` (relative to `src_dir`).
+pub(crate) fn book_toml_links(
+    book_toml: &Path,
+    src_dir: &Path,
+    chapter: Option<&Path>,
+    files: &mut Files<String>,
+) -> BookTomlLinks {
+    let text = match std::fs::read_to_string(book_toml) {
+        Ok(text) => text,
+        Err(e) => {
+            tracing::debug!(
+                "Unable to read \"{}\": {}",
+                book_toml.display(),
+                e
+            );
+            return BookTomlLinks::default();
+        },
+    };
+
+    let (urls, redirects) =
+        match html_urls(&text, chapter).and_then(|urls| {
+            redirects(&text).map(|redirects| (urls, redirects))
+        }) {
+            Ok(found) => found,
+            Err(e) => {
+                tracing::warn!(
+                    "Unable to parse \"{}\": {}",
+                    book_toml.display(),
+                    e
+                );
+                return BookTomlLinks::default();
+            },
+        };
+    if urls.is_empty() && redirects.is_empty() {
+        return BookTomlLinks::default();
+    }
+
+    let file = files.add(display_name(book_toml, src_dir), text);
+    let mut got = BookTomlLinks {
+        links: urls
+            .into_iter()
+            .map(|(url, span)| Link::new(url, span, file))
+            .collect(),
+        ..Default::default()
+    };
+
+    // redirects can point at other redirects
+    let sources: HashSet<PathBuf> = redirects
+        .iter()
+        .filter_map(|r| local_target(Path::new("index.md"), &r.source))
+        .collect();
+
+    for redirect in redirects {
+        let link = Link::new(redirect.destination.clone(), redirect.span, file);
+
+        if is_web_link(&redirect.destination) {
+            got.links.push(link);
+        } else if let Err(reason) = check_redirect(&redirect, src_dir, &sources)
+        {
+            got.broken_redirects.push(InvalidLink { link, reason });
+        }
+    }
+
+    got
+}
+
+/// An entry in the `[output.html.redirect]` table.
+#[derive(Debug, Clone, PartialEq)]
+struct Redirect {
+    /// The old page (e.g. `/old/page.html`).
+    source: String,
+    /// Where it now lives, either as a URL or relative to `source`.
+    destination: String,
+    /// Where `destination` appears in `book.toml`.
+    span: Span,
+}
+
+/// Make sure a redirect points at a page in the book.
+fn check_redirect(
+    redirect: &Redirect,
+    src_dir: &Path,
+    sources: &HashSet<PathBuf>,
+) -> Result<(), Reason> {
+    // mdbook writes each redirect to the `source` path, so relative
+    // destinations are resolved from there
+    let page = redirect.source.trim_start_matches('/');
+    let target = match local_target(Path::new(page), &redirect.destination) {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+
+    let mut candidates =
+        vec![target.join("index.md"), target.join("README.md")];
+    // the index preprocessor turns README.md into index.html
+    if target.file_name().is_some_and(|name| name == "index.md") {
+        candidates.push(target.with_file_name("README.md"));
+    }
+    candidates.push(target.clone());
+    let exists = candidates
+        .iter()
+        .any(|candidate| src_dir.join(candidate).is_file());
+
+    if exists || sources.contains(&target) {
+        Ok(())
+    } else {
+        Err(Reason::Io(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "\"{}\" redirects to \"{}\", which doesn't exist",
+                redirect.source,
+                target.display()
+            ),
+        )))
+    }
+}
+
+/// Chapters are named relative to the source directory, so do the same for
+/// `book.toml` (e.g. `../book.toml`).
+fn display_name(book_toml: &Path, src_dir: &Path) -> String {
+    let relative = book_toml
+        .parent()
+        .and_then(|root| src_dir.strip_prefix(root).ok());
+
+    match relative {
+        Some(relative) => {
+            let mut name = PathBuf::new();
+            for _ in relative.components() {
+                name.push("..");
+            }
+            name.push("book.toml");
+            name.display().to_string()
+        },
+        None => book_toml.display().to_string(),
+    }
+}
+
+/// The parts of `book.toml` we care about, with the `[output.html]` table
+/// deserialized as `Html`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, bound = "Html: Deserialize<'de> + Default")]
+struct BookToml<Html> {
+    output: Output<Html>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, bound = "Html: Deserialize<'de> + Default")]
+struct Output<Html> {
+    html: Html,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RedirectTable {
+    redirect: BTreeMap<String, Spanned<String>>,
+}
+
+/// Get every URL in the `[output.html]` table, along with where it appears.
+fn html_urls(
+    text: &str,
+    chapter: Option<&Path>,
+) -> Result<Vec<(String, Span)>, toml::de::Error> {
+    let doc: BookToml<BTreeMap<String, Spanned<toml::Value>>> =
+        toml::from_str(text)?;
+    let mut urls = Vec::new();
+
+    for value in doc.output.html.values() {
+        let raw = match value.get_ref().as_str() {
+            Some(raw) => raw,
+            None => continue,
+        };
+        let url = match chapter {
+            Some(chapter) => raw.replace(
+                "{path}",
+                &chapter.to_string_lossy().replace('\\', "/"),
+            ),
+            // there's no chapter to fill in the template with
+            None if raw.contains("{path}") => continue,
+            None => raw.to_string(),
+        };
+
+        if url.starts_with("https://") || url.starts_with("http://") {
+            urls.push((url, span_of(text, value)));
+        }
+    }
+
+    urls.sort_by_key(|(_, span)| span.start());
+    Ok(urls)
+}
+
+/// Get every entry in the `[output.html.redirect]` table.
+fn redirects(text: &str) -> Result<Vec<Redirect>, toml::de::Error> {
+    let doc: BookToml<RedirectTable> = toml::from_str(text)?;
+    let mut redirects: Vec<_> = doc
+        .output
+        .html
+        .redirect
+        .into_iter()
+        .map(|(source, destination)| Redirect {
+            span: span_of(text, &destination),
+            source,
+            destination: destination.into_inner(),
+        })
+        .collect();
+
+    redirects.sort_by_key(|redirect| redirect.span.start());
+    Ok(redirects)
+}
+
+/// Where a string's contents appear in the original text, without the quotes
+/// around it.
+fn span_of<T>(text: &str, value: &Spanned<T>) -> Span {
+    let (start, end) = value.span();
+    let raw = text.get(start..end).unwrap_or_default();
+    let quotes = ["\"\"\"", "'''", "\"", "'"]
+        .iter()
+        .find(|quote| raw.len() >= 2 * quote.len() && raw.starts_with(*quote))
+        .map_or(0, |quote| quote.len());
+
+    Span::new((start + quotes) as u32, (end - quotes) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_urls_in_the_html_table() {
+        let text = r#"
+[book]
+title = "My Book"
+
+[output.html]
+edit-url-template = "https://github.com/user/repo/edit/main/{path}"
+git-repository-url = 'https://github.com/user/repo'
+site-url = "/my-book/"
+default-theme = "light"
+cname = "https://example.com/\u0061"
+
+[output.html.playground]
+editable = true
+"#;
+
+        let got = html_urls(text, Some(Path::new("nested/chapter_1.md")))
+            .unwrap();
+
+        let urls: Vec<_> = got.iter().map(|(url, _)| url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://github.com/user/repo/edit/main/nested/chapter_1.md",
+                "https://github.com/user/repo",
+                "https://example.com/a",
+            ]
+        );
+        let (_, span) = &got[0];
+        assert_eq!(
+            &text[span.start().to_usize()..span.end().to_usize()],
+            "https://github.com/user/repo/edit/main/{path}"
+        );
+        assert_eq!(
+            got[1].1.start().to_usize(),
+            text.find("'https").unwrap() + 1
+        );
+        // escapes are unescaped, but the span still covers the original text
+        assert_eq!(got[2].0, "https://example.com/a");
+        assert_eq!(
+            &text[got[2].1.start().to_usize()..got[2].1.end().to_usize()],
+            r"https://example.com/\u0061"
+        );
+    }
+
+    #[test]
+    fn redirects_to_missing_pages_are_broken() {
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("all-green")
+            .join("src");
+        let text = r#"
+[output.html.redirect]
+"/old/chapter.html" = "../chapter_1.html"
+"/moved.html" = "nested/index.html"
+"/gone.html" = "deleted.html"
+"/older.html" = "old/chapter.html"
+"/elsewhere.html" = "https://example.com/"
+"#;
+        let redirects = redirects(text).unwrap();
+        let sources: HashSet<PathBuf> = redirects
+            .iter()
+            .filter_map(|r| local_target(Path::new("index.md"), &r.source))
+            .collect();
+
+        let broken: Vec<_> = redirects
+            .iter()
+            .filter(|r| !is_web_link(&r.destination))
+            .filter(|r| check_redirect(r, &src_dir, &sources).is_err())
+            .map(|r| r.source.as_str())
+            .collect();
+
+        assert_eq!(broken, vec!["/gone.html"]);
+        let gone = &redirects[2];
+        assert_eq!(
+            &text[gone.span.start().to_usize()..gone.span.end().to_usize()],
+            "deleted.html"
+        );
+    }
+
+    #[test]
+    fn book_toml_is_named_relative_to_the_source_directory() {
+        let got = display_name(
+            Path::new("/path/to/book/book.toml"),
+            Path::new("/path/to/book/src"),
+        );
+
+        assert_eq!(
+            got,
+            Path::new("..").join("book.toml").display().to_string()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use http::StatusCode;
+use linkcheck::validation::CacheEntry;
+use reqwest::Url;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+    time::{Duration, SystemTime},
+};
+
+/// The results from previous runs, so web links which were checked recently
+/// don't need to be checked again.
+///
+/// This is a [`linkcheck::validation::Cache`] (which it dereferences to), plus
+/// the details about each result that it has no room for, like when it
+/// expires and the redirects which were followed. Cache files written by
+/// older versions can still be loaded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cache {
+    #[serde(flatten)]
+    results: linkcheck::validation::Cache,
+    /// When each result stops being fresh (e.g. because of the server's
+    /// `Cache-Control` header).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    expires: BTreeMap<String, SystemTime>,
+    /// The redirects followed while checking each URL.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    redirects: BTreeMap<String, Vec<Redirect>>,
+}
+
+impl Cache {
+    /// Remember whether `url` is valid, a result which stays fresh for `ttl`.
+    pub(crate) fn insert_fresh_for(
+        &mut self,
+        url: Url,
+        valid: bool,
+        ttl: Duration,
+    ) {
+        let now = SystemTime::now();
+        if let Some(expires) = now.checked_add(ttl) {
+            self.expires.insert(url.to_string(), expires);
+        }
+        self.results.insert(url, CacheEntry::new(now, valid));
+    }
+
+    /// Remember a result without an expiry, so it is fresh for as long as the
+    /// timeout given to [`Cache::url_is_still_valid()`].
+    pub fn insert(&mut self, url: Url, entry: CacheEntry) {
+        self.expires.remove(url.as_str());
+        self.results.insert(url, entry);
+    }
+
+    /// Was `url` valid the last time it was checked, and is that result still
+    /// fresh? Results are never used once they are older than `timeout` or
+    /// past their expiry.
+    pub fn url_is_still_valid(&self, url: &Url, timeout: Duration) -> bool {
+        let expired = self
+            .expires
+            .get(url.as_str())
+            .is_some_and(|expires| SystemTime::now() >= *expires);
+
+        !expired && self.results.url_is_still_valid(url, timeout)
+    }
+
+    /// Remember the redirects which were followed when `url` was checked,
+    /// replacing any from an earlier check.
+    pub(crate) fn set_redirects(
+        &mut self,
+        url: &Url,
+        redirects: Vec<Redirect>,
+    ) {
+        if redirects.is_empty() {
+            self.redirects.remove(url.as_str());
+        } else {
+            self.redirects.insert(url.to_string(), redirects);
+        }
+    }
+
+    /// The redirects which were followed when `url` was last checked.
+    pub(crate) fn redirects(&self, url: &Url) -> &[Redirect] {
+        self.redirects
+            .get(url.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.expires.clear();
+        self.redirects.clear();
+    }
+}
+
+impl From<linkcheck::validation::Cache> for Cache {
+    fn from(results: linkcheck::validation::Cache) -> Self {
+        Cache {
+            results,
+            expires: BTreeMap::new(),
+            redirects: BTreeMap::new(),
+        }
+    }
+}
+
+impl Deref for Cache {
+    type Target = linkcheck::validation::Cache;
+
+    fn deref(&self) -> &Self::Target { &self.results }
+}
+
+impl DerefMut for Cache {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.results }
+}
+
+/// A single redirect that was followed, so it can be remembered for links
+/// whose result came from the [`Cache`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Redirect {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) status: u16,
+}
+
+impl Redirect {
+    pub(crate) fn new(from: &Url, to: &Url, status: StatusCode) -> Self {
+        Redirect {
+            from: from.to_string(),
+            to: to.to_string(),
+            status: status.as_u16(),
+        }
+    }
+
+    /// The URLs and status code, if they are still valid.
+    pub(crate) fn parse(&self) -> Option<(Url, Url, StatusCode)> {
+        Some((
+            self.from.parse().ok()?,
+            self.to.parse().ok()?,
+            StatusCode::from_u16(self.status).ok()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkcheck::validation::CacheEntry;
+    use std::time::SystemTime;
+
+    #[test]
+    fn caches_from_older_versions_can_be_loaded() {
+        let url: Url = "https://example.com/".parse().unwrap();
+        let mut old = linkcheck::validation::Cache::default();
+        old.insert(url.clone(), CacheEntry::new(SystemTime::now(), true));
+        let json = serde_json::to_string(&old).unwrap();
+
+        let got: Cache = serde_json::from_str(&json).unwrap();
+
+        assert!(got.lookup(&url).unwrap().valid);
+        assert!(got.redirects(&url).is_empty());
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let url: Url = "https://example.com/".parse().unwrap();
+        let longest = Duration::from_secs(60 * 60);
+        let still_valid = |valid: bool, ttl: Duration| {
+            let mut cache = Cache::default();
+            cache.insert_fresh_for(url.clone(), valid, ttl);
+            let entry = cache.lookup(&url).unwrap();
+            // the real time is recorded, not one faking the ttl
+            assert!(entry.timestamp.elapsed().unwrap() < longest);
+            cache.url_is_still_valid(&url, longest)
+        };
+
+        assert!(still_valid(true, Duration::from_secs(60)));
+        assert!(!still_valid(true, Duration::ZERO));
+        assert!(!still_valid(false, longest));
+    }
+
+    #[test]
+    fn the_timeout_still_applies_to_entries_with_an_expiry() {
+        let url: Url = "https://example.com/".parse().unwrap();
+        let mut cache = Cache::default();
+        cache.insert_fresh_for(url.clone(), true, Duration::from_secs(3600));
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        cache.results.insert(url.clone(), CacheEntry::new(an_hour_ago, true));
+
+        assert!(!cache.url_is_still_valid(&url, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn redirects_are_saved_with_the_results() {
+        let url: Url = "http://example.com/".parse().unwrap();
+        let to: Url = "https://example.com/".parse().unwrap();
+        let mut cache = Cache::default();
+        cache.insert(url.clone(), CacheEntry::new(SystemTime::now(), true));
+        cache.set_redirects(&url, vec![Redirect::new(
+            &url,
+            &to,
+            StatusCode::MOVED_PERMANENTLY,
+        )]);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let got: Cache = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            got.redirects(&url)[0].parse(),
+            Some((url, to, StatusCode::MOVED_PERMANENTLY))
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use http::header::{HeaderMap, CACHE_CONTROL};
+use std::time::Duration;
+
+/// What a response's `Cache-Control` header says about caching it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum CacheControl {
+    /// The response mustn't be stored at all (`no-store`).
+    NoStore,
+    /// The response may be stored, but has to be checked again before it is
+    /// reused (`no-cache`).
+    NoCache,
+    /// The response stays fresh for this long (`max-age`).
+    MaxAge(Duration),
+}
+
+/// Read the `Cache-Control` header. When there are several directives,
+/// `no-store` wins over `no-cache`, which wins over `max-age`.
+pub(crate) fn cache_control(headers: &HeaderMap) -> Option<CacheControl> {
+    let mut no_cache = false;
+    let mut max_age = None;
+
+    for value in headers.get_all(CACHE_CONTROL) {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        for directive in value.split(',') {
+            let mut parts = directive.trim().splitn(2, '=');
+            let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let argument =
+                parts.next().map(|arg| arg.trim().trim_matches('"'));
+
+            match (name.as_str(), argument) {
+                ("no-store", _) => return Some(CacheControl::NoStore),
+                ("no-cache", _) => no_cache = true,
+                ("max-age", Some(secs)) => {
+                    if let Ok(secs) = secs.parse() {
+                        max_age = Some(Duration::from_secs(secs));
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    if no_cache {
+        Some(CacheControl::NoCache)
+    } else {
+        max_age.map(CacheControl::MaxAge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    #[test]
+    fn parse_the_cache_control_header() {
+        let max_age =
+            |secs| Some(CacheControl::MaxAge(Duration::from_secs(secs)));
+        let inputs = vec![
+            (vec!["max-age=86400"], max_age(86400)),
+            (vec!["public, max-age=60, must-revalidate"], max_age(60)),
+            (vec!["Max-Age=\"30\""], max_age(30)),
+            (vec!["private", "max-age=10"], max_age(10)),
+            (vec!["max-age=3600, no-cache"], Some(CacheControl::NoCache)),
+            (vec!["no-cache", "no-store"], Some(CacheControl::NoStore)),
+            (vec!["no-store"], Some(CacheControl::NoStore)),
+            (vec!["s-maxage=100"], None),
+            (vec!["max-age=forever"], None),
+            (vec![], None),
+        ];
+
+        for (values, should_be) in inputs {
+            let mut headers = HeaderMap::new();
+            for value in &values {
+                headers
+                    .append(CACHE_CONTROL, HeaderValue::from_static(value));
+            }
+
+            assert_eq!(cache_control(&headers), should_be, "{:?}", values);
+        }
+    }
+}
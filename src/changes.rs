@@ -0,0 +1,295 @@
+use crate::config::is_web_link;
+use anyhow::{Context as _, Error};
+use codespan::Files;
+use mdbook::book::{Book, BookItem};
+use regex::Regex;
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+/// Find the chapters which were affected by changes since a particular git
+/// revision (e.g. `HEAD` for uncommitted changes or `origin/master` for
+/// everything on the current branch).
+///
+/// This includes chapters which were modified themselves, as well as any
+/// chapters linking to files which were modified or deleted.
+pub fn changed_chapters(
+    book: &Book,
+    src_dir: &Path,
+    revision: &str,
+) -> Result<Vec<PathBuf>, Error> {
+    let changed = changed_in_git(src_dir, revision)?;
+    tracing::debug!("Files changed since {}: {:?}", revision, changed);
+
+    Ok(affected_chapters(book, &changed))
+}
+
+/// Find the chapters matching any of these patterns (e.g. `ch03/**`), plus
+/// any chapters with links pointing into them.
+///
+/// Patterns are relative to the source directory, although they may also
+/// start with the source directory's path relative to `root` (e.g.
+/// `src/ch03/**`). A `*` matches anything except `/`, `**` matches anything
+/// at all, `?` matches a single character, and a pattern naming a directory
+/// selects everything inside it.
+pub fn selected_chapters(
+    book: &Book,
+    src_dir: &Path,
+    root: &Path,
+    patterns: &[String],
+) -> Result<Vec<PathBuf>, Error> {
+    let globs = patterns
+        .iter()
+        .map(|pattern| glob_to_regex(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+    let prefix = src_dir.strip_prefix(root).unwrap_or(Path::new(""));
+    let is_match = |path: &Path| {
+        [path.to_path_buf(), prefix.join(path)].iter().any(|path| {
+            let path = path.display().to_string().replace('\\', "/");
+            globs.iter().any(|glob| glob.is_match(&path))
+        })
+    };
+
+    let matching: BTreeSet<PathBuf> = book
+        .iter()
+        .filter_map(|item| match item {
+            BookItem::Chapter(ref ch) => ch.path.clone(),
+            BookItem::Separator | BookItem::PartTitle(_) => None,
+        })
+        .filter(|path| is_match(path))
+        .collect();
+    tracing::debug!("Chapters matching {:?}: {:?}", patterns, matching);
+
+    Ok(affected_chapters(book, &matching))
+}
+
+/// Turn a glob into a regular expression which matches the whole path (or
+/// anything inside it, if it is a directory).
+pub(crate) fn glob_to_regex(glob: &str) -> Result<Regex, Error> {
+    let glob = glob.trim_start_matches("./").trim_end_matches('/');
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            },
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            other => pattern.push_str(&regex::escape(&other.to_string())),
+        }
+    }
+    pattern.push_str("(?:/.*)?$");
+
+    Regex::new(&pattern)
+        .with_context(|| format!("\"{}\" isn't a valid pattern", glob))
+}
+
+/// Ask git which files (relative to `dir`) have changed since `revision`,
+/// including staged and untracked files.
+fn changed_in_git(
+    dir: &Path,
+    revision: &str,
+) -> Result<BTreeSet<PathBuf>, Error> {
+    let diff =
+        git(dir, &["diff", "--name-only", "--relative", revision, "--"])?;
+    let untracked = git(dir, &["ls-files", "--others", "--exclude-standard"])?;
+
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("Unable to run git")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::msg(format!(
+            "\"git {}\" failed: {}",
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Find the chapters which need to be re-checked after some files changed.
+///
+/// That's the changed chapters themselves, as well as any chapter with a link
+/// pointing at one of the changed files.
+pub(crate) fn affected_chapters(
+    book: &Book,
+    changed: &BTreeSet<PathBuf>,
+) -> Vec<PathBuf> {
+    let mut files = Files::new();
+    let file_ids = crate::load_files_into_memory(book, &mut files, |_| true);
+    let (links, _) = crate::extract_links(file_ids.clone(), &files);
+
+    let mut affected = BTreeSet::new();
+
+    for id in &file_ids {
+        let chapter = PathBuf::from(files.name(*id));
+        if changed.contains(&chapter) {
+            affected.insert(chapter);
+        }
+    }
+
+    for link in &links {
+        let chapter = Path::new(files.name(link.file));
+
+        if let Some(target) = local_target(chapter, &link.href) {
+            if changed.contains(&target) {
+                affected.insert(chapter.to_path_buf());
+            }
+        }
+    }
+
+    affected.into_iter().collect()
+}
+
+/// Figure out which file (relative to the source directory) a local link
+/// points to.
+pub(crate) fn local_target(chapter: &Path, href: &str) -> Option<PathBuf> {
+    if is_web_link(href) {
+        return None;
+    }
+
+    let path = crate::normalize::local_path(href);
+    let path = path.to_str()?;
+    if path.is_empty() {
+        return None;
+    }
+
+    let joined = match path.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => chapter.parent().unwrap_or(Path::new("")).join(path),
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    // mdbook lets you link to the rendered HTML, so map it back to the source
+    if normalized.extension().is_some_and(|ext| ext == "html") {
+        normalized.set_extension("md");
+    }
+
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdbook::book::Chapter;
+
+    #[test]
+    fn chapters_linking_to_a_changed_file_are_rechecked() {
+        let mut book = Book::new();
+        for (path, content) in &[
+            ("index.md", "[Nested](nested/chapter.md#heading)"),
+            ("nested/chapter.md", "# Heading"),
+            ("nested/sibling.md", "[Back](../index.html)"),
+            (
+                "unrelated.md",
+                "[Web](https://example.com/nested/chapter.md)",
+            ),
+        ] {
+            book.push_item(Chapter::new(
+                path,
+                content.to_string(),
+                path,
+                Vec::new(),
+            ));
+        }
+        let changed = vec![PathBuf::from("nested/chapter.md")]
+            .into_iter()
+            .collect();
+
+        let got = affected_chapters(&book, &changed);
+
+        assert_eq!(
+            got,
+            vec![
+                PathBuf::from("index.md"),
+                PathBuf::from("nested/chapter.md")
+            ]
+        );
+    }
+
+    #[test]
+    fn select_chapters_with_globs() {
+        let mut book = Book::new();
+        for (path, content) in &[
+            ("index.md", "[Chapter 3](ch03/intro.md)"),
+            ("ch03/intro.md", "# Intro"),
+            ("ch03/nested/details.md", "# Details"),
+            ("ch04.md", "[Web](https://example.com/ch03/intro.md)"),
+        ] {
+            book.push_item(Chapter::new(
+                path,
+                content.to_string(),
+                path,
+                Vec::new(),
+            ));
+        }
+        let root = Path::new("/book");
+        let src_dir = root.join("src");
+        let select = |pattern: &str| {
+            let patterns = vec![pattern.to_string()];
+            selected_chapters(&book, &src_dir, root, &patterns).unwrap()
+        };
+        let paths = |paths: &[&str]| -> Vec<PathBuf> {
+            paths.iter().map(PathBuf::from).collect()
+        };
+
+        assert_eq!(
+            select("src/ch03/**"),
+            paths(&["ch03/intro.md", "ch03/nested/details.md", "index.md"])
+        );
+        assert_eq!(select("ch03"), select("src/ch03/**"));
+        assert_eq!(select("ch03/*.md"), paths(&["ch03/intro.md", "index.md"]));
+        assert_eq!(select("**/details.md"), paths(&["ch03/nested/details.md"]));
+        assert_eq!(select("ch0?.md"), paths(&["ch04.md"]));
+    }
+
+    #[test]
+    fn resolve_local_link_targets() {
+        let inputs = vec![
+            ("index.md", "./chapter.md", Some("chapter.md")),
+            ("a/b.md", "../c.md#section", Some("c.md")),
+            ("a/b.md", "/root.html", Some("root.md")),
+            ("a/b.md", "#same-page", None),
+            ("a/b.md", "https://example.com/", None),
+        ];
+
+        for (chapter, href, should_be) in inputs {
+            let got = local_target(Path::new(chapter), href);
+            assert_eq!(got, should_be.map(PathBuf::from), "{}", href);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    anchors::AnchorIndex, classify, drafts::Drafts, front_matter::Aliases,
+    server::REQUEST_FILE, Cache, Config, RealFileSystem, ValidationOutcome,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files, Span};
+use linkcheck::Link;
+use reqwest::Url;
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// Run a single link through the same pipeline as a normal check, explaining
+/// each step along the way (which exclusions and rewrites apply, what is in
+/// the cache, and the result), to help figure out why a link is broken.
+///
+/// The link is resolved relative to the `from` chapter, or the book's source
+/// directory if there isn't one. The cache is only looked at, so the link is
+/// always checked for real. Turn on debug logging to see the requests being
+/// sent and the responses which come back.
+///
+/// Returns whether the link is valid (or ignored).
+pub fn check_url<W: Write>(
+    root: &Path,
+    href: &str,
+    from: Option<&str>,
+    cache_file: Option<&Path>,
+    mut output: W,
+) -> Result<bool, Error> {
+    let (src_dir, book, cfg) = crate::load_standalone(root)?;
+    let mut files = Files::new();
+    let file_ids = crate::load_files_into_memory(&book, &mut files, |_| true);
+    let link = match from {
+        Some(chapter) => {
+            let file = find_chapter(chapter, &file_ids, &files)?;
+            let span = match files.source(file).find(href) {
+                Some(start) => {
+                    let end = start + href.len();
+                    Span::new(start as u32, end as u32)
+                },
+                None => Span::initial(),
+            };
+            Link::new(href, span, file)
+        },
+        None => {
+            let file = files.add(REQUEST_FILE, href.to_string());
+            Link::new(href, Span::new(0, href.len() as u32), file)
+        },
+    };
+
+    writeln!(output, "Link: {}", href)?;
+    writeln!(output, "From: {}", files.name(link.file).to_string_lossy())?;
+
+    let rewritten = cfg.rewrite(href);
+    let href = match rewritten {
+        Some(ref rewritten) => {
+            writeln!(output, "Rewritten to: {}", rewritten)?;
+            rewritten.as_str()
+        },
+        None => href,
+    };
+    if let Some(local) = cfg.strip_deploy_prefix(href) {
+        writeln!(output, "Deployed copy of: {}", local)?;
+    }
+    if let Some(stripped) = cfg.strip(href) {
+        writeln!(output, "Checked as: {}", stripped)?;
+    }
+    writeln!(output, "Treatment: {}", classify(href, &cfg))?;
+    if let Some((_, fragment)) = href.split_once('#') {
+        let treatment = anchor_treatment(href, fragment, &cfg);
+        writeln!(output, "Anchor: {}", treatment)?;
+    }
+
+    if let Some(cache_file) = cache_file {
+        let cache: Cache = crate::load_cache(cache_file);
+        writeln!(output, "Cache: {}", cache_status(href, &cache, &cfg))?;
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let (outcome, _) = runtime.block_on(crate::validate::validate_batches(
+        futures::stream::iter(std::iter::once((vec![link], Vec::new()))),
+        &cfg,
+        &src_dir,
+        &mut Cache::default(),
+        &files,
+        &file_ids,
+        &crate::chapter_names(&book),
+        &Drafts::new(&book, &src_dir, &RealFileSystem),
+        &Aliases::new(&book, &src_dir, &RealFileSystem),
+        None,
+        &AnchorIndex::new(cfg.slugs.clone()),
+        None,
+        &RealFileSystem,
+        None,
+    ))?;
+
+    let (valid, result) = result(&outcome);
+    writeln!(output, "Result: {}", result)?;
+
+    Ok(valid)
+}
+
+fn find_chapter(
+    chapter: &str,
+    file_ids: &[FileId],
+    files: &Files<String>,
+) -> Result<FileId, Error> {
+    file_ids
+        .iter()
+        .copied()
+        .find(|&id| Path::new(files.name(id)) == Path::new(chapter))
+        .with_context(|| format!("\"{}\" isn't one of the chapters", chapter))
+}
+
+fn anchor_treatment(
+    href: &str,
+    fragment: &str,
+    cfg: &Config,
+) -> &'static str {
+    if !cfg.check_anchors {
+        "not checked (check-anchors is off)"
+    } else if cfg.should_skip_anchor(href, fragment) {
+        "not checked (matches exclude-anchors or strip-fragment)"
+    } else if cfg.is_known_anchor(href, fragment) {
+        "always valid (listed in known-anchors)"
+    } else if crate::config::is_web_link(href) {
+        "not checked (only anchors in local files are checked)"
+    } else {
+        "checked"
+    }
+}
+
+/// Describe what the cache says about a web link.
+fn cache_status(href: &str, cache: &Cache, cfg: &Config) -> String {
+    let href = cfg.strip(href).unwrap_or_else(|| href.to_string());
+    let url = match href.parse::<Url>() {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => return String::from("not used for this link"),
+    };
+    let url = crate::normalize::normalize(&url);
+
+    let entry = match cache.lookup(&url) {
+        Some(entry) => entry,
+        None => return format!("nothing cached for {}", url),
+    };
+    let verdict = if entry.valid { "valid" } else { "broken" };
+    let age = SystemTime::now()
+        .duration_since(entry.timestamp)
+        .unwrap_or_default();
+    let timeout = cfg.longest_cache_timeout();
+    let freshness = if entry.valid && age < timeout {
+        "so it would be used"
+    } else if entry.valid {
+        "which has expired, so it would be checked again"
+    } else {
+        "so it would be checked again"
+    };
+
+    format!(
+        "{} as of {} ago (the limit is {}), {}",
+        verdict,
+        human_duration(age),
+        human_duration(timeout),
+        freshness
+    )
+}
+
+fn result(outcome: &ValidationOutcome) -> (bool, String) {
+    if let Some(invalid) = outcome.invalid_links.first() {
+        let reason = crate::validate::most_specific_error_message(invalid);
+        (false, format!("broken ({})", reason))
+    } else if !outcome.valid_links.is_empty() {
+        (true, String::from("valid"))
+    } else if !outcome.ignored.is_empty() {
+        (true, String::from("ignored"))
+    } else {
+        (false, String::from("unknown (the link couldn't be checked)"))
+    }
+}
+
+/// Describe a duration the way a person would (e.g. `2d 3h`).
+fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkcheck::validation::CacheEntry;
+
+    #[test]
+    fn explain_each_step() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("broken-links");
+        let mut output = Vec::new();
+
+        let valid = check_url(
+            &root,
+            "./chapter_1.md#missing-heading",
+            Some("chapter_1.md"),
+            None,
+            &mut output,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(!valid, "{}", output);
+        assert!(output.contains("Treatment: check (local"), "{}", output);
+        assert!(output.contains("Anchor: checked"), "{}", output);
+        assert!(output.contains("Result: broken"), "{}", output);
+    }
+
+    #[test]
+    fn describe_cached_results() {
+        let cfg = Config {
+            cache_timeout: 3600,
+            ..Default::default()
+        };
+        let mut cache = Cache::default();
+        let entry = |age: u64| CacheEntry {
+            timestamp: SystemTime::now() - Duration::from_secs(age),
+            valid: true,
+        };
+        cache.insert("https://example.com/".parse().unwrap(), entry(600));
+        cache.insert("https://example.com/old".parse().unwrap(), entry(7200));
+
+        let inputs = vec![
+            ("https://example.com#intro", "so it would be used"),
+            ("https://example.com/old/", "which has expired"),
+            ("https://example.com/new", "nothing cached"),
+            ("./chapter_1.md", "not used for this link"),
+        ];
+
+        for (href, should_contain) in inputs {
+            let got = cache_status(href, &cache, &cfg);
+            assert!(got.contains(should_contain), "{}: {}", href, got);
+        }
+    }
+}
@@ -0,0 +1,851 @@
+use crate::{
+    anchors::AnchorIndex, book_toml::BookTomlLinks, drafts::Drafts,
+    front_matter::{Aliases, FrontMatter}, Cache, ChapterIndex, ClassifiedLink,
+    Config, DiagnosticSink, Extractor, FileProvider, Fix, HttpClient,
+    IncompleteLink, RealFileSystem, ValidationOutcome,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::{Diagnostic, Severity};
+use futures::StreamExt;
+use linkcheck::Link;
+use mdbook::{
+    book::{Book, BookItem, Chapter},
+    config::BuildConfig,
+};
+use reqwest::Client;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::runtime::Builder;
+
+/// A high-level interface to the link checker, for embedding it in other
+/// tools without needing to go through `mdbook`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mdbook_linkcheck::{Checker, Config};
+///
+/// # fn main() -> Result<(), anyhow::Error> {
+/// let report = Checker::builder()
+///     .source_dir("path/to/book/src")
+///     .config(Config::default())
+///     .check()?;
+///
+/// for invalid in &report.outcome.invalid_links {
+///     println!("{} is broken: {}", invalid.link.href, invalid.reason);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Checker {
+    source_dir: PathBuf,
+    config: Config,
+    book: Book,
+    selected_files: Option<Vec<PathBuf>>,
+    chapter_index: Option<ChapterIndex>,
+    checkpoint_file: Option<PathBuf>,
+    extractors: Vec<Arc<dyn Extractor>>,
+    book_toml: Option<PathBuf>,
+    client: Option<Arc<dyn HttpClient>>,
+    file_provider: Arc<dyn FileProvider>,
+    sink: Option<Arc<dyn DiagnosticSink>>,
+}
+
+impl Checker {
+    /// Start constructing a new [`Checker`].
+    pub fn builder() -> CheckerBuilder { CheckerBuilder::default() }
+
+    /// The configuration being used.
+    pub fn config(&self) -> &Config { &self.config }
+
+    /// The book being checked.
+    pub fn book(&self) -> &Book { &self.book }
+
+    /// The directory containing the book's source.
+    pub fn source_dir(&self) -> &Path { &self.source_dir }
+
+    /// Where the book's files are read from.
+    pub(crate) fn file_provider(&self) -> &dyn FileProvider {
+        &*self.file_provider
+    }
+
+    /// Check all the links in the book.
+    pub fn check(&self) -> Result<Report, Error> {
+        self.check_with_cache(Cache::default())
+    }
+
+    /// Check all the links in the book, using a [`Cache`] of previous web
+    /// results to skip unnecessary network requests.
+    ///
+    /// The updated [`Cache`] is available as [`Report::cache`].
+    pub fn check_with_cache(&self, cache: Cache) -> Result<Report, Error> {
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        runtime.block_on(self.check_async(cache))
+    }
+
+    /// The `async` equivalent of [`Checker::check_with_cache()`], for use
+    /// inside an existing `tokio` runtime (pass in [`Cache::default()`] if you
+    /// don't have one).
+    pub async fn check_async(&self, mut cache: Cache) -> Result<Report, Error> {
+        let skipped = match self.chapter_index {
+            Some(ref index) => index.unchanged_chapters(
+                &self.book,
+                &self.source_dir,
+                &self.config,
+                &cache,
+            ),
+            None => HashSet::new(),
+        };
+        if !skipped.is_empty() {
+            tracing::info!("Skipping {} unchanged chapters", skipped.len());
+        }
+
+        tracing::info!("Scanning book for links");
+        let mut files = Files::new();
+        let mut file_ids =
+            crate::load_files_into_memory(&self.book, &mut files, |path| {
+                self.is_selected(path) && !skipped.contains(path)
+            });
+        file_ids.extend(self.load_extra_files(&mut files));
+        let broken_includes = crate::includes::check_includes(
+            &self.book,
+            &self.source_dir,
+            &mut files,
+            &file_ids,
+            &*self.file_provider,
+            |path| self.is_selected(path) && !skipped.contains(path),
+        );
+        let book_toml = self.book_toml_links(&mut files);
+        // chapters are scanned a batch at a time so the first web requests
+        // can be sent while the rest of the book is still being scanned
+        let batches =
+            futures::stream::iter(file_ids.chunks(CHAPTERS_PER_BATCH))
+                .then(|chunk| {
+                    self.extract_links_in_background(chunk.to_vec(), &files)
+                })
+                .chain(futures::stream::once(futures::future::ready((
+                    book_toml.links,
+                    Vec::new(),
+                ))));
+
+        let anchor_index = match self.chapter_index {
+            Some(ref index) => AnchorIndex::with_known(
+                index.known_anchors(),
+                self.config.slugs.clone(),
+            ),
+            None => AnchorIndex::new(self.config.slugs.clone()),
+        };
+        let (mut outcome, links) = crate::validate::validate_batches(
+            batches,
+            &self.config,
+            &self.source_dir,
+            &mut cache,
+            &files,
+            &file_ids,
+            &crate::chapter_names(&self.book),
+            &Drafts::new(&self.book, &self.source_dir, &*self.file_provider),
+            &Aliases::new(&self.book, &self.source_dir, &*self.file_provider),
+            self.checkpoint_file.as_deref(),
+            &anchor_index,
+            self.client.as_deref(),
+            &*self.file_provider,
+            self.sink.as_deref(),
+        )
+        .await?;
+        tracing::info!(
+            "Found {} links ({} incomplete links)",
+            links.len(),
+            outcome.incomplete_links.len()
+        );
+        outcome.broken_includes = broken_includes;
+        outcome.html_problems =
+            crate::html::check_html(&file_ids, &files, &self.config);
+        outcome.missing_alt_text = crate::alt_text::missing_alt_text(
+            &file_ids,
+            &files,
+            &self.config,
+        );
+        outcome.invalid_links.extend(book_toml.broken_redirects);
+        if self.config.orphan_chapters.is_some()
+            && self.selected_files.is_none()
+        {
+            outcome.orphans = crate::orphans::find_orphans(
+                &self.book,
+                &self.source_dir,
+                &*self.file_provider,
+            );
+        }
+
+        let chapter_index = self.chapter_index.as_ref().map(|index| {
+            let mut index = index.for_book(&self.book, &self.config);
+            let diags = outcome.generate_diagnostics(&files, &self.config);
+            index.record_checked_chapters(
+                &self.source_dir,
+                &files,
+                &file_ids,
+                &links,
+                &diags,
+            );
+            index.record_anchors(anchor_index.into_known());
+            index
+        });
+
+        Ok(Report {
+            files,
+            outcome,
+            cache,
+            chapter_index,
+            config: self.config.clone(),
+        })
+    }
+
+    /// Find links in the [`Report`] which can be fixed automatically (see
+    /// [`Fix`] for details).
+    ///
+    /// This may need to send web requests, to see whether a link permanently
+    /// redirects somewhere else.
+    pub fn find_fixes(&self, report: &Report) -> Result<Vec<Fix>, Error> {
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        Ok(runtime.block_on(self.find_fixes_async(report)))
+    }
+
+    /// The `async` equivalent of [`Checker::find_fixes()`].
+    pub async fn find_fixes_async(&self, report: &Report) -> Vec<Fix> {
+        let chapters: Vec<PathBuf> = self
+            .book
+            .iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ref ch) => ch.path.clone(),
+                _ => None,
+            })
+            .collect();
+
+        let mut fixes = crate::fix::moved_file_fixes(
+            &report.outcome.invalid_links,
+            &chapters,
+            &report.files,
+        );
+        let default_client = self.config.client();
+        let http = self.client.as_deref().unwrap_or(&default_client);
+        fixes.extend(
+            crate::fix::redirect_fixes(
+                &report.outcome.valid_links,
+                &report.outcome.permanent_redirects,
+                &self.config,
+                &report.files,
+                http,
+            )
+            .await,
+        );
+
+        fixes
+    }
+
+    /// Find every link in the (selected) chapters and `book.toml`, along with
+    /// how each one would be handled, without checking anything.
+    ///
+    /// Each link's span is a byte range within its file, and the returned
+    /// [`Files`] has every file's name and source.
+    pub fn links(&self) -> (Files<String>, Vec<ClassifiedLink>) {
+        let mut files = Files::new();
+        let mut file_ids =
+            crate::load_files_into_memory(&self.book, &mut files, |path| {
+                self.is_selected(path)
+            });
+        file_ids.extend(self.load_extra_files(&mut files));
+        let book_toml = self.book_toml_links(&mut files);
+        let (mut links, _) = self.extract_links(file_ids, &files);
+        links.extend(book_toml.links);
+
+        let links = links
+            .into_iter()
+            .map(|link| ClassifiedLink::new(link, &self.config))
+            .collect();
+        (files, links)
+    }
+
+    /// The same as [`Checker::links()`], except for a single markdown
+    /// document which doesn't need to be part of the book. Each link's span
+    /// is a byte range within `src`.
+    pub fn links_in_document(&self, src: &str) -> Vec<ClassifiedLink> {
+        let mut files = Files::new();
+        let id = files.add("<document>", src.to_string());
+        let (links, _) = self.extract_links(vec![id], &files);
+
+        links
+            .into_iter()
+            .map(|link| ClassifiedLink::new(link, &self.config))
+            .collect()
+    }
+
+    /// Find all the links in these files, including any found by the
+    /// [`Config::link_patterns`], the [`Config::front_matter_keys`], and
+    /// [`CheckerBuilder::extractor()`]s.
+    pub(crate) fn extract_links(
+        &self,
+        file_ids: Vec<FileId>,
+        files: &Files<String>,
+    ) -> (Vec<Link>, Vec<IncompleteLink>) {
+        let extractors = self.all_extractors();
+        let extractors: Vec<&dyn Extractor> =
+            extractors.iter().map(|e| &**e).collect();
+
+        crate::links::extract_with_options(
+            file_ids,
+            files,
+            &extractors,
+            self.config.markdown,
+        )
+    }
+
+    /// The same as [`Checker::extract_links()`], except each file is parsed
+    /// on `tokio`'s blocking thread pool, so a large book's chapters are
+    /// scanned in parallel.
+    async fn extract_links_in_background(
+        &self,
+        file_ids: Vec<FileId>,
+        files: &Files<String>,
+    ) -> (Vec<Link>, Vec<IncompleteLink>) {
+        let extractors = Arc::new(self.all_extractors());
+        let markdown = self.config.markdown;
+        let span =
+            tracing::debug_span!("extract_links", files = file_ids.len());
+
+        let found: Vec<_> = futures::stream::iter(file_ids)
+            .map(|id| {
+                let name = files.name(id).to_string_lossy().into_owned();
+                let src = files.source(id).clone();
+                let extractors = Arc::clone(&extractors);
+                let span = span.clone();
+
+                tokio::task::spawn_blocking(move || {
+                    let _guard = span.enter();
+                    let extractors: Vec<&dyn Extractor> =
+                        extractors.iter().map(|e| &**e).collect();
+                    crate::links::extract_file(
+                        id,
+                        &name,
+                        &src,
+                        &extractors,
+                        markdown,
+                    )
+                })
+            })
+            // keep the links in the same order as the files
+            .buffered(crate::parallelism())
+            .collect()
+            .await;
+
+        let mut links = Vec::new();
+        let mut incomplete_links = Vec::new();
+        for result in found {
+            let (found, incomplete) = match result {
+                Ok(found) => found,
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
+            };
+            links.extend(found);
+            incomplete_links.extend(incomplete);
+        }
+
+        (links, incomplete_links)
+    }
+
+    /// Everything which finds links the markdown parser doesn't know about
+    /// (the [`Config::link_patterns`], the [`Config::front_matter_keys`], and
+    /// any [`CheckerBuilder::extractor()`]s).
+    fn all_extractors(&self) -> Vec<Arc<dyn Extractor>> {
+        let mut extractors: Vec<Arc<dyn Extractor>> = self
+            .config
+            .link_patterns
+            .iter()
+            .map(|pattern| Arc::new(pattern.clone()) as Arc<dyn Extractor>)
+            .collect();
+        if !self.config.front_matter_keys.is_empty() {
+            let keys = self.config.front_matter_keys.clone();
+            extractors.push(Arc::new(FrontMatter::new(keys)));
+        }
+        extractors.extend(self.extractors.iter().cloned());
+
+        extractors
+    }
+
+    /// Find the links in `book.toml` (see [`CheckerBuilder::book_toml()`]),
+    /// adding it to `files`. They are skipped when only checking some of the
+    /// chapters.
+    pub(crate) fn book_toml_links(
+        &self,
+        files: &mut Files<String>,
+    ) -> BookTomlLinks {
+        match self.book_toml {
+            Some(ref book_toml) if self.selected_files.is_none() => {
+                // the edit-url-template is checked using the first chapter
+                let chapter = self.book.iter().find_map(|item| match item {
+                    BookItem::Chapter(ch) => {
+                        ch.source_path.as_deref().or(ch.path.as_deref())
+                    },
+                    _ => None,
+                });
+                crate::book_toml::book_toml_links(
+                    book_toml,
+                    &self.source_dir,
+                    chapter,
+                    files,
+                )
+            },
+            _ => BookTomlLinks::default(),
+        }
+    }
+
+    /// Add the [`Config::extra_files`] to `files` (named relative to the
+    /// source directory), returning their IDs. They are skipped when only
+    /// checking some of the chapters.
+    pub(crate) fn load_extra_files(
+        &self,
+        files: &mut Files<String>,
+    ) -> Vec<FileId> {
+        if self.selected_files.is_some() {
+            return Vec::new();
+        }
+
+        let provider = &*self.file_provider;
+        let mut ids = Vec::new();
+        for raw in &self.config.extra_files {
+            let path = self.source_dir.join(raw);
+            let loaded = if provider.is_dir(&path) {
+                load_markdown_directory(&path, provider).map(|book| {
+                    book.iter()
+                        .filter_map(|item| match item {
+                            BookItem::Chapter(Chapter {
+                                path: Some(ref chapter),
+                                ref content,
+                                ..
+                            }) => {
+                                let name = Path::new(raw).join(chapter);
+                                let name = name.display().to_string();
+                                Some(files.add(name, content.clone()))
+                            },
+                            _ => None,
+                        })
+                        .collect()
+                })
+            } else {
+                provider
+                    .read_to_string(&path)
+                    .map(|src| vec![files.add(raw.as_str(), src)])
+                    .map_err(Error::from)
+            };
+
+            match loaded {
+                Ok(loaded) => ids.extend(loaded),
+                Err(e) => tracing::warn!(
+                    "Unable to load the extra file \"{}\": {:#}",
+                    raw,
+                    e
+                ),
+            }
+        }
+
+        ids
+    }
+
+    pub(crate) fn is_selected(&self, path: &Path) -> bool {
+        match self.selected_files {
+            Some(ref selected) => selected.iter().any(|s| s == path),
+            None => true,
+        }
+    }
+}
+
+/// A builder for constructing a [`Checker`].
+#[derive(Debug, Default)]
+pub struct CheckerBuilder {
+    source_dir: Option<PathBuf>,
+    config: Config,
+    book: Option<Book>,
+    selected_files: Option<Vec<PathBuf>>,
+    chapter_index: Option<ChapterIndex>,
+    checkpoint_file: Option<PathBuf>,
+    extractors: Vec<Arc<dyn Extractor>>,
+    book_toml: Option<PathBuf>,
+    client: Option<Arc<dyn HttpClient>>,
+    file_provider: Option<Arc<dyn FileProvider>>,
+    sink: Option<Arc<dyn DiagnosticSink>>,
+}
+
+impl CheckerBuilder {
+    /// The directory containing the book's source (i.e. the folder with
+    /// `SUMMARY.md` in it).
+    ///
+    /// If there is no `SUMMARY.md`, every markdown file in the directory (and
+    /// its sub-directories) will be checked instead.
+    pub fn source_dir<P: Into<PathBuf>>(self, source_dir: P) -> Self {
+        CheckerBuilder {
+            source_dir: Some(source_dir.into()),
+            ..self
+        }
+    }
+
+    /// Set the [`Config`] to use.
+    pub fn config(self, config: Config) -> Self {
+        CheckerBuilder { config, ..self }
+    }
+
+    /// Use an already loaded [`Book`] instead of reading `SUMMARY.md` from
+    /// the [`CheckerBuilder::source_dir()`].
+    pub fn book(self, book: Book) -> Self {
+        CheckerBuilder {
+            book: Some(book),
+            ..self
+        }
+    }
+
+    /// Only check links in these chapters (paths are relative to the source
+    /// directory).
+    pub fn selected_files<I, P>(self, selected_files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        CheckerBuilder {
+            selected_files: Some(
+                selected_files.into_iter().map(Into::into).collect(),
+            ),
+            ..self
+        }
+    }
+
+    /// Skip chapters which haven't changed since they were last checked,
+    /// according to the [`ChapterIndex`] from a previous [`Report`].
+    pub fn chapter_index(self, chapter_index: ChapterIndex) -> Self {
+        CheckerBuilder {
+            chapter_index: Some(chapter_index),
+            ..self
+        }
+    }
+
+    /// Regularly save the [`Cache`] to this file while web links are being
+    /// checked, so an interrupted run can resume where it left off instead of
+    /// starting from scratch.
+    pub fn checkpoint_file<P: Into<PathBuf>>(self, checkpoint_file: P) -> Self {
+        CheckerBuilder {
+            checkpoint_file: Some(checkpoint_file.into()),
+            ..self
+        }
+    }
+
+    /// Use an [`Extractor`] to find links written in syntax the markdown
+    /// parser doesn't understand. This can be called multiple times.
+    pub fn extractor<E: Extractor + 'static>(mut self, extractor: E) -> Self {
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+
+    /// Also check the URLs in this `book.toml`'s `[output.html]` table (e.g.
+    /// `git-repository-url` and `edit-url-template`) and the destination of
+    /// each `[output.html.redirect]`.
+    pub fn book_toml<P: Into<PathBuf>>(self, book_toml: P) -> Self {
+        CheckerBuilder {
+            book_toml: Some(book_toml.into()),
+            ..self
+        }
+    }
+
+    /// Send web requests using this [`Client`] (e.g. so a long-running tool
+    /// can reuse its connection pool between checks) instead of creating a
+    /// new one from the [`Config`].
+    ///
+    /// The [`Config::user_agent`] and [`WebConfig::timeout`] are only applied
+    /// to clients created from the [`Config`].
+    ///
+    /// [`WebConfig::timeout`]: crate::WebConfig::timeout
+    pub fn client(self, client: Client) -> Self { self.http_client(client) }
+
+    /// Send web requests using a custom [`HttpClient`] (e.g. to add an
+    /// authentication flow, sign requests, or return canned responses in
+    /// tests).
+    ///
+    /// Every request sent while checking the book goes through this client,
+    /// including each redirect and optional checks like
+    /// [`Config::check_crates`]. The only exception is
+    /// [`crate::WebConfig::outdated_tls`], which needs a client that refuses
+    /// old TLS versions.
+    pub fn http_client<C: HttpClient + 'static>(self, client: C) -> Self {
+        CheckerBuilder {
+            client: Some(Arc::new(client)),
+            ..self
+        }
+    }
+
+    /// Look up the files local links point to using this [`FileProvider`]
+    /// instead of the [`RealFileSystem`] (e.g. to check a book which only
+    /// exists in memory).
+    ///
+    /// The book itself isn't loaded using the [`FileProvider`], so it should
+    /// be given to [`CheckerBuilder::book()`] as well.
+    pub fn file_provider<F: FileProvider + 'static>(
+        self,
+        file_provider: F,
+    ) -> Self {
+        CheckerBuilder {
+            file_provider: Some(Arc::new(file_provider)),
+            ..self
+        }
+    }
+
+    /// Tell this [`DiagnosticSink`] about each broken link as soon as it is
+    /// found, while the rest of the book is still being checked.
+    pub fn diagnostic_sink<S: DiagnosticSink + 'static>(self, sink: S) -> Self {
+        CheckerBuilder {
+            sink: Some(Arc::new(sink)),
+            ..self
+        }
+    }
+
+    /// Create the [`Checker`], loading the book from disk if necessary.
+    pub fn build(self) -> Result<Checker, Error> {
+        let file_provider = self
+            .file_provider
+            .unwrap_or_else(|| Arc::new(RealFileSystem));
+        let source_dir = self
+            .source_dir
+            .ok_or_else(|| Error::msg("No source directory was provided"))?;
+        let source_dir = file_provider
+            .canonicalize(&source_dir)
+            .context("Unable to resolve the source directory")?;
+
+        let mut book = match self.book {
+            Some(book) => book,
+            None if file_provider
+                .canonicalize(&source_dir.join("SUMMARY.md"))
+                .is_ok() =>
+            {
+                // we're only reading the book, so make sure mdbook doesn't
+                // try to create any chapters that are missing
+                let build_cfg = BuildConfig {
+                    create_missing: false,
+                    ..Default::default()
+                };
+                mdbook::book::load_book(&source_dir, &build_cfg)
+                    .context("Unable to load the book")?
+            },
+            None => match crate::languages::load_book(
+                &source_dir,
+                &*file_provider,
+            )? {
+                Some(book) => book,
+                None => {
+                    tracing::debug!(
+                        "There is no SUMMARY.md, so treating \"{}\" as a plain directory of markdown files",
+                        source_dir.display()
+                    );
+                    load_markdown_directory(&source_dir, &*file_provider)?
+                },
+            },
+        };
+        if self.config.raw_sources {
+            use_raw_sources(&mut book, &source_dir, &*file_provider);
+        }
+
+        let mut config = self.config;
+        let book_root = match self.book_toml {
+            Some(ref book_toml) => book_toml
+                .parent()
+                .and_then(|dir| file_provider.canonicalize(dir).ok()),
+            None => source_dir
+                .ancestors()
+                .find(|dir| {
+                    file_provider.canonicalize(&dir.join("book.toml")).is_ok()
+                })
+                .map(Path::to_path_buf),
+        };
+        if let Some(relative) = book_root
+            .as_deref()
+            .and_then(|root| source_dir.strip_prefix(root).ok())
+        {
+            config.rebase_overrides(relative);
+        }
+
+        Ok(Checker {
+            source_dir,
+            config,
+            book,
+            selected_files: self.selected_files,
+            chapter_index: self.chapter_index,
+            checkpoint_file: self.checkpoint_file,
+            extractors: self.extractors,
+            book_toml: self.book_toml,
+            client: self.client,
+            file_provider,
+            sink: self.sink,
+        })
+    }
+
+    /// A shortcut for [`CheckerBuilder::build()`] followed by
+    /// [`Checker::check()`].
+    pub fn check(self) -> Result<Report, Error> { self.build()?.check() }
+}
+
+/// Replace each chapter's content (which preprocessors may have changed) with
+/// the original source, for [`Config::raw_sources`].
+fn use_raw_sources(
+    book: &mut Book,
+    source_dir: &Path,
+    files: &dyn FileProvider,
+) {
+    book.for_each_mut(|item| {
+        let ch = match item {
+            BookItem::Chapter(ch) => ch,
+            BookItem::Separator | BookItem::PartTitle(_) => return,
+        };
+        // draft chapters and generated content have no source
+        let path = match ch.source_path {
+            Some(ref path) => source_dir.join(path),
+            None => return,
+        };
+
+        match files.read(&path).map(String::from_utf8) {
+            Ok(Ok(raw)) => ch.content = raw,
+            Ok(Err(e)) => warn_unreadable(&path, &e),
+            Err(e) => warn_unreadable(&path, &e),
+        }
+    });
+}
+
+fn warn_unreadable(path: &Path, error: &dyn std::error::Error) {
+    tracing::warn!(
+        "Unable to read \"{}\", so checking its preprocessed content instead: {}",
+        path.display(),
+        error
+    );
+}
+
+/// Create a [`Book`] containing every markdown file under a directory, for
+/// when we're checking something which isn't an `mdbook` project (e.g. a
+/// repository's `README.md` and `docs/` folder).
+fn load_markdown_directory(
+    root: &Path,
+    files: &dyn FileProvider,
+) -> Result<Book, Error> {
+    fn visit(
+        root: &Path,
+        dir: &Path,
+        files: &dyn FileProvider,
+        book: &mut Book,
+    ) -> Result<(), std::io::Error> {
+        let mut entries = files.read_dir(dir)?;
+        // make sure files are always checked in the same order
+        entries.sort();
+
+        for path in entries {
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if name.starts_with('.') || IGNORED_DIRECTORIES.contains(&name) {
+                continue;
+            }
+
+            if files.is_dir(&path) {
+                visit(root, &path, files, book)?;
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                let content = files.read_to_string(&path)?;
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                book.push_item(Chapter::new(
+                    name,
+                    content,
+                    relative,
+                    Vec::new(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut book = Book::new();
+    visit(root, root, files, &mut book).with_context(|| {
+        format!(
+            "Unable to read the markdown files in \"{}\"",
+            root.display()
+        )
+    })?;
+
+    Ok(book)
+}
+
+/// How many chapters to scan for links before handing them over to be
+/// checked. Each batch is still scanned in parallel.
+const CHAPTERS_PER_BATCH: usize = 64;
+
+/// Directories which never contain documentation we'd want to check.
+const IGNORED_DIRECTORIES: &[&str] = &["target", "node_modules"];
+
+/// The result of running a [`Checker`].
+#[derive(Debug)]
+pub struct Report {
+    /// The source text for every chapter that was checked.
+    pub files: Files<String>,
+    /// The outcome of validating every link.
+    pub outcome: ValidationOutcome,
+    /// The [`Cache`], updated with the results from this run.
+    pub cache: Cache,
+    /// The updated [`ChapterIndex`], if one was provided with
+    /// [`CheckerBuilder::chapter_index()`].
+    pub chapter_index: Option<ChapterIndex>,
+    config: Config,
+}
+
+impl Report {
+    /// Generate [`Diagnostic`]s for any problems that were found.
+    pub fn diagnostics(&self) -> Vec<Diagnostic<FileId>> {
+        self.outcome.generate_diagnostics(&self.files, &self.config)
+    }
+
+    /// Did any of the links fail validation in a way that should be treated
+    /// as an error?
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics()
+            .iter()
+            .any(|diag| diag.severity >= Severity::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_provider::in_memory_book;
+    use std::future::Future;
+
+    fn assert_send<F: Future + Send>(_: F) {}
+
+    #[test]
+    fn the_async_entry_points_can_be_spawned() {
+        let (files, book) = in_memory_book(&[("README.md", "")]);
+        let checker = Checker::builder()
+            .source_dir("/book/src")
+            .book(book)
+            .file_provider(files)
+            .build()
+            .unwrap();
+        let report = checker.check().unwrap();
+        let files = Files::new();
+
+        assert_send(checker.check_async(Cache::default()));
+        assert_send(checker.find_fixes_async(&report));
+        assert_send(crate::validate_async(
+            &[],
+            checker.config(),
+            checker.source_dir(),
+            &mut Cache::default(),
+            &files,
+            &[],
+            Vec::new(),
+        ));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::{data_uri::is_data_uri, tel::is_tel_link, Checker, Config};
+use anyhow::Error;
+use linkcheck::Link;
+use reqwest::Url;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::Write,
+};
+
+/// How the link checker would treat a particular link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkKind {
+    /// A file on the local filesystem.
+    Local,
+    /// A `http://` or `https://` link.
+    Web,
+    /// Skipped because it matched one of the `exclude` patterns.
+    Excluded {
+        /// The pattern which matched.
+        pattern: String,
+    },
+    /// A web link which is skipped because [`Config::follow_web_links`] is
+    /// disabled and it didn't match [`Config::follow_web_links_matching`].
+    NotFollowed,
+    /// A link to somewhere else in the same page (e.g. `#some-heading`).
+    CurrentPage,
+    /// A `mailto:` link.
+    MailTo,
+    /// A `data:` URI, which is only checked to make sure it is well-formed.
+    DataUri,
+    /// A `tel:` link, which is checked against [`Config::tel_pattern`].
+    Tel,
+    /// A URL using one of the [`Config::skip_schemes`].
+    SkippedScheme {
+        /// The URL's scheme.
+        scheme: String,
+    },
+    /// A URL with a scheme other than HTTP(S) (e.g. `ftp://`).
+    UnknownScheme {
+        /// The URL's scheme.
+        scheme: String,
+    },
+    /// Something which doesn't look like a link at all.
+    Unknown,
+}
+
+impl LinkKind {
+    /// Would this link actually be validated?
+    pub fn will_be_checked(&self) -> bool {
+        matches!(
+            self,
+            LinkKind::Local
+                | LinkKind::Web
+                | LinkKind::CurrentPage
+                | LinkKind::DataUri
+                | LinkKind::Tel
+        )
+    }
+}
+
+impl Display for LinkKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkKind::Local => write!(f, "check (local file)"),
+            LinkKind::Web => write!(f, "check (web)"),
+            LinkKind::Excluded { pattern } => {
+                write!(f, "skip (matches the exclude pattern '{}')", pattern)
+            },
+            LinkKind::NotFollowed => {
+                write!(f, "skip (follow-web-links is disabled)")
+            },
+            LinkKind::CurrentPage => write!(f, "check (same chapter)"),
+            LinkKind::MailTo => {
+                write!(f, "skip (mailto: links aren't checked)")
+            },
+            LinkKind::DataUri => write!(f, "check (data: URI)"),
+            LinkKind::Tel => write!(f, "check (phone number)"),
+            LinkKind::SkippedScheme { scheme } => {
+                write!(f, "skip (\"{}\" is listed in skip-schemes)", scheme)
+            },
+            LinkKind::UnknownScheme { scheme } => {
+                write!(f, "skip (unknown scheme \"{}\")", scheme)
+            },
+            LinkKind::Unknown => write!(f, "skip (not a recognised link)"),
+        }
+    }
+}
+
+/// A link found by [`Checker::links()`], along with how it would be handled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifiedLink {
+    /// The link, including the file it is in and its byte span.
+    pub link: Link,
+    /// The link after applying [`Config::rewrites`], if any of them matched.
+    pub rewritten: Option<String>,
+    /// How the (rewritten) link would be handled.
+    pub kind: LinkKind,
+}
+
+impl ClassifiedLink {
+    pub(crate) fn new(link: Link, cfg: &Config) -> Self {
+        let rewritten = cfg.rewrite(&link.href);
+        let kind = classify(rewritten.as_deref().unwrap_or(&link.href), cfg);
+
+        ClassifiedLink {
+            link,
+            rewritten,
+            kind,
+        }
+    }
+}
+
+/// Figure out how a link would be handled, without checking it.
+///
+/// This mirrors the decisions made during validation, so [`Config::rewrites`]
+/// should already have been applied.
+pub fn classify(href: &str, cfg: &Config) -> LinkKind {
+    if href.is_empty() {
+        return LinkKind::Unknown;
+    }
+
+    if let Some(pattern) = cfg.matching_exclude(href) {
+        return LinkKind::Excluded {
+            pattern: pattern.string.clone(),
+        };
+    }
+
+    if cfg.strip_deploy_prefix(href).is_some() {
+        return LinkKind::Local;
+    }
+
+    if let Some(scheme) = cfg.skipped_scheme(href) {
+        return LinkKind::SkippedScheme {
+            scheme: scheme.to_string(),
+        };
+    }
+
+    if href.starts_with("mailto:") {
+        return LinkKind::MailTo;
+    }
+
+    if is_data_uri(href) {
+        return LinkKind::DataUri;
+    }
+
+    if is_tel_link(href) {
+        return LinkKind::Tel;
+    }
+
+    if let Some(scheme) = cfg.unrecognised_scheme(href) {
+        return LinkKind::UnknownScheme { scheme };
+    }
+
+    if let Ok(url) = href.parse::<Url>() {
+        return if !cfg.should_follow_web_link(href) {
+            LinkKind::NotFollowed
+        } else if matches!(url.scheme(), "http" | "https") {
+            LinkKind::Web
+        } else {
+            LinkKind::UnknownScheme {
+                scheme: url.scheme().to_string(),
+            }
+        };
+    }
+
+    if href.starts_with('#') {
+        LinkKind::CurrentPage
+    } else {
+        LinkKind::Local
+    }
+}
+
+/// Print every link in the book along with what would be done with it,
+/// without validating anything.
+pub fn list_links<W: Write>(
+    checker: &Checker,
+    mut output: W,
+) -> Result<(), Error> {
+    let (files, links) = checker.links();
+    let mut checked = 0;
+
+    for ClassifiedLink {
+        link,
+        rewritten,
+        kind,
+    } in &links
+    {
+        let location = match files.location(link.file, link.span.start()) {
+            Ok(loc) => format!(
+                "{}:{}:{}",
+                files.name(link.file).to_string_lossy(),
+                loc.line.number(),
+                loc.column.number()
+            ),
+            Err(_) => files.name(link.file).to_string_lossy().into_owned(),
+        };
+
+        if kind.will_be_checked() {
+            checked += 1;
+        }
+
+        match rewritten {
+            Some(ref rewritten) => writeln!(
+                output,
+                "{}: {} (rewritten to {}) - {}",
+                location, link.href, rewritten, kind
+            )?,
+            None => writeln!(output, "{}: {} - {}", location, link.href, kind)?,
+        }
+    }
+
+    writeln!(
+        output,
+        "{} links found, {} would be checked and {} skipped",
+        links.len(),
+        checked,
+        links.len() - checked
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_links_the_same_way_validation_would() {
+        let cfg = Config {
+            exclude: vec![r"google\.com".parse().unwrap()],
+            follow_web_links_matching: vec!["^https://example"
+                .parse()
+                .unwrap()],
+            deploy_prefix: Some(String::from("https://example.com/docs/book/")),
+            skip_schemes: vec![String::from("magnet")],
+            ..Default::default()
+        };
+        let inputs = vec![
+            ("./chapter_1.md", LinkKind::Local),
+            ("https://example.com/", LinkKind::Web),
+            ("https://rust-lang.org/", LinkKind::NotFollowed),
+            (
+                "https://google.com/",
+                LinkKind::Excluded {
+                    pattern: String::from(r"google\.com"),
+                },
+            ),
+            ("#heading", LinkKind::CurrentPage),
+            ("mailto:me@example.com", LinkKind::MailTo),
+            ("data:text/plain,Hello", LinkKind::DataUri),
+            ("tel:+1-555-123-4567", LinkKind::Tel),
+            ("https://example.com/docs/book/ch1.html", LinkKind::Local),
+            (
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+                LinkKind::SkippedScheme {
+                    scheme: String::from("magnet"),
+                },
+            ),
+            ("", LinkKind::Unknown),
+        ];
+
+        for (href, should_be) in inputs {
+            assert_eq!(classify(href, &cfg), should_be, "{}", href);
+        }
+
+        assert_eq!(
+            classify("ftp://example.com/", &cfg),
+            LinkKind::UnknownScheme {
+                scheme: String::from("ftp")
+            }
+        );
+    }
+
+    #[test]
+    fn links_in_a_single_document() {
+        let checker = Checker::builder()
+            .source_dir(env!("CARGO_MANIFEST_DIR"))
+            .book(mdbook::book::Book::new())
+            .config(Config {
+                exclude: vec![r"google\.com".parse().unwrap()],
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let src = "[local](./chapter_1.md) and [web](https://google.com/)";
+
+        let got = checker.links_in_document(src);
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].link.href, "./chapter_1.md");
+        assert_eq!(got[0].kind, LinkKind::Local);
+        let span = got[1].link.span;
+        assert_eq!(
+            &src[span.start().to_usize()..span.end().to_usize()],
+            "[web](https://google.com/)"
+        );
+        assert_eq!(
+            got[1].kind,
+            LinkKind::Excluded {
+                pattern: String::from(r"google\.com")
+            }
+        );
+    }
+
+    #[test]
+    fn only_the_selected_chapters_are_listed() {
+        let (files, book) = crate::file_provider::in_memory_book(&[
+            ("changed.md", "[web](https://example.com/)"),
+            ("same.md", "[local](./changed.md)"),
+        ]);
+        let checker = Checker::builder()
+            .source_dir("/book/src")
+            .book(book)
+            .file_provider(files)
+            .selected_files(vec!["changed.md"])
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+
+        list_links(&checker, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("https://example.com/"), "{}", output);
+        assert!(!output.contains("./changed.md"), "{}", output);
+        assert!(output.contains("1 links found"), "{}", output);
+    }
+}
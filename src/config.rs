@@ -1,43 +1,643 @@
 use crate::hashed_regex::HashedRegex;
-use anyhow::Error;
+use anyhow::{Context as _, Error};
+use codespan_reporting::diagnostic::Severity;
 use http::header::{HeaderName, HeaderValue};
-use log::Level;
-use reqwest::Client;
+use pulldown_cmark::Options;
+use reqwest::{redirect::Policy, Client, ClientBuilder, Url};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde_derive::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::TryFrom,
     fmt::{self, Display, Formatter},
+    net::IpAddr,
+    path::{Path, PathBuf},
     str::FromStr,
     time::Duration,
 };
 
 /// The configuration options available with this backend.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct Config {
     /// If a link on the internet is encountered, should we still try to check
     /// if it's valid? Defaults to `false` because this has a big performance
     /// impact.
     pub follow_web_links: bool,
+    /// A list of URL patterns which should always be checked, even when
+    /// [`Config::follow_web_links`] is `false`.
+    #[serde(default)]
+    pub follow_web_links_matching: Vec<HashedRegex>,
     /// Are we allowed to link to files outside of the book's source directory?
+    ///
+    /// See [`Config::external_roots`], [`Config::allowed_external_paths`],
+    /// and [`Config::max_parent_depth`] for more targeted alternatives.
     pub traverse_parent_directories: bool,
+    /// Files and directories outside the book's source directory which links
+    /// may point to (e.g. `../shared-docs` or `../../LICENSE`), relative to
+    /// the source directory. Links to anywhere else outside the source
+    /// directory are still rejected.
+    #[serde(default)]
+    pub external_roots: Vec<String>,
+    /// Glob patterns for files outside the book's source directory which
+    /// links may point to (e.g. `../shared/**` or `../../LICENSE`), relative
+    /// to the source directory. A `*` matches anything except `/` and `**`
+    /// matches anything at all.
+    #[serde(default)]
+    pub allowed_external_paths: Vec<String>,
+    /// Markdown files and directories outside the book's source directory
+    /// (e.g. `../CHANGELOG.md` or `../docs`), relative to the source
+    /// directory, whose links should be checked too. Chapters are allowed to
+    /// link to them.
+    #[serde(default)]
+    pub extra_files: Vec<String>,
+    /// Let links leave the book's source directory by going up at most this
+    /// many directories (e.g. `1` allows `../README.md` but not
+    /// `../../secrets.txt`).
+    pub max_parent_depth: Option<usize>,
+    /// The root directories of other books which this book links into (e.g.
+    /// `../../reference`), relative to the source directory like
+    /// [`Config::external_roots`].
+    ///
+    /// Links pointing into one of these books are checked against that
+    /// book's source and `SUMMARY.md` instead of being rejected for leaving
+    /// the source directory.
+    #[serde(default)]
+    pub workspace_books: Vec<String>,
+    /// The root directories of each translation in a multilingual book (e.g.
+    /// `src/en` and `src/fr`), relative to the directory containing
+    /// `book.toml`.
+    ///
+    /// Links from one translation to its counterpart in another are checked
+    /// against that translation, and chapters linking to a counterpart which
+    /// doesn't exist are listed at the end of the report.
+    #[serde(default)]
+    pub translations: Vec<String>,
+    /// The language (e.g. `en`) that links to pages which haven't been
+    /// translated yet fall back to, when each language has its own directory
+    /// and `SUMMARY.md` in the source directory. Defaults to mdbook's
+    /// `book.language`.
+    pub default_language: Option<String>,
     /// A list of URL patterns to ignore when checking remote links.
     #[serde(default)]
     pub exclude: Vec<HashedRegex>,
+    /// URL schemes (e.g. `irc` or `magnet`) which are used on purpose, so
+    /// links using them are skipped instead of being checked.
+    #[serde(default)]
+    pub skip_schemes: Vec<String>,
+    /// Should the fragment in local links (e.g. `./chapter_1.md#some-heading`
+    /// or `#some-heading`) be checked to make sure the anchor exists?
+    /// Defaults to `false`, because pages can generate their anchors at
+    /// runtime.
+    pub check_anchors: bool,
+    /// A list of patterns for links whose fragment (the `#section` bit)
+    /// shouldn't be checked. Each pattern is matched against both the full
+    /// link and just the anchor name.
+    #[serde(default)]
+    pub exclude_anchors: Vec<HashedRegex>,
+    /// A list of patterns for links whose query string (the `?key=value`
+    /// bit) should be removed before they are checked, for servers which
+    /// only complain about the query.
+    #[serde(default)]
+    pub strip_query: Vec<HashedRegex>,
+    /// A list of patterns for links whose fragment should be removed before
+    /// they are checked, so only the page they point to needs to exist.
+    #[serde(default)]
+    pub strip_fragment: Vec<HashedRegex>,
+    /// Extra regular expressions for finding links written in syntax the
+    /// markdown parser doesn't understand (e.g. a preprocessor's
+    /// `{{#button href="..."}}` directive).
+    ///
+    /// The link is taken from the capture group called `link`, or the first
+    /// capture group if there isn't one.
+    #[serde(default)]
+    pub link_patterns: Vec<HashedRegex>,
+    /// Keys in a chapter's YAML front matter (e.g. `canonical_url` or
+    /// `image`) whose values are links which should be checked too. Only
+    /// top-level keys are looked at, and each value may be a single link or
+    /// a list of them.
+    ///
+    /// The old URLs listed under `redirect_from` point to the chapter, so
+    /// they are never checked. Links to them are treated as links to the
+    /// chapter instead.
+    #[serde(default)]
+    pub front_matter_keys: Vec<String>,
+    /// Check each chapter's source as it was written, instead of the content
+    /// mdbook's preprocessors turned it into (e.g. after templates and
+    /// variables have been expanded).
+    ///
+    /// Links generated by preprocessors won't be checked, but every problem
+    /// points at the line it is on in the original file.
+    pub raw_sources: bool,
+    /// Check links to the DOI resolver (e.g. `https://doi.org/10.1000/182`)
+    /// by asking it whether the DOI exists instead of following its redirect
+    /// to the publisher (who may reject automated requests). Malformed DOIs
+    /// are reported without making a request.
+    pub check_dois: bool,
+    /// Look up the crate (and version, if the link pins one) behind links to
+    /// `docs.rs` and `crates.io` in the crates.io registry. Links to crates or
+    /// versions which don't exist are reported as broken, and links pinning a
+    /// yanked or outdated version are reported according to
+    /// [`Config::outdated_crates`].
+    pub check_crates: bool,
+    /// How should links pinning a yanked or outdated version of a crate be
+    /// reported when [`Config::check_crates`] is enabled? Defaults to a
+    /// warning.
+    pub outdated_crates: WarningPolicy,
+    /// The regular expression the phone number in `tel:` links must match,
+    /// ignoring any parameters (e.g. `;ext=123`) and visual separators
+    /// (spaces, dashes, dots, and parentheses). Defaults to a loose version of
+    /// E.164, `^\+?[0-9]{3,15}$`.
+    pub tel_pattern: Option<HashedRegex>,
     /// The user-agent used whenever any web requests are made.
     #[serde(default = "default_user_agent")]
     pub user_agent: String,
     /// The number of seconds a cached result is valid for.
     #[serde(default = "default_cache_timeout")]
     pub cache_timeout: u64,
+    /// Cache each web result for as long as the server's `Cache-Control`
+    /// header says (its `max-age`) instead of for [`Config::cache_timeout`].
+    /// Results marked `no-cache` are stored but checked again next time, and
+    /// ones marked `no-store` aren't stored at all.
+    pub respect_cache_control: bool,
+    /// The longest a result may be cached for (in seconds) when
+    /// [`Config::respect_cache_control`] is enabled. Defaults to the
+    /// [`Config::cache_timeout`], meaning servers can only shorten it.
+    pub max_cache_timeout: Option<u64>,
+    /// The oldest (in seconds) a cached web result may be before it has to be
+    /// checked again, overriding [`Config::cache_timeout`] and
+    /// [`Config::max_cache_timeout`] when they are longer. This lets CI
+    /// guarantee every web link gets re-checked periodically.
+    pub require_fresh_within: Option<u64>,
+    /// Where to save the cache between runs, instead of the backend's output
+    /// directory. A leading `~` and environment variables (e.g. `$HOME`) are
+    /// expanded, and relative paths are resolved relative to the book's root.
+    pub cache_file: Option<String>,
+    /// Remember which chapters were free of problems last time, and skip them
+    /// if neither they nor the files they link to have changed since.
+    ///
+    /// This relies on the cache, so it has no effect when caching is
+    /// disabled.
+    pub incremental: bool,
+    /// Write statistics about each run (the number of links, cache hit rate,
+    /// request latency, etc.) to this file. It uses the OpenMetrics text
+    /// format unless the file name ends in `.json`.
+    ///
+    /// The path is resolved the same way as [`Config::cache_file`].
+    pub metrics_file: Option<String>,
+    /// Write a graph of the links between chapters to this file, with broken
+    /// links highlighted. It uses GraphViz's DOT format unless the file name
+    /// ends in `.json`.
+    ///
+    /// The path is resolved the same way as [`Config::cache_file`].
+    pub link_graph_file: Option<String>,
+    /// Append a one-line JSON summary of each run (when it happened, the
+    /// number of errors and warnings, the [`crate::Metrics`], and how many
+    /// links broke since the last run) to this file, and say how the number
+    /// of broken links changed since the previous entry.
+    ///
+    /// The path is resolved the same way as [`Config::cache_file`].
+    pub history_file: Option<String>,
+    /// A JSON file listing broken links which are already known about (e.g.
+    /// ones added with `--interactive`). They are still checked, but aren't
+    /// reported, so only new problems fail the build.
+    ///
+    /// The path is resolved the same way as [`Config::cache_file`].
+    pub baseline_file: Option<String>,
+    /// When a run completes, `POST` a JSON summary (the number of errors and
+    /// warnings, the [`crate::Metrics`], links which broke since the last run,
+    /// and the [`Config::report_url`]) to this URL. Environment variables
+    /// (e.g. `$SLACK_WEBHOOK`) are expanded.
+    pub notify_webhook: Option<String>,
+    /// A link to the full report (e.g. the CI job) to include in the
+    /// [`Config::notify_webhook`] summary. Environment variables are
+    /// expanded.
+    pub report_url: Option<String>,
+    /// The maximum number of seconds to spend checking the book. When it is
+    /// exceeded, any outstanding links and checks (e.g. for assets or
+    /// certificates) are cancelled and the results so far are reported.
+    pub max_runtime: Option<u64>,
+    /// At the end of a run, list this many of the slowest web links (and the
+    /// average response time for each domain).
+    pub report_slow_links: Option<usize>,
+    /// At the end of a run, print a table showing how many links pointed at
+    /// each domain, how many were broken, the average response time, and how
+    /// often the domain asked us to slow down.
+    pub report_domains: bool,
+    /// Write every permanent redirect seen while checking web links to a
+    /// `rewrites.toml` file in the output directory, mapping each old URL to
+    /// where it ends up. This only happens when running as a `mdbook`
+    /// backend.
+    pub suggest_rewrites: bool,
     /// The policy to use when warnings are encountered.
     #[serde(default)]
     pub warning_policy: WarningPolicy,
+    /// The maximum number of warnings allowed before the linkcheck fails,
+    /// even if none of them would be errors on their own.
+    pub max_warnings: Option<usize>,
+    /// Stop checking links as soon as a broken link is found that would be
+    /// reported as an error, instead of checking the rest of the book.
+    pub fail_fast: bool,
+    /// Print each web request and its response (the method, headers, status,
+    /// and how long it took) to stderr, with credentials redacted. This can
+    /// also be enabled with `--trace-http`.
+    pub trace_http: bool,
+    /// Print each broken link to stderr as soon as it is found, so long runs
+    /// show their progress, as well as reporting everything at the end.
+    ///
+    /// This is off by default so embedders don't get output they didn't ask
+    /// for (use [`crate::CheckerBuilder::diagnostic_sink()`] instead), but the
+    /// `mdbook-linkcheck` binary turns it on unless `book.toml` says otherwise
+    /// or `--no-stream` is used.
+    pub stream_failures: bool,
+    /// Only print this many diagnostics, followed by a count of the ones that
+    /// were left out. The most severe ones are kept and printed first. Every
+    /// diagnostic still counts towards whether the linkcheck fails.
+    pub max_diagnostics: Option<usize>,
+    /// Warn about `data:` URIs longer than this many bytes, because they
+    /// bloat the page (and are sometimes truncated by preprocessors).
+    pub max_data_uri_size: Option<usize>,
+    /// Warn about links to downloads (e.g. PDFs, archives, and installers)
+    /// bigger than this many bytes. Remote assets need an extra request to
+    /// find out how big they are.
+    pub max_asset_size: Option<u64>,
+    /// How should plain `http://` links be reported? They are allowed when
+    /// this isn't set.
+    pub forbid_http: Option<WarningPolicy>,
+    /// Patterns for `http://` links which are allowed even when
+    /// [`Config::forbid_http`] is set (e.g. `localhost` or intranet sites).
+    #[serde(default)]
+    pub forbid_http_exclude: Vec<HashedRegex>,
+    /// How should links to `localhost`, loopback, or private network addresses
+    /// (e.g. `http://192.168.1.10/`) be reported? They usually leak from
+    /// local testing and won't work for readers, but are allowed when this
+    /// isn't set.
+    pub private_network_links: Option<WarningPolicy>,
+    /// Patterns for links which are allowed even when
+    /// [`Config::private_network_links`] is set (e.g. intentional examples).
+    #[serde(default)]
+    pub private_network_links_exclude: Vec<HashedRegex>,
+    /// Domains which mustn't be linked to (e.g. URL shorteners or deprecated
+    /// internal hosts), including their subdomains. Links to them are
+    /// reported even if they still work.
+    #[serde(default)]
+    pub deny_domains: Vec<String>,
+    /// How should links to one of the [`Config::deny_domains`] be reported?
+    /// Defaults to an error.
+    pub denied_domain_policy: WarningPolicy,
+    /// How should links to internationalized domains which mix scripts or
+    /// only look like they're Latin (e.g. `аpple.com` with a Cyrillic "а")
+    /// be reported? They are allowed when this isn't set.
+    pub homograph_domains: Option<WarningPolicy>,
+    /// How should links which were changed by [`Config::rewrites`] be
+    /// reported? They aren't reported when this isn't set.
+    pub report_rewrites: Option<WarningPolicy>,
+    /// The URL the book is published at (e.g. `https://example.com/my-book/`).
+    /// Defaults to `site-url` from the `[output.html]` table when that is a
+    /// full URL.
+    pub site_url: Option<String>,
+    /// Where the book is deployed, either as a path (e.g. `/docs/book/`) or a
+    /// full URL (e.g. `https://example.com/docs/book/`).
+    ///
+    /// Absolute links under this prefix (e.g. `/docs/book/ch1.html`) are
+    /// checked against the book's source instead of being treated as files
+    /// outside it or sent over the network.
+    pub deploy_prefix: Option<String>,
+    /// How should absolute links into the book's own site (see
+    /// [`Config::site_url`]) be reported? They usually should be relative so
+    /// they work in previews and offline, but are allowed when this isn't
+    /// set.
+    pub self_referential_links: Option<WarningPolicy>,
+    /// How should `javascript:` links be reported? They are almost always a
+    /// mistake (or a security smell) in documentation, but are allowed when
+    /// this isn't set.
+    pub javascript_links: Option<WarningPolicy>,
+    /// How should `file://` links be reported? They only work on the
+    /// author's machine, but are allowed when this isn't set.
+    pub file_links: Option<WarningPolicy>,
+    /// How should links to files outside the source directory be reported
+    /// when they are allowed (e.g. by [`Config::external_roots`])? mdbook
+    /// doesn't copy them into the rendered book, so the link will be broken
+    /// once it is published.
+    #[serde(default)]
+    pub unpublished_files: WarningPolicy,
+    /// How should links to files mdbook reads but never renders (e.g.
+    /// `SUMMARY.md`, `book.toml`, or a theme's `*.hbs` templates) be
+    /// reported?
+    #[serde(default)]
+    pub special_files: WarningPolicy,
+    /// How should links to empty files be reported? They show up as a blank
+    /// page, but books sometimes have placeholder chapters on purpose.
+    #[serde(default)]
+    pub empty_files: WarningPolicy,
+    /// How should links with a URL scheme the link checker doesn't know
+    /// about (e.g. `ftp://`) be reported? They are never checked, and schemes
+    /// listed in [`Config::skip_schemes`] aren't reported.
+    #[serde(default)]
+    pub unknown_scheme: WarningPolicy,
+    /// How should `{{#include file.rs:anchor}}` directives be reported when
+    /// the `ANCHOR: anchor` marker doesn't exist, so nothing is included?
+    /// Defaults to an error. Anchors which are never closed are reported
+    /// according to [`Config::warning_policy`].
+    pub missing_include_anchors: WarningPolicy,
+    /// How should HTML links which open in a new tab (`target="_blank"`)
+    /// without `rel="noopener noreferrer"` be reported? They aren't reported
+    /// when this isn't set.
+    pub unsafe_target_blank: Option<WarningPolicy>,
+    /// How should assets in embedded HTML (e.g. `<img src="http://...">`)
+    /// which are loaded over plain HTTP be reported? This only applies when
+    /// the [`Config::site_url`] uses HTTPS.
+    pub mixed_content: Option<WarningPolicy>,
+    /// How should markdown images without any alt text (e.g.
+    /// `![](./diagram.png)`) be reported? Screen readers can't describe them.
+    /// They aren't reported when this isn't set, and can be allowed in a
+    /// single chapter (e.g. for decorative images) with a
+    /// `<!-- linkcheck-allow: missing-alt-text -->` comment.
+    pub missing_alt_text: Option<WarningPolicy>,
+    /// How should markdown files which aren't in `SUMMARY.md` and aren't
+    /// linked to from any chapter be reported? They are ignored when this
+    /// isn't set.
+    pub orphan_chapters: Option<WarningPolicy>,
     /// The map of regexes representing sets of web sites and
     /// the list of HTTP headers that must be sent to matching sites.
     #[serde(default)]
     pub http_headers: HashMap<HashedRegex, Vec<HttpHeader>>,
+    /// Rules for rewriting links before they are checked (e.g. to point at a
+    /// mirror), mapping a pattern to its replacement. The replacement may
+    /// refer to capture groups from the pattern (e.g. `$1`).
+    #[serde(default)]
+    pub rewrites: HashMap<HashedRegex, String>,
+    /// Anchors which are known to exist in the pages matching a pattern, even
+    /// though they can't be found in the page's source (e.g. a Swagger UI
+    /// which generates its anchors with JavaScript). Links to these anchors
+    /// are always treated as valid.
+    #[serde(default)]
+    pub known_anchors: HashMap<HashedRegex, Vec<String>>,
+    /// Settings which only apply to links on the local filesystem.
+    #[serde(default)]
+    pub local: LocalConfig,
+    /// Settings which only apply to links on the web.
+    #[serde(default)]
+    pub web: WebConfig,
+    /// Which markdown extensions to enable when looking for links.
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    /// How heading IDs are generated, when checking links to a section of
+    /// another chapter.
+    #[serde(default)]
+    pub slugs: SlugConfig,
+    /// Settings which only apply to some chapters (the
+    /// `[[output.linkcheck.overrides]]` tables), e.g. to be more lenient with
+    /// generated appendices. When several match the same chapter, the later
+    /// ones take precedence.
+    #[serde(default)]
+    pub overrides: Vec<ChapterOverride>,
+    /// Post problems on lines changed by this pull request as review
+    /// comments. Usually set with `--github-review`.
+    pub github_review: Option<GithubReview>,
+}
+
+/// Settings specific to links pointing at files on the local filesystem (the
+/// `[output.linkcheck.local]` table).
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, PartialEq, JsonSchema,
+)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LocalConfig {
+    /// How should broken local links be reported? Defaults to
+    /// [`WarningPolicy::Error`].
+    pub severity: Option<WarningPolicy>,
+    /// Extra URL patterns to ignore, on top of [`Config::exclude`].
+    pub exclude: Vec<HashedRegex>,
+    /// The number of seconds to spend checking local links (e.g. on a slow
+    /// network drive) before giving up. Any which haven't been checked by
+    /// then are reported as unchecked. By default there is no timeout.
+    pub timeout: Option<u64>,
+}
+
+/// Settings specific to links on the web (the `[output.linkcheck.web]` table).
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, PartialEq, JsonSchema,
+)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WebConfig {
+    /// How should broken web links be reported? Defaults to
+    /// [`WarningPolicy::Error`].
+    pub severity: Option<WarningPolicy>,
+    /// Extra URL patterns to ignore, on top of [`Config::exclude`].
+    pub exclude: Vec<HashedRegex>,
+    /// The number of seconds to wait for a web request before giving up. By
+    /// default there is no timeout.
+    pub timeout: Option<u64>,
+    /// How many seconds to remember what each host name resolves to, instead
+    /// of asking the resolver for every connection. Defaults to 5 minutes,
+    /// and `0` turns this off. The system resolver doesn't say how long each
+    /// record may be cached for, so this is used for all of them.
+    pub dns_cache_ttl: Option<u64>,
+    /// How should remote images (e.g. `![logo](https://example.com/logo.png)`)
+    /// which aren't served with an `image/*` content type be reported?
+    /// Markdown images reuse the response from checking the link, while
+    /// HTML `<img>` tags need an extra request unless they are cached.
+    pub image_content_type: Option<WarningPolicy>,
+    /// How should web links which redirect to a different domain be
+    /// reported? The redirects are the ones followed while checking each
+    /// link (or when its result was cached).
+    pub cross_domain_redirects: Option<WarningPolicy>,
+    /// How should remote downloads (e.g. `https://example.com/manual.pdf`)
+    /// which are served as a HTML page be reported? That's usually an error
+    /// or login page. This needs an extra request for each download, so they
+    /// aren't checked when this isn't set.
+    pub asset_content_type: Option<WarningPolicy>,
+    /// How should HTTPS links to servers which only support TLS versions
+    /// older than 1.2 be reported? This needs an extra request for each
+    /// server, so they aren't checked when this isn't set.
+    pub outdated_tls: Option<WarningPolicy>,
+    /// Warn about HTTPS links to servers whose certificate expires within
+    /// this many days. This needs an extra request to each server, so
+    /// certificates aren't looked at when this isn't set. A custom
+    /// [`crate::HttpClient`] needs to be created with
+    /// [`reqwest::ClientBuilder::tls_info()`] for the certificates to be
+    /// seen.
+    pub certificate_expiry: Option<u64>,
+}
+
+/// Settings for the chapters matching a glob (an
+/// `[[output.linkcheck.overrides]]` table).
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, PartialEq, JsonSchema,
+)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ChapterOverride {
+    /// The chapters these settings apply to, as a glob relative to the
+    /// book's root (e.g. `src/appendix/**`). Globs relative to the source
+    /// directory (e.g. `appendix/**`) work too.
+    pub path: String,
+    /// How should broken links in these chapters be reported, instead of the
+    /// `severity` for their category?
+    pub severity: Option<WarningPolicy>,
+    /// Extra URL patterns to ignore in these chapters.
+    pub exclude: Vec<HashedRegex>,
+    /// Should web links in these chapters be checked? This replaces
+    /// [`Config::follow_web_links`] and [`Config::follow_web_links_matching`]
+    /// when set.
+    pub follow_web_links: Option<bool>,
+}
+
+impl ChapterOverride {
+    /// Does this override apply to a chapter (relative to the source
+    /// directory)?
+    pub fn applies_to(&self, chapter: &Path) -> bool {
+        let chapter = chapter.to_string_lossy().replace('\\', "/");
+
+        crate::changes::glob_to_regex(&self.path)
+            .map(|pattern| pattern.is_match(&chapter))
+            .unwrap_or(false)
+    }
+
+    /// Is the link ignored by this override's [`ChapterOverride::exclude`]?
+    pub fn excludes(&self, link: &str) -> bool {
+        self.exclude.iter().any(|pat| pat.find(link).is_some())
+    }
+}
+
+/// The markdown extensions to enable when looking for links (the
+/// `[output.linkcheck.markdown]` table).
+///
+/// Only footnotes are enabled by default, which is how chapters have always
+/// been parsed. Enable tables, strikethrough and task lists to find links
+/// exactly where `mdbook` would render them, or turn everything off to parse
+/// chapters as strict CommonMark.
+#[derive(
+    Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema,
+)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MarkdownConfig {
+    /// GitHub-style tables.
+    pub tables: bool,
+    /// Footnote references (`[^1]`) and definitions (`[^1]: ...`).
+    pub footnotes: bool,
+    /// `~~strikethrough~~` text.
+    pub strikethrough: bool,
+    /// Task list items (`- [x] done`).
+    pub tasklists: bool,
+    /// Curly quotes, en and em dashes, and ellipses.
+    pub smart_punctuation: bool,
+    /// GitHub's extended autolinks, where bare URLs (e.g.
+    /// `https://example.com/` or `www.example.com`) in the text are links.
+    pub autolinks: bool,
+}
+
+impl MarkdownConfig {
+    /// The [`Options`] to give `pulldown_cmark`'s parser.
+    pub(crate) fn options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.tasklists);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options
+    }
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig {
+            tables: false,
+            footnotes: true,
+            strikethrough: false,
+            tasklists: false,
+            smart_punctuation: false,
+            autolinks: false,
+        }
+    }
+}
+
+/// How headings are turned into anchors (the `[output.linkcheck.slugs]`
+/// table), so links to a section of a chapter are checked against the IDs
+/// the book's renderer actually generates.
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema,
+)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SlugConfig {
+    /// The algorithm used to generate each heading's ID.
+    pub style: SlugStyle,
+    /// Use the ID given by a heading attribute (e.g. `# Heading {#custom}`)
+    /// instead of generating one, like `mdbook` does when heading attributes
+    /// are enabled.
+    pub heading_attributes: bool,
+    /// Text to replace in each heading before its ID is generated (e.g.
+    /// `"&" = "and"` or `"ä" = "ae"`), for themes which customise their IDs.
+    pub replacements: BTreeMap<String, String>,
+}
+
+/// The algorithms for turning a heading into an ID.
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlugStyle {
+    /// What `mdbook`'s HTML renderer does, which only lowercases ASCII
+    /// letters.
+    #[default]
+    Mdbook,
+    /// What GitHub does when rendering markdown files, which lowercases
+    /// every letter.
+    Github,
+}
+
+/// A GitHub pull request to post problems to as review comments (the
+/// `[output.linkcheck.github-review]` table). The API token is read from the
+/// `GITHUB_TOKEN` environment variable.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct GithubReview {
+    /// The repository the pull request was opened against (e.g.
+    /// `rust-lang/mdBook`).
+    pub repository: String,
+    /// The pull request's number.
+    pub pull_request: u64,
+}
+
+impl FromStr for GithubReview {
+    type Err = Error;
+
+    /// Parse a pull request in the form `owner/repo#123`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || {
+            Error::msg(format!(
+                "Expected a pull request like `owner/repo#123`, not `{}`",
+                s
+            ))
+        };
+        let (repository, number) = s.split_once('#').ok_or_else(malformed)?;
+        let is_repository = repository
+            .split_once('/')
+            .is_some_and(|(owner, name)| {
+                !owner.is_empty() && !name.is_empty() && !name.contains('/')
+            });
+        if !is_repository {
+            return Err(malformed());
+        }
+
+        Ok(GithubReview {
+            repository: repository.to_string(),
+            pull_request: number.parse().map_err(|_| malformed())?,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -49,7 +649,25 @@ pub struct HttpHeader {
 
 impl HttpHeader {
     pub(crate) fn interpolate(&self) -> Result<HeaderValue, Error> {
-        interpolate_env(&self.value)
+        Ok(interpolate_env(&self.value)?.parse()?)
+    }
+}
+
+impl JsonSchema for HttpHeader {
+    fn schema_name() -> String { String::from("HttpHeader") }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(String::from(
+                    "A HTTP header in the form `key: value`",
+                )),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
     }
 }
 
@@ -67,17 +685,431 @@ impl Config {
     pub const DEFAULT_USER_AGENT: &'static str =
         concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION"));
 
-    /// Checks [`Config::exclude`] to see if the provided link should be
-    /// skipped.
+    /// Figure out where the cache should be saved if [`Config::cache_file`]
+    /// was provided, expanding `~` and any environment variables.
+    pub fn resolve_cache_file(
+        &self,
+        root: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        resolve_path(self.cache_file.as_deref(), root, "cache file")
+    }
+
+    /// Figure out where [`Config::metrics_file`] should be written, if it was
+    /// provided.
+    pub fn resolve_metrics_file(
+        &self,
+        root: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        resolve_path(self.metrics_file.as_deref(), root, "metrics file")
+    }
+
+    /// Figure out where [`Config::link_graph_file`] should be written, if it
+    /// was provided.
+    pub fn resolve_link_graph_file(
+        &self,
+        root: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        resolve_path(self.link_graph_file.as_deref(), root, "link graph file")
+    }
+
+    /// Figure out where [`Config::history_file`] should be written, if it was
+    /// provided.
+    pub fn resolve_history_file(
+        &self,
+        root: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        resolve_path(self.history_file.as_deref(), root, "history file")
+    }
+
+    /// Figure out where [`Config::baseline_file`] is, if it was provided.
+    pub fn resolve_baseline_file(
+        &self,
+        root: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        resolve_path(self.baseline_file.as_deref(), root, "baseline file")
+    }
+
+    /// The longest any web result can be cached for, taking
+    /// [`Config::respect_cache_control`] and
+    /// [`Config::require_fresh_within`] into account.
+    pub(crate) fn longest_cache_timeout(&self) -> Duration {
+        let secs = match self.max_cache_timeout {
+            Some(max) if self.respect_cache_control => {
+                max.max(self.cache_timeout)
+            },
+            _ => self.cache_timeout,
+        };
+        let secs = match self.require_fresh_within {
+            Some(limit) => secs.min(limit),
+            None => secs,
+        };
+
+        Duration::from_secs(secs)
+    }
+
+    /// Checks [`Config::exclude`] (and the `exclude` list for the link's
+    /// category) and [`Config::skip_schemes`] to see if the provided link
+    /// should be skipped.
     pub fn should_skip(&self, link: &str) -> bool {
-        self.exclude.iter().any(|pat| pat.find(link).is_some())
+        self.matching_exclude(link).is_some()
+            || self.skipped_scheme(link).is_some()
+    }
+
+    /// The link's scheme, if it is one of the [`Config::skip_schemes`].
+    pub fn skipped_scheme<'a>(&self, link: &'a str) -> Option<&'a str> {
+        let (scheme, _) = link.split_once(':')?;
+        let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if !is_scheme {
+            return None;
+        }
+
+        self.skip_schemes
+            .iter()
+            .map(|skipped| {
+                skipped.trim_end_matches("://").trim_end_matches(':')
+            })
+            .any(|skipped| skipped.eq_ignore_ascii_case(scheme))
+            .then_some(scheme)
+    }
+
+    /// The link's scheme, if it is a URL the link checker doesn't know how to
+    /// handle (see [`Config::unknown_scheme`]).
+    pub fn unrecognised_scheme(&self, link: &str) -> Option<String> {
+        const KNOWN: &[&str] =
+            &["http", "https", "mailto", "data", "tel", "file", "javascript"];
+
+        let url = link.parse::<Url>().ok()?;
+        if KNOWN.contains(&url.scheme()) || self.skipped_scheme(link).is_some()
+        {
+            return None;
+        }
+
+        Some(url.scheme().to_string())
+    }
+
+    /// Find the first pattern from [`Config::exclude`] (or the `exclude` list
+    /// for the link's category) which matches the provided link.
+    pub fn matching_exclude(&self, link: &str) -> Option<&HashedRegex> {
+        let category_specific = if is_web_link(link) {
+            &self.web.exclude
+        } else {
+            &self.local.exclude
+        };
+
+        self.exclude
+            .iter()
+            .chain(category_specific)
+            .find(|pat| pat.find(link).is_some())
+    }
+
+    /// Can links point outside the book's source directory at all (see
+    /// [`Config::traverse_parent_directories`], [`Config::external_roots`],
+    /// [`Config::allowed_external_paths`], [`Config::extra_files`], and
+    /// [`Config::max_parent_depth`])?
+    pub fn may_leave_source_directory(&self) -> bool {
+        self.traverse_parent_directories
+            || !self.external_roots.is_empty()
+            || !self.allowed_external_paths.is_empty()
+            || !self.extra_files.is_empty()
+            || self.max_parent_depth.is_some()
+    }
+
+    /// Checks [`Config::exclude_anchors`] and [`Config::strip_fragment`] to
+    /// see if the fragment in the provided link should not be checked.
+    pub fn should_skip_anchor(&self, link: &str, anchor: &str) -> bool {
+        self.exclude_anchors
+            .iter()
+            .any(|pat| pat.find(link).is_some() || pat.find(anchor).is_some())
+            || self.strip_fragment.iter().any(|pat| pat.find(link).is_some())
+    }
+
+    /// Remove the query string and/or fragment from a link, according to
+    /// [`Config::strip_query`] and [`Config::strip_fragment`]. Returns `None`
+    /// when the link is left alone.
+    pub fn strip(&self, link: &str) -> Option<String> {
+        let matches = |patterns: &[HashedRegex]| {
+            patterns.iter().any(|pat| pat.find(link).is_some())
+        };
+        let strip_query = matches(&self.strip_query);
+        let strip_fragment = matches(&self.strip_fragment);
+
+        let (rest, fragment) = match link.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (link, None),
+        };
+        let mut stripped = match rest.split_once('?') {
+            Some((before, _)) if strip_query => before.to_string(),
+            _ => rest.to_string(),
+        };
+        if let Some(fragment) = fragment.filter(|_| !strip_fragment) {
+            stripped.push('#');
+            stripped.push_str(fragment);
+        }
+
+        if stripped == link {
+            None
+        } else {
+            Some(stripped)
+        }
+    }
+
+    /// Is this a plain `http://` link which should be reported because of
+    /// [`Config::forbid_http`]?
+    pub fn is_forbidden_http(&self, link: &str) -> bool {
+        let is_http = link
+            .get(..7)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://"));
+
+        is_http
+            && !self
+                .forbid_http_exclude
+                .iter()
+                .any(|pat| pat.find(link).is_some())
+    }
+
+    /// Does this link point to `localhost` or a private network address,
+    /// and should it be reported because of
+    /// [`Config::private_network_links`]?
+    pub fn is_private_network_link(&self, link: &str) -> bool {
+        let url = match Url::parse(link) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return false,
+        };
+        let is_private = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                ip.is_loopback()
+                    || ip.is_private()
+                    || ip.is_link_local()
+                    || ip.is_unspecified()
+            },
+            Ok(IpAddr::V6(ip)) => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            },
+            Err(_) => {
+                let domain = host.trim_end_matches('.').to_lowercase();
+                domain == "localhost" || domain.ends_with(".localhost")
+            },
+        };
+
+        is_private
+            && !self
+                .private_network_links_exclude
+                .iter()
+                .any(|pat| pat.find(link).is_some())
+    }
+
+    /// Find the entry in [`Config::deny_domains`] which this link's host
+    /// matches, if any.
+    pub fn denied_domain(&self, link: &str) -> Option<&str> {
+        let url = Url::parse(link).ok()?;
+        let host = url.host_str()?.trim_end_matches('.').to_lowercase();
+
+        self.deny_domains
+            .iter()
+            .map(|domain| domain.as_str())
+            .find(|domain| {
+                let domain = domain.trim_end_matches('.').to_lowercase();
+                // the link's host is always in punycode
+                let domain = idna::domain_to_ascii(&domain).unwrap_or(domain);
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+    }
+
+    /// If this is an absolute link into the book's own site (see
+    /// [`Config::site_url`]), get the path it points to relative to the root
+    /// of the book, including any query string and fragment.
+    pub fn self_referential_path(&self, link: &str) -> Option<String> {
+        let site = Url::parse(self.site_url.as_deref()?).ok()?;
+        let url = Url::parse(link).ok()?;
+
+        path_within_site(&site, &url)
+    }
+
+    /// If this link points into the book using its [`Config::deploy_prefix`]
+    /// (e.g. `/docs/book/ch1.html`), get the equivalent absolute link
+    /// relative to the book's source directory (e.g. `/ch1.html`).
+    pub fn strip_deploy_prefix(&self, link: &str) -> Option<String> {
+        let prefix = self.deploy_prefix.as_deref()?;
+
+        let is_full_url = Url::parse(prefix).is_ok();
+        // a prefix which is just a path gets compared as if the book and its
+        // links were on a made up site
+        let site = Url::parse("http://localhost/").unwrap().join(prefix).ok()?;
+        let url = if link.starts_with('/') && !link.starts_with("//") {
+            site.join(link).ok()?
+        } else if is_full_url {
+            Url::parse(link).ok()?
+        } else {
+            return None;
+        };
+
+        path_within_site(&site, &url).map(|path| format!("/{}", path))
+    }
+
+    /// Should this web link be checked, taking into account both
+    /// [`Config::follow_web_links`] and [`Config::follow_web_links_matching`]?
+    pub fn should_follow_web_link(&self, link: &str) -> bool {
+        self.follow_web_links
+            || self
+                .follow_web_links_matching
+                .iter()
+                .any(|pat| pat.find(link).is_some())
+    }
+
+    /// Is this anchor listed in [`Config::known_anchors`] for a pattern which
+    /// matches the link?
+    pub fn is_known_anchor(&self, link: &str, anchor: &str) -> bool {
+        self.known_anchors.iter().any(|(pattern, anchors)| {
+            pattern.find(link).is_some()
+                && anchors.iter().any(|known| known == anchor)
+        })
+    }
+
+    /// Apply the first matching rule from [`Config::rewrites`] to a link,
+    /// returning `None` if no rules match.
+    ///
+    /// Rules are tried in order of their pattern, so the result doesn't
+    /// depend on how the table was written.
+    pub fn rewrite(&self, link: &str) -> Option<String> {
+        let mut rules: Vec<_> = self.rewrites.iter().collect();
+        rules.sort_by(|(left, _), (right, _)| left.string.cmp(&right.string));
+
+        rules
+            .into_iter()
+            .find(|(pattern, _)| pattern.is_match(link))
+            .map(|(pattern, replacement)| {
+                pattern.replace(link, replacement.as_str()).into_owned()
+            })
+    }
+
+    /// The [`Config::overrides`] which apply to a chapter (relative to the
+    /// source directory), in the order they were given.
+    pub fn overrides_for(&self, chapter: &Path) -> Vec<&ChapterOverride> {
+        self.overrides
+            .iter()
+            .filter(|o| o.applies_to(chapter))
+            .collect()
+    }
+
+    /// Make the [`ChapterOverride::path`]s which start with the source
+    /// directory (relative to the book's root, e.g. `src`) relative to the
+    /// source directory instead, because that's how chapters are named.
+    pub(crate) fn rebase_overrides(&mut self, source_dir: &Path) {
+        let prefix = source_dir.to_string_lossy().replace('\\', "/");
+        if prefix.is_empty() {
+            return;
+        }
+
+        for o in &mut self.overrides {
+            let path = o.path.trim_start_matches("./").trim_end_matches('/');
+            let rebased = match path.strip_prefix(prefix.as_str()) {
+                Some("") => String::from("**"),
+                Some(rest) if rest.starts_with('/') => rest[1..].to_string(),
+                _ => continue,
+            };
+            o.path = rebased;
+        }
+    }
+
+    /// The same as [`Config::should_follow_web_link()`], except the
+    /// [`ChapterOverride::follow_web_links`] for the link's chapter win.
+    pub(crate) fn should_follow_web_link_in(
+        &self,
+        link: &str,
+        overrides: &[&ChapterOverride],
+    ) -> bool {
+        overrides
+            .iter()
+            .rev()
+            .find_map(|o| o.follow_web_links)
+            .unwrap_or_else(|| self.should_follow_web_link(link))
+    }
+
+    /// A copy of this configuration which never sends web requests, for
+    /// checking local links as quickly as possible.
+    pub(crate) fn without_web_checks(&self) -> Config {
+        let mut cfg = self.clone();
+        cfg.follow_web_links = false;
+        cfg.follow_web_links_matching.clear();
+        cfg.check_dois = false;
+        cfg.check_crates = false;
+        cfg.web.image_content_type = None;
+        cfg.web.cross_domain_redirects = None;
+        cfg.web.asset_content_type = None;
+        cfg.web.outdated_tls = None;
+        cfg.web.certificate_expiry = None;
+        for o in &mut cfg.overrides {
+            o.follow_web_links = Some(false);
+        }
+
+        cfg
+    }
+
+    /// The same as [`Config::broken_link_policy()`], except the
+    /// [`ChapterOverride::severity`] for the link's chapter wins.
+    pub(crate) fn broken_link_policy_in(
+        &self,
+        link: &str,
+        overrides: &[&ChapterOverride],
+    ) -> WarningPolicy {
+        overrides
+            .iter()
+            .rev()
+            .find_map(|o| o.severity)
+            .unwrap_or_else(|| self.broken_link_policy(link))
+    }
+
+    /// How should a broken link be reported, given the `severity` for its
+    /// category?
+    pub(crate) fn broken_link_policy(&self, link: &str) -> WarningPolicy {
+        let severity = if is_web_link(link) {
+            self.web.severity
+        } else {
+            self.local.severity
+        };
+
+        severity.unwrap_or(WarningPolicy::Error)
     }
 
     pub(crate) fn client(&self) -> Client {
+        self.client_builder().build().unwrap()
+    }
+
+    /// A [`ClientBuilder`] with the user-agent and timeout already set, for
+    /// when a [`Client`] needs some extra tweaks.
+    ///
+    /// Redirects aren't followed, because that is done for every
+    /// [`crate::HttpClient`] (see [`crate::http_client`]).
+    pub(crate) fn client_builder(&self) -> ClientBuilder {
         let mut headers = http::HeaderMap::new();
         headers
             .insert(http::header::USER_AGENT, self.user_agent.parse().unwrap());
-        Client::builder().default_headers(headers).build().unwrap()
+        let mut builder = Client::builder()
+            .default_headers(headers)
+            .redirect(Policy::none());
+
+        if let Some(timeout) = self.web.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        if self.web.certificate_expiry.is_some() {
+            // so the certificate can be read from each response
+            builder = builder.tls_info(true);
+        }
+
+        builder
     }
 
     pub(crate) fn interpolate_headers(
@@ -85,7 +1117,6 @@ impl Config {
         warning_policy: WarningPolicy,
     ) -> Vec<(HashedRegex, Vec<(HeaderName, HeaderValue)>)> {
         let mut all_headers = Vec::new();
-        let log_level = warning_policy.to_log_level();
 
         for (pattern, headers) in &self.http_headers {
             let mut interpolated = Vec::new();
@@ -102,12 +1133,10 @@ impl Config {
                         //
                         // If it was important, the user would notice a "broken"
                         // link and read back through the logs.
-                        log::log!(
-                            log_level,
+                        warning_policy.log(format_args!(
                             "Unable to interpolate \"{}\" because {}",
-                            header,
-                            e
-                        );
+                            header, e
+                        ));
                     },
                 }
             }
@@ -115,20 +1144,130 @@ impl Config {
             all_headers.push((pattern.clone(), interpolated));
         }
 
+        // make sure headers are always applied in the same order, regardless
+        // of how the HashMap is iterated
+        all_headers
+            .sort_by(|(left, _), (right, _)| left.string.cmp(&right.string));
+
         all_headers
     }
 }
 
+/// Get the path `url` points to relative to the root of `site`, including any
+/// query string and fragment.
+fn path_within_site(site: &Url, url: &Url) -> Option<String> {
+    let is_web = |url: &Url| matches!(url.scheme(), "http" | "https");
+    if !is_web(site)
+        || !is_web(url)
+        || site.host_str().is_none()
+        || site.host_str() != url.host_str()
+        || site.port() != url.port()
+    {
+        return None;
+    }
+
+    let site_path = site.path().trim_end_matches('/');
+    let rest = url.path().strip_prefix(site_path)?;
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        // e.g. "/my-book-2/" when the book is at "/my-book/"
+        return None;
+    }
+
+    let mut path = rest.trim_start_matches('/').to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    if let Some(fragment) = url.fragment() {
+        path.push('#');
+        path.push_str(fragment);
+    }
+
+    Some(path)
+}
+
 impl Default for Config {
     fn default() -> Config {
         Config {
             follow_web_links: false,
+            follow_web_links_matching: Vec::new(),
             traverse_parent_directories: false,
+            external_roots: Vec::new(),
+            allowed_external_paths: Vec::new(),
+            extra_files: Vec::new(),
+            max_parent_depth: None,
+            workspace_books: Vec::new(),
+            translations: Vec::new(),
+            default_language: None,
             exclude: Vec::new(),
+            skip_schemes: Vec::new(),
+            check_anchors: false,
+            exclude_anchors: Vec::new(),
+            strip_query: Vec::new(),
+            strip_fragment: Vec::new(),
+            link_patterns: Vec::new(),
+            front_matter_keys: Vec::new(),
+            raw_sources: false,
+            check_dois: false,
+            check_crates: false,
+            outdated_crates: WarningPolicy::Warn,
+            tel_pattern: None,
             user_agent: default_user_agent(),
             http_headers: HashMap::new(),
             warning_policy: WarningPolicy::Warn,
+            cache_file: None,
+            incremental: false,
+            metrics_file: None,
+            link_graph_file: None,
+            history_file: None,
+            baseline_file: None,
+            notify_webhook: None,
+            report_url: None,
+            max_runtime: None,
+            report_slow_links: None,
+            report_domains: false,
+            suggest_rewrites: false,
+            max_warnings: None,
+            fail_fast: false,
+            trace_http: false,
+            stream_failures: false,
+            max_diagnostics: None,
+            max_data_uri_size: None,
+            max_asset_size: None,
+            forbid_http: None,
+            forbid_http_exclude: Vec::new(),
+            private_network_links: None,
+            private_network_links_exclude: Vec::new(),
+            deny_domains: Vec::new(),
+            denied_domain_policy: WarningPolicy::Error,
+            homograph_domains: None,
+            report_rewrites: None,
+            site_url: None,
+            deploy_prefix: None,
+            self_referential_links: None,
+            javascript_links: None,
+            file_links: None,
+            unpublished_files: WarningPolicy::Warn,
+            special_files: WarningPolicy::Warn,
+            empty_files: WarningPolicy::Warn,
+            unknown_scheme: WarningPolicy::Warn,
+            missing_include_anchors: WarningPolicy::Error,
+            unsafe_target_blank: None,
+            mixed_content: None,
+            missing_alt_text: None,
+            orphan_chapters: None,
+            rewrites: HashMap::new(),
+            known_anchors: HashMap::new(),
             cache_timeout: Config::DEFAULT_CACHE_TIMEOUT.as_secs(),
+            respect_cache_control: false,
+            max_cache_timeout: None,
+            require_fresh_within: None,
+            local: LocalConfig::default(),
+            web: WebConfig::default(),
+            markdown: MarkdownConfig::default(),
+            slugs: SlugConfig::default(),
+            overrides: Vec::new(),
+            github_review: None,
         }
     }
 }
@@ -169,17 +1308,54 @@ impl TryFrom<String> for HttpHeader {
     }
 }
 
-impl Into<String> for HttpHeader {
-    fn into(self) -> String {
-        let HttpHeader { name, value, .. } = self;
+impl From<HttpHeader> for String {
+    fn from(header: HttpHeader) -> String {
+        let HttpHeader { name, value, .. } = header;
         format!("{}: {}", name, value)
     }
 }
 
+/// Does this link point to something on the web (as opposed to the local
+/// filesystem)?
+pub(crate) fn is_web_link(link: &str) -> bool { link.parse::<Url>().is_ok() }
+
+fn resolve_path(
+    raw: Option<&str>,
+    root: &Path,
+    what: &str,
+) -> Result<Option<PathBuf>, Error> {
+    match raw {
+        Some(raw) => {
+            let path = expand_path(raw, &env_vars()).with_context(|| {
+                format!("Unable to resolve the \"{}\" {}", raw, what)
+            })?;
+            Ok(Some(root.join(path)))
+        },
+        None => Ok(None),
+    }
+}
+
 fn default_cache_timeout() -> u64 { Config::DEFAULT_CACHE_TIMEOUT.as_secs() }
 fn default_user_agent() -> String { Config::DEFAULT_USER_AGENT.to_string() }
 
-fn interpolate_env(value: &str) -> Result<HeaderValue, Error> {
+/// The process's environment variables, skipping any which aren't UTF-8.
+fn env_vars() -> HashMap<String, String> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        })
+        .collect()
+}
+
+pub(crate) fn interpolate_env(value: &str) -> Result<String, Error> {
+    interpolate(value, &env_vars())
+}
+
+/// Replace each `$VARIABLE` in `value` with its value from `env`.
+fn interpolate(
+    value: &str,
+    env: &HashMap<String, String>,
+) -> Result<String, Error> {
     use std::{iter::Peekable, str::CharIndices};
 
     fn is_ident(ch: char) -> bool { ch.is_ascii_alphanumeric() || ch == '_' }
@@ -221,12 +1397,13 @@ fn interpolate_env(value: &str) -> Result<HeaderValue, Error> {
                     let end = ident_end(start, &mut iter);
                     let name = &value[start..end];
 
-                    match std::env::var(name) {
-                        Ok(env) => res.push_str(&env),
-                        Err(e) => {
+                    match env.get(name) {
+                        Some(value) => res.push_str(value),
+                        None => {
                             return Err(Error::msg(format!(
                                 "Failed to retrieve `{}` env var: {}",
-                                name, e
+                                name,
+                                std::env::VarError::NotPresent
                             )))
                         },
                     }
@@ -242,61 +1419,378 @@ fn interpolate_env(value: &str) -> Result<HeaderValue, Error> {
         res.push('\\');
     }
 
-    Ok(res.parse()?)
+    Ok(res)
+}
+
+/// Expand a leading `~` to the user's home directory and interpolate any
+/// environment variables from `env`.
+fn expand_path(
+    raw: &str,
+    env: &HashMap<String, String>,
+) -> Result<PathBuf, Error> {
+    let interpolated = interpolate(raw, env)?;
+
+    let rest = match interpolated.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            rest.trim_start_matches(['/', '\\'])
+        },
+        _ => return Ok(PathBuf::from(interpolated)),
+    };
+
+    let home = env
+        .get("HOME")
+        .or_else(|| env.get("USERPROFILE"))
+        .ok_or_else(|| {
+            Error::msg(
+                "Unable to expand \"~\" because the home directory is unknown",
+            )
+        })?;
+
+    Ok(Path::new(home).join(rest))
 }
 
 /// How should warnings be treated?
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize, JsonSchema,
+)]
 #[serde(rename_all = "kebab-case")]
 pub enum WarningPolicy {
     /// Silently ignore them.
     Ignore,
     /// Warn the user, but don't fail the linkcheck.
+    #[default]
     Warn,
     /// Treat warnings as errors.
     Error,
 }
 
 impl WarningPolicy {
-    pub(crate) fn to_log_level(self) -> Level {
+    /// Log a message at the level corresponding to this policy.
+    pub(crate) fn log(self, message: fmt::Arguments<'_>) {
         match self {
-            WarningPolicy::Error => Level::Error,
-            WarningPolicy::Warn => Level::Warn,
-            WarningPolicy::Ignore => Level::Debug,
+            WarningPolicy::Error => tracing::error!("{}", message),
+            WarningPolicy::Warn => tracing::warn!("{}", message),
+            WarningPolicy::Ignore => tracing::debug!("{}", message),
         }
     }
-}
 
-impl Default for WarningPolicy {
-    fn default() -> WarningPolicy { WarningPolicy::Warn }
+    pub(crate) fn to_severity(self) -> Option<Severity> {
+        match self {
+            WarningPolicy::Error => Some(Severity::Error),
+            WarningPolicy::Warn => Some(Severity::Warning),
+            WarningPolicy::Ignore => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::{convert::TryInto, iter::FromIterator};
-    use toml;
 
     const CONFIG: &str = r#"follow-web-links = true
+follow-web-links-matching = ["example\\.com"]
 traverse-parent-directories = true
+external-roots = ["../shared-docs", "../../LICENSE"]
+allowed-external-paths = ["../shared/**", "../../LICENSE"]
+extra-files = ["../CHANGELOG.md", "../docs"]
+max-parent-depth = 1
+workspace-books = ["../reference"]
+translations = ["src/en", "src/fr"]
+default-language = "en"
 exclude = ["google\\.com"]
+skip-schemes = ["irc", "magnet"]
+check-anchors = true
+exclude-anchors = ["^operations-"]
+strip-query = ["^https://api\\.example\\.com/"]
+strip-fragment = ["^https://spa\\.example\\.com/"]
+link-patterns = ["\\{\\{#button [^}]*href=\"(?P<link>[^\"]+)\""]
+front-matter-keys = ["canonical_url", "redirect_from"]
+raw-sources = true
+check-dois = true
+check-crates = true
+outdated-crates = "error"
+tel-pattern = "^\\+61[0-9]{9}$"
 user-agent = "Internet Explorer"
 cache-timeout = 3600
+respect-cache-control = true
+max-cache-timeout = 604800
+require-fresh-within = 86400
+cache-file = "$CACHE_DIR/linkcheck.json"
+incremental = true
+metrics-file = "metrics.json"
+link-graph-file = "links.dot"
+history-file = "history.jsonl"
+baseline-file = "baseline.json"
+notify-webhook = "$WEBHOOK_URL"
+report-url = "https://ci.example.com/$JOB_ID"
+max-runtime = 600
+report-slow-links = 5
+report-domains = true
+suggest-rewrites = true
 warning-policy = "error"
+max-warnings = 10
+fail-fast = true
+trace-http = true
+stream-failures = true
+max-diagnostics = 100
+max-data-uri-size = 4096
+max-asset-size = 10000000
+forbid-http = "warn"
+forbid-http-exclude = ["^http://localhost"]
+private-network-links = "warn"
+private-network-links-exclude = ["^http://localhost:3000/"]
+deny-domains = ["bit.ly", "wiki.internal.corp"]
+denied-domain-policy = "warn"
+homograph-domains = "error"
+report-rewrites = "warn"
+site-url = "https://example.com/my-book/"
+deploy-prefix = "/docs/book/"
+self-referential-links = "warn"
+javascript-links = "error"
+file-links = "error"
+unpublished-files = "error"
+special-files = "error"
+empty-files = "ignore"
+unknown-scheme = "ignore"
+missing-include-anchors = "warn"
+unsafe-target-blank = "warn"
+mixed-content = "error"
+missing-alt-text = "warn"
+orphan-chapters = "warn"
 
 [http-headers]
 https = ["accept: html/text", "authorization: Basic $TOKEN"]
+
+[rewrites]
+"^https://internal\\.corp/(.*)$" = "https://mirror.example.com/$1"
+
+[known-anchors]
+"swagger\\.html" = ["/pets/listPets", "/store/getInventory"]
+
+[local]
+severity = "warn"
+exclude = ["\\.pdf$"]
+timeout = 5
+
+[web]
+severity = "error"
+exclude = ["localhost"]
+timeout = 30
+dns-cache-ttl = 60
+image-content-type = "warn"
+cross-domain-redirects = "warn"
+asset-content-type = "warn"
+outdated-tls = "warn"
+certificate-expiry = 14
+
+[markdown]
+tables = false
+footnotes = true
+strikethrough = true
+tasklists = true
+smart-punctuation = true
+autolinks = true
+
+[slugs]
+style = "github"
+heading-attributes = true
+
+[slugs.replacements]
+"&" = "and"
+
+[[overrides]]
+path = "src/appendix/**"
+severity = "warn"
+exclude = ["generated\\.example\\.com"]
+follow-web-links = false
+
+[github-review]
+repository = "owner/repo"
+pull-request = 42
 "#;
 
+    #[test]
+    fn parse_github_pull_requests() {
+        let got: GithubReview = "rust-lang/mdBook#1234".parse().unwrap();
+        assert_eq!(
+            got,
+            GithubReview {
+                repository: String::from("rust-lang/mdBook"),
+                pull_request: 1234,
+            }
+        );
+
+        for bad in &["rust-lang/mdBook", "mdBook#1", "a/b/c#1", "a/b#one"] {
+            assert!(bad.parse::<GithubReview>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn detect_links_into_the_books_own_site() {
+        let cfg = Config {
+            site_url: Some(String::from("https://example.com/my-book")),
+            ..Default::default()
+        };
+        let inputs = vec![
+            ("https://example.com/my-book/intro.html", Some("intro.html")),
+            ("http://example.com/my-book/a/b.html#c", Some("a/b.html#c")),
+            ("https://EXAMPLE.com/my-book/", Some("")),
+            ("https://example.com/my-book-2/intro.html", None),
+            ("https://example.com:8080/my-book/intro.html", None),
+            ("https://other.com/my-book/intro.html", None),
+            ("./intro.md", None),
+        ];
+
+        for (link, should_be) in inputs {
+            let got = cfg.self_referential_path(link);
+            assert_eq!(got.as_deref(), should_be, "{}", link);
+        }
+    }
+
+    #[test]
+    fn overrides_only_apply_to_matching_chapters() {
+        let lenient = ChapterOverride {
+            path: String::from("src/appendix/**"),
+            severity: Some(WarningPolicy::Warn),
+            follow_web_links: Some(false),
+            ..Default::default()
+        };
+        let strict = ChapterOverride {
+            path: String::from("appendix/generated.md"),
+            severity: Some(WarningPolicy::Error),
+            ..Default::default()
+        };
+        let mut cfg = Config {
+            follow_web_links: true,
+            overrides: vec![lenient, strict],
+            ..Default::default()
+        };
+        cfg.rebase_overrides(Path::new("src"));
+
+        assert_eq!(cfg.overrides[0].path, "appendix/**");
+        assert!(cfg.overrides_for(Path::new("intro.md")).is_empty());
+        let appendix = cfg.overrides_for(Path::new("appendix/a/b.md"));
+        assert_eq!(appendix.len(), 1);
+        assert!(!cfg.should_follow_web_link_in("https://a.com/", &appendix));
+        assert_eq!(
+            cfg.broken_link_policy_in("./x.md", &appendix),
+            WarningPolicy::Warn
+        );
+        let generated = cfg.overrides_for(Path::new("appendix/generated.md"));
+        assert_eq!(generated.len(), 2);
+        assert_eq!(
+            cfg.broken_link_policy_in("./x.md", &generated),
+            WarningPolicy::Error
+        );
+        assert!(cfg.should_follow_web_link_in("https://a.com/", &[]));
+    }
+
+    #[test]
+    fn resolve_links_under_the_deploy_prefix() {
+        let inputs = vec![
+            ("/docs/book/ch1.html", Some("/ch1.html")),
+            ("/docs/book/a/ch2.html#intro", Some("/a/ch2.html#intro")),
+            ("/docs/book", Some("/")),
+            ("/docs/book-2/ch1.html", None),
+            ("/docs/ch1.html", None),
+            ("./ch1.html", None),
+            ("//example.com/docs/book/ch1.html", None),
+        ];
+        let cfg = Config {
+            deploy_prefix: Some(String::from("/docs/book/")),
+            ..Default::default()
+        };
+
+        for (link, should_be) in inputs {
+            let got = cfg.strip_deploy_prefix(link);
+            assert_eq!(got.as_deref(), should_be, "{}", link);
+        }
+
+        let cfg = Config {
+            deploy_prefix: Some(String::from("https://example.com/docs/book")),
+            ..Default::default()
+        };
+        let got = cfg.strip_deploy_prefix("https://example.com/docs/book/a.md");
+        assert_eq!(got.as_deref(), Some("/a.md"));
+        let got = cfg.strip_deploy_prefix("/docs/book/a.md");
+        assert_eq!(got.as_deref(), Some("/a.md"));
+        let got = cfg.strip_deploy_prefix("https://other.com/docs/book/");
+        assert_eq!(got, None);
+    }
+
     #[test]
     fn deserialize_a_config() {
         std::env::set_var("TOKEN", "QWxhZGRpbjpPcGVuU2VzYW1l");
 
         let should_be = Config {
             follow_web_links: true,
+            follow_web_links_matching: vec![
+                HashedRegex::new(r"example\.com").unwrap()
+            ],
             warning_policy: WarningPolicy::Error,
+            max_warnings: Some(10),
+            fail_fast: true,
+            trace_http: true,
+            stream_failures: true,
+            max_diagnostics: Some(100),
+            max_data_uri_size: Some(4096),
+            max_asset_size: Some(10_000_000),
+            forbid_http: Some(WarningPolicy::Warn),
+            forbid_http_exclude: vec![
+                HashedRegex::new("^http://localhost").unwrap()
+            ],
+            private_network_links: Some(WarningPolicy::Warn),
+            private_network_links_exclude: vec![HashedRegex::new(
+                "^http://localhost:3000/",
+            )
+            .unwrap()],
+            deny_domains: vec![
+                String::from("bit.ly"),
+                String::from("wiki.internal.corp"),
+            ],
+            denied_domain_policy: WarningPolicy::Warn,
+            homograph_domains: Some(WarningPolicy::Error),
             traverse_parent_directories: true,
+            external_roots: vec![
+                String::from("../shared-docs"),
+                String::from("../../LICENSE"),
+            ],
+            allowed_external_paths: vec![
+                String::from("../shared/**"),
+                String::from("../../LICENSE"),
+            ],
+            extra_files: vec![
+                String::from("../CHANGELOG.md"),
+                String::from("../docs"),
+            ],
+            max_parent_depth: Some(1),
+            workspace_books: vec![String::from("../reference")],
+            translations: vec![String::from("src/en"), String::from("src/fr")],
+            default_language: Some(String::from("en")),
             exclude: vec![HashedRegex::new(r"google\.com").unwrap()],
+            skip_schemes: vec![String::from("irc"), String::from("magnet")],
+            check_anchors: true,
+            exclude_anchors: vec![HashedRegex::new("^operations-").unwrap()],
+            strip_query: vec![
+                HashedRegex::new(r"^https://api\.example\.com/").unwrap()
+            ],
+            strip_fragment: vec![
+                HashedRegex::new(r"^https://spa\.example\.com/").unwrap()
+            ],
+            link_patterns: vec![HashedRegex::new(
+                r#"\{\{#button [^}]*href="(?P<link>[^"]+)""#,
+            )
+            .unwrap()],
+            front_matter_keys: vec![
+                String::from("canonical_url"),
+                String::from("redirect_from"),
+            ],
+            raw_sources: true,
+            check_dois: true,
+            check_crates: true,
+            outdated_crates: WarningPolicy::Error,
+            tel_pattern: Some(HashedRegex::new(r"^\+61[0-9]{9}$").unwrap()),
             user_agent: String::from("Internet Explorer"),
             http_headers: HashMap::from_iter(vec![(
                 HashedRegex::new("https").unwrap(),
@@ -305,7 +1799,90 @@ https = ["accept: html/text", "authorization: Basic $TOKEN"]
                     "Authorization: Basic $TOKEN".try_into().unwrap(),
                 ],
             )]),
+            report_rewrites: Some(WarningPolicy::Warn),
+            site_url: Some(String::from("https://example.com/my-book/")),
+            deploy_prefix: Some(String::from("/docs/book/")),
+            self_referential_links: Some(WarningPolicy::Warn),
+            javascript_links: Some(WarningPolicy::Error),
+            file_links: Some(WarningPolicy::Error),
+            unpublished_files: WarningPolicy::Error,
+            special_files: WarningPolicy::Error,
+            empty_files: WarningPolicy::Ignore,
+            unknown_scheme: WarningPolicy::Ignore,
+            missing_include_anchors: WarningPolicy::Warn,
+            unsafe_target_blank: Some(WarningPolicy::Warn),
+            mixed_content: Some(WarningPolicy::Error),
+            missing_alt_text: Some(WarningPolicy::Warn),
+            orphan_chapters: Some(WarningPolicy::Warn),
+            rewrites: HashMap::from_iter(vec![(
+                HashedRegex::new(r"^https://internal\.corp/(.*)$").unwrap(),
+                String::from("https://mirror.example.com/$1"),
+            )]),
+            known_anchors: HashMap::from_iter(vec![(
+                HashedRegex::new(r"swagger\.html").unwrap(),
+                vec![
+                    String::from("/pets/listPets"),
+                    String::from("/store/getInventory"),
+                ],
+            )]),
             cache_timeout: 3600,
+            respect_cache_control: true,
+            max_cache_timeout: Some(604800),
+            require_fresh_within: Some(86400),
+            cache_file: Some(String::from("$CACHE_DIR/linkcheck.json")),
+            incremental: true,
+            metrics_file: Some(String::from("metrics.json")),
+            link_graph_file: Some(String::from("links.dot")),
+            history_file: Some(String::from("history.jsonl")),
+            baseline_file: Some(String::from("baseline.json")),
+            notify_webhook: Some(String::from("$WEBHOOK_URL")),
+            report_url: Some(String::from("https://ci.example.com/$JOB_ID")),
+            max_runtime: Some(600),
+            report_slow_links: Some(5),
+            report_domains: true,
+            suggest_rewrites: true,
+            local: LocalConfig {
+                severity: Some(WarningPolicy::Warn),
+                exclude: vec![HashedRegex::new(r"\.pdf$").unwrap()],
+                timeout: Some(5),
+            },
+            web: WebConfig {
+                severity: Some(WarningPolicy::Error),
+                exclude: vec![HashedRegex::new("localhost").unwrap()],
+                timeout: Some(30),
+                dns_cache_ttl: Some(60),
+                image_content_type: Some(WarningPolicy::Warn),
+                cross_domain_redirects: Some(WarningPolicy::Warn),
+                asset_content_type: Some(WarningPolicy::Warn),
+                outdated_tls: Some(WarningPolicy::Warn),
+                certificate_expiry: Some(14),
+            },
+            markdown: MarkdownConfig {
+                strikethrough: true,
+                tasklists: true,
+                smart_punctuation: true,
+                autolinks: true,
+                ..Default::default()
+            },
+            slugs: SlugConfig {
+                style: SlugStyle::Github,
+                heading_attributes: true,
+                replacements: vec![(String::from("&"), String::from("and"))]
+                    .into_iter()
+                    .collect(),
+            },
+            overrides: vec![ChapterOverride {
+                path: String::from("src/appendix/**"),
+                severity: Some(WarningPolicy::Warn),
+                exclude: vec![
+                    HashedRegex::new(r"generated\.example\.com").unwrap()
+                ],
+                follow_web_links: Some(false),
+            }],
+            github_review: Some(GithubReview {
+                repository: String::from("owner/repo"),
+                pull_request: 42,
+            }),
         };
 
         let got: Config = toml::from_str(CONFIG).unwrap();
@@ -325,6 +1902,273 @@ https = ["accept: html/text", "authorization: Basic $TOKEN"]
         assert_eq!(reserialized, CONFIG);
     }
 
+    #[test]
+    fn cached_results_must_be_fresh_enough() {
+        let mut cfg = Config {
+            cache_timeout: 3600,
+            respect_cache_control: true,
+            max_cache_timeout: Some(604800),
+            ..Default::default()
+        };
+        assert_eq!(cfg.longest_cache_timeout(), Duration::from_secs(604800));
+
+        cfg.require_fresh_within = Some(86400);
+        assert_eq!(cfg.longest_cache_timeout(), Duration::from_secs(86400));
+
+        // it never makes results last longer
+        cfg.respect_cache_control = false;
+        assert_eq!(cfg.longest_cache_timeout(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn exclusions_are_specific_to_each_category() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+
+        // the flat `exclude` list applies to everything
+        assert!(cfg.should_skip("https://google.com/"));
+        assert!(cfg.should_skip("./google.com.md"));
+        // but these only apply to their own category
+        assert!(cfg.should_skip("./manual.pdf"));
+        assert!(!cfg.should_skip("https://example.com/manual.pdf"));
+        assert!(cfg.should_skip("http://localhost:8080/"));
+        assert!(!cfg.should_skip("./localhost.md"));
+    }
+
+    #[test]
+    fn skip_intentional_schemes() {
+        let cfg = Config {
+            skip_schemes: vec![String::from("irc"), String::from("matrix:")],
+            ..Default::default()
+        };
+
+        assert_eq!(cfg.skipped_scheme("irc://irc.libera.chat/"), Some("irc"));
+        assert_eq!(cfg.skipped_scheme("IRC://irc.libera.chat/"), Some("IRC"));
+        assert!(cfg.should_skip("matrix:r/rust:matrix.org"));
+        assert!(!cfg.should_skip("ircs://irc.libera.chat/"));
+        assert!(!cfg.should_skip("./irc:notes.md"));
+        assert!(!cfg.should_skip("https://example.com/irc:1"));
+
+        let unrecognised = |link| cfg.unrecognised_scheme(link);
+        assert_eq!(unrecognised("FTP://example.com/").unwrap(), "ftp");
+        assert_eq!(unrecognised("irc://irc.libera.chat/"), None);
+        assert_eq!(unrecognised("HTTPS://example.com/"), None);
+        assert_eq!(unrecognised("mailto:me@example.com"), None);
+        assert_eq!(unrecognised("./chapter_1.md"), None);
+    }
+
+    #[test]
+    fn skip_anchors_by_link_or_by_name() {
+        let cfg = Config {
+            exclude_anchors: vec![
+                HashedRegex::new(r"swagger\.html").unwrap(),
+                HashedRegex::new("^operations-").unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        assert!(cfg.should_skip_anchor("./api/swagger.html#tag", "tag"));
+        assert!(
+            cfg.should_skip_anchor("./api.md#operations-42", "operations-42")
+        );
+        assert!(!cfg.should_skip_anchor("./api.md#usage", "usage"));
+    }
+
+    #[test]
+    fn strip_queries_and_fragments() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        let inputs = vec![
+            (
+                "https://api.example.com/v1?key=abc#intro",
+                Some("https://api.example.com/v1#intro"),
+            ),
+            (
+                "https://spa.example.com/?page=2#/users",
+                Some("https://spa.example.com/?page=2"),
+            ),
+            ("https://api.example.com/v1", None),
+            ("https://example.com/?key=abc#intro", None),
+        ];
+
+        for (link, should_be) in inputs {
+            assert_eq!(cfg.strip(link).as_deref(), should_be, "{}", link);
+        }
+        assert!(
+            cfg.should_skip_anchor("https://spa.example.com/#/users", "/users")
+        );
+    }
+
+    #[test]
+    fn anchors_generated_at_runtime_can_be_listed() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+
+        let known = |link: &str| {
+            let anchor = link.split_once('#').unwrap().1;
+            cfg.is_known_anchor(link, anchor)
+        };
+
+        assert!(known("./swagger.html#/pets/listPets"));
+        assert!(!known("./swagger.html#/pets/addPet"));
+        assert!(!known("./other.html#/pets/listPets"));
+    }
+
+    #[test]
+    fn detect_forbidden_http_links() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+
+        assert!(cfg.is_forbidden_http("http://example.com/"));
+        assert!(cfg.is_forbidden_http("HTTP://example.com/"));
+        assert!(!cfg.is_forbidden_http("https://example.com/"));
+        assert!(!cfg.is_forbidden_http("http://localhost:8080/"));
+        assert!(!cfg.is_forbidden_http("./http.md"));
+    }
+
+    #[test]
+    fn detect_private_network_links() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        let inputs = vec![
+            ("http://localhost:8080/", true),
+            ("http://app.localhost/", true),
+            ("http://127.0.0.1/", true),
+            ("http://10.0.0.1/", true),
+            ("https://172.16.5.4/", true),
+            ("https://192.168.1.10:8000/admin", true),
+            ("http://[::1]/", true),
+            ("http://localhost:3000/intentional-example", false),
+            ("https://172.32.0.1/", false),
+            ("https://example.com/localhost", false),
+            ("./localhost.md", false),
+        ];
+
+        for (link, should_be) in inputs {
+            assert_eq!(
+                cfg.is_private_network_link(link),
+                should_be,
+                "{}",
+                link
+            );
+        }
+    }
+
+    #[test]
+    fn detect_links_to_denied_domains() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(cfg.denied_domain("https://bit.ly/abc123"), Some("bit.ly"));
+        assert_eq!(cfg.denied_domain("http://BIT.LY./abc123"), Some("bit.ly"));
+        assert_eq!(
+            cfg.denied_domain("https://eu.wiki.internal.corp/page"),
+            Some("wiki.internal.corp")
+        );
+        assert_eq!(cfg.denied_domain("https://rabbit.ly/"), None);
+        assert_eq!(cfg.denied_domain("https://example.com/bit.ly"), None);
+        assert_eq!(cfg.denied_domain("./bit.ly.md"), None);
+
+        // internationalized domains match however they're written
+        let cfg = Config {
+            deny_domains: vec![String::from("bücher.example")],
+            ..Default::default()
+        };
+        assert!(cfg.denied_domain("https://BÜCHER.example/").is_some());
+        assert!(cfg.denied_domain("https://xn--bcher-kva.example/").is_some());
+    }
+
+    #[test]
+    fn rewrite_links_matching_a_rule() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(
+            cfg.rewrite("https://internal.corp/docs/index.html")
+                .unwrap(),
+            "https://mirror.example.com/docs/index.html"
+        );
+        assert_eq!(cfg.rewrite("https://example.com/"), None);
+    }
+
+    #[test]
+    fn only_follow_web_links_matching_a_pattern() {
+        let pattern = HashedRegex::new(r"^https://(www\.)?example\.com/");
+        let cfg = Config {
+            follow_web_links: false,
+            follow_web_links_matching: vec![pattern.unwrap()],
+            ..Default::default()
+        };
+
+        assert!(cfg.should_follow_web_link("https://example.com/index.html"));
+        assert!(cfg.should_follow_web_link("https://www.example.com/"));
+        assert!(!cfg.should_follow_web_link("https://google.com/"));
+    }
+
+    #[test]
+    fn broken_links_are_errors_by_default() {
+        let cfg = Config::default();
+
+        assert_eq!(
+            cfg.broken_link_policy("https://example.com/"),
+            WarningPolicy::Error
+        );
+        assert_eq!(cfg.broken_link_policy("./foo.md"), WarningPolicy::Error);
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(
+            cfg.broken_link_policy("https://example.com/"),
+            WarningPolicy::Error
+        );
+        assert_eq!(cfg.broken_link_policy("./foo.md"), WarningPolicy::Warn);
+    }
+
+    #[test]
+    fn expand_the_cache_file() {
+        let root = Path::new("/path/to/book");
+        let inputs = vec![
+            ("cache.json", root.join("cache.json")),
+            ("/tmp/cache.json", PathBuf::from("/tmp/cache.json")),
+        ];
+
+        for (raw, should_be) in inputs {
+            let cfg = Config {
+                cache_file: Some(raw.to_string()),
+                ..Default::default()
+            };
+
+            let got = cfg.resolve_cache_file(root).unwrap();
+
+            assert_eq!(got, Some(should_be), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn expand_environment_variables_and_the_home_directory() {
+        let env = HashMap::from_iter(vec![
+            (String::from("HOME"), String::from("/home/me")),
+            (String::from("CACHE_DIR"), String::from("/var/cache")),
+        ]);
+
+        let got = expand_path("~/.cache/linkcheck.json", &env).unwrap();
+        assert_eq!(got, Path::new("/home/me/.cache/linkcheck.json"));
+        let got = expand_path("$CACHE_DIR/linkcheck.json", &env).unwrap();
+        assert_eq!(got, Path::new("/var/cache/linkcheck.json"));
+        let got = expand_path("~foo", &env).unwrap();
+        assert_eq!(got, PathBuf::from("~foo"));
+        assert!(expand_path("$MISSING/linkcheck.json", &env).is_err());
+        assert!(expand_path("~/linkcheck.json", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn generate_a_json_schema() {
+        let schema = schemars::schema_for!(Config);
+        let schema = serde_json::to_value(&schema).unwrap();
+
+        let properties = &schema["properties"];
+        assert_eq!(properties["follow-web-links"]["type"], "boolean");
+        assert_eq!(
+            properties["exclude"]["items"]["$ref"],
+            "#/definitions/Regex"
+        );
+        assert!(properties["web"].is_object());
+        assert!(schema["definitions"]["WarningPolicy"].is_object());
+    }
+
     #[test]
     fn interpolation() {
         std::env::set_var("SUPER_SECRET_TOKEN", "abcdefg123456");
@@ -1,63 +1,115 @@
-use crate::{Config, HashedRegex};
-use codespan::Files;
-use http::header::{HeaderMap, HeaderName, HeaderValue};
-use linkcheck::{
-    validation::{Cache, Options},
-    Link,
+use crate::{
+    dns::DnsCache,
+    suggested_rewrites::RedirectRecorder,
+    throttle::{Throttle, DEFAULT_CONCURRENCY},
+    validate::LocalFiles,
+    Cache, ChapterOverride, Config, HashedRegex, HttpClient,
 };
+use codespan::FileId;
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use linkcheck::Link;
 use reqwest::{Client, Url};
 use std::{
-    path::Path,
+    collections::{BTreeMap, HashMap},
     sync::{Mutex, MutexGuard},
+    time::Duration,
 };
+use tokio::sync::watch;
 
-/// The [`linkcheck::validation::Context`].
+/// Everything needed while checking links.
+///
+/// This does the job of a [`linkcheck::validation::Context`] without
+/// implementing it, because that needs a [`linkcheck::validation::Options`]
+/// which can't be sent between threads.
 #[derive(Debug)]
 pub struct Context<'a> {
     pub(crate) cfg: &'a Config,
-    pub(crate) src_dir: &'a Path,
     pub(crate) cache: Mutex<Cache>,
-    pub(crate) files: &'a Files<String>,
     pub(crate) client: Client,
-    pub(crate) filesystem_options: Options,
+    /// What web links are actually checked with.
+    pub(crate) http: &'a dyn HttpClient,
+    /// Looks up hosts ahead of time when requests go through the default
+    /// client (see [`crate::WebConfig::dns_cache_ttl`]).
+    pub(crate) dns_cache: Option<&'a DnsCache>,
+    /// The redirects followed while checking web links.
+    pub(crate) redirects: RedirectRecorder,
+    /// The `Content-Type` each web link was served with, when
+    /// [`crate::WebConfig::image_content_type`] needs them.
+    pub(crate) content_types: Mutex<BTreeMap<String, String>>,
+    /// Used to check links to local files.
+    pub(crate) local: LocalFiles<'a>,
     pub(crate) interpolated_headers:
         Vec<(HashedRegex, Vec<(HeaderName, HeaderValue)>)>,
+    /// Set when [`Config::fail_fast`] is enabled and a broken link was found.
+    pub(crate) failed: watch::Sender<bool>,
+    /// How many requests each host can handle at once.
+    pub(crate) throttle: Throttle,
+    /// The [`Config::overrides`] for each chapter which has any.
+    pub(crate) chapter_overrides: HashMap<FileId, Vec<&'a ChapterOverride>>,
 }
 
-impl<'a> linkcheck::validation::Context for Context<'a> {
-    fn client(&self) -> &Client { &self.client }
+impl<'a> Context<'a> {
+    /// Stop checking links (see [`Config::fail_fast`]).
+    pub(crate) fn fail(&self) { self.failed.send_replace(true); }
+
+    pub(crate) fn has_failed(&self) -> bool { *self.failed.borrow() }
 
-    fn filesystem_options(&self) -> &Options { &self.filesystem_options }
+    /// The [`Config::overrides`] for the chapter a link came from.
+    pub(crate) fn overrides_for(&self, link: &Link) -> &[&'a ChapterOverride] {
+        self.chapter_overrides
+            .get(&link.file)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
 
-    fn cache(&self) -> Option<MutexGuard<Cache>> {
-        Some(self.cache.lock().expect("Lock was poisoned"))
+    /// The results from previous runs (see [`Cache`]).
+    pub(crate) fn lock_cache(&self) -> MutexGuard<'_, Cache> {
+        tracing::trace!("Accessing the cache");
+        self.cache.lock().expect("Lock was poisoned")
     }
 
-    fn should_ignore(&self, link: &Link) -> bool {
-        if !self.cfg.follow_web_links {
-            if let Ok(_) = link.href.parse::<Url>() {
-                return true;
+    /// Wait until [`Context::fail()`] is called.
+    pub(crate) async fn failure(&self) {
+        let mut failed = self.failed.subscribe();
+
+        while !*failed.borrow() {
+            if failed.changed().await.is_err() {
+                // the sender is in self, so this can't happen
+                break;
             }
         }
+    }
 
-        self.cfg
-            .exclude
-            .iter()
-            .any(|re| re.find(&link.href).is_some())
+    /// How many links are checked at once.
+    pub(crate) fn concurrency(&self) -> usize { DEFAULT_CONCURRENCY }
+
+    /// The longest a cached result can be used for.
+    pub(crate) fn cache_timeout(&self) -> Duration {
+        self.cfg.longest_cache_timeout()
     }
 
-    fn url_specific_headers(&self, url: &Url) -> HeaderMap {
-        let url = url.to_string();
-        let mut headers = HeaderMap::new();
+    /// Should this link be skipped instead of checked?
+    pub(crate) fn should_ignore(&self, link: &Link) -> bool {
+        let overrides = self.overrides_for(link);
+        if overrides.iter().any(|o| o.excludes(&link.href)) {
+            return true;
+        }
 
-        for (pattern, matching_headers) in &self.interpolated_headers {
-            if pattern.find(&url).is_some() {
-                for (name, value) in matching_headers {
-                    headers.insert(name.clone(), value.clone());
-                }
-            }
+        if link.href.parse::<Url>().is_ok()
+            && !self.cfg.should_follow_web_link_in(&link.href, overrides)
+        {
+            return true;
         }
 
-        headers
+        // these are reported separately, depending on `unknown-scheme`
+        self.cfg.should_skip(&link.href)
+            || self.cfg.unrecognised_scheme(&link.href).is_some()
+    }
+
+    /// The extra headers to send with a request to `url`.
+    pub(crate) fn url_specific_headers(&self, url: &Url) -> HeaderMap {
+        // this gets called right before each request is sent
+        tracing::debug!(%url, "Sending a request");
+        crate::http_client::headers_for(&self.interpolated_headers, url)
     }
 }
@@ -0,0 +1,379 @@
+use crate::{http_client::describe, Config, HttpClient};
+use linkcheck::{
+    validation::{InvalidLink, Outcomes, Reason},
+    Link,
+};
+use reqwest::{Method, Request, StatusCode, Url};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+/// The crates.io API only allows crawlers to make one request per second.
+///
+/// See <https://crates.io/data-access> for more.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A link to `docs.rs` or `crates.io` which pins a version of a crate that
+/// probably shouldn't be linked to any more.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrateProblem {
+    /// The link.
+    pub link: Link,
+    /// The crate's name.
+    pub name: String,
+    /// The version being linked to.
+    pub version: String,
+    /// What is wrong with the version.
+    pub kind: CrateProblemKind,
+}
+
+/// Why a pinned crate version (see [`CrateProblem`]) shouldn't be linked to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum CrateProblemKind {
+    /// The version was yanked.
+    Yanked,
+    /// The version isn't semver-compatible with the latest release.
+    Outdated {
+        /// The latest stable version.
+        latest: String,
+    },
+}
+
+impl Display for CrateProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            CrateProblemKind::Yanked => write!(
+                f,
+                "Version {} of the \"{}\" crate was yanked",
+                self.version, self.name
+            ),
+            CrateProblemKind::Outdated { ref latest } => write!(
+                f,
+                "Version {} of the \"{}\" crate is outdated (the latest is {})",
+                self.version, self.name, latest
+            ),
+        }
+    }
+}
+
+/// The crate (and version, if there is one) a link to `docs.rs` or
+/// `crates.io` refers to.
+#[derive(Debug, Clone, PartialEq)]
+struct CrateLink {
+    name: String,
+    version: Option<Version>,
+}
+
+/// Figure out which crate a link to `docs.rs` or `crates.io` points to.
+fn crate_link(link: &str) -> Option<CrateLink> {
+    let url = Url::parse(link).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let segments: Vec<&str> =
+        url.path_segments()?.filter(|s| !s.is_empty()).collect();
+
+    let (name, version) = match (url.host_str()?, segments.as_slice()) {
+        ("crates.io", ["crates", name, rest @ ..]) => (*name, rest.first()),
+        ("docs.rs", ["crate", name, rest @ ..]) => (*name, rest.first()),
+        // everything else under docs.rs/ is a crate's documentation, apart
+        // from a couple of docs.rs's own pages
+        ("docs.rs", [name, rest @ ..])
+            if !["releases", "about", "-"].contains(name) =>
+        {
+            (*name, rest.first())
+        },
+        _ => return None,
+    };
+
+    let is_crate_name = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !is_crate_name {
+        return None;
+    }
+
+    Some(CrateLink {
+        name: name.to_string(),
+        // "latest" and version requirements (e.g. "^1") don't pin anything
+        version: version.and_then(|v| Version::parse(v).ok()),
+    })
+}
+
+/// The bits of the crates.io API's response we care about.
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+    versions: Vec<VersionInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateInfo {
+    max_stable_version: Option<String>,
+    max_version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionInfo {
+    num: String,
+    yanked: bool,
+}
+
+/// Look up every crate linked to from `docs.rs` or `crates.io` in the
+/// registry (see [`Config::check_crates`]).
+///
+/// Links to crates or versions which don't exist are moved from
+/// `outcomes.valid` to `outcomes.invalid`, because `docs.rs` and `crates.io`
+/// are happy to serve a page for them anyway.
+pub(crate) async fn check_crates(
+    outcomes: &mut Outcomes,
+    cfg: &Config,
+    http: &dyn HttpClient,
+) -> Vec<CrateProblem> {
+    let mut registry: BTreeMap<String, Lookup> = BTreeMap::new();
+
+    for link in &outcomes.valid {
+        if let Some(crate_link) = crate_link(&link.href) {
+            registry.entry(crate_link.name).or_insert(Lookup::Unknown);
+        }
+    }
+
+    // each lookup gets its own slot, so a slow response doesn't hold up the
+    // ones after it
+    let start = tokio::time::Instant::now();
+    let lookups = registry.keys().enumerate().map(|(ix, name)| async move {
+        tokio::time::sleep_until(start + REQUEST_INTERVAL * ix as u32).await;
+        (name.clone(), lookup(http, cfg, name).await)
+    });
+    for (name, info) in futures::future::join_all(lookups).await {
+        registry.insert(name, info);
+    }
+
+    let mut problems = Vec::new();
+    let valid = std::mem::take(&mut outcomes.valid);
+
+    for link in valid {
+        let crate_link = match crate_link(&link.href) {
+            Some(crate_link) => crate_link,
+            None => {
+                outcomes.valid.push(link);
+                continue;
+            },
+        };
+        let info = match registry.get(&crate_link.name) {
+            Some(Lookup::Found(info)) => info,
+            Some(Lookup::Missing) => {
+                outcomes.invalid.push(missing_crate(link, crate_link));
+                continue;
+            },
+            // we couldn't ask the registry, so give it the benefit of the
+            // doubt
+            _ => {
+                outcomes.valid.push(link);
+                continue;
+            },
+        };
+        let version = match crate_link.version {
+            Some(ref version) => version,
+            None => {
+                outcomes.valid.push(link);
+                continue;
+            },
+        };
+
+        match check_version(info, version) {
+            Ok(kind) => {
+                problems.extend(kind.map(|kind| CrateProblem {
+                    link: link.clone(),
+                    name: crate_link.name.clone(),
+                    version: version.to_string(),
+                    kind,
+                }));
+                outcomes.valid.push(link);
+            },
+            Err(()) => {
+                outcomes.invalid.push(missing_crate(link, crate_link));
+            },
+        }
+    }
+
+    problems
+}
+
+/// What the registry told us about a crate.
+#[derive(Debug)]
+enum Lookup {
+    Found(CrateResponse),
+    Missing,
+    /// The request failed. This is logged and otherwise ignored, so a
+    /// crates.io outage doesn't break everyone's builds.
+    Unknown,
+}
+
+async fn lookup(http: &dyn HttpClient, cfg: &Config, name: &str) -> Lookup {
+    let result = async {
+        let url: Url =
+            format!("https://crates.io/api/v1/crates/{}", name).parse()?;
+        let request = Request::new(Method::GET, url);
+        let response = crate::http_client::send(http, cfg, request)
+            .await
+            .map_err(|reason| anyhow::Error::msg(describe(&reason)))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Lookup::Missing);
+        }
+        let body = response.error_for_status()?.text().await?;
+        Ok::<_, anyhow::Error>(Lookup::Found(serde_json::from_str(&body)?))
+    }
+    .await;
+
+    result.unwrap_or_else(|e| {
+        tracing::warn!("Unable to look up the \"{}\" crate: {}", name, e);
+        Lookup::Unknown
+    })
+}
+
+/// Is there anything wrong with this version of the crate? Returns `Err` if
+/// the version doesn't exist.
+fn check_version(
+    info: &CrateResponse,
+    version: &Version,
+) -> Result<Option<CrateProblemKind>, ()> {
+    let found = info
+        .versions
+        .iter()
+        .find(|v| Version::parse(&v.num).ok().as_ref() == Some(version))
+        .ok_or(())?;
+    if found.yanked {
+        return Ok(Some(CrateProblemKind::Yanked));
+    }
+
+    let latest = info
+        .krate
+        .max_stable_version
+        .as_deref()
+        .unwrap_or(&info.krate.max_version);
+    let compatible = VersionReq::parse(&format!("^{}", version))
+        .ok()
+        .zip(Version::parse(latest).ok())
+        .is_none_or(|(req, latest)| req.matches(&latest));
+
+    if compatible {
+        Ok(None)
+    } else {
+        Ok(Some(CrateProblemKind::Outdated {
+            latest: latest.to_string(),
+        }))
+    }
+}
+
+fn missing_crate(link: Link, crate_link: CrateLink) -> InvalidLink {
+    let reason = Reason::Io(std::io::Error::other(MissingCrate {
+        name: crate_link.name,
+        version: crate_link.version.map(|v| v.to_string()),
+    }));
+
+    InvalidLink { link, reason }
+}
+
+/// An error that is emitted when a link to `docs.rs` or `crates.io` refers to
+/// a crate (or version) which doesn't exist.
+#[derive(Debug)]
+pub struct MissingCrate {
+    /// The crate's name.
+    pub name: String,
+    /// The version, if the link pinned one.
+    pub version: Option<String>,
+}
+
+impl Display for MissingCrate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(ref version) => write!(
+                f,
+                "Version {} of the \"{}\" crate doesn't exist",
+                version, self.name
+            ),
+            None => write!(f, "The \"{}\" crate doesn't exist", self.name),
+        }
+    }
+}
+
+impl std::error::Error for MissingCrate {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_the_crate_being_linked_to() {
+        let version = |v: &str| Some(Version::parse(v).unwrap());
+        let inputs = vec![
+            ("https://docs.rs/serde", Some(("serde", None))),
+            (
+                "https://docs.rs/serde/1.0.100/serde/trait.Serialize.html",
+                Some(("serde", version("1.0.100"))),
+            ),
+            ("https://docs.rs/serde/latest/serde/", Some(("serde", None))),
+            ("https://docs.rs/serde/^1/serde/", Some(("serde", None))),
+            (
+                "https://docs.rs/crate/tokio/1.0.0",
+                Some(("tokio", version("1.0.0"))),
+            ),
+            (
+                "https://crates.io/crates/mdbook-linkcheck",
+                Some(("mdbook-linkcheck", None)),
+            ),
+            (
+                "https://crates.io/crates/regex/1.5.4",
+                Some(("regex", version("1.5.4"))),
+            ),
+            ("https://docs.rs/releases/queue", None),
+            ("https://docs.rs/", None),
+            ("https://crates.io/", None),
+            ("https://example.com/crates/serde", None),
+        ];
+
+        for (link, should_be) in inputs {
+            let should_be = should_be.map(|(name, version)| CrateLink {
+                name: name.to_string(),
+                version,
+            });
+            assert_eq!(crate_link(link), should_be, "{}", link);
+        }
+    }
+
+    #[test]
+    fn check_pinned_versions() {
+        let info: CrateResponse = serde_json::from_str(
+            r#"{
+                "crate": { "max_stable_version": "1.2.0", "max_version": "2.0.0-beta" },
+                "versions": [
+                    { "num": "2.0.0-beta", "yanked": false },
+                    { "num": "1.2.0", "yanked": false },
+                    { "num": "1.1.0", "yanked": true },
+                    { "num": "0.9.0", "yanked": false }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let check =
+            |v: &str| check_version(&info, &Version::parse(v).unwrap());
+
+        assert_eq!(check("1.2.0"), Ok(None));
+        assert_eq!(check("1.1.0"), Ok(Some(CrateProblemKind::Yanked)));
+        assert_eq!(
+            check("0.9.0"),
+            Ok(Some(CrateProblemKind::Outdated {
+                latest: String::from("1.2.0")
+            }))
+        );
+        assert_eq!(check("1.3.0"), Err(()));
+    }
+}
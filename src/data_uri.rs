@@ -0,0 +1,158 @@
+use linkcheck::validation::Reason;
+use std::fmt::{self, Display, Formatter};
+
+/// Is this a `data:` URI (e.g. `data:image/png;base64,iVBORw0KGgo...`)?
+pub(crate) fn is_data_uri(link: &str) -> bool {
+    link.get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Make sure a `data:` URI is well-formed, according to
+/// [RFC 2397](https://www.rfc-editor.org/rfc/rfc2397) and the way browsers
+/// decode base64.
+pub(crate) fn check_data_uri(link: &str) -> Result<(), Reason> {
+    parse(link).map_err(|reason| {
+        Reason::Io(std::io::Error::other(MalformedDataUri {
+            reason: reason.to_string(),
+        }))
+    })
+}
+
+fn parse(link: &str) -> Result<(), &'static str> {
+    let rest = &link[5..];
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or("there is no \",\" separating the media type from the data")?;
+
+    let mut params = header.split(';');
+    let media_type = params.next().unwrap_or_default().trim();
+    if !media_type.is_empty() {
+        let (kind, subtype) = media_type
+            .split_once('/')
+            .ok_or("the media type should look like \"type/subtype\"")?;
+        if !is_token(kind) || !is_token(subtype) {
+            return Err("the media type should look like \"type/subtype\"");
+        }
+    }
+
+    let params: Vec<&str> = params.map(str::trim).collect();
+    let is_base64 = params
+        .last()
+        .is_some_and(|last| last.eq_ignore_ascii_case("base64"));
+    let attributes = if is_base64 {
+        &params[..params.len() - 1]
+    } else {
+        &params[..]
+    };
+    if attributes.iter().any(|param| !param.contains('=')) {
+        return Err("parameters should look like \"attribute=value\"");
+    }
+
+    if is_base64 {
+        let decoded = percent_decode(data);
+        if !is_forgiving_base64(&decoded) {
+            return Err("the data isn't valid base64");
+        }
+    }
+
+    Ok(())
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+}
+
+pub(crate) fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+
+    decoded
+}
+
+/// The "forgiving-base64 decode" algorithm from the
+/// [WHATWG infra spec](https://infra.spec.whatwg.org/#forgiving-base64-decode),
+/// which is what browsers use for `data:` URIs.
+fn is_forgiving_base64(data: &[u8]) -> bool {
+    let mut data: Vec<u8> = data
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+
+    if data.len() % 4 == 0 {
+        for _ in 0..2 {
+            if data.last() == Some(&b'=') {
+                data.pop();
+            }
+        }
+    }
+
+    data.len() % 4 != 1
+        && data
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
+/// An error that is emitted when a `data:` URI is malformed.
+#[derive(Debug)]
+pub struct MalformedDataUri {
+    /// What was wrong with it.
+    pub reason: String,
+}
+
+impl Display for MalformedDataUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Malformed data: URI, {}", self.reason)
+    }
+}
+
+impl std::error::Error for MalformedDataUri {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_malformed_data_uris() {
+        let inputs = vec![
+            ("data:,Hello%2C%20World%21", true),
+            ("data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==", true),
+            ("DATA:image/png;BASE64,iVBORw0KGgo", true),
+            ("data:text/html;charset=utf-8,%3Ch1%3EHi%3C%2Fh1%3E", true),
+            ("data:;base64,SGVs%0AbG8=", true),
+            ("data:text/plain", false),
+            ("data:text,Hello", false),
+            ("data:text/plain;charset,Hello", false),
+            ("data:image/png;base64,not base64!", false),
+            ("data:image/png;base64,SGVsbG8sI", false),
+        ];
+
+        for (link, should_be) in inputs {
+            assert!(is_data_uri(link));
+            assert_eq!(check_data_uri(link).is_ok(), should_be, "{}", link);
+        }
+
+        assert!(!is_data_uri("./data.md"));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{Config, HttpClient};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use linkcheck::validation::Reason;
+use reqwest::{Client, Request, Response, Url};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long a host's addresses are remembered when
+/// [`crate::WebConfig::dns_cache_ttl`] isn't set.
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// A host's addresses being looked up (or already found), along with a
+/// [`Client`] which connects to them without asking the resolver again.
+type Lookup = Shared<BoxFuture<'static, (Option<Client>, Instant)>>;
+
+/// Finds the addresses for a host and port.
+type Resolver = fn(&str, u16) -> io::Result<Vec<SocketAddr>>;
+
+/// Sends requests to each host using a [`Client`] which already knows the
+/// host's addresses, so big books only resolve each host name once instead
+/// of for every connection.
+///
+/// The system resolver doesn't say how long each record may be cached for,
+/// so addresses are looked up again after
+/// [`crate::WebConfig::dns_cache_ttl`] seconds. Hosts are looked up in the
+/// background as soon as [`DnsCache::prefetch()`] sees them, so the answer
+/// is usually ready before there is room to send the request.
+pub(crate) struct DnsCache {
+    cfg: Config,
+    resolver: Resolver,
+    ttl: Duration,
+    /// Used when a host couldn't be looked up, so the request fails with
+    /// the usual error.
+    fallback: Client,
+    hosts: Mutex<HashMap<(String, u16), Lookup>>,
+}
+
+impl DnsCache {
+    pub(crate) fn new(cfg: &Config, fallback: Client) -> Self {
+        let ttl = cfg
+            .web
+            .dns_cache_ttl
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TTL);
+
+        DnsCache {
+            cfg: cfg.clone(),
+            resolver: system_resolver,
+            ttl,
+            fallback,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Is the cache turned on (see [`crate::WebConfig::dns_cache_ttl`])?
+    pub(crate) fn is_enabled(&self) -> bool { !self.ttl.is_zero() }
+
+    /// Start looking up the link's host in the background, unless it is
+    /// already known.
+    pub(crate) fn prefetch(&self, url: &Url) {
+        if let Some(lookup) = self.lookup(url) {
+            tokio::spawn(lookup);
+        }
+    }
+
+    /// Get the lookup for a URL's host, starting a new one if it hasn't been
+    /// looked up yet or the answer has expired.
+    fn lookup(&self, url: &Url) -> Option<Lookup> {
+        let host = url.host_str()?;
+        // IP addresses don't need resolving
+        if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+            return None;
+        }
+        let key = (host.to_string(), url.port_or_known_default()?);
+
+        let mut hosts = self.hosts.lock().expect("Lock was poisoned");
+        let expired = match hosts.get(&key).and_then(|lookup| lookup.peek()) {
+            Some((_, resolved_at)) => resolved_at.elapsed() >= self.ttl,
+            None => !hosts.contains_key(&key),
+        };
+        if expired {
+            tracing::debug!(host = %key.0, "Looking up the host's addresses");
+            hosts.insert(key.clone(), self.resolve(key.0.clone(), key.1));
+        }
+
+        hosts.get(&key).cloned()
+    }
+
+    fn resolve(&self, host: String, port: u16) -> Lookup {
+        let builder = self.cfg.client_builder();
+        let resolver = self.resolver;
+
+        async move {
+            let name = host.clone();
+            let addrs =
+                tokio::task::spawn_blocking(move || resolver(&name, port))
+                    .await;
+
+            let client = match addrs {
+                Ok(Ok(addrs)) if !addrs.is_empty() => {
+                    builder.resolve_to_addrs(&host, &addrs).build().ok()
+                },
+                Ok(Err(e)) => {
+                    tracing::debug!(%host, error = %e, "Unable to look up");
+                    None
+                },
+                _ => None,
+            };
+
+            (client, Instant::now())
+        }
+        .boxed()
+        .shared()
+    }
+
+    async fn client_for(&self, url: &Url) -> Client {
+        let lookup = match self.lookup(url) {
+            Some(lookup) => lookup,
+            None => return self.fallback.clone(),
+        };
+
+        match lookup.await {
+            (Some(client), _) => client,
+            (None, _) => self.fallback.clone(),
+        }
+    }
+}
+
+impl HttpClient for DnsCache {
+    fn send(
+        &self,
+        request: Request,
+    ) -> BoxFuture<'_, Result<Response, Reason>> {
+        Box::pin(async move {
+            let client = self.client_for(request.url()).await;
+            HttpClient::send(&client, request).await
+        })
+    }
+}
+
+fn system_resolver(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    (host, port)
+        .to_socket_addrs()
+        .map(|addrs| addrs.collect())
+}
+
+impl Debug for DnsCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let hosts = self.hosts.lock().expect("Lock was poisoned");
+
+        f.debug_struct("DnsCache")
+            .field("ttl", &self.ttl)
+            .field("hosts", &hosts.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WebConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LOOKUPS: AtomicUsize = AtomicUsize::new(0);
+
+    fn counting_resolver(_: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        LOOKUPS.fetch_add(1, Ordering::SeqCst);
+        Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))])
+    }
+
+    #[test]
+    fn each_host_is_only_looked_up_once() {
+        let cfg = Config {
+            web: WebConfig {
+                dns_cache_ttl: Some(3600),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut dns = DnsCache::new(&cfg, cfg.client());
+        dns.resolver = counting_resolver;
+        let url: Url = "http://example.com/".parse().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let resolved_at = runtime.block_on(async {
+            dns.prefetch(&url);
+            dns.prefetch(&url);
+            let (client, resolved_at) = dns.lookup(&url).unwrap().await;
+            assert!(client.is_some());
+            resolved_at
+        });
+
+        assert_eq!(LOOKUPS.load(Ordering::SeqCst), 1);
+        assert_eq!(dns.hosts.lock().unwrap().len(), 1);
+        let (_, again) = runtime.block_on(dns.lookup(&url).unwrap());
+        assert_eq!(again, resolved_at);
+        let ip: Url = "http://127.0.0.1/".parse().unwrap();
+        assert!(dns.lookup(&ip).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    data_uri::percent_decode,
+    web::{is_cached, Source},
+    Context,
+};
+use linkcheck::{
+    validation::{InvalidLink, Outcomes, Reason},
+    Link,
+};
+use regex::Regex;
+use reqwest::{Method, Request, Url};
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+/// The hosts for the DOI resolver.
+const RESOLVERS: &[&str] = &["doi.org", "dx.doi.org", "www.doi.org"];
+
+/// Get the DOI from a link to the `doi.org` resolver (e.g. `10.1000/182` for
+/// `https://doi.org/10.1000/182`).
+pub(crate) fn doi_in_link(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = url.host_str()?.to_lowercase();
+    if !RESOLVERS.contains(&host.as_str()) {
+        return None;
+    }
+
+    let path = url.path().trim_start_matches('/');
+    Some(String::from_utf8_lossy(&percent_decode(path)).into_owned())
+}
+
+/// Does this look like a DOI (`10.<registrant>/<suffix>`)?
+fn is_well_formed(doi: &str) -> bool {
+    Regex::new(r"^10\.[0-9]{4,9}(\.[0-9]+)*/\S+$")
+        .expect("The pattern is valid")
+        .is_match(doi)
+}
+
+/// Check a link to the `doi.org` resolver (see [`crate::Config::check_dois`]).
+///
+/// Malformed DOIs are reported without making a request. Otherwise we ask the
+/// resolver about the DOI without following its redirect, because a
+/// redirect is how it says the DOI exists, and publishers are notorious for
+/// rejecting automated requests.
+pub(crate) async fn check_doi(
+    link: Link,
+    doi: &str,
+    ctx: &Context<'_>,
+) -> (Outcomes, Option<Source>) {
+    let mut outcomes = Outcomes::default();
+
+    if ctx.should_ignore(&link) {
+        outcomes.ignored.push(link);
+        return (outcomes, None);
+    }
+    if !is_well_formed(doi) {
+        let reason = Reason::Io(std::io::Error::other(MalformedDoi {
+            doi: doi.to_string(),
+        }));
+        outcomes.invalid.push(InvalidLink { link, reason });
+        return (outcomes, None);
+    }
+
+    let url: Url = link
+        .href
+        .parse()
+        .expect("doi_in_link() already parsed the URL");
+    if is_cached(&url, ctx) {
+        outcomes.valid.push(link);
+        return (outcomes, Some(Source::Cache));
+    }
+
+    match resolve(url, ctx).await {
+        Ok(()) => outcomes.valid.push(link),
+        Err(reason) => outcomes.invalid.push(InvalidLink { link, reason }),
+    }
+
+    (outcomes, Some(Source::Network))
+}
+
+async fn resolve(url: Url, ctx: &Context<'_>) -> Result<(), Reason> {
+    let mut request = Request::new(Method::HEAD, url.clone());
+    *request.headers_mut() = ctx.url_specific_headers(&url);
+    let result = crate::http_client::send(ctx.http, ctx.cfg, request)
+        .await
+        .and_then(|response| {
+            response.error_for_status().map_err(Reason::from)
+        })
+        .map(|_| ());
+
+    let ttl = Duration::from_secs(ctx.cfg.cache_timeout);
+    ctx.lock_cache()
+        .insert_fresh_for(url, result.is_ok(), ttl.min(ctx.cache_timeout()));
+
+    result
+}
+
+/// An error that is emitted when a link to the DOI resolver doesn't contain
+/// a valid DOI.
+#[derive(Debug)]
+pub struct MalformedDoi {
+    /// The supposed DOI.
+    pub doi: String,
+}
+
+impl Display for MalformedDoi {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" isn't a valid DOI (it should look like \"10.1000/182\")",
+            self.doi
+        )
+    }
+}
+
+impl std::error::Error for MalformedDoi {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use codespan::{Files, Span};
+    use crate::Cache;
+    use std::path::Path;
+
+    #[test]
+    fn find_dois_in_links() {
+        let inputs = vec![
+            ("https://doi.org/10.1000/182", Some("10.1000/182")),
+            (
+                "http://dx.doi.org/10.1002/(SICI)1097-4571%28199806%2949",
+                Some("10.1002/(SICI)1097-4571(199806)49"),
+            ),
+            ("https://DOI.org/", Some("")),
+            ("https://example.com/10.1000/182", None),
+            ("./doi.org/10.1000/182", None),
+        ];
+
+        for (link, should_be) in inputs {
+            assert_eq!(doi_in_link(link).as_deref(), should_be, "{}", link);
+        }
+    }
+
+    #[test]
+    fn detect_malformed_dois() {
+        let inputs = vec![
+            ("10.1000/182", true),
+            ("10.1038/nphys1170", true),
+            ("10.1000.10/abc", true),
+            ("10.1002/(SICI)1097-4571(199806)49", true),
+            ("", false),
+            ("10.1000", false),
+            ("10.12/abc", false),
+            ("11.1000/182", false),
+            ("10.1000/", false),
+        ];
+
+        for (doi, should_be) in inputs {
+            assert_eq!(is_well_formed(doi), should_be, "{}", doi);
+        }
+    }
+
+    #[test]
+    fn malformed_dois_are_reported_without_a_request() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links =
+            vec![Link::new("https://doi.org/not-a-doi", Span::new(0, 1), id)];
+        let cfg = Config {
+            follow_web_links: true,
+            check_dois: true,
+            ..Default::default()
+        };
+
+        let got = crate::validate(
+            &links,
+            &cfg,
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            &mut Cache::default(),
+            &files,
+            &[id],
+            Vec::new(),
+        )
+        .unwrap();
+
+        assert_eq!(got.invalid_links.len(), 1);
+        let msg =
+            crate::validate::most_specific_error_message(&got.invalid_links[0]);
+        assert!(msg.contains("isn't a valid DOI"), "{}", msg);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::FileProvider;
+use linkcheck::validation::Reason;
+use mdbook::book::{Book, BookItem};
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    path::Path,
+};
+
+/// An error that is emitted when a link points to a chapter which is still a
+/// draft (an entry in `SUMMARY.md` without a file, like `- [Future Work]()`),
+/// so there is nothing for the link to go to yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DraftChapter {
+    /// The chapter's name in `SUMMARY.md`.
+    pub name: String,
+    /// The line in `SUMMARY.md` (starting from 1) with the draft entry, if
+    /// it could be found.
+    pub line: Option<usize>,
+}
+
+impl DraftChapter {
+    /// A note pointing at the draft's entry in `SUMMARY.md`.
+    pub(crate) fn note(&self) -> String {
+        match self.line {
+            Some(line) => format!(
+                "\"{}\" is a draft entry on line {} of SUMMARY.md: - [{}]()",
+                self.name, line, self.name
+            ),
+            None => {
+                format!("\"{}\" is a draft entry in SUMMARY.md", self.name)
+            },
+        }
+    }
+}
+
+impl Display for DraftChapter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Link to a draft chapter, \"{}\" hasn't been written yet",
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for DraftChapter {}
+
+/// The book's draft chapters, used to explain why links to their (missing)
+/// files are broken instead of just saying "file not found".
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Drafts {
+    /// Each draft, along with its name ready to compare with a file name.
+    drafts: Vec<(String, DraftChapter)>,
+}
+
+impl Drafts {
+    pub(crate) fn new(
+        book: &Book,
+        src_dir: &Path,
+        files: &dyn FileProvider,
+    ) -> Self {
+        let names: Vec<&str> = book
+            .iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) if ch.is_draft_chapter() => {
+                    Some(ch.name.as_str())
+                },
+                _ => None,
+            })
+            .collect();
+        if names.is_empty() {
+            return Drafts::default();
+        }
+
+        let summary = files
+            .read(&src_dir.join("SUMMARY.md"))
+            .map(|raw| String::from_utf8_lossy(&raw).into_owned())
+            .unwrap_or_default();
+        let drafts = names
+            .into_iter()
+            .map(|name| {
+                let draft = DraftChapter {
+                    name: name.to_string(),
+                    line: line_in_summary(&summary, name),
+                };
+                (squash(name), draft)
+            })
+            .collect();
+
+        Drafts { drafts }
+    }
+
+    /// If a link to a missing file is probably meant for one of the draft
+    /// chapters, say so instead of just "file not found".
+    ///
+    /// A file is assumed to belong to a draft when its name matches the
+    /// draft's title, ignoring case and punctuation (e.g. `future-work.md`
+    /// and `Future Work`).
+    pub(crate) fn explain(&self, reason: Reason, link: &Path) -> Reason {
+        if self.drafts.is_empty() || !reason.file_not_found() {
+            return reason;
+        }
+        let stem = match link.file_stem() {
+            Some(stem) => squash(&stem.to_string_lossy()),
+            None => return reason,
+        };
+
+        match self.drafts.iter().find(|(name, _)| *name == stem) {
+            Some((_, draft)) => Reason::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                draft.clone(),
+            )),
+            None => reason,
+        }
+    }
+}
+
+/// Lowercase a name and drop anything that isn't a letter or digit, so
+/// `Future Work`, `future-work` and `future_work` are all the same.
+fn squash(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Find the line (starting from 1) with a draft's entry, `- [Name]()`.
+fn line_in_summary(summary: &str, name: &str) -> Option<usize> {
+    let entry = format!("[{}](", name);
+
+    summary
+        .lines()
+        .position(|line| {
+            line.match_indices(&entry).any(|(ix, _)| {
+                line[ix + entry.len()..].trim_start().starts_with(')')
+            })
+        })
+        .map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file_provider::in_memory_book, validate::LocalFiles, Checker, Config,
+        FailureReason,
+    };
+    use mdbook::book::Chapter;
+
+    #[test]
+    fn links_to_draft_chapters_are_recognised() {
+        let summary = "# Summary\n\n- [Intro](intro.md)\n- [Future Work]()\n";
+        let intro = "# Intro\n\n[later](./future-work.md) [gone](./gone.md)";
+        let (files, mut book) =
+            in_memory_book(&[("SUMMARY.md", summary), ("intro.md", intro)]);
+        book.push_item(Chapter::new_draft("Future Work", Vec::new()));
+
+        let report = Checker::builder()
+            .source_dir("/book/src")
+            .config(Config::default())
+            .book(book)
+            .file_provider(files)
+            .check()
+            .unwrap();
+
+        let mut broken: Vec<_> = report
+            .outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| {
+                let reason = FailureReason::from(&invalid.reason);
+                (invalid.link.href.as_str(), reason)
+            })
+            .collect();
+        broken.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(
+            broken,
+            vec![
+                ("./future-work.md", FailureReason::DraftChapter {
+                    name: String::from("Future Work"),
+                }),
+                ("./gone.md", FailureReason::FileNotFound),
+            ]
+        );
+        let diags = report.diagnostics();
+        let draft = diags
+            .iter()
+            .find(|diag| diag.message.contains("draft"))
+            .unwrap();
+        assert_eq!(draft.notes, vec![
+            "\"Future Work\" is a draft entry on line 4 of SUMMARY.md: - \
+             [Future Work]()"
+        ]);
+    }
+
+    #[test]
+    fn drafts_are_recognised_as_soon_as_the_link_is_checked() {
+        let summary = "# Summary\n\n- [Intro](intro.md)\n- [Future Work]( )\n";
+        let src_dir = Path::new("/book/src");
+        let (files, mut book) = in_memory_book(&[("SUMMARY.md", summary)]);
+        book.push_item(Chapter::new_draft("Future Work", Vec::new()));
+        let drafts = Drafts::new(&book, src_dir, &files);
+        let local =
+            LocalFiles::new(&Config::default(), src_dir, Vec::new(), &files)
+                .with_drafts(&drafts);
+
+        // this is what fail-fast and streamed failures see
+        let reason = local
+            .check(src_dir, Path::new("future_work.md"))
+            .unwrap_err();
+
+        assert!(reason.file_not_found());
+        assert_eq!(FailureReason::from(&reason), FailureReason::DraftChapter {
+            name: String::from("Future Work"),
+        });
+        assert_eq!(
+            drafts.drafts[0].1.line,
+            Some(4),
+            "The entry is found without a regex"
+        );
+    }
+}
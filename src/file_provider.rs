@@ -0,0 +1,303 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    io::{Error, ErrorKind},
+    path::{Component, Path, PathBuf},
+};
+
+/// Where the files that local links point to are looked up.
+///
+/// By default links are resolved against the [`RealFileSystem`], but a book
+/// which only exists in memory (e.g. content straight from a CMS, or a git
+/// tree object) can be checked by providing an [`InMemoryFiles`] (or your own
+/// implementation) to [`crate::CheckerBuilder::file_provider()`].
+pub trait FileProvider: Debug + Send + Sync {
+    /// Get the canonical version of a path, with any `.` and `..` components
+    /// (and symlinks, if the provider has them) resolved.
+    ///
+    /// This should fail with [`ErrorKind::NotFound`] if nothing exists at
+    /// that path.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error>;
+
+    /// Is there a directory at this path?
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Read a file's contents.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error>;
+
+    /// List the files and directories immediately inside a directory.
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error>;
+
+    /// Read a file's contents as UTF-8 text.
+    fn read_to_string(&self, path: &Path) -> Result<String, Error> {
+        let contents = self.read(path)?;
+        String::from_utf8(contents)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Get the size of a file in bytes, failing if it can't be read.
+    fn file_size(&self, path: &Path) -> Result<u64, Error> {
+        self.read(path).map(|contents| contents.len() as u64)
+    }
+}
+
+/// A [`FileProvider`] which looks at the files on disk.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RealFileSystem;
+
+impl FileProvider for RealFileSystem {
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        dunce::canonicalize(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool { path.is_dir() }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        std::fs::read(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect()
+    }
+
+    fn read_to_string(&self, path: &Path) -> Result<String, Error> {
+        std::fs::read_to_string(path)
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, Error> {
+        // opening the file makes sure we are allowed to read it, without
+        // reading the whole thing
+        let file = std::fs::File::open(path)?;
+        Ok(file.metadata()?.len())
+    }
+}
+
+/// A [`FileProvider`] for books which only exist in memory.
+///
+/// Directories don't need to be added explicitly, because any path which is
+/// the parent of a file is treated as a directory.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InMemoryFiles {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl InMemoryFiles {
+    /// Create an empty set of files.
+    pub fn new() -> Self { InMemoryFiles::default() }
+
+    /// Add a file, replacing anything which was already at that path.
+    pub fn insert<P, C>(&mut self, path: P, contents: C)
+    where
+        P: AsRef<Path>,
+        C: Into<Vec<u8>>,
+    {
+        self.files.insert(normalize(path.as_ref()), contents.into());
+    }
+}
+
+impl FileProvider for InMemoryFiles {
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        let path = normalize(path);
+
+        if self.files.contains_key(&path) || self.is_dir(&path) {
+            Ok(path)
+        } else {
+            Err(ErrorKind::NotFound.into())
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let path = normalize(path);
+
+        self.files
+            .keys()
+            .any(|file| file != &path && file.starts_with(&path))
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| ErrorKind::NotFound.into())
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let path = normalize(path);
+        if !self.is_dir(&path) {
+            return Err(ErrorKind::NotFound.into());
+        }
+
+        let entries: BTreeSet<PathBuf> = self
+            .files
+            .keys()
+            .filter_map(|file| file.strip_prefix(&path).ok())
+            .filter_map(|relative| relative.components().next())
+            .map(|first| path.join(first))
+            .collect();
+
+        Ok(entries.into_iter().collect())
+    }
+}
+
+/// An in-memory book whose source directory is `/book/src`, with a chapter
+/// for each of the files (apart from any `SUMMARY.md`).
+#[cfg(test)]
+pub(crate) fn in_memory_book(
+    chapters: &[(&str, &str)],
+) -> (InMemoryFiles, mdbook::book::Book) {
+    let mut files = InMemoryFiles::new();
+    let mut book = mdbook::book::Book::new();
+
+    for &(path, content) in chapters {
+        files.insert(Path::new("/book/src").join(path), content);
+        if !path.ends_with("SUMMARY.md") {
+            book.push_item(mdbook::book::Chapter::new(
+                path,
+                content.into(),
+                path,
+                Vec::new(),
+            ));
+        }
+    }
+
+    (files, book)
+}
+
+/// Resolve `.` and `..` components without touching the filesystem.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Checker, Config, WarningPolicy};
+
+    #[test]
+    fn in_memory_files_have_implicit_directories() {
+        let mut files = InMemoryFiles::new();
+        files.insert("/book/src/chapter_1.md", "# Chapter 1");
+        files.insert("/book/src/nested/README.md", "");
+
+        assert_eq!(
+            files
+                .canonicalize(Path::new("/book/src/nested/../chapter_1.md"))
+                .unwrap(),
+            Path::new("/book/src/chapter_1.md")
+        );
+        assert!(files.is_dir(Path::new("/book/src/nested/")));
+        assert!(!files.is_dir(Path::new("/book/src/chapter_1.md")));
+        assert_eq!(
+            files
+                .canonicalize(Path::new("/book/missing.md"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+        let size = files.file_size(Path::new("/book/src/chapter_1.md"));
+        assert_eq!(size.unwrap(), 11);
+        assert_eq!(files.read_dir(Path::new("/book/src")).unwrap(), vec![
+            PathBuf::from("/book/src/chapter_1.md"),
+            PathBuf::from("/book/src/nested"),
+        ]);
+    }
+
+    #[test]
+    fn check_a_book_which_only_exists_in_memory() {
+        let chapters = [
+            (
+                "chapter_1.md",
+                "# Chapter 1\n\n[ok](./nested/chapter_2.md#intro) \
+                 [missing](./missing.md) [anchor](nested/chapter_2.html#nope)",
+            ),
+            ("nested/chapter_2.md", "# Intro\n\n[back](../chapter_1.md)"),
+        ];
+        let (files, book) = in_memory_book(&chapters);
+
+        let cfg = Config {
+            check_anchors: true,
+            ..Default::default()
+        };
+
+        let report = Checker::builder()
+            .source_dir("/book/src")
+            .config(cfg)
+            .book(book)
+            .file_provider(files)
+            .check()
+            .unwrap();
+
+        let mut broken: Vec<_> = report
+            .outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| invalid.link.href.as_str())
+            .collect();
+        broken.sort_unstable();
+        assert_eq!(broken, ["./missing.md", "nested/chapter_2.html#nope"]);
+        assert_eq!(report.outcome.valid_links.len(), 2);
+    }
+
+    #[test]
+    fn includes_and_assets_are_read_from_the_file_provider() {
+        let chapter_1 = "# Chapter 1\n\n{{#include code.rs:renamed}}\n\n\
+                         [heading](./chapter_2.md#from-an-include) \
+                         [download](./download.zip) \
+                         [read me](../README.md)";
+        let chapter_2 = "# Chapter 2\n\n{{#include ./part.md}}";
+        let src_dir = Path::new("/book/src");
+        let (mut files, book) = in_memory_book(&[
+            ("chapter_1.md", chapter_1),
+            ("chapter_2.md", chapter_2),
+        ]);
+        files.insert(src_dir.join("part.md"), "## From an Include");
+        files.insert(src_dir.join("code.rs"), "// ANCHOR: main\n");
+        files.insert(src_dir.join("download.zip"), vec![0; 42]);
+        files.insert(src_dir.join("forgotten.md"), "# Forgotten");
+        files.insert("/book/README.md", "# Read Me");
+        let cfg = Config {
+            check_anchors: true,
+            max_asset_size: Some(10),
+            orphan_chapters: Some(WarningPolicy::Warn),
+            extra_files: vec![String::from("../README.md")],
+            ..Default::default()
+        };
+
+        let report = Checker::builder()
+            .source_dir(src_dir)
+            .config(cfg)
+            .book(book)
+            .file_provider(files)
+            .check()
+            .unwrap();
+
+        let outcome = &report.outcome;
+        assert!(outcome.invalid_links.is_empty(), "{:?}", outcome);
+        assert_eq!(outcome.broken_includes.len(), 1);
+        assert_eq!(
+            outcome.broken_includes[0].target,
+            src_dir.join("code.rs")
+        );
+        let too_large: Vec<_> = outcome
+            .asset_problems
+            .iter()
+            .map(|problem| problem.link.href.as_str())
+            .collect();
+        assert_eq!(too_large, ["./download.zip"]);
+        assert_eq!(outcome.orphans, [PathBuf::from("forgotten.md")]);
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{
+    changes::local_target,
+    config::is_web_link,
+    validate::relative_path_to_file,
+    web::Prober,
+    Config, HttpClient,
+};
+use anyhow::{Context as _, Error};
+use codespan::Files;
+use linkcheck::{validation::InvalidLink, Link};
+use reqwest::Url;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// An automatic fix for a link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// The chapter containing the link, relative to the source directory.
+    pub chapter: PathBuf,
+    /// Where the link's href is in the chapter's text.
+    pub range: Range<usize>,
+    /// The original href.
+    pub original: String,
+    /// What the href should be changed to.
+    pub replacement: String,
+    /// Why the link needs to change.
+    pub reason: FixReason,
+}
+
+/// Why a [`Fix`] was suggested.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FixReason {
+    /// The server responded with a `301 Moved Permanently` or
+    /// `308 Permanent Redirect`.
+    PermanentRedirect,
+    /// The file being linked to doesn't exist, but there is a file with a
+    /// similar name elsewhere in the book (e.g. because it was moved).
+    MovedFile,
+}
+
+impl Display for FixReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FixReason::PermanentRedirect => write!(f, "permanent redirect"),
+            FixReason::MovedFile => write!(f, "moved file"),
+        }
+    }
+}
+
+/// Find fixes for web links which permanently redirect somewhere else.
+///
+/// Links whose redirects were already seen while checking (see
+/// [`crate::ValidationOutcome::permanent_redirects`]) don't need another
+/// request.
+pub(crate) async fn redirect_fixes(
+    valid_links: &[Link],
+    known: &BTreeMap<String, String>,
+    cfg: &Config,
+    files: &Files<String>,
+    http: &dyn HttpClient,
+) -> Vec<Fix> {
+    let normalized = |link: &Link| {
+        let url: Url = link.href.parse().ok()?;
+        Some((url.clone(), crate::normalize::normalize(&url).to_string()))
+    };
+    let web_links: Vec<&Link> = valid_links
+        .iter()
+        .filter(|link| is_web_link(&link.href))
+        .collect();
+    let unknown: Vec<Link> = web_links
+        .iter()
+        .filter(|link| {
+            normalized(link).is_some_and(|(_, url)| !known.contains_key(&url))
+        })
+        .map(|link| crate::normalize::normalize_link(link))
+        .collect();
+
+    let prober = Prober::new(cfg, http);
+    prober.head_each(&unknown, |_, _| Some(())).await;
+    let mut destinations = known.clone();
+    destinations.extend(prober.redirects.destinations(&unknown));
+
+    let mut fixes = Vec::new();
+
+    for link in web_links {
+        let (url, normalized) = match normalized(link) {
+            Some(urls) => urls,
+            None => continue,
+        };
+        let mut destination = match destinations
+            .get(&normalized)
+            .and_then(|destination| destination.parse::<Url>().ok())
+        {
+            Some(destination) => destination,
+            None => continue,
+        };
+        // a redirect won't preserve the fragment
+        if destination.fragment().is_none() {
+            destination.set_fragment(url.fragment());
+        }
+        fixes.extend(fix_for(
+            link,
+            files,
+            destination.to_string(),
+            FixReason::PermanentRedirect,
+        ));
+    }
+
+    fixes
+}
+
+/// Find fixes for local links pointing to files which were moved.
+pub(crate) fn moved_file_fixes(
+    invalid_links: &[InvalidLink],
+    chapters: &[PathBuf],
+    files: &Files<String>,
+) -> Vec<Fix> {
+    let mut fixes = Vec::new();
+
+    for invalid in invalid_links {
+        let link = &invalid.link;
+        if is_web_link(&link.href) || !invalid.reason.file_not_found() {
+            continue;
+        }
+
+        let chapter = Path::new(files.name(link.file));
+        let target = match local_target(chapter, &link.href) {
+            Some(target) => target,
+            None => continue,
+        };
+        // a chapter linking to itself is almost never what was intended
+        let others: Vec<PathBuf> = chapters
+            .iter()
+            .filter(|other| other.as_path() != chapter)
+            .cloned()
+            .collect();
+        let candidate = match did_you_mean(&target, &others) {
+            Some(candidate) => candidate,
+            None => continue,
+        };
+
+        let absolute = Path::new("/").join(candidate);
+        let mut replacement = match relative_path_to_file(chapter, &absolute) {
+            Some(path) => path,
+            None => continue,
+        };
+        if let Some(fragment) = link.href.find('#').map(|ix| &link.href[ix..]) {
+            replacement.push_str(fragment);
+        }
+
+        fixes.extend(fix_for(link, files, replacement, FixReason::MovedFile));
+    }
+
+    fixes
+}
+
+/// Find the chapter that a broken link was most likely meant to point to.
+///
+/// This prefers a unique chapter with the same file name (i.e. the file was
+/// moved to another directory), falling back to a unique chapter whose path
+/// is only a couple of characters different (i.e. a typo or small rename).
+pub(crate) fn did_you_mean<'a>(
+    target: &Path,
+    chapters: &'a [PathBuf],
+) -> Option<&'a PathBuf> {
+    const MAX_DISTANCE: usize = 2;
+
+    let same_name: Vec<_> = chapters
+        .iter()
+        .filter(|chapter| chapter.file_name() == target.file_name())
+        .collect();
+    if let [only] = same_name.as_slice() {
+        return Some(only);
+    }
+
+    let target = target.display().to_string();
+    let mut best: Option<(usize, &PathBuf)> = None;
+    let mut ambiguous = false;
+
+    for chapter in chapters {
+        let distance = levenshtein(&target, &chapter.display().to_string());
+        if distance > MAX_DISTANCE {
+            continue;
+        }
+
+        match best {
+            Some((best_distance, _)) if distance > best_distance => {},
+            Some((best_distance, _)) if distance == best_distance => {
+                ambiguous = true
+            },
+            _ => {
+                best = Some((distance, chapter));
+                ambiguous = false;
+            },
+        }
+    }
+
+    match best {
+        Some((_, chapter)) if !ambiguous => Some(chapter),
+        _ => None,
+    }
+}
+
+fn levenshtein(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+
+    for (i, l) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, r) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(l != *r);
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+            current.push(substitution.min(insertion).min(deletion));
+        }
+
+        previous = current;
+    }
+
+    previous[right.len()]
+}
+
+/// Figure out where a link's href is so it can be replaced.
+fn fix_for(
+    link: &Link,
+    files: &Files<String>,
+    replacement: String,
+    reason: FixReason,
+) -> Option<Fix> {
+    let src = files.source(link.file);
+    let start = link.span.start().to_usize();
+    let end = link.span.end().to_usize();
+
+    // The destination comes after the link text (e.g. `[text](href)`), so
+    // search backwards in case the text is the URL itself. Reference-style
+    // links don't contain the href, so they can't be fixed.
+    let offset = match src[start..end].rfind(link.href.as_str()) {
+        Some(offset) => offset,
+        None => {
+            tracing::debug!("Unable to find \"{}\" in its link", link.href);
+            return None;
+        },
+    };
+    let start = start + offset;
+
+    Some(Fix {
+        chapter: PathBuf::from(files.name(link.file)),
+        range: start..start + link.href.len(),
+        original: link.href.clone(),
+        replacement,
+        reason,
+    })
+}
+
+fn group_by_chapter(fixes: &[Fix]) -> BTreeMap<&Path, Vec<&Fix>> {
+    let mut grouped: BTreeMap<&Path, Vec<&Fix>> = BTreeMap::new();
+
+    for fix in fixes {
+        grouped.entry(&fix.chapter).or_default().push(fix);
+    }
+
+    for chapter_fixes in grouped.values_mut() {
+        chapter_fixes.sort_by_key(|fix| fix.range.start);
+        chapter_fixes.dedup_by_key(|fix| fix.range.start);
+    }
+
+    grouped
+}
+
+/// Apply the fixes to a chapter's text, returning `None` if the text doesn't
+/// match what the fixes expect (e.g. because a preprocessor changed it).
+fn apply(original: &str, fixes: &[&Fix]) -> Option<String> {
+    let mut fixed = original.to_string();
+
+    for fix in fixes.iter().rev() {
+        if fixed.get(fix.range.clone()) != Some(fix.original.as_str()) {
+            return None;
+        }
+        fixed.replace_range(fix.range.clone(), &fix.replacement);
+    }
+
+    Some(fixed)
+}
+
+/// Rewrite the chapters on disk, returning the number of fixes that were
+/// applied.
+pub fn apply_fixes(src_dir: &Path, fixes: &[Fix]) -> Result<usize, Error> {
+    let mut applied = 0;
+
+    for (chapter, chapter_fixes) in group_by_chapter(fixes) {
+        let path = src_dir.join(chapter);
+        let original = std::fs::read_to_string(&path).with_context(|| {
+            format!("Unable to read \"{}\"", path.display())
+        })?;
+
+        match apply(&original, &chapter_fixes) {
+            Some(fixed) => {
+                std::fs::write(&path, fixed).with_context(|| {
+                    format!("Unable to write to \"{}\"", path.display())
+                })?;
+                applied += chapter_fixes.len();
+            },
+            None => tracing::warn!(
+                "Not fixing \"{}\" because it was modified by a preprocessor",
+                chapter.display()
+            ),
+        }
+    }
+
+    Ok(applied)
+}
+
+/// Show the changes [`apply_fixes()`] would make as a diff.
+pub fn preview_fixes(src_dir: &Path, fixes: &[Fix]) -> Result<String, Error> {
+    let mut diff = String::new();
+
+    for (chapter, chapter_fixes) in group_by_chapter(fixes) {
+        let path = src_dir.join(chapter);
+        let original = std::fs::read_to_string(&path).with_context(|| {
+            format!("Unable to read \"{}\"", path.display())
+        })?;
+        let fixed = match apply(&original, &chapter_fixes) {
+            Some(fixed) => fixed,
+            None => continue,
+        };
+
+        diff.push_str(&format!(
+            "--- a/{0}\n+++ b/{0}\n",
+            chapter.display().to_string().replace('\\', "/")
+        ));
+
+        // hrefs never contain newlines, so lines always match up
+        for (line_number, (before, after)) in
+            original.lines().zip(fixed.lines()).enumerate()
+        {
+            if before != after {
+                diff.push_str(&format!(
+                    "@@ -{0} +{0} @@\n-{1}\n+{2}\n",
+                    line_number + 1,
+                    before,
+                    after
+                ));
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggest_chapters_for_moved_files() {
+        let chapters: Vec<PathBuf> =
+            vec!["intro.md", "guide/setup.md", "guide/usage.md", "usage.md"]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect();
+        let inputs = vec![
+            // moved into a sub-directory
+            ("setup.md", Some("guide/setup.md")),
+            // typo
+            ("intor.md", Some("intro.md")),
+            // more than one chapter with this name
+            ("other/usage.md", None),
+            ("completely-different.md", None),
+        ];
+
+        for (target, should_be) in inputs {
+            let got = did_you_mean(Path::new(target), &chapters);
+            assert_eq!(
+                got,
+                should_be.map(PathBuf::from).as_ref(),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn replace_the_destination_and_not_the_link_text() {
+        let mut files = Files::new();
+        let src = "See [./old.md](./old.md) for more";
+        let id = files.add("chapter.md", src.to_string());
+        let link = Link::new("./old.md", codespan::Span::new(4, 24), id);
+
+        let fix = fix_for(
+            &link,
+            &files,
+            "./new.md".to_string(),
+            FixReason::MovedFile,
+        )
+        .unwrap();
+        let got = apply(src, &[&fix]).unwrap();
+
+        assert_eq!(got, "See [./old.md](./new.md) for more");
+    }
+
+    #[test]
+    fn follow_permanent_redirects_with_the_configured_headers() {
+        let client = crate::http_client::Canned::default()
+            .respond("https://example.com/old", 301, &[("Location", "/new")])
+            .respond("https://example.com/new", 308, &[(
+                "Location",
+                "https://other.example.com/page",
+            )]);
+        let requested = std::sync::Arc::clone(&client.requested);
+        let mut files = Files::new();
+        let src = "[docs](https://example.com/old#setup)";
+        let id = files.add("chapter.md", src.to_string());
+        let link = Link::new(
+            "https://example.com/old#setup",
+            codespan::Span::new(0, src.len() as u32),
+            id,
+        );
+
+        let got = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(redirect_fixes(
+                &[link],
+                &BTreeMap::new(),
+                &Config::default(),
+                &files,
+                &client,
+            ));
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].replacement, "https://other.example.com/page#setup");
+        assert_eq!(requested.lock().unwrap().as_slice(), [
+            "https://example.com/old",
+            "https://example.com/new",
+            "https://other.example.com/page",
+        ]);
+    }
+}
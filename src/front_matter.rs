@@ -0,0 +1,306 @@
+use crate::{file_provider::normalize, Extractor, FileProvider};
+use mdbook::book::{Book, BookItem};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// The front matter key listing a chapter's old URLs (the same one Jekyll's
+/// `jekyll-redirect-from` uses).
+const ALIAS_KEY: &str = "redirect_from";
+
+/// An [`Extractor`] for the URLs in a chapter's YAML front matter (the block
+/// between two `---` lines at the very top of the file), used for
+/// [`crate::Config::front_matter_keys`].
+///
+/// This isn't a full YAML parser. It understands the top-level keys people
+/// actually put links in, whose value is either a single string, a flow
+/// sequence (`[a, b]`), or a block sequence (one `- item` per line).
+///
+/// `redirect_from` entries are old URLs which point *to* the chapter, so they
+/// are never checked (see [`Aliases`]).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FrontMatter {
+    keys: Vec<String>,
+}
+
+impl FrontMatter {
+    pub(crate) fn new(mut keys: Vec<String>) -> Self {
+        keys.retain(|key| key != ALIAS_KEY);
+        FrontMatter { keys }
+    }
+
+    fn is_wanted(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| k == key)
+    }
+}
+
+impl Extractor for FrontMatter {
+    fn extract(&self, src: &str) -> Vec<(String, Range<usize>)> {
+        values(src, |key| self.is_wanted(key))
+    }
+}
+
+/// The old URLs listed in each chapter's `redirect_from` front matter, so
+/// links to them are treated as links to the chapter.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Aliases {
+    /// Each alias (as an absolute path) mapped to the chapter it belongs to.
+    targets: HashMap<PathBuf, PathBuf>,
+}
+
+impl Aliases {
+    pub(crate) fn new(
+        book: &Book,
+        src_dir: &Path,
+        files: &dyn FileProvider,
+    ) -> Self {
+        let root = files
+            .canonicalize(src_dir)
+            .unwrap_or_else(|_| src_dir.to_path_buf());
+        let mut targets = HashMap::new();
+
+        for item in book.iter() {
+            let (content, path) = match item {
+                BookItem::Chapter(ch) => match ch.source_path {
+                    Some(ref path) => (&ch.content, src_dir.join(path)),
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            for (alias, _) in values(content, |key| key == ALIAS_KEY) {
+                // like the rest of the site's URLs, aliases are relative to
+                // the root of the book
+                let alias = alias.split(['#', '?']).next().unwrap_or_default();
+                let alias = alias.trim_start_matches('/');
+                for base in &[src_dir, root.as_path()] {
+                    targets.insert(normalize(&base.join(alias)), path.clone());
+                }
+            }
+        }
+
+        Aliases { targets }
+    }
+
+    /// The chapter a path (e.g. `/book/src/old/chapter-1.html`) is an alias
+    /// for.
+    pub(crate) fn target(&self, path: &Path) -> Option<&Path> {
+        if self.targets.is_empty() {
+            return None;
+        }
+
+        self.targets.get(&normalize(path)).map(PathBuf::as_path)
+    }
+}
+
+/// Find the values for the top-level keys we want in the front matter, and
+/// where each one is.
+fn values<F>(src: &str, is_wanted: F) -> Vec<(String, Range<usize>)>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut links = Vec::new();
+    // are we inside a block sequence belonging to one of our keys?
+    let mut in_wanted_list = false;
+
+    for (offset, line) in front_matter_lines(src) {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if indent > 0 || trimmed.starts_with("- ") {
+            if let Some(item) = trimmed.strip_prefix("- ") {
+                if in_wanted_list {
+                    let start = offset + line.len() - item.len();
+                    links.extend(scalar(item, start));
+                }
+            }
+            continue;
+        }
+
+        in_wanted_list = false;
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value),
+            None => continue,
+        };
+        if !is_wanted(unquote(key)) {
+            continue;
+        }
+
+        let start = offset + key.len() + 1;
+        let value_start = start + value.len() - value.trim_start().len();
+        let value = value.trim();
+
+        if value.is_empty() {
+            in_wanted_list = true;
+        } else if let Some(items) = value
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let mut item_start = value_start + 1;
+            for item in items.split(',') {
+                links.extend(scalar(item, item_start));
+                item_start += item.len() + 1;
+            }
+        } else {
+            links.extend(scalar(value, value_start));
+        }
+    }
+
+    links
+}
+
+/// The lines between the opening and closing `---`, and where each one
+/// starts.
+fn front_matter_lines(src: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for (i, line) in src.split_inclusive('\n').enumerate() {
+        let text = line.trim_end_matches(['\r', '\n']);
+
+        if i == 0 {
+            if text != "---" {
+                // no front matter
+                return Vec::new();
+            }
+        } else if text == "---" || text == "..." {
+            return lines;
+        } else {
+            lines.push((offset, text));
+        }
+
+        offset += line.len();
+    }
+
+    // the front matter was never closed, so it's just a horizontal rule
+    Vec::new()
+}
+
+/// Parse a (possibly quoted) scalar which starts at `offset`, ignoring any
+/// trailing comment.
+fn scalar(raw: &str, offset: usize) -> Option<(String, Range<usize>)> {
+    let start = offset + raw.len() - raw.trim_start().len();
+    let raw = raw.trim();
+
+    let value = match raw.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let inner = &raw[1..];
+            let end = inner.find(quote)?;
+            let start = start + 1;
+            return Some((inner[..end].to_string(), start..start + end));
+        },
+        // a comment has to be preceded by whitespace, so URL fragments are
+        // left alone
+        _ => match raw.find(" #") {
+            Some(ix) => raw[..ix].trim_end(),
+            None => raw,
+        },
+    };
+
+    if value.is_empty() {
+        None
+    } else {
+        Some((value.to_string(), start..start + value.len()))
+    }
+}
+
+fn unquote(key: &str) -> &str {
+    key.trim_matches(|c| c == '"' || c == '\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file_provider::in_memory_book, Checker, Config};
+
+    #[test]
+    fn find_links_in_front_matter() {
+        let src = "---\n\
+                   title: Chapter 1\n\
+                   canonical_url: https://example.com/chapter-1#top # old\n\
+                   image: './images/cover.png'\n\
+                   redirect_from:\n  \
+                     - /old/chapter-1.html\n  \
+                     - \"/older/chapter-1.html\"\n\
+                   tags: [a, b]\n\
+                   aliases: [/one.html, /two.html]\n\
+                   ---\n\
+                   \n\
+                   canonical_url: https://example.com/not-front-matter\n";
+        let extractor = FrontMatter::new(vec![
+            String::from("canonical_url"),
+            String::from("image"),
+            String::from("redirect_from"),
+            String::from("aliases"),
+        ]);
+
+        let got = extractor.extract(src);
+
+        let hrefs: Vec<_> = got.iter().map(|(href, _)| href.as_str()).collect();
+        // redirect_from lists old URLs for this chapter, not links
+        assert_eq!(
+            hrefs,
+            vec![
+                "https://example.com/chapter-1#top",
+                "./images/cover.png",
+                "/one.html",
+                "/two.html",
+            ]
+        );
+        for (href, range) in got {
+            assert_eq!(&src[range], href);
+        }
+    }
+
+    #[test]
+    fn a_leading_horizontal_rule_isnt_front_matter() {
+        let src = "---\n\ncanonical_url: https://example.com/\n";
+        let extractor = FrontMatter::new(vec![String::from("canonical_url")]);
+
+        assert!(extractor.extract(src).is_empty());
+    }
+
+    #[test]
+    fn links_to_a_chapters_old_urls_are_valid() {
+        let chapter_1 = "---\n\
+                         redirect_from:\n  \
+                           - /old/chapter-1.html\n\
+                         ---\n\
+                         # Chapter 1\n";
+        let chapter_2 = "[old](./old/chapter-1.html) \
+                         [heading](/old/chapter-1.html#chapter-1) \
+                         [missing](/old/chapter-1.html#nope) \
+                         [gone](./old/chapter-2.html)";
+        let (files, book) = in_memory_book(&[
+            ("chapter_1.md", chapter_1),
+            ("chapter_2.md", chapter_2),
+        ]);
+
+        let cfg = Config {
+            check_anchors: true,
+            ..Default::default()
+        };
+
+        let report = Checker::builder()
+            .source_dir("/book/src")
+            .config(cfg)
+            .book(book)
+            .file_provider(files)
+            .check()
+            .unwrap();
+
+        let mut broken: Vec<_> = report
+            .outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| invalid.link.href.as_str())
+            .collect();
+        broken.sort();
+        assert_eq!(broken, vec![
+            "./old/chapter-2.html",
+            "/old/chapter-1.html#nope"
+        ]);
+    }
+}
@@ -0,0 +1,555 @@
+use crate::{
+    changes::git, http_client, Config, FileProvider, GithubReview, HttpClient,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle};
+use http::header::{HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, Request, Response, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use tokio::runtime::Builder;
+
+/// The environment variable holding the API token.
+const TOKEN_VARIABLE: &str = "GITHUB_TOKEN";
+
+/// The most items the API will list per page.
+const PER_PAGE: usize = 100;
+
+/// Hidden text added to the body of each review, so the next run can find the
+/// reviews it should replace.
+const MARKER: &str = "<!-- mdbook-linkcheck -->";
+
+/// A comment on a single line of a pull request.
+#[derive(Debug, Clone, PartialEq)]
+struct ReviewComment {
+    /// The file, relative to the root of the repository.
+    path: String,
+    /// The (one-based) line number in the new version of the file.
+    line: usize,
+    body: String,
+}
+
+/// Post the diagnostics which point at lines changed by a pull request as
+/// review comments on it (see [`Config::github_review`]).
+///
+/// Problems on lines the pull request didn't touch are left out, so authors
+/// only hear about what they changed. The comments from reviews posted by
+/// previous runs are deleted first, so each problem is only mentioned once.
+pub(crate) fn post_review(
+    review: &GithubReview,
+    diags: &[Diagnostic<FileId>],
+    files: &Files<String>,
+    src_dir: &Path,
+    cfg: &Config,
+    http: &dyn HttpClient,
+    file_provider: &dyn FileProvider,
+) -> Result<(), Error> {
+    let token = std::env::var(TOKEN_VARIABLE).with_context(|| {
+        format!("The {} environment variable isn't set", TOKEN_VARIABLE)
+    })?;
+    // the source directory's path relative to the root of the repository
+    let prefix = git(src_dir, &["rev-parse", "--show-prefix"])?;
+    let sources = source_files(diags, files, src_dir, file_provider);
+    let github = GitHub {
+        http,
+        cfg,
+        api: std::env::var("GITHUB_API_URL")
+            .unwrap_or_else(|_| String::from("https://api.github.com")),
+        token,
+        review,
+    };
+
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let changed = github.changed_lines().await?;
+        let comments =
+            review_comments(diags, files, &sources, prefix.trim(), &changed);
+        github.retract_previous_reviews().await?;
+        tracing::info!(
+            "Posting {} comments to {}#{}",
+            comments.len(),
+            review.repository,
+            review.pull_request
+        );
+
+        if comments.is_empty() {
+            return Ok(());
+        }
+        github.post(&comments).await
+    })
+}
+
+/// Read the original source for each file the diagnostics point into, so
+/// lines in the (preprocessed) content can be mapped back to the lines in the
+/// repository.
+fn source_files(
+    diags: &[Diagnostic<FileId>],
+    files: &Files<String>,
+    src_dir: &Path,
+    file_provider: &dyn FileProvider,
+) -> HashMap<FileId, String> {
+    let ids: HashSet<FileId> = diags
+        .iter()
+        .flat_map(|diag| &diag.labels)
+        .map(|label| label.file_id)
+        .collect();
+
+    ids.into_iter()
+        .filter_map(|id| {
+            let path = src_dir.join(files.name(id));
+            let raw = file_provider.read_to_string(&path).ok()?;
+            Some((id, raw))
+        })
+        .collect()
+}
+
+struct GitHub<'a> {
+    http: &'a dyn HttpClient,
+    cfg: &'a Config,
+    api: String,
+    token: String,
+    review: &'a GithubReview,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestFile {
+    filename: String,
+    /// Missing for binary files and very large diffs.
+    patch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostedReview {
+    id: u64,
+    body: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostedComment {
+    id: u64,
+}
+
+impl GitHub<'_> {
+    fn request(&self, method: Method, url: &str) -> Result<Request, Error> {
+        let url: Url = url.parse()?;
+        let mut request = Request::new(method, url);
+        let bearer = format!("Bearer {}", self.token);
+        let mut token = HeaderValue::from_str(&bearer)
+            .context("The GitHub token isn't a valid header value")?;
+        token.set_sensitive(true);
+        let headers = request.headers_mut();
+        headers.insert(AUTHORIZATION, token);
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.github+json"),
+        );
+
+        Ok(request)
+    }
+
+    fn pull_request_url(&self) -> String {
+        format!(
+            "{}/repos/{}/pulls/{}",
+            self.api.trim_end_matches('/'),
+            self.review.repository,
+            self.review.pull_request
+        )
+    }
+
+    fn json_request(
+        &self,
+        method: Method,
+        url: &str,
+        body: &Value,
+    ) -> Result<Request, Error> {
+        let mut request = self.request(method, url)?;
+        request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        *request.body_mut() = Some(body.to_string().into());
+
+        Ok(request)
+    }
+
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        let response = http_client::send(self.http, self.cfg, request)
+            .await?
+            .error_for_status()?;
+
+        Ok(response)
+    }
+
+    /// Fetch every page of a list from the API.
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> Result<Vec<T>, Error> {
+        let mut items = Vec::new();
+
+        for page in 1.. {
+            let url = format!("{}?per_page={}&page={}", url, PER_PAGE, page);
+            let request = self.request(Method::GET, &url)?;
+            let body = self.send(request).await?.text().await?;
+            let got: Vec<T> = serde_json::from_str(&body)
+                .with_context(|| format!("Unable to parse \"{}\"", url))?;
+            let is_last_page = got.len() < PER_PAGE;
+            items.extend(got);
+
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Find the lines added or changed by the pull request, for each file.
+    async fn changed_lines(
+        &self,
+    ) -> Result<HashMap<String, HashSet<usize>>, Error> {
+        let url = format!("{}/files", self.pull_request_url());
+        let files: Vec<PullRequestFile> = self
+            .get_all(&url)
+            .await
+            .context("Unable to get the pull request's files")?;
+
+        Ok(files
+            .into_iter()
+            .filter_map(|file| Some((file.filename, added_lines(&file.patch?))))
+            .collect())
+    }
+
+    /// Delete the comments left by reviews from previous runs and say they
+    /// were replaced (a submitted review can't be deleted itself).
+    async fn retract_previous_reviews(&self) -> Result<(), Error> {
+        let url = format!("{}/reviews", self.pull_request_url());
+        let reviews: Vec<PostedReview> = self
+            .get_all(&url)
+            .await
+            .context("Unable to get the previous reviews")?;
+        let previous = reviews.iter().filter(|review| {
+            review.body.as_deref().unwrap_or_default().contains(MARKER)
+        });
+
+        for review in previous {
+            let review_url = format!("{}/{}", url, review.id);
+            let comments: Vec<PostedComment> =
+                self.get_all(&format!("{}/comments", review_url)).await?;
+            tracing::debug!(
+                review = review.id,
+                "Deleting {} comments from a previous review",
+                comments.len()
+            );
+
+            for comment in comments {
+                let comment_url = format!(
+                    "{}/repos/{}/pulls/comments/{}",
+                    self.api.trim_end_matches('/'),
+                    self.review.repository,
+                    comment.id
+                );
+                let request = self.request(Method::DELETE, &comment_url)?;
+                self.send(request)
+                    .await
+                    .context("Unable to delete a previous comment")?;
+            }
+
+            // without the marker, so it is only retracted once
+            let body = json!({
+                "body": format!(
+                    "This review from {} was replaced by a newer one.",
+                    env!("CARGO_PKG_NAME")
+                ),
+            });
+            let request = self.json_request(Method::PUT, &review_url, &body)?;
+            self.send(request)
+                .await
+                .context("Unable to update a previous review")?;
+        }
+
+        Ok(())
+    }
+
+    async fn post(&self, comments: &[ReviewComment]) -> Result<(), Error> {
+        let comments: Vec<Value> = comments
+            .iter()
+            .map(|comment| {
+                json!({
+                    "path": comment.path,
+                    "line": comment.line,
+                    "side": "RIGHT",
+                    "body": comment.body,
+                })
+            })
+            .collect();
+        let review = json!({
+            "event": "COMMENT",
+            "body": format!(
+                "{}\n{} found {} problems with the links in this pull request.",
+                MARKER,
+                env!("CARGO_PKG_NAME"),
+                comments.len()
+            ),
+            "comments": comments,
+        });
+
+        let url = format!("{}/reviews", self.pull_request_url());
+        let request = self.json_request(Method::POST, &url, &review)?;
+        self.send(request).await.context("Unable to post the review")?;
+
+        Ok(())
+    }
+}
+
+/// Find the (one-based) line numbers of the lines added by a unified diff.
+fn added_lines(patch: &str) -> HashSet<usize> {
+    let mut added = HashSet::new();
+    let mut line = 0;
+
+    for text in patch.lines() {
+        if let Some(header) = text.strip_prefix("@@ ") {
+            // @@ -old_start,old_len +new_start,new_len @@
+            line = header
+                .split_whitespace()
+                .find_map(|range| range.strip_prefix('+'))
+                .and_then(|range| range.split(',').next())
+                .and_then(|start| start.parse().ok())
+                .unwrap_or(0);
+        } else if text.starts_with('+') {
+            added.insert(line);
+            line += 1;
+        } else if !text.starts_with('-') && !text.starts_with('\\') {
+            // context lines are in both versions
+            line += 1;
+        }
+    }
+
+    added
+}
+
+/// Turn each diagnostic pointing at a changed line into a review comment.
+fn review_comments(
+    diags: &[Diagnostic<FileId>],
+    files: &Files<String>,
+    sources: &HashMap<FileId, String>,
+    prefix: &str,
+    changed: &HashMap<String, HashSet<usize>>,
+) -> Vec<ReviewComment> {
+    diags
+        .iter()
+        .filter_map(|diag| {
+            let label = diag
+                .labels
+                .iter()
+                .find(|label| label.style == LabelStyle::Primary)?;
+            let name = files.name(label.file_id).to_string_lossy();
+            let path = format!("{}{}", prefix, name.replace('\\', "/"));
+            let content = files.source(label.file_id);
+            let before = &content[..label.range.start.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let line = match sources.get(&label.file_id) {
+                Some(source) => source_line(content, source, line)?,
+                None => line,
+            };
+
+            if !changed.get(&path)?.contains(&line) {
+                return None;
+            }
+
+            Some(ReviewComment {
+                path,
+                line,
+                body: comment_body(diag),
+            })
+        })
+        .collect()
+}
+
+/// Find the line in a chapter's source file that a line of its (possibly
+/// preprocessed) content came from, picking the closest one when the same
+/// text appears more than once. Lines which a preprocessor generated or
+/// rewrote aren't in the source, so there is nowhere to comment.
+fn source_line(content: &str, source: &str, line: usize) -> Option<usize> {
+    let text = content.lines().nth(line - 1)?;
+
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, candidate)| *candidate == text)
+        .map(|(ix, _)| ix + 1)
+        .min_by_key(|&candidate| {
+            (candidate as isize - line as isize).abs()
+        })
+}
+
+fn comment_body(diag: &Diagnostic<FileId>) -> String {
+    let severity = crate::severity_name(diag.severity);
+    let mut body = format!("**{}**: {}", severity, diag.message);
+
+    for label in &diag.labels {
+        if !label.message.is_empty() {
+            body.push_str("\n\n");
+            body.push_str(&label.message);
+        }
+    }
+    for note in &diag.notes {
+        body.push_str("\n\n");
+        body.push_str(note);
+    }
+
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::Canned;
+    use codespan_reporting::diagnostic::Label;
+    use std::sync::Arc;
+
+    #[test]
+    fn find_the_lines_a_patch_adds() {
+        let patch = [
+            "@@ -1,3 +1,4 @@",
+            " # Chapter 1",
+            "-old line",
+            "+new line",
+            "+another line",
+            " context",
+            "@@ -10,2 +11,2 @@ fn heading()",
+            " unchanged",
+            "-removed",
+            "+added",
+            "\\ No newline at end of file",
+        ]
+        .join("\n");
+
+        let got = added_lines(&patch);
+
+        assert_eq!(got, [2, 3, 12].iter().copied().collect());
+    }
+
+    #[test]
+    fn only_comment_on_changed_lines() {
+        let mut files = Files::new();
+        let id = files.add(
+            "chapter_1.md",
+            String::from("# Chapter 1\n\n[old](./old.md)\n[new](./new.md)\n"),
+        );
+        let diag_on_line = |line: &str| {
+            let src = files.source(id);
+            let start = src.find(line).unwrap();
+            Diagnostic::error()
+                .with_message("File not found")
+                .with_labels(vec![Label::primary(id, start..start + 1)
+                    .with_message("Failed here")])
+        };
+        let diags = vec![diag_on_line("[old]"), diag_on_line("[new]")];
+        let mut changed = HashMap::new();
+        changed.insert(
+            String::from("book/src/chapter_1.md"),
+            [4].iter().copied().collect(),
+        );
+
+        let got = review_comments(
+            &diags,
+            &files,
+            &HashMap::new(),
+            "book/src/",
+            &changed,
+        );
+
+        assert_eq!(
+            got,
+            vec![ReviewComment {
+                path: String::from("book/src/chapter_1.md"),
+                line: 4,
+                body: String::from("**error**: File not found\n\nFailed here"),
+            }]
+        );
+    }
+
+    #[test]
+    fn comment_on_the_line_in_the_source_file() {
+        let source =
+            "# Chapter 1\n\n{{#include intro.md}}\n\n[new](./new.md)\n";
+        let content = "# Chapter 1\n\nAn included\nparagraph\n\n\
+                       [new](./new.md)\n";
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from(content));
+        let included = content.find("paragraph").unwrap();
+        let link = content.find("[new]").unwrap();
+        let diags: Vec<_> = [included, link]
+            .iter()
+            .map(|&start| {
+                Diagnostic::error()
+                    .with_message("File not found")
+                    .with_labels(vec![Label::primary(id, start..start + 1)])
+            })
+            .collect();
+        let mut sources = HashMap::new();
+        sources.insert(id, String::from(source));
+        let mut changed = HashMap::new();
+        changed.insert(
+            String::from("chapter_1.md"),
+            (1..=5).collect::<HashSet<usize>>(),
+        );
+
+        let got = review_comments(&diags, &files, &sources, "", &changed);
+
+        // the included line isn't in chapter_1.md, and the link moved up
+        assert_eq!(got, vec![ReviewComment {
+            path: String::from("chapter_1.md"),
+            line: 5,
+            body: String::from("**error**: File not found"),
+        }]);
+    }
+
+    #[test]
+    fn retract_the_comments_from_previous_runs() {
+        let pulls = "https://api.example.com/repos/owner/book/pulls";
+        let reviews = format!("{}/42/reviews", pulls);
+        let client = Canned::default()
+            .respond_with_body(
+                &format!("{}?per_page=100&page=1", reviews),
+                r#"[
+                    {"id": 1, "body": "<!-- mdbook-linkcheck -->\nFound 2"},
+                    {"id": 2, "body": "Looks good to me"},
+                    {"id": 3, "body": null}
+                ]"#,
+            )
+            .respond_with_body(
+                &format!("{}/1/comments?per_page=100&page=1", reviews),
+                r#"[{"id": 10}, {"id": 11}]"#,
+            );
+        let requested = Arc::clone(&client.requested);
+        let review = GithubReview {
+            repository: String::from("owner/book"),
+            pull_request: 42,
+        };
+        let cfg = Config::default();
+        let github = GitHub {
+            http: &client,
+            cfg: &cfg,
+            api: String::from("https://api.example.com/"),
+            token: String::from("secret"),
+            review: &review,
+        };
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        runtime.block_on(github.retract_previous_reviews()).unwrap();
+
+        assert_eq!(*requested.lock().unwrap(), vec![
+            format!("{}?per_page=100&page=1", reviews),
+            format!("{}/1/comments?per_page=100&page=1", reviews),
+            format!("{}/comments/10", pulls),
+            format!("{}/comments/11", pulls),
+            format!("{}/1", reviews),
+        ]);
+    }
+}
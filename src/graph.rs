@@ -0,0 +1,243 @@
+use crate::{changes::local_target, config::is_web_link, ValidationOutcome};
+use anyhow::{Context as _, Error};
+use codespan::Files;
+use linkcheck::Link;
+use serde_derive::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+/// The links between a book's chapters, for [`crate::Config::link_graph_file`].
+///
+/// Every chapter is a node, and there is an edge for each chapter that links
+/// to another one. Broken links to files which don't exist get a node of
+/// their own, so they stand out.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LinkGraph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Node {
+    /// The file, relative to the source directory.
+    path: PathBuf,
+    /// Is this one of the book's chapters?
+    chapter: bool,
+    /// How many other chapters link here. Chapters nobody links to can only
+    /// be reached from the sidebar.
+    incoming: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Edge {
+    from: PathBuf,
+    to: PathBuf,
+    /// The number of links from one chapter to the other.
+    links: usize,
+    /// How many of those links are broken.
+    broken: usize,
+}
+
+impl LinkGraph {
+    /// Build the graph from the results of checking the links in these
+    /// chapters. Links within a chapter and links to anything other than a
+    /// chapter (e.g. images) are left out, unless they are broken.
+    pub(crate) fn new(
+        chapters: &[PathBuf],
+        outcome: &ValidationOutcome,
+        files: &Files<String>,
+    ) -> Self {
+        let chapter_set: BTreeSet<&Path> =
+            chapters.iter().map(PathBuf::as_path).collect();
+        let mut edges: BTreeMap<(PathBuf, PathBuf), Edge> = BTreeMap::new();
+        let links = outcome
+            .valid_links
+            .iter()
+            .map(|link| (link, false))
+            .chain(outcome.invalid_links.iter().map(|i| (&i.link, true)));
+
+        for (link, broken) in links {
+            let (from, to) = match endpoints(link, files) {
+                Some(endpoints) => endpoints,
+                None => continue,
+            };
+            if from == to || (!broken && !chapter_set.contains(to.as_path())) {
+                continue;
+            }
+
+            let edge = edges.entry((from.clone(), to.clone())).or_insert(Edge {
+                from,
+                to,
+                links: 0,
+                broken: 0,
+            });
+            edge.links += 1;
+            if broken {
+                edge.broken += 1;
+            }
+        }
+
+        let mut incoming: BTreeMap<&Path, usize> = BTreeMap::new();
+        for edge in edges.values() {
+            *incoming.entry(&edge.to).or_default() += 1;
+        }
+        let mut nodes: Vec<Node> = chapters
+            .iter()
+            .map(|path| Node {
+                path: path.clone(),
+                chapter: true,
+                incoming: incoming.get(path.as_path()).copied().unwrap_or(0),
+            })
+            .collect();
+        let missing: BTreeSet<&Path> = incoming
+            .keys()
+            .copied()
+            .filter(|path| !chapter_set.contains(path))
+            .collect();
+        nodes.extend(missing.into_iter().map(|path| Node {
+            path: path.to_path_buf(),
+            chapter: false,
+            incoming: incoming[path],
+        }));
+
+        LinkGraph {
+            nodes,
+            edges: edges.into_values().collect(),
+        }
+    }
+
+    /// Format the graph using [GraphViz's DOT language][dot]. Broken links
+    /// are red, and nodes which aren't chapters are dashed.
+    ///
+    /// [dot]: https://graphviz.org/doc/info/lang.html
+    pub(crate) fn to_dot(&self) -> String {
+        let mut out = String::from("digraph book {\n");
+
+        for node in &self.nodes {
+            let style = if node.chapter { "" } else { " [style=dashed]" };
+            let _ = writeln!(out, "    {}{};", quoted(&node.path), style);
+        }
+        for edge in &self.edges {
+            let style = if edge.broken > 0 {
+                format!(" [color=red, label=\"{} broken\"]", edge.broken)
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                out,
+                "    {} -> {}{};",
+                quoted(&edge.from),
+                quoted(&edge.to),
+                style
+            );
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Format the graph as a JSON object with `nodes` and `edges`.
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self)
+            .expect("Serializing to JSON should never fail")
+    }
+}
+
+/// The chapter a link is in and the file it points to, both relative to the
+/// source directory.
+fn endpoints(link: &Link, files: &Files<String>) -> Option<(PathBuf, PathBuf)> {
+    if is_web_link(&link.href) {
+        return None;
+    }
+
+    let from = PathBuf::from(files.name(link.file));
+    let to = local_target(&from, &link.href)?;
+    Some((from, to))
+}
+
+fn quoted(path: &Path) -> String {
+    let path = path.display().to_string().replace('\\', "/");
+    format!("\"{}\"", path.replace('"', "\\\""))
+}
+
+/// Write the graph to a file, using the DOT format unless the file has a
+/// `*.json` extension.
+pub(crate) fn write_link_graph(
+    path: &Path,
+    graph: &LinkGraph,
+) -> Result<(), Error> {
+    let is_json = path.extension().is_some_and(|ext| ext == "json");
+    let text = if is_json {
+        graph.to_json()
+    } else {
+        graph.to_dot()
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!("Unable to create \"{}\"", parent.display())
+        })?;
+    }
+
+    tracing::debug!("Writing the link graph to {}", path.display());
+    std::fs::write(path, text).with_context(|| {
+        format!("Unable to write the link graph to \"{}\"", path.display())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+    use linkcheck::validation::{InvalidLink, Reason};
+
+    #[test]
+    fn chapters_and_the_links_between_them() {
+        let mut files = Files::new();
+        let first = files.add("chapter_1.md", String::new());
+        let second = files.add("nested/chapter_2.md", String::new());
+        let link = |href: &str, file| Link::new(href, Span::new(0, 1), file);
+        let outcome = ValidationOutcome {
+            valid_links: vec![
+                link("./nested/chapter_2.md", first),
+                link("nested/chapter_2.html#intro", first),
+                link("#heading", first),
+                link("./image.png", first),
+                link("https://example.com/", first),
+                link("../chapter_1.md", second),
+            ],
+            invalid_links: vec![InvalidLink {
+                link: link("./missing.md", second),
+                reason: Reason::Io(std::io::ErrorKind::NotFound.into()),
+            }],
+            ..Default::default()
+        };
+        let chapters = vec![
+            PathBuf::from("chapter_1.md"),
+            PathBuf::from("nested/chapter_2.md"),
+            PathBuf::from("orphan.md"),
+        ];
+
+        let got = LinkGraph::new(&chapters, &outcome, &files);
+
+        let should_be = r#"digraph book {
+    "chapter_1.md";
+    "nested/chapter_2.md";
+    "orphan.md";
+    "nested/missing.md" [style=dashed];
+    "chapter_1.md" -> "nested/chapter_2.md";
+    "nested/chapter_2.md" -> "chapter_1.md";
+    "nested/chapter_2.md" -> "nested/missing.md" [color=red, label="1 broken"];
+}
+"#;
+        assert_eq!(got.to_dot(), should_be);
+        assert_eq!(got.edges[0].links, 2);
+        assert_eq!(got.nodes[2].incoming, 0);
+    }
+}
@@ -1,4 +1,9 @@
 use regex::Regex;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use std::{
     hash::{Hash, Hasher},
@@ -59,6 +64,19 @@ impl<'de> Deserialize<'de> for HashedRegex {
     }
 }
 
+impl JsonSchema for HashedRegex {
+    fn schema_name() -> String { String::from("Regex") }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some(String::from("regex")),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl Hash for HashedRegex {
     fn hash<H: Hasher>(&self, state: &mut H) { self.string.hash(state); }
 }
@@ -0,0 +1,141 @@
+use crate::Metrics;
+use anyhow::{Context as _, Error};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A summary of a single run, appended to [`crate::Config::history_file`] as
+/// one line of JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct HistoryEntry {
+    /// When the run finished, in seconds since the Unix epoch.
+    pub(crate) timestamp: u64,
+    pub(crate) errors: usize,
+    pub(crate) warnings: usize,
+    #[serde(flatten)]
+    pub(crate) metrics: Metrics,
+    /// The number of broken web links which were fine the last time they
+    /// were checked.
+    pub(crate) regressions: usize,
+}
+
+impl HistoryEntry {
+    pub(crate) fn new(
+        errors: usize,
+        warnings: usize,
+        metrics: Metrics,
+        regressions: usize,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        HistoryEntry {
+            timestamp,
+            errors,
+            warnings,
+            metrics,
+            regressions,
+        }
+    }
+}
+
+/// Append the entry to the history file, returning a short description of
+/// how the number of broken links changed since the previous entry (if
+/// there was one).
+pub(crate) fn append(
+    path: &Path,
+    entry: &HistoryEntry,
+) -> Result<Option<String>, Error> {
+    let previous = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|history| previous_broken_links(&history));
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!("Unable to create \"{}\"", parent.display())
+        })?;
+    }
+
+    tracing::debug!("Appending this run to {}", path.display());
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Unable to open \"{}\"", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?).with_context(|| {
+        format!("Unable to write the history to \"{}\"", path.display())
+    })?;
+
+    Ok(previous.map(|previous| trend(previous, entry.metrics.invalid_links)))
+}
+
+/// The number of broken links in the last valid entry.
+fn previous_broken_links(history: &str) -> Option<usize> {
+    history
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find_map(|entry| entry["invalid-links"].as_u64())
+        .map(|count| count as usize)
+}
+
+fn trend(previous: usize, current: usize) -> String {
+    let plural = |n: usize| if n == 1 { "link" } else { "links" };
+
+    if current < previous {
+        let fewer = previous - current;
+        format!("{} fewer broken {} than last run", fewer, plural(fewer))
+    } else if current > previous {
+        let more = current - previous;
+        format!("{} more broken {} than last run", more, plural(more))
+    } else {
+        String::from("The same number of broken links as last run")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_against_the_previous_run() {
+        let path = std::env::temp_dir().join(format!(
+            "mdbook-linkcheck-history-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let entry = |invalid_links| {
+            let metrics = Metrics {
+                invalid_links,
+                ..Default::default()
+            };
+            HistoryEntry::new(invalid_links, 0, metrics, 0)
+        };
+
+        assert_eq!(append(&path, &entry(5)).unwrap(), None);
+        assert_eq!(
+            append(&path, &entry(2)).unwrap().unwrap(),
+            "3 fewer broken links than last run"
+        );
+        assert_eq!(
+            append(&path, &entry(3)).unwrap().unwrap(),
+            "1 more broken link than last run"
+        );
+        assert_eq!(
+            append(&path, &entry(3)).unwrap().unwrap(),
+            "The same number of broken links as last run"
+        );
+
+        let history = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(history.lines().count(), 4);
+        assert!(history.starts_with("{\"timestamp\":"), "{}", history);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
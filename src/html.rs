@@ -0,0 +1,359 @@
+use crate::Config;
+use codespan::{FileId, Files, Span};
+use linkcheck::Link;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{Event, Options, Parser};
+use regex::Regex;
+use reqwest::Url;
+use serde_derive::Serialize;
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+static TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<([a-zA-Z][a-zA-Z0-9]*)\b[^>]*>").unwrap());
+static IMG_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<img\b[^>]*>").unwrap());
+static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\s([^\s"'>/=]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#)
+        .unwrap()
+});
+
+/// A potential security problem with some HTML embedded in a chapter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HtmlProblem {
+    /// The chapter containing the HTML.
+    pub file: FileId,
+    /// Where the offending tag is in the chapter's source text.
+    pub span: Span,
+    /// What is wrong with it.
+    pub kind: HtmlProblemKind,
+}
+
+/// The HTML security lints (see [`Config::unsafe_target_blank`] and
+/// [`Config::mixed_content`]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum HtmlProblemKind {
+    /// A `<a target="_blank">` without `rel="noopener noreferrer"`, letting
+    /// the page it opens access `window.opener`.
+    UnsafeTargetBlank,
+    /// An asset (e.g. `<img src="http://...">`) loaded over plain HTTP by a
+    /// book that is published over HTTPS, which browsers will block or warn
+    /// about.
+    MixedContent {
+        /// The asset's URL.
+        url: String,
+    },
+}
+
+impl Display for HtmlProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            HtmlProblemKind::UnsafeTargetBlank => write!(
+                f,
+                "target=\"_blank\" should be used with rel=\"noopener noreferrer\""
+            ),
+            HtmlProblemKind::MixedContent { ref url } => write!(
+                f,
+                "\"{}\" is loaded over HTTP, but the book is served over HTTPS",
+                url
+            ),
+        }
+    }
+}
+
+/// Check the HTML embedded in each chapter for the lints enabled by
+/// [`Config::unsafe_target_blank`] and [`Config::mixed_content`].
+pub(crate) fn check_html(
+    file_ids: &[FileId],
+    files: &Files<String>,
+    cfg: &Config,
+) -> Vec<HtmlProblem> {
+    let check_target_blank = cfg.unsafe_target_blank.is_some();
+    let check_mixed_content =
+        cfg.mixed_content.is_some() && published_over_https(cfg);
+    if !check_target_blank && !check_mixed_content {
+        return Vec::new();
+    }
+
+    let mut problems = Vec::new();
+
+    for &file in file_ids {
+        let src = files.source(file);
+        for fragment in html_fragments(src, cfg.markdown.options()) {
+            for tag in TAG.captures_iter(&fragment.html) {
+                let whole = tag.get(0).unwrap();
+                let name = tag[1].to_lowercase();
+                let span = fragment.span(whole.range());
+
+                if check_target_blank
+                    && name == "a"
+                    && is_unsafe_target_blank(whole.as_str())
+                {
+                    problems.push(HtmlProblem {
+                        file,
+                        span,
+                        kind: HtmlProblemKind::UnsafeTargetBlank,
+                    });
+                }
+
+                if check_mixed_content {
+                    problems.extend(
+                        insecure_assets(&name, whole.as_str()).into_iter().map(
+                            |url| HtmlProblem {
+                                file,
+                                span,
+                                kind: HtmlProblemKind::MixedContent { url },
+                            },
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// Find the images embedded with a HTML `<img>` tag, which the markdown
+/// parser doesn't report as links. Each link's span is the whole tag.
+pub(crate) fn html_images(
+    file_ids: &[FileId],
+    files: &Files<String>,
+    cfg: &Config,
+) -> Vec<Link> {
+    let mut images = Vec::new();
+
+    for &file in file_ids {
+        let src = files.source(file);
+        for fragment in html_fragments(src, cfg.markdown.options()) {
+            for tag in IMG_TAG.find_iter(&fragment.html) {
+                if let Some(href) = attribute(tag.as_str(), "src") {
+                    let span = fragment.span(tag.range());
+                    images.push(Link::new(href, span, file));
+                }
+            }
+        }
+    }
+
+    images
+}
+
+fn published_over_https(cfg: &Config) -> bool {
+    cfg.site_url
+        .as_deref()
+        .and_then(|url| Url::parse(url).ok())
+        .is_some_and(|url| url.scheme() == "https")
+}
+
+/// Some raw HTML from a markdown document.
+#[derive(Debug, Default)]
+struct Fragment {
+    html: String,
+    /// Where each piece of `html` starts, and where that piece came from in
+    /// the markdown.
+    pieces: Vec<(usize, usize)>,
+}
+
+impl Fragment {
+    fn push(&mut self, html: &str, range: Range<usize>) {
+        self.pieces.push((self.html.len(), range.start));
+        self.html.push_str(html);
+    }
+
+    /// Map a range in [`Fragment::html`] back to the markdown.
+    fn span(&self, range: Range<usize>) -> Span {
+        let to_source = |offset: usize| {
+            let (html_start, src_start) = self
+                .pieces
+                .iter()
+                .rev()
+                .find(|(html_start, _)| *html_start <= offset)
+                .copied()
+                .unwrap_or_default();
+            src_start + offset - html_start
+        };
+        let start = to_source(range.start);
+        let end = to_source(range.end.saturating_sub(1).max(range.start)) + 1;
+
+        Span::new(start as u32, end.max(start) as u32)
+    }
+}
+
+/// Find the raw HTML in a markdown document, along with where it came from.
+///
+/// A HTML block is reported as several events (one per line), so we glue
+/// them back together to make sure tags spanning multiple lines are found.
+/// Inside block quotes and list items each line starts after a `>` or some
+/// indentation, so every line remembers its own offset.
+fn html_fragments(src: &str, options: Options) -> Vec<Fragment> {
+    let mut fragments: Vec<Fragment> = Vec::new();
+    let mut previous_end = None;
+
+    for (event, range) in Parser::new_ext(src, options).into_offset_iter() {
+        let html = match event {
+            Event::Html(html) => html,
+            _ => {
+                previous_end = None;
+                continue;
+            },
+        };
+
+        // the next line of the same HTML block
+        let continues = previous_end.is_some_and(|end: usize| {
+            src.get(end..range.start)
+                .is_some_and(|gap| !gap.contains('\n'))
+        });
+        previous_end = Some(range.end);
+
+        match fragments.last_mut() {
+            Some(fragment) if continues => fragment.push(&html, range),
+            _ => {
+                let mut fragment = Fragment::default();
+                fragment.push(&html, range);
+                fragments.push(fragment);
+            },
+        }
+    }
+
+    fragments
+}
+
+/// Get the value of an attribute in a tag (e.g. `<a href="...">`).
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let caps = ATTRIBUTE
+        .captures_iter(tag)
+        .find(|caps| caps[1].eq_ignore_ascii_case(name))?;
+
+    caps.get(2)
+        .or_else(|| caps.get(3))
+        .or_else(|| caps.get(4))
+        .map(|m| m.as_str())
+}
+
+fn is_unsafe_target_blank(tag: &str) -> bool {
+    let opens_new_window = attribute(tag, "target")
+        .is_some_and(|target| target.eq_ignore_ascii_case("_blank"));
+    if !opens_new_window {
+        return false;
+    }
+
+    let rel = attribute(tag, "rel").unwrap_or_default().to_lowercase();
+    let has = |keyword: &str| rel.split_whitespace().any(|r| r == keyword);
+
+    !(has("noopener") && has("noreferrer"))
+}
+
+/// The attributes which make the browser load something when the page is
+/// displayed, for each type of tag.
+const ASSET_ATTRIBUTES: &[(&str, &str)] = &[
+    ("img", "src"),
+    ("script", "src"),
+    ("link", "href"),
+    ("iframe", "src"),
+    ("video", "src"),
+    ("video", "poster"),
+    ("audio", "src"),
+    ("source", "src"),
+    ("track", "src"),
+    ("embed", "src"),
+    ("object", "data"),
+];
+
+fn insecure_assets(name: &str, tag: &str) -> Vec<String> {
+    ASSET_ATTRIBUTES
+        .iter()
+        .filter(|(tag_name, _)| *tag_name == name)
+        .filter_map(|(_, attr)| attribute(tag, attr))
+        .filter(|url| {
+            url.get(..7)
+                .is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://"))
+        })
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WarningPolicy;
+
+    #[test]
+    fn flag_insecure_html() {
+        let src = r#"# Chapter 1
+
+<a href="https://example.com/" target="_blank">Unsafe</a>
+and <a href="https://example.com/" target=_blank rel="noopener noreferrer">safe</a>
+
+<img src="http://example.com/logo.png"
+     alt="Logo">
+
+<img src="https://example.com/logo.png">
+
+`<script src="http://example.com/app.js">` in code is fine
+"#;
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from(src));
+        let cfg = Config {
+            site_url: Some(String::from("https://example.com/my-book/")),
+            unsafe_target_blank: Some(WarningPolicy::Warn),
+            mixed_content: Some(WarningPolicy::Error),
+            ..Default::default()
+        };
+
+        let got = check_html(&[id], &files, &cfg);
+
+        let kinds: Vec<_> = got.iter().map(|p| p.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                HtmlProblemKind::UnsafeTargetBlank,
+                HtmlProblemKind::MixedContent {
+                    url: String::from("http://example.com/logo.png")
+                },
+            ]
+        );
+        let span = got[1].span;
+        assert_eq!(
+            &src[span.start().to_usize()..span.end().to_usize()],
+            "<img src=\"http://example.com/logo.png\"\n     alt=\"Logo\">"
+        );
+
+        // mixed content only matters when the book is served over HTTPS
+        let cfg = Config {
+            site_url: Some(String::from("http://example.com/my-book/")),
+            ..cfg
+        };
+        assert_eq!(check_html(&[id], &files, &cfg).len(), 1);
+    }
+
+    #[test]
+    fn spans_inside_block_quotes_and_list_items() {
+        let src = "# Chapter 1\n\n\
+                   > <div>\n> <img src=\"quoted.png\">\n> </div>\n\n\
+                   - item\n\n  <div>\n  <img\n  src=\"listed.png\">\n\
+                   \x20 </div>\n";
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from(src));
+
+        let got = html_images(&[id], &files, &Config::default());
+
+        let spans: Vec<_> = got
+            .iter()
+            .map(|link| {
+                let span = link.span;
+                (
+                    link.href.as_str(),
+                    &src[span.start().to_usize()..span.end().to_usize()],
+                )
+            })
+            .collect();
+        assert_eq!(spans, vec![
+            ("quoted.png", "<img src=\"quoted.png\">"),
+            ("listed.png", "<img\n  src=\"listed.png\">"),
+        ]);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{Config, HashedRegex};
+use futures::future::BoxFuture;
+use http::{
+    header::{HeaderName, HeaderValue, LOCATION},
+    HeaderMap, Method, StatusCode,
+};
+use linkcheck::validation::Reason;
+use reqwest::{
+    tls::TlsInfo, Client, Request, Response, ResponseBuilderExt, Url,
+};
+use std::{
+    fmt::Debug,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+use tracing::Instrument;
+
+/// The maximum number of redirects followed for each request (the same as
+/// `reqwest`'s default policy).
+pub(crate) const MAX_REDIRECTS: usize = 10;
+
+/// Something which can send the web requests used to check links.
+///
+/// This is implemented for [`reqwest::Client`], which is what gets used by
+/// default. Other implementations can be given to
+/// [`crate::CheckerBuilder::http_client()`] to add custom authentication,
+/// sign requests, or return canned responses in tests so they don't need the
+/// network.
+///
+/// Redirects are followed by `mdbook-linkcheck` itself so each hop can be
+/// inspected (e.g. for [`crate::Config::suggest_rewrites`]), so an
+/// implementation should return redirect responses as-is. The [`Client`]s
+/// created from a [`Config`] never follow redirects.
+///
+/// A [`Response`] can be created from a [`http::Response`] (e.g.
+/// `http::Response::builder().status(404).body("").unwrap().into()`), and
+/// failures which aren't a HTTP status code (e.g. a refused connection)
+/// should be reported using [`Reason::Io`].
+pub trait HttpClient: Debug + Send + Sync {
+    /// Send a request, returning the response (even if it has an error status
+    /// code).
+    fn send(
+        &self,
+        request: Request,
+    ) -> BoxFuture<'_, Result<Response, Reason>>;
+
+    /// Send a `HEAD` request with these headers.
+    fn head(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+    ) -> BoxFuture<'_, Result<Response, Reason>> {
+        let mut request = Request::new(Method::HEAD, url);
+        *request.headers_mut() = headers;
+        self.send(request)
+    }
+}
+
+impl HttpClient for Client {
+    fn send(
+        &self,
+        request: Request,
+    ) -> BoxFuture<'_, Result<Response, Reason>> {
+        Box::pin(async move {
+            self.execute(request).await.map_err(Reason::from)
+        })
+    }
+}
+
+/// Counts the bytes in the response bodies downloaded through another
+/// [`HttpClient`] (see [`crate::Metrics::bytes_fetched`]).
+///
+/// Each body is read before the response is handed back, which is fine
+/// because the bodies we ask for are always read in full anyway (and `HEAD`
+/// responses don't have one).
+#[derive(Debug)]
+pub(crate) struct Counting<'a> {
+    inner: &'a dyn HttpClient,
+    bytes: AtomicU64,
+}
+
+impl<'a> Counting<'a> {
+    pub(crate) fn new(inner: &'a dyn HttpClient) -> Self {
+        Counting {
+            inner,
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> u64 { self.bytes.load(Ordering::Relaxed) }
+}
+
+impl HttpClient for Counting<'_> {
+    fn send(
+        &self,
+        request: Request,
+    ) -> BoxFuture<'_, Result<Response, Reason>> {
+        Box::pin(async move {
+            let response = self.inner.send(request).await?;
+            let mut buffered = http::Response::builder()
+                .status(response.status())
+                .version(response.version())
+                .url(response.url().clone());
+            if let Some(headers) = buffered.headers_mut() {
+                *headers = response.headers().clone();
+            }
+            if let Some(tls_info) = response.extensions().get::<TlsInfo>() {
+                buffered = buffered.extension(tls_info.clone());
+            }
+
+            let body = response.bytes().await?;
+            self.bytes.fetch_add(body.len() as u64, Ordering::Relaxed);
+
+            Ok(buffered
+                .body(body)
+                .expect("The parts came from a valid response")
+                .into())
+        })
+    }
+}
+
+/// Send a single request, printing it when [`Config::trace_http`] is enabled.
+///
+/// Every web request goes through here.
+pub(crate) async fn send(
+    http: &dyn HttpClient,
+    cfg: &Config,
+    request: Request,
+) -> Result<Response, Reason> {
+    let method = request.method().clone();
+    let url = request.url().clone();
+    let headers = request.headers().clone();
+    let span = tracing::debug_span!("http_request", %method, %url);
+
+    let start = Instant::now();
+    let response = http.send(request).instrument(span).await;
+    crate::http_trace::trace(
+        method.as_str(),
+        &url,
+        &headers,
+        response.as_ref().map_err(describe),
+        start.elapsed(),
+        cfg,
+    );
+
+    response
+}
+
+/// Describe why a request failed (the [`Reason`]'s own message just says
+/// which kind of error it was).
+pub(crate) fn describe(reason: &Reason) -> String {
+    match reason {
+        Reason::Io(e) => e.to_string(),
+        Reason::Web(e) => e.to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Send a `HEAD` request, following redirects the same way a browser would.
+///
+/// The headers for each hop come from `headers_for`, so credentials meant
+/// for one host aren't sent to another, and `on_redirect` is told about each
+/// redirect (the URL, where it redirects to, and the status code) as it is
+/// followed.
+pub(crate) async fn head_following_redirects<H, R>(
+    http: &dyn HttpClient,
+    cfg: &Config,
+    mut url: Url,
+    headers_for: H,
+    mut on_redirect: R,
+) -> Result<Response, Reason>
+where
+    H: Fn(&Url) -> HeaderMap,
+    R: FnMut(&Url, &Url, StatusCode),
+{
+    for _ in 0..=MAX_REDIRECTS {
+        let mut request = Request::new(Method::HEAD, url.clone());
+        *request.headers_mut() = headers_for(&url);
+        let response = send(http, cfg, request).await?;
+
+        let next = match redirect_target(&url, &response) {
+            Some(next) => next,
+            None => return Ok(response),
+        };
+        tracing::debug!("\"{}\" redirects to \"{}\"", url, next);
+        on_redirect(&url, &next, response.status());
+        url = next;
+    }
+
+    Err(Reason::Io(std::io::Error::other(format!(
+        "Too many redirects for \"{}\"",
+        url
+    ))))
+}
+
+/// The [`Config::http_headers`] (already interpolated, see
+/// [`Config::interpolate_headers()`]) to send with a request to this URL.
+pub(crate) fn headers_for(
+    interpolated: &[(HashedRegex, Vec<(HeaderName, HeaderValue)>)],
+    url: &Url,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for (pattern, matching_headers) in interpolated {
+        if pattern.find(url.as_str()).is_some() {
+            for (name, value) in matching_headers {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    headers
+}
+
+/// Where a redirect response sends us, if it is a redirect.
+pub(crate) fn redirect_target(url: &Url, response: &Response) -> Option<Url> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    url.join(location).ok()
+}
+
+/// Answers every request without touching the network, remembering which
+/// URLs were asked for.
+///
+/// URLs given a response with [`Canned::respond()`] get those responses (in
+/// order) first. Otherwise requests for `/missing` get a 404, `/offline`
+/// fails like a refused connection, `/hang` never gets a response, and
+/// everything else gets a 200.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct Canned {
+    pub(crate) requested: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    responses: std::sync::Mutex<
+        std::collections::HashMap<String, std::collections::VecDeque<Reply>>,
+    >,
+}
+
+/// A status code, headers and body to respond with.
+#[cfg(test)]
+type Reply = (u16, Vec<(String, String)>, String);
+
+#[cfg(test)]
+impl Canned {
+    /// Respond to the next request for a URL with this status code and
+    /// headers.
+    pub(crate) fn respond(
+        self,
+        url: &str,
+        status: u16,
+        headers: &[(&str, &str)],
+    ) -> Self {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .push_back((status, headers, String::new()));
+
+        self
+    }
+
+    /// Respond to the next request for a URL with a `200 OK` and this body.
+    pub(crate) fn respond_with_body(self, url: &str, body: &str) -> Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .push_back((200, Vec::new(), body.to_string()));
+
+        self
+    }
+}
+
+#[cfg(test)]
+impl HttpClient for Canned {
+    fn send(
+        &self,
+        request: Request,
+    ) -> BoxFuture<'_, Result<Response, Reason>> {
+        let url = request.url();
+        self.requested.lock().unwrap().push(url.to_string());
+        let canned = self
+            .responses
+            .lock()
+            .unwrap()
+            .get_mut(url.as_str())
+            .and_then(|responses| responses.pop_front());
+        let result = match (canned, url.path()) {
+            (Some(canned), _) => Ok(canned),
+            (None, "/missing") => Ok((404, Vec::new(), String::new())),
+            (None, "/offline") => {
+                Err(Reason::Io(std::io::ErrorKind::ConnectionRefused.into()))
+            },
+            (None, "/hang") => {
+                return Box::pin(futures::future::pending());
+            },
+            (None, _) => Ok((200, Vec::new(), String::new())),
+        };
+
+        Box::pin(async move {
+            let (status, headers, body) = result?;
+            let mut response = http::Response::builder().status(status);
+            for (name, value) in headers {
+                response = response.header(name, value);
+            }
+            Ok(Response::from(response.body(body).unwrap()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file_provider::in_memory_book, Checker, Config, FailureReason,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn web_requests_can_be_sent_by_something_else() {
+        let src = "[a](https://example.com/) [b](https://example.com/missing) \
+                   [c](https://example.com/offline)";
+        let (files, book) = in_memory_book(&[("chapter_1.md", src)]);
+        let client = Canned::default();
+        let requested = Arc::clone(&client.requested);
+
+        let report = Checker::builder()
+            .source_dir("/book/src")
+            .config(Config {
+                follow_web_links: true,
+                ..Default::default()
+            })
+            .book(book)
+            .file_provider(files)
+            .http_client(client)
+            .check()
+            .unwrap();
+
+        assert_eq!(requested.lock().unwrap().len(), 3);
+        assert_eq!(report.outcome.valid_links.len(), 1);
+        let mut reasons: Vec<_> = report
+            .outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| FailureReason::from(&invalid.reason))
+            .collect();
+        reasons.sort_by_key(|reason| format!("{:?}", reason));
+        assert!(
+            matches!(reasons[0], FailureReason::HttpStatus { status: 404, .. }),
+            "{:?}",
+            reasons
+        );
+        assert!(
+            matches!(reasons[1], FailureReason::Io { .. }),
+            "{:?}",
+            reasons
+        );
+    }
+
+    #[derive(Debug)]
+    struct Body(&'static str);
+
+    impl HttpClient for Body {
+        fn send(
+            &self,
+            request: Request,
+        ) -> BoxFuture<'_, Result<Response, Reason>> {
+            let response = http::Response::builder()
+                .status(200)
+                .header("x-requested", request.url().as_str())
+                .body(self.0)
+                .unwrap();
+            Box::pin(futures::future::ready(Ok(response.into())))
+        }
+    }
+
+    #[test]
+    fn count_the_bytes_in_response_bodies() {
+        let inner = Body("Hello, World!");
+        let counting = Counting::new(&inner);
+        let url: Url = "https://example.com/".parse().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let body = runtime.block_on(async {
+            let get = Request::new(Method::GET, url.clone());
+            let first = counting.send(get).await.unwrap();
+            assert_eq!(first.headers()["x-requested"], url.as_str());
+            let body = first.text().await.unwrap();
+            counting.head(url, HeaderMap::new()).await.unwrap();
+            body
+        });
+
+        // the body is still there after being counted
+        assert_eq!(body, "Hello, World!");
+        assert_eq!(counting.bytes(), 26);
+    }
+}
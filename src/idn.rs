@@ -0,0 +1,201 @@
+use reqwest::Url;
+use std::fmt::{self, Display, Formatter};
+
+/// A domain which could be mistaken for another one because its characters
+/// come from several scripts, or only look like they're Latin (e.g.
+/// `аpple.com` with a Cyrillic "а").
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Homograph {
+    /// The domain, as it would be displayed.
+    pub domain: String,
+    /// The domain's ASCII (punycode) form, which is what actually gets
+    /// requested.
+    pub ascii: String,
+    /// Why the domain looks suspicious.
+    pub kind: HomographKind,
+}
+
+/// Why a domain was flagged as a [`Homograph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HomographKind {
+    /// A single label (e.g. the `аpple` in `аpple.com`) mixes characters from
+    /// scripts which are easily confused with each other.
+    MixedScripts(Vec<Script>),
+    /// A label is written entirely in one script using only characters that
+    /// look like Latin letters (e.g. a Cyrillic `аре`).
+    LatinLookalike(Script),
+}
+
+/// The scripts with characters that are easily confused with Latin ones.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Script {
+    /// Latin, including accented letters.
+    Latin,
+    /// Greek (e.g. `ο` and `ν`).
+    Greek,
+    /// Cyrillic (e.g. `а` and `р`).
+    Cyrillic,
+    /// Armenian (e.g. `օ` and `ս`).
+    Armenian,
+    /// Cherokee (e.g. `Ꭺ` and `Ꮯ`).
+    Cherokee,
+}
+
+impl Display for Script {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(
+            match self {
+                Script::Latin => "Latin",
+                Script::Greek => "Greek",
+                Script::Cyrillic => "Cyrillic",
+                Script::Armenian => "Armenian",
+                Script::Cherokee => "Cherokee",
+            },
+            f,
+        )
+    }
+}
+
+impl Display for Homograph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            HomographKind::MixedScripts(ref scripts) => {
+                let names: Vec<String> =
+                    scripts.iter().map(|s| s.to_string()).collect();
+                write!(
+                    f,
+                    "\"{}\" mixes {} characters",
+                    self.domain,
+                    names.join(" and ")
+                )
+            },
+            HomographKind::LatinLookalike(script) => write!(
+                f,
+                "\"{}\" is made of {} characters which look like Latin ones",
+                self.domain, script
+            ),
+        }
+    }
+}
+
+/// Cyrillic and Greek letters which are indistinguishable from Latin ones in
+/// most fonts.
+const LATIN_LOOKALIKES: &str = "аеорсухіѕјӏԁԛԝһαικνορυχϲϳ";
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' => Some(Script::Latin),
+        '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => {
+            Some(Script::Latin)
+        },
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => {
+            Some(Script::Greek)
+        },
+        '\u{0400}'..='\u{052F}' | '\u{2DE0}'..='\u{2DFF}' => {
+            Some(Script::Cyrillic)
+        },
+        '\u{0530}'..='\u{058F}' => Some(Script::Armenian),
+        '\u{13A0}'..='\u{13FF}' => Some(Script::Cherokee),
+        // digits, hyphens, and scripts which don't look like Latin
+        _ => None,
+    }
+}
+
+/// Check the domain in a web link for homographs (see
+/// [`crate::Config::homograph_domains`]).
+///
+/// The link may spell the domain in Unicode or in punycode (`xn--...`), so
+/// we always decode it before looking at the characters.
+pub(crate) fn homograph(link: &str) -> Option<Homograph> {
+    let url = Url::parse(link).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let ascii = url.domain()?.trim_end_matches('.').to_string();
+    if !ascii.split('.').any(|label| label.starts_with("xn--")) {
+        // plain ASCII can't be a homograph
+        return None;
+    }
+    let (domain, _) = idna::domain_to_unicode(&ascii);
+
+    let kind = domain.split('.').find_map(check_label)?;
+
+    Some(Homograph {
+        domain,
+        ascii,
+        kind,
+    })
+}
+
+fn check_label(label: &str) -> Option<HomographKind> {
+    let mut scripts: Vec<Script> = label.chars().filter_map(script).collect();
+    scripts.sort();
+    scripts.dedup();
+
+    match scripts.as_slice() {
+        [] | [Script::Latin] => None,
+        [script] => {
+            let all_lookalikes = label
+                .chars()
+                .filter(|c| c.is_alphabetic())
+                .all(|c| LATIN_LOOKALIKES.contains(c));
+            if all_lookalikes {
+                Some(HomographKind::LatinLookalike(*script))
+            } else {
+                None
+            }
+        },
+        _ => Some(HomographKind::MixedScripts(scripts)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_homographs() {
+        let inputs = vec![
+            ("https://example.com/", None),
+            ("https://bücher.example/", None),
+            ("https://xn--bcher-kva.example/", None),
+            ("https://пример.рф/", None),
+            ("https://例え.jp/", None),
+            (
+                "https://аpple.com/",
+                Some(HomographKind::MixedScripts(vec![
+                    Script::Latin,
+                    Script::Cyrillic,
+                ])),
+            ),
+            (
+                "https://xn--pple-43d.com/",
+                Some(HomographKind::MixedScripts(vec![
+                    Script::Latin,
+                    Script::Cyrillic,
+                ])),
+            ),
+            (
+                "https://аре.com/",
+                Some(HomographKind::LatinLookalike(Script::Cyrillic)),
+            ),
+            ("./аpple.md", None),
+        ];
+
+        for (link, should_be) in inputs {
+            assert_eq!(homograph(link).map(|h| h.kind), should_be, "{}", link);
+        }
+    }
+
+    #[test]
+    fn domains_are_requested_as_punycode() {
+        let got = homograph("https://аpple.com/").unwrap();
+
+        assert_eq!(got.domain, "аpple.com");
+        assert_eq!(got.ascii, "xn--pple-43d.com");
+        assert_eq!(
+            got.to_string(),
+            "\"аpple.com\" mixes Latin and Cyrillic characters"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    config::is_web_link, html::html_images, web::Prober, Cache, Config,
+    HttpClient,
+};
+use codespan::{FileId, Files};
+use http::header::CONTENT_TYPE;
+use linkcheck::Link;
+use reqwest::Url;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+/// A remote image (e.g. `![logo](https://example.com/logo.png)`) which was
+/// served with something other than an `image/*` content type, so it would
+/// show up as a broken image even though the request succeeded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WrongContentType {
+    /// The image link.
+    pub link: Link,
+    /// The `Content-Type` the server responded with (e.g. `text/html`).
+    pub content_type: String,
+}
+
+/// Is this link a markdown image (i.e. `![alt](href)` or `![alt][ref]`)?
+pub(crate) fn is_image(link: &Link, files: &Files<String>) -> bool {
+    files
+        .source(link.file)
+        .get(link.span.start().to_usize()..)
+        .is_some_and(|src| src.starts_with("!["))
+}
+
+/// Look for remote images which aren't served as an `image/*`.
+///
+/// Markdown images were already requested while checking links, so the
+/// `content_types` they were served with are reused. Images in a HTML
+/// `<img>` tag aren't links, so they are sent a `HEAD` request, unless the
+/// cache says they were valid recently.
+///
+/// Images without a `Content-Type` header, or where the request fails, are
+/// given the benefit of the doubt.
+pub(crate) async fn wrong_content_types(
+    valid_links: &[Link],
+    content_types: &BTreeMap<String, String>,
+    cfg: &Config,
+    files: &Files<String>,
+    file_ids: &[FileId],
+    cache: &Cache,
+    http: &dyn HttpClient,
+) -> Vec<WrongContentType> {
+    let recorded = |link: &Link| {
+        let url = link.href.parse::<Url>().ok()?;
+        content_types.get(crate::normalize::normalize(&url).as_str())
+    };
+    let wrong_content_type = |link: &Link, content_type: &str| {
+        if is_image_content_type(content_type) {
+            return None;
+        }
+
+        Some(WrongContentType {
+            link: link.clone(),
+            content_type: content_type.to_string(),
+        })
+    };
+
+    let mut wrong: Vec<WrongContentType> = valid_links
+        .iter()
+        .filter(|link| is_web_link(&link.href) && is_image(link, files))
+        .filter_map(|link| wrong_content_type(link, recorded(link)?))
+        .collect();
+
+    // several tags can share an image
+    let mut unchecked: BTreeMap<String, Vec<Link>> = BTreeMap::new();
+    for link in html_images(file_ids, files, cfg) {
+        if !is_web_link(&link.href)
+            || !cfg.should_follow_web_link(&link.href)
+            || cfg.should_skip(&link.href)
+        {
+            continue;
+        }
+        match recorded(&link) {
+            Some(content_type) => {
+                wrong.extend(wrong_content_type(&link, content_type))
+            },
+            None if is_cached(&link, cache, cfg) => {},
+            None => unchecked.entry(link.href.clone()).or_default().push(link),
+        }
+    }
+
+    let prober = Prober::new(cfg, http);
+    let served = prober
+        .head_each(unchecked.values().map(|links| &links[0]), |link, response| {
+            let content_type =
+                response.headers().get(CONTENT_TYPE)?.to_str().ok()?;
+            Some((link.href.clone(), content_type.to_string()))
+        })
+        .await;
+    for (href, content_type) in served {
+        for link in &unchecked[&href] {
+            wrong.extend(wrong_content_type(link, &content_type));
+        }
+    }
+
+    wrong.sort_by_key(|w| (w.link.file, w.link.span));
+    wrong
+}
+
+fn is_cached(link: &Link, cache: &Cache, cfg: &Config) -> bool {
+    link.href.parse::<Url>().is_ok_and(|url| {
+        let url = crate::normalize::normalize(&url);
+        cache.url_is_still_valid(&url, cfg.longest_cache_timeout())
+    })
+}
+
+fn is_image_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+    mime.get(..6)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("image/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http_client::Canned, validate::validate_with, WarningPolicy,
+        WebConfig,
+    };
+    use codespan::Span;
+    use linkcheck::validation::CacheEntry;
+    use std::{sync::Arc, time::SystemTime};
+
+    fn image_config() -> Config {
+        Config {
+            follow_web_links: true,
+            web: WebConfig {
+                image_content_type: Some(WarningPolicy::Warn),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_markdown_images_are_images() {
+        let mut files = Files::new();
+        let src = "[text](https://example.com/a.png) ![alt](https://example.com/b.png)";
+        let id = files.add("chapter_1.md", String::from(src));
+
+        let links: Vec<Link> = linkcheck::scanners::markdown(src)
+            .map(|(href, span)| Link::new(href, span, id))
+            .collect();
+
+        assert!(!is_image(&links[0], &files));
+        assert!(is_image(&links[1], &files));
+        let out_of_bounds =
+            Link::new("https://example.com/", Span::new(500, 510), id);
+        assert!(!is_image(&out_of_bounds, &files));
+    }
+
+    #[test]
+    fn reuse_the_responses_from_checking_links() {
+        let client = Canned::default()
+            .respond("https://example.com/inline.png", 200, &[(
+                "Content-Type",
+                "image/png",
+            )])
+            .respond("https://example.com/reference.png", 200, &[(
+                "Content-Type",
+                "text/html",
+            )])
+            .respond("https://example.com/html.png", 200, &[(
+                "Content-Type",
+                "text/html",
+            )]);
+        let requested = Arc::clone(&client.requested);
+        let src = "![inline](https://example.com/inline.png)\n\n\
+                   ![reference][logo]\n\n\
+                   <img src=\"https://example.com/html.png\">\n\n\
+                   [logo]: https://example.com/reference.png\n";
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from(src));
+        let (links, _) = crate::links::extract(vec![id], &files);
+
+        let got = validate_with(&client, &links, &image_config(), &files);
+
+        let wrong: Vec<_> = got
+            .wrong_content_types
+            .iter()
+            .map(|w| w.link.href.as_str())
+            .collect();
+        assert_eq!(wrong, vec![
+            "https://example.com/reference.png",
+            "https://example.com/html.png"
+        ]);
+        // each image is only requested once
+        let mut requested = requested.lock().unwrap().clone();
+        requested.sort();
+        assert_eq!(requested, vec![
+            "https://example.com/html.png",
+            "https://example.com/inline.png",
+            "https://example.com/reference.png",
+        ]);
+    }
+
+    #[test]
+    fn cached_images_are_not_requested() {
+        let client = Canned::default();
+        let src = "<img src=\"https://example.com/logo.png\">";
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from(src));
+        let mut cache = Cache::default();
+        cache.insert(
+            "https://example.com/logo.png".parse().unwrap(),
+            CacheEntry::new(SystemTime::now(), true),
+        );
+
+        let got = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(wrong_content_types(
+                &[],
+                &BTreeMap::new(),
+                &image_config(),
+                &files,
+                &[id],
+                &cache,
+                &client,
+            ));
+
+        assert!(got.is_empty());
+        assert!(client.requested.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn recognise_image_content_types() {
+        let inputs = vec![
+            ("image/png", true),
+            ("IMAGE/svg+xml", true),
+            ("image/jpeg; charset=binary", true),
+            ("text/html; charset=utf-8", false),
+            ("application/octet-stream", false),
+            ("", false),
+        ];
+
+        for (content_type, should_be) in inputs {
+            assert_eq!(
+                is_image_content_type(content_type),
+                should_be,
+                "{}",
+                content_type
+            );
+        }
+    }
+}
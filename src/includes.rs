@@ -0,0 +1,455 @@
+use crate::FileProvider;
+use codespan::{FileId, Files, Span};
+use mdbook::book::{Book, BookItem};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_derive::Serialize;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+// mirrors the pattern mdbook uses for its own links preprocessor
+static DIRECTIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?x)
+        \\\{\{\#.*\}\}
+        | \{\{\s*\#(?:include|rustdoc_include)\s+([^}]+)\}\}",
+    )
+    .unwrap()
+});
+static ANCHOR_START: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"ANCHOR:\s*([\w_-]+)").unwrap());
+static ANCHOR_END: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"ANCHOR_END:\s*([\w_-]+)").unwrap());
+
+/// An `{{#include file.rs:some_anchor}}` directive which won't include what
+/// the author expected because of a problem with the `ANCHOR` markers in the
+/// included file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BrokenInclude {
+    /// The chapter containing the `{{#include}}`.
+    pub file: FileId,
+    /// Where the directive is in the chapter's source text.
+    pub span: Span,
+    /// The file being included.
+    pub target: PathBuf,
+    /// The name of the anchor.
+    pub anchor: String,
+    /// What went wrong.
+    pub problem: IncludeProblem,
+}
+
+/// The ways an anchored `{{#include}}` can go wrong.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum IncludeProblem {
+    /// There is no `ANCHOR: name` marker, so nothing gets included.
+    MissingAnchor,
+    /// There is an `ANCHOR: name` marker, but no `ANCHOR_END: name` after
+    /// it, so everything up to the end of the file gets included.
+    UnterminatedAnchor,
+}
+
+impl Display for BrokenInclude {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.problem {
+            IncludeProblem::MissingAnchor => write!(
+                f,
+                "There is no \"{}\" anchor in \"{}\"",
+                self.anchor,
+                self.target.display()
+            ),
+            IncludeProblem::UnterminatedAnchor => write!(
+                f,
+                "The \"{}\" anchor in \"{}\" is never closed",
+                self.anchor,
+                self.target.display()
+            ),
+        }
+    }
+}
+
+/// Look for `{{#include}}` and `{{#rustdoc_include}}` directives which refer
+/// to missing or unbalanced `ANCHOR` markers.
+///
+/// By the time a backend sees the book, `mdbook` has already expanded every
+/// `{{#include}}`, so this reads each chapter's original source using the
+/// [`FileProvider`]. If anything is wrong the original source is added to
+/// `files` so diagnostics can point at the directive, unless it is the same
+/// as the chapter already loaded into `files` (one of the `chapters`).
+pub(crate) fn check_includes<F>(
+    book: &Book,
+    src_dir: &Path,
+    files: &mut Files<String>,
+    chapters: &[FileId],
+    file_provider: &dyn FileProvider,
+    filter: F,
+) -> Vec<BrokenInclude>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut broken = Vec::new();
+
+    for item in book.iter() {
+        let ch = match item {
+            BookItem::Chapter(ch) => ch,
+            BookItem::Separator | BookItem::PartTitle(_) => continue,
+        };
+        let path = match ch.path {
+            Some(ref path) if filter(path) => path,
+            _ => continue,
+        };
+        let source_path = src_dir.join(ch.source_path.as_ref().unwrap_or(path));
+        let src = match file_provider.read_to_string(&source_path) {
+            Ok(src) => src,
+            Err(_) => continue,
+        };
+
+        let chapter_dir = source_path.parent().unwrap_or(src_dir);
+        let problems: Vec<_> = anchored_includes(&src)
+            .into_iter()
+            .filter_map(|(span, target, anchor)| {
+                let target = chapter_dir.join(target);
+                let problem = check_anchor(&target, &anchor, file_provider)?;
+                Some((span, target, anchor, problem))
+            })
+            .collect();
+
+        if problems.is_empty() {
+            continue;
+        }
+
+        let name = path.display().to_string();
+        let loaded = chapters.iter().copied().find(|&id| {
+            files.name(id) == name.as_str() && files.source(id) == &src
+        });
+        let file = match loaded {
+            Some(file) => file,
+            None => files.add(name, src),
+        };
+        broken.extend(problems.into_iter().map(
+            |(span, target, anchor, problem)| BrokenInclude {
+                file,
+                span,
+                target,
+                anchor,
+                problem,
+            },
+        ));
+    }
+
+    broken
+}
+
+/// Find every `{{#include path:anchor}}` directive, returning where it is,
+/// the path, and the anchor name. Includes using line ranges are skipped.
+fn anchored_includes(src: &str) -> Vec<(Span, String, String)> {
+    include_directives(src)
+        .into_iter()
+        .filter_map(|(span, path, anchor)| {
+            let anchor = anchor?;
+
+            if anchor.is_empty() || is_line_range(&anchor) {
+                None
+            } else {
+                Some((span, path, anchor))
+            }
+        })
+        .collect()
+}
+
+/// Get the paths of every file pulled in with a `{{#include}}` or
+/// `{{#rustdoc_include}}` directive, relative to the chapter.
+pub(crate) fn included_files(src: &str) -> Vec<String> {
+    include_directives(src)
+        .into_iter()
+        .map(|(_, path, _)| path)
+        .collect()
+}
+
+/// How deeply `{{#include}}`s can be nested, the same limit `mdbook` uses.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Replace every `{{#include}}` and `{{#rustdoc_include}}` directive with the
+/// text it pulls in (resolved relative to `dir`), so headings in included
+/// files end up in the chapter the same way they would after `mdbook`'s
+/// links preprocessor runs. Directives which can't be read are left alone.
+pub(crate) fn expand_includes(
+    src: &str,
+    dir: &Path,
+    files: &dyn FileProvider,
+) -> String {
+    expand(src, dir, files, 0)
+}
+
+fn expand(
+    src: &str,
+    dir: &Path,
+    files: &dyn FileProvider,
+    depth: usize,
+) -> String {
+    if depth >= MAX_INCLUDE_DEPTH {
+        return src.to_string();
+    }
+
+    let mut expanded = String::with_capacity(src.len());
+    let mut last_end = 0;
+
+    for (span, path, selector) in include_directives(src) {
+        let target = dir.join(path);
+        let included = match files.read_to_string(&target) {
+            Ok(included) => included,
+            Err(_) => continue,
+        };
+        let included = select(&included, selector.as_deref());
+        let target_dir = target.parent().unwrap_or(dir);
+
+        expanded.push_str(&src[last_end..span.start().to_usize()]);
+        expanded.push_str(&expand(&included, target_dir, files, depth + 1));
+        last_end = span.end().to_usize();
+    }
+
+    expanded.push_str(&src[last_end..]);
+    expanded
+}
+
+/// Pick out the part of an included file asked for by whatever came after
+/// the path (a line range like `10:20` or an anchor name).
+fn select(src: &str, selector: Option<&str>) -> String {
+    let selector = match selector {
+        Some(selector) if !selector.is_empty() => selector,
+        _ => return src.to_string(),
+    };
+    let lines: Vec<&str> = src.lines().collect();
+
+    if is_line_range(selector) {
+        // line numbers are one-based and the end is inclusive
+        let mut bounds = selector.splitn(2, ':').map(|n| n.parse::<usize>());
+        let start = match bounds.next() {
+            Some(Ok(start)) => start.saturating_sub(1),
+            _ => 0,
+        };
+        let end = match bounds.next() {
+            Some(Ok(end)) => end,
+            // "file.rs:10" means line 10 onwards
+            _ => lines.len(),
+        };
+        let end = end.min(lines.len());
+        return lines.get(start..end).unwrap_or_default().join("\n");
+    }
+
+    let (start, end) = (&*ANCHOR_START, &*ANCHOR_END);
+    let is_marker = |marker: &Regex, line: &str| {
+        marker
+            .captures(line)
+            .is_some_and(|caps| &caps[1] == selector)
+    };
+    lines
+        .iter()
+        .skip_while(|line| !is_marker(start, line))
+        .skip(1)
+        .take_while(|line| !is_marker(end, line))
+        // markers for other anchors aren't included either
+        .filter(|line| !start.is_match(line) && !end.is_match(line))
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Is this something like `10:20`, `10`, or `:20`?
+fn is_line_range(selector: &str) -> bool {
+    selector.chars().all(|c| c.is_ascii_digit() || c == ':')
+}
+
+/// Find every `{{#include}}` directive, returning where it is, the path, and
+/// whatever came after the first `:` (an anchor name or line range).
+fn include_directives(src: &str) -> Vec<(Span, String, Option<String>)> {
+    DIRECTIVE
+        .captures_iter(src)
+        .filter_map(|caps| {
+            // escaped (e.g. "\{{#include ...}}") directives won't have any
+            // arguments
+            let args = caps.get(1)?.as_str().trim();
+            let mut parts = args.splitn(2, ':');
+            let path = parts.next()?.trim();
+            let rest = parts.next().map(|rest| rest.trim().to_string());
+
+            let whole = caps.get(0)?;
+            Some((
+                Span::new(whole.start() as u32, whole.end() as u32),
+                path.to_string(),
+                rest,
+            ))
+        })
+        .collect()
+}
+
+fn check_anchor(
+    target: &Path,
+    anchor: &str,
+    files: &dyn FileProvider,
+) -> Option<IncludeProblem> {
+    match files.read_to_string(target) {
+        Ok(src) => anchor_problem(&src, anchor),
+        Err(e) => {
+            // mdbook already complains loudly about missing includes
+            tracing::debug!(
+                "Unable to read \"{}\" to check its anchors: {}",
+                target.display(),
+                e
+            );
+            None
+        },
+    }
+}
+
+/// Check that `anchor` is opened and closed in the included file, using the
+/// same rules as `mdbook`.
+fn anchor_problem(src: &str, anchor: &str) -> Option<IncludeProblem> {
+    let mut opened = false;
+    let is_marker = |marker: &Regex, line: &str| {
+        marker.captures(line).is_some_and(|caps| &caps[1] == anchor)
+    };
+
+    for line in src.lines() {
+        if opened {
+            if is_marker(&ANCHOR_END, line) {
+                return None;
+            }
+        } else if is_marker(&ANCHOR_START, line) {
+            opened = true;
+        }
+    }
+
+    if opened {
+        Some(IncludeProblem::UnterminatedAnchor)
+    } else {
+        Some(IncludeProblem::MissingAnchor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file_provider::in_memory_book, InMemoryFiles};
+    use codespan_reporting::diagnostic::Severity;
+
+    #[test]
+    fn only_anchored_includes_are_found() {
+        let src = r"
+{{#include file.rs:some_anchor}}
+{{ #rustdoc_include ../other.rs:main }}
+{{#include file.rs}}
+{{#include file.rs:10:20}}
+{{#include file.rs::20}}
+\{{#include file.rs:escaped}}
+";
+
+        let got: Vec<_> = anchored_includes(src)
+            .into_iter()
+            .map(|(_, path, anchor)| (path, anchor))
+            .collect();
+
+        assert_eq!(
+            got,
+            vec![
+                (String::from("file.rs"), String::from("some_anchor")),
+                (String::from("../other.rs"), String::from("main")),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_missing_and_unbalanced_anchors() {
+        let src = "
+// ANCHOR: all
+// ANCHOR: main
+fn main() {}
+// ANCHOR_END: main
+// ANCHOR: unterminated
+// ANCHOR_END: all
+";
+
+        assert_eq!(anchor_problem(src, "main"), None);
+        assert_eq!(anchor_problem(src, "all"), None);
+        assert_eq!(
+            anchor_problem(src, "unterminated"),
+            Some(IncludeProblem::UnterminatedAnchor)
+        );
+        assert_eq!(
+            anchor_problem(src, "renamed"),
+            Some(IncludeProblem::MissingAnchor)
+        );
+        // names need to match exactly
+        assert_eq!(
+            anchor_problem(src, "mai"),
+            Some(IncludeProblem::MissingAnchor)
+        );
+    }
+
+    #[test]
+    fn broken_includes_point_at_the_loaded_chapter() {
+        let src_dir = Path::new("/book/src");
+        let src = "# Chapter\n\n{{#include code.rs:gone}}\n";
+        let (mut file_provider, book) =
+            in_memory_book(&[("chapter_1.md", src)]);
+        file_provider.insert(src_dir.join("code.rs"), "fn main() {}\n");
+        let mut files = Files::new();
+        let chapters =
+            crate::load_files_into_memory(&book, &mut files, |_| true);
+
+        let got = check_includes(
+            &book,
+            src_dir,
+            &mut files,
+            &chapters,
+            &file_provider,
+            |_| true,
+        );
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].file, chapters[0]);
+        assert_eq!(got[0].problem, IncludeProblem::MissingAnchor);
+
+        let outcome = crate::ValidationOutcome {
+            broken_includes: got,
+            ..Default::default()
+        };
+        let ignored = crate::Config {
+            missing_include_anchors: crate::WarningPolicy::Ignore,
+            ..Default::default()
+        };
+        assert!(outcome.generate_diagnostics(&files, &ignored).is_empty());
+        let diags =
+            outcome.generate_diagnostics(&files, &crate::Config::default());
+        assert_eq!(diags[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn expand_nested_includes() {
+        let dir = Path::new("/book/src");
+        let mut files = InMemoryFiles::new();
+        files.insert(
+            dir.join("nested/part.md"),
+            "## Included Heading\n\n{{#include ./lines.md:2:3}}\n",
+        );
+        files.insert(dir.join("nested/lines.md"), "one\ntwo\nthree\nfour\n");
+        files.insert(
+            dir.join("code.rs"),
+            "// ANCHOR: main\n// ANCHOR: inner\nfn main() {}\n\
+             // ANCHOR_END: inner\n// ANCHOR_END: main\nfn other() {}\n",
+        );
+        let src = "# Chapter\n{{#include nested/part.md}}\n\
+                   {{#rustdoc_include code.rs:main}}\n{{#include missing.md}}";
+
+        let got = expand_includes(src, dir, &files);
+
+        assert_eq!(
+            got,
+            "# Chapter\n## Included Heading\n\ntwo\nthree\n\n\
+             fn main() {}\n{{#include missing.md}}"
+        );
+    }
+}
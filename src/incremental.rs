@@ -0,0 +1,264 @@
+use crate::{changes::local_target, config::is_web_link, Config};
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::Diagnostic;
+use crate::Cache;
+use linkcheck::Link;
+use mdbook::book::{Book, BookItem};
+use reqwest::Url;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// A record of the chapters which had no problems the last time they were
+/// checked, used to skip them on the next run if nothing they depend on has
+/// changed.
+///
+/// See [`Config::incremental`] for more.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChapterIndex {
+    /// A hash of the configuration and the book's layout. If either changes,
+    /// every chapter needs to be checked again.
+    fingerprint: u64,
+    chapters: HashMap<PathBuf, ChapterState>,
+    /// The anchors in every file linked to last time, keyed by a hash of the
+    /// file's contents, so unchanged files don't need to be parsed again.
+    #[serde(default)]
+    anchors: HashMap<u64, HashSet<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChapterState {
+    content: u64,
+    /// The hash of every local file this chapter links to (`None` if the file
+    /// couldn't be read).
+    targets: BTreeMap<PathBuf, Option<u64>>,
+    web_links: Vec<String>,
+}
+
+impl ChapterIndex {
+    /// Find the chapters which can be skipped because they were fine last
+    /// time and nothing they depend on has changed since.
+    pub(crate) fn unchanged_chapters(
+        &self,
+        book: &Book,
+        src_dir: &Path,
+        cfg: &Config,
+        cache: &Cache,
+    ) -> HashSet<PathBuf> {
+        if self.fingerprint != fingerprint(book, cfg) {
+            tracing::debug!(
+                "The book's layout or config changed since last time"
+            );
+            return HashSet::new();
+        }
+
+        let timeout = cfg.longest_cache_timeout();
+        let web_link_is_fresh = |href: &String| {
+            if cfg.should_skip(href) || !cfg.should_follow_web_link(href) {
+                return true;
+            }
+
+            match href.parse::<Url>() {
+                Ok(url) => cache.url_is_still_valid(
+                    &crate::normalize::normalize(&url),
+                    timeout,
+                ),
+                Err(_) => false,
+            }
+        };
+
+        chapters(book)
+            .filter(|(path, content)| match self.chapters.get(*path) {
+                Some(state) => {
+                    state.content == hash(content.as_bytes())
+                        && state.targets.iter().all(|(target, previous)| {
+                            hash_file(&src_dir.join(target)) == *previous
+                        })
+                        && state.web_links.iter().all(web_link_is_fresh)
+                },
+                None => false,
+            })
+            .map(|(path, _)| path.to_path_buf())
+            .collect()
+    }
+
+    /// Get a copy of this index to be updated after checking a book, throwing
+    /// everything away if the book's layout or config has changed.
+    pub(crate) fn for_book(&self, book: &Book, cfg: &Config) -> ChapterIndex {
+        let fingerprint = fingerprint(book, cfg);
+
+        if fingerprint == self.fingerprint {
+            self.clone()
+        } else {
+            ChapterIndex {
+                fingerprint,
+                chapters: HashMap::new(),
+                // anchors only depend on the file's contents
+                anchors: self.anchors.clone(),
+            }
+        }
+    }
+
+    /// The anchors found in each file last time, keyed by a hash of the
+    /// file's contents.
+    pub(crate) fn known_anchors(&self) -> HashMap<u64, HashSet<String>> {
+        self.anchors.clone()
+    }
+
+    /// Remember the anchors found during this run, forgetting any files which
+    /// weren't linked to.
+    pub(crate) fn record_anchors(
+        &mut self,
+        anchors: HashMap<u64, HashSet<String>>,
+    ) {
+        self.anchors = anchors;
+    }
+
+    /// Update the index with the results of checking some chapters.
+    ///
+    /// Chapters that were checked without any problems are recorded, and
+    /// everything else is removed so it'll be checked again next time.
+    pub(crate) fn record_checked_chapters(
+        &mut self,
+        src_dir: &Path,
+        files: &Files<String>,
+        file_ids: &[FileId],
+        links: &[Link],
+        diags: &[Diagnostic<FileId>],
+    ) {
+        let with_problems: HashSet<FileId> = diags
+            .iter()
+            .flat_map(|diag| diag.labels.iter().map(|label| label.file_id))
+            .collect();
+        let mut links_by_file: HashMap<FileId, Vec<&Link>> = HashMap::new();
+        for link in links {
+            links_by_file.entry(link.file).or_default().push(link);
+        }
+
+        for &id in file_ids {
+            let chapter = PathBuf::from(files.name(id));
+
+            if with_problems.contains(&id) {
+                self.chapters.remove(&chapter);
+                continue;
+            }
+
+            let mut targets = BTreeMap::new();
+            let mut web_links = Vec::new();
+
+            for link in links_by_file.get(&id).into_iter().flatten() {
+                if is_web_link(&link.href) {
+                    web_links.push(link.href.clone());
+                } else if let Some(target) = local_target(&chapter, &link.href)
+                {
+                    let hash = hash_file(&src_dir.join(&target));
+                    targets.insert(target, hash);
+                }
+            }
+
+            let state = ChapterState {
+                content: hash(files.source(id).as_bytes()),
+                targets,
+                web_links,
+            };
+            self.chapters.insert(chapter, state);
+        }
+    }
+}
+
+fn chapters(book: &Book) -> impl Iterator<Item = (&Path, &str)> {
+    book.iter().filter_map(|item| match item {
+        BookItem::Chapter(ref ch) => {
+            ch.path.as_deref().map(|path| (path, ch.content.as_str()))
+        },
+        _ => None,
+    })
+}
+
+fn fingerprint(book: &Book, cfg: &Config) -> u64 {
+    // Note: going through a `Value` sorts the keys of any maps, so the
+    // `http-headers` table always serializes the same way
+    let mut buffer = serde_json::to_value(cfg)
+        .and_then(|value| serde_json::to_vec(&value))
+        .unwrap_or_default();
+
+    for (path, _) in chapters(book) {
+        buffer.extend(path.display().to_string().bytes());
+        buffer.push(0);
+    }
+
+    hash(&buffer)
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    std::fs::read(path).ok().map(|bytes| hash(&bytes))
+}
+
+/// A 64-bit FNV-1a hash. We can't use the standard library's hasher because
+/// its output isn't guaranteed to be stable across releases.
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdbook::book::Chapter;
+    use std::convert::TryInto;
+
+    #[test]
+    fn the_fingerprint_is_stable() {
+        let headers = vec!["Accept: text/html".try_into().unwrap()];
+        let cfg = || Config {
+            http_headers: ["a", "b", "c", "d"]
+                .iter()
+                .map(|pattern| (pattern.parse().unwrap(), headers.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        let book = Book::new();
+
+        assert_eq!(fingerprint(&book, &cfg()), fingerprint(&book, &cfg()));
+    }
+
+    #[test]
+    fn skip_clean_chapters_until_something_they_link_to_changes() {
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("plain-directory");
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Chapter",
+            "[Guide](docs/guide.md)".to_string(),
+            "chapter.md",
+            Vec::new(),
+        ));
+        let cfg = Config::default();
+        let mut files = Files::new();
+        let file_ids =
+            crate::load_files_into_memory(&book, &mut files, |_| true);
+        let (links, _) = crate::extract_links(file_ids.clone(), &files);
+
+        let mut index = ChapterIndex::default().for_book(&book, &cfg);
+        index.record_checked_chapters(&src_dir, &files, &file_ids, &links, &[]);
+
+        let got =
+            index.unchanged_chapters(&book, &src_dir, &cfg, &Cache::default());
+        assert!(got.contains(Path::new("chapter.md")));
+
+        // pretend the guide was edited
+        index
+            .chapters
+            .get_mut(Path::new("chapter.md"))
+            .unwrap()
+            .targets
+            .insert(PathBuf::from("docs/guide.md"), Some(42));
+        let got =
+            index.unchanged_chapters(&book, &src_dir, &cfg, &Cache::default());
+        assert!(got.is_empty());
+    }
+}
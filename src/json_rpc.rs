@@ -0,0 +1,228 @@
+use anyhow::{Context as _, Error};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, Write},
+    sync::Mutex,
+};
+
+/// How messages are separated from each other on the wire.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Framing {
+    /// One message per line (used by [`crate::serve()`]).
+    Lines,
+    /// Each message is preceded by a `Content-Length` header, like the
+    /// [Language Server Protocol][lsp].
+    ///
+    /// [lsp]: https://microsoft.github.io/language-server-protocol/
+    Headers,
+}
+
+/// Something which answers [JSON-RPC 2.0][rpc] requests.
+///
+/// [rpc]: https://www.jsonrpc.org/specification
+pub(crate) trait Handler {
+    /// Handle a single request or notification, returning `None` if the
+    /// method isn't supported.
+    ///
+    /// The `exit` notification is handled by [`run()`].
+    fn handle(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Option<Result<Value, Error>>;
+
+    /// Should the server stop as soon as it has answered a `shutdown`
+    /// request, instead of waiting for the `exit` notification?
+    fn exit_on_shutdown(&self) -> bool { false }
+}
+
+/// Read messages from `input` and give them to the `handler`, writing each
+/// response to `output`, until `input` is closed or the client sends an
+/// `exit` notification.
+///
+/// Notifications never get a response, so their errors are only logged.
+pub(crate) fn run<R, W, H>(
+    mut input: R,
+    output: &Mutex<W>,
+    framing: Framing,
+    handler: &mut H,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+    H: Handler,
+{
+    while let Some(body) = read_message(&mut input, framing)? {
+        let message: Value = match serde_json::from_str(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = error(&Value::Null, -32700, e.to_string());
+                write_message(output, framing, &error)?;
+                continue;
+            },
+        };
+        let id = &message["id"];
+        let method = message["method"].as_str().unwrap_or_default();
+
+        if method == "exit" {
+            return Ok(());
+        }
+
+        let response = match handler.handle(method, &message["params"]) {
+            Some(Ok(_)) | None if id.is_null() => None,
+            Some(Err(e)) if id.is_null() => {
+                tracing::warn!("Unable to handle \"{}\": {:?}", method, e);
+                None
+            },
+            Some(Ok(result)) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result,
+            })),
+            Some(Err(e)) => Some(error(id, -32000, format!("{:#}", e))),
+            None => {
+                let msg = format!("Unknown method: {}", method);
+                Some(error(id, -32601, msg))
+            },
+        };
+
+        if let Some(response) = response {
+            write_message(output, framing, &response)?;
+        }
+
+        if method == "shutdown" && handler.exit_on_shutdown() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a notification to the client.
+pub(crate) fn notify<W: Write>(
+    output: &Mutex<W>,
+    framing: Framing,
+    method: &str,
+    params: Value,
+) -> Result<(), Error> {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+    });
+    write_message(output, framing, &notification)
+}
+
+fn error(id: &Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Read the body of a single message, returning `None` when the client hangs
+/// up.
+fn read_message<R: BufRead>(
+    input: &mut R,
+    framing: Framing,
+) -> Result<Option<String>, Error> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        match framing {
+            Framing::Lines if line.trim().is_empty() => continue,
+            Framing::Lines => return Ok(Some(line)),
+            Framing::Headers => {},
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+
+    let content_length = content_length
+        .context("The message didn't have a Content-Length header")?;
+    let mut body = vec![0; content_length];
+    input.read_exact(&mut body)?;
+
+    String::from_utf8(body)
+        .context("The message wasn't valid UTF-8")
+        .map(Some)
+}
+
+fn write_message<W: Write>(
+    output: &Mutex<W>,
+    framing: Framing,
+    message: &Value,
+) -> Result<(), Error> {
+    let body = serde_json::to_string(message)?;
+    let mut output = output.lock().unwrap();
+    match framing {
+        Framing::Lines => writeln!(output, "{}", body)?,
+        Framing::Headers => {
+            write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?
+        },
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl Handler for Echo {
+        fn handle(
+            &mut self,
+            method: &str,
+            params: &Value,
+        ) -> Option<Result<Value, Error>> {
+            match method {
+                "echo" => Some(Ok(params.clone())),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn bad_messages_get_an_error_and_exit_stops_the_server() {
+        let message = |body: &str| {
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        };
+        let input = [
+            message("{ not json"),
+            message(r#"{"jsonrpc":"2.0","id":1,"method":"echo","params":7}"#),
+            message(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+            message(r#"{"jsonrpc":"2.0","id":2,"method":"echo","params":8}"#),
+        ]
+        .concat();
+        let output = Mutex::new(Vec::new());
+
+        run(input.as_bytes(), &output, Framing::Headers, &mut Echo).unwrap();
+
+        let output = String::from_utf8(output.into_inner().unwrap()).unwrap();
+        let got: Vec<Value> = output
+            .split("Content-Length: ")
+            .filter_map(|msg| msg.split_once("\r\n\r\n"))
+            .map(|(_, body)| serde_json::from_str(body).unwrap())
+            .collect();
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0]["error"]["code"], -32700);
+        assert_eq!(got[1]["result"], 7);
+    }
+}
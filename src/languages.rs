@@ -0,0 +1,245 @@
+use crate::{file_provider, Config, FileProvider};
+use anyhow::{Context, Error};
+use mdbook::{
+    book::{Book, BookItem},
+    config::BuildConfig,
+};
+use std::{
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+/// The directories inside the source directory which each hold a different
+/// language of a translated book (e.g. `src/en/SUMMARY.md` and
+/// `src/ja/SUMMARY.md`).
+///
+/// Absolute links (e.g. `/chapter_1.md`) in a chapter are resolved relative
+/// to its own language, and links to pages which haven't been translated yet
+/// fall back to the [`Config::default_language`], like the rendered book
+/// does.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Languages {
+    /// Each language's directory, relative to the source directory.
+    roots: Vec<PathBuf>,
+    default: Option<PathBuf>,
+}
+
+impl Languages {
+    /// Find the language directories, using the [`Config::translations`]
+    /// inside the source directory if there are any, otherwise treating each
+    /// chapter's top-level directory as a language if it has its own
+    /// `SUMMARY.md`.
+    pub(crate) fn new(
+        cfg: &Config,
+        root: &Path,
+        file_names: &[OsString],
+        files: &dyn FileProvider,
+    ) -> Languages {
+        let mut roots: Vec<PathBuf> = if cfg.translations.is_empty() {
+            file_names
+                .iter()
+                .filter_map(|name| match Path::new(name).components().next() {
+                    Some(Component::Normal(dir)) => Some(PathBuf::from(dir)),
+                    _ => None,
+                })
+                .filter(|dir| {
+                    let summary = root.join(dir).join("SUMMARY.md");
+                    files.canonicalize(&summary).is_ok()
+                })
+                .collect()
+        } else {
+            let book_root = root
+                .ancestors()
+                .find(|dir| files.canonicalize(&dir.join("book.toml")).is_ok())
+                .unwrap_or(root);
+            cfg.translations
+                .iter()
+                .filter_map(|raw| files.canonicalize(&book_root.join(raw)).ok())
+                .filter_map(|dir| Some(dir.strip_prefix(root).ok()?.to_owned()))
+                .filter(|dir| dir.components().next().is_some())
+                .collect()
+        };
+        roots.sort();
+        roots.dedup();
+
+        let default = cfg.default_language.as_ref().and_then(|language| {
+            roots
+                .iter()
+                .find(|dir| dir.file_name() == Some(language.as_ref()))
+                .cloned()
+        });
+
+        Languages { roots, default }
+    }
+
+    /// The directory (relative to the source directory) of the language a
+    /// path is in.
+    fn language_of(&self, relative: &Path) -> Option<&Path> {
+        self.roots
+            .iter()
+            .find(|dir| relative.starts_with(dir))
+            .map(|dir| dir.as_path())
+    }
+
+    /// The directory absolute links from a chapter in `current_dir` are
+    /// relative to.
+    pub(crate) fn root_for(&self, root: &Path, current_dir: &Path) -> PathBuf {
+        match current_dir
+            .strip_prefix(root)
+            .ok()
+            .and_then(|relative| self.language_of(relative))
+        {
+            Some(language) => root.join(language),
+            None => root.to_path_buf(),
+        }
+    }
+
+    /// Where a page which doesn't exist in its own language would be found
+    /// in the default language.
+    pub(crate) fn fallback(
+        &self,
+        root: &Path,
+        target: &Path,
+    ) -> Option<PathBuf> {
+        let default = self.default.as_ref()?;
+        let target = file_provider::normalize(target);
+        let relative = target.strip_prefix(root).ok()?;
+        let language = self.language_of(relative)?;
+
+        if language == default {
+            return None;
+        }
+
+        let within_language = relative.strip_prefix(language).ok()?;
+        Some(root.join(default).join(within_language))
+    }
+}
+
+/// Load a book which has a `SUMMARY.md` for each language instead of one at
+/// the top of the source directory, returning `None` if there aren't any.
+///
+/// Each language's chapters are added one after the other, keeping their
+/// language's directory at the start of their path.
+pub(crate) fn load_book(
+    src_dir: &Path,
+    files: &dyn FileProvider,
+) -> Result<Option<Book>, Error> {
+    let mut languages: Vec<PathBuf> = files
+        .read_dir(src_dir)?
+        .into_iter()
+        .filter(|dir| {
+            let summary = dir.join("SUMMARY.md");
+            files.canonicalize(&summary).is_ok() && !files.is_dir(&summary)
+        })
+        .collect();
+    languages.sort();
+
+    if languages.is_empty() {
+        return Ok(None);
+    }
+
+    // we're only reading the book, so make sure mdbook doesn't try to create
+    // any chapters that are missing
+    let build_cfg = BuildConfig {
+        create_missing: false,
+        ..Default::default()
+    };
+    let mut book = Book::new();
+
+    for dir in languages {
+        let mut translation = mdbook::book::load_book(&dir, &build_cfg)
+            .with_context(|| {
+                format!("Unable to load the book in \"{}\"", dir.display())
+            })?;
+        let language = dir.strip_prefix(src_dir).unwrap_or(&dir).to_owned();
+
+        translation.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = item {
+                for path in [&mut ch.path, &mut ch.source_path] {
+                    *path = path.take().map(|path| language.join(path));
+                }
+            }
+        });
+        for item in translation.sections {
+            book.push_item(item);
+        }
+    }
+
+    Ok(Some(book))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file_provider::in_memory_book, Checker, InMemoryFiles};
+
+    fn localized_book() -> (InMemoryFiles, Book) {
+        let chapters = [
+            ("en/SUMMARY.md", ""),
+            ("en/intro.md", "# Intro\n\n[Setup](/setup.md)"),
+            ("en/setup.md", "# Setup\n\n[Advanced](./advanced.md#usage)"),
+            ("en/advanced.md", "# Usage"),
+            ("ja/SUMMARY.md", ""),
+            ("ja/intro.md", "# はじめに\n\n[Setup](/setup.md)"),
+            ("ja/setup.md", "# Setup\n\n[Advanced](./advanced.md#usage)"),
+        ];
+
+        in_memory_book(&chapters)
+    }
+
+    #[test]
+    fn resolve_links_within_their_own_language() {
+        let (files, _) = localized_book();
+        let root = Path::new("/book/src");
+        let file_names: Vec<OsString> =
+            vec!["en/intro.md".into(), "ja/intro.md".into(), "x.md".into()];
+        let cfg = Config {
+            default_language: Some(String::from("en")),
+            ..Default::default()
+        };
+
+        let languages = Languages::new(&cfg, root, &file_names, &files);
+
+        assert_eq!(languages.roots, vec![Path::new("en"), Path::new("ja")]);
+        assert_eq!(
+            languages.root_for(root, &root.join("ja").join("nested")),
+            root.join("ja")
+        );
+        assert_eq!(languages.root_for(root, root), root);
+        assert_eq!(
+            languages.fallback(root, &root.join("ja/nested/../advanced.md")),
+            Some(root.join("en").join("advanced.md"))
+        );
+        let already_default = root.join("en").join("advanced.md");
+        assert_eq!(languages.fallback(root, &already_default), None);
+    }
+
+    #[test]
+    fn untranslated_pages_fall_back_to_the_default_language() {
+        let (files, book) = localized_book();
+
+        let check = |default_language: Option<&str>| {
+            let report = Checker::builder()
+                .source_dir("/book/src")
+                .config(Config {
+                    default_language: default_language.map(String::from),
+                    ..Default::default()
+                })
+                .book(book.clone())
+                .file_provider(files.clone())
+                .check()
+                .unwrap();
+            let mut broken: Vec<_> = report
+                .outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| invalid.link.href.clone())
+                .collect();
+            broken.sort();
+            broken
+        };
+
+        assert!(check(Some("en")).is_empty());
+        assert_eq!(check(None), vec!["./advanced.md#usage"]);
+    }
+}
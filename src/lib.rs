@@ -8,6 +8,9 @@
 //! 3. Cache the results in the output directory for reuse by step 2 in the next
 //!    round
 //! 4. Emit errors/warnings to the user
+//!
+//! If you want to embed the link checker in another tool, the [`Checker`]
+//! wraps all of these steps up behind a builder.
 
 // Note: older versions of Rust (e.g. v1.46.0) don't know about "rustdoc" lints
 #![allow(unknown_lints)]
@@ -25,18 +28,111 @@ extern crate pretty_assertions;
 /// A semver range specifying which versions of `mdbook` this crate supports.
 pub const COMPATIBLE_MDBOOK_VERSIONS: &str = "^0.4.0";
 
+mod allow;
+mod alt_text;
+mod anchors;
+mod assets;
+mod baseline;
+mod book_toml;
+mod cache;
+mod cache_control;
+mod changes;
+mod check_url;
+mod checker;
+mod classify;
 mod config;
 mod context;
+mod crates;
+mod data_uri;
+mod dns;
+mod doctor;
+mod doi;
+mod drafts;
+mod file_provider;
+mod fix;
+mod front_matter;
+mod github;
+mod graph;
 mod hashed_regex;
+mod history;
+mod html;
+mod http_client;
+mod http_trace;
+mod idn;
+mod images;
+mod includes;
+mod incremental;
+mod json_rpc;
 mod links;
+mod languages;
+mod lsp;
+mod metrics;
+mod normalize;
+mod notify;
+mod orphans;
+mod outcome;
+mod preprocessor;
+mod redirects;
+mod rendered;
+mod server;
+mod sink;
+mod suggested_rewrites;
+mod tel;
+mod throttle;
+mod tls;
+mod triage;
 mod validate;
+mod watch;
+mod web;
+mod workspace;
 
 pub use crate::{
-    config::{Config, WarningPolicy},
+    assets::{AssetProblem, AssetProblemKind},
+    cache::Cache,
+    changes::{changed_chapters, selected_chapters},
+    check_url::check_url,
+    checker::{Checker, CheckerBuilder, Report},
+    classify::{classify, list_links, ClassifiedLink, LinkKind},
+    config::{
+        ChapterOverride, Config, GithubReview, LocalConfig, MarkdownConfig,
+        SlugConfig, SlugStyle, WarningPolicy, WebConfig,
+    },
     context::Context,
+    crates::{CrateProblem, CrateProblemKind, MissingCrate},
+    data_uri::MalformedDataUri,
+    doctor::doctor,
+    doi::MalformedDoi,
+    drafts::DraftChapter,
+    file_provider::{FileProvider, InMemoryFiles, RealFileSystem},
+    fix::{apply_fixes, preview_fixes, Fix, FixReason},
     hashed_regex::HashedRegex,
-    links::{extract as extract_links, IncompleteLink},
-    validate::{validate, NotInSummary, ValidationOutcome},
+    html::{HtmlProblem, HtmlProblemKind},
+    http_client::HttpClient,
+    idn::{Homograph, HomographKind, Script},
+    images::WrongContentType,
+    includes::{BrokenInclude, IncludeProblem},
+    incremental::ChapterIndex,
+    links::{
+        extract as extract_links, extract_with as extract_links_with,
+        Extractor, IncompleteLink,
+    },
+    lsp::serve_language_server,
+    metrics::{Latency, Metrics},
+    outcome::{DiagnosticCategory, FailureReason},
+    preprocessor::LinkcheckPreprocessor,
+    redirects::CrossDomainRedirect,
+    rendered::check_rendered,
+    server::serve,
+    sink::DiagnosticSink,
+    tel::MalformedPhoneNumber,
+    tls::{ExpiringCertificate, OutdatedTls},
+    triage::triage,
+    validate::{
+        validate, validate_async, DeadlineExceeded, FileLink,
+        MissingAnchor, NotInSummary, RequestTiming, RewrittenLink,
+        SpecialFileLink, UnreadableFile, ValidationOutcome,
+    },
+    watch::watch,
 };
 
 use anyhow::{Context as _, Error};
@@ -45,13 +141,20 @@ use codespan_reporting::{
     diagnostic::{Diagnostic, Severity},
     term::termcolor::{ColorChoice, StandardStream},
 };
-use linkcheck::validation::Cache;
 use mdbook::{
     book::{Book, BookItem},
-    renderer::RenderContext,
+    renderer::{RenderContext, Renderer},
+    MDBook,
 };
 use semver::{Version, VersionReq};
-use std::{fs::File, path::Path};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    fs::File,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
 /// Run the link checking pipeline.
 ///
@@ -66,59 +169,378 @@ pub fn run(
     ctx: &RenderContext,
     selected_files: Option<Vec<String>>,
 ) -> Result<(), Error> {
-    let mut cache = if let Some(cache_file) = cache_file {
+    let cache = if let Some(cache_file) = cache_file {
         load_cache(cache_file)
     } else {
         Cache::default()
     };
 
-    log::info!("Started the link checker");
-    log::debug!("Selected file: {:?}", selected_files);
+    tracing::info!("Started the link checker");
+    tracing::debug!("Selected file: {:?}", selected_files);
 
     let cfg = crate::get_config(&ctx.config)?;
     crate::version_check(&ctx.version)?;
 
-    if log::log_enabled!(log::Level::Trace) {
-        for line in format!("{:#?}", cfg).lines() {
-            log::trace!("{}", line);
-        }
+    // Note: the config is only formatted if someone is listening
+    tracing::trace!("Config: {:#?}", cfg);
+
+    let index_file = chapter_index_file(cache_file, &cfg);
+    let rewrites_file = if cfg.suggest_rewrites {
+        Some(ctx.destination.join(suggested_rewrites::FILE_NAME))
+    } else {
+        None
+    };
+    let mut builder = Checker::builder()
+        .source_dir(ctx.source_dir())
+        .config(cfg)
+        .book(ctx.book.clone())
+        .book_toml(ctx.root.join("book.toml"));
+    if let Some(ref index_file) = index_file {
+        builder = builder.chapter_index(load_cache(index_file));
+    }
+    if let Some(selected_files) = selected_files {
+        builder = builder.selected_files(selected_files);
+    }
+    if let Some(cache_file) = cache_file {
+        builder = builder.checkpoint_file(cache_file);
     }
 
-    let file_filter = |fname: &Path| {
-        if let Some(ref selected_files) = selected_files {
-            selected_files.contains(&fname.display().to_string())
-        } else {
-            true
+    run_checker(
+        builder.build()?,
+        cache,
+        cache_file,
+        index_file.as_deref(),
+        &ctx.root,
+        rewrites_file.as_deref(),
+        colour,
+    )
+}
+
+/// Run the book's preprocessors and get the [`RenderContext`] that `mdbook
+/// build` would give the `linkcheck` backend, so links generated by
+/// preprocessors get checked when running standalone.
+pub fn preprocess(md: &MDBook) -> Result<RenderContext, Error> {
+    /// A backend which just remembers what it was given.
+    struct Capture(RefCell<Option<RenderContext>>);
+
+    impl Renderer for Capture {
+        fn name(&self) -> &str { "linkcheck" }
+
+        fn render(&self, ctx: &RenderContext) -> Result<(), Error> {
+            *self.0.borrow_mut() = Some(ctx.clone());
+            Ok(())
         }
+    }
+
+    let capture = Capture(RefCell::new(None));
+    md.execute_build_process(&capture)?;
+
+    capture
+        .0
+        .into_inner()
+        .context("The preprocessed book was never rendered")
+}
+
+/// Run the link checker over a plain directory of markdown files (e.g. a
+/// repository's `README.md` and `docs/` folder), without needing `mdbook`.
+///
+/// If the directory contains a `SUMMARY.md` it will be loaded like the `src/`
+/// directory of a normal book, otherwise every `*.md` file is checked.
+pub fn run_directory(
+    dir: &Path,
+    cfg: Config,
+    cache_file: Option<&Path>,
+    colour: ColorChoice,
+    selected_files: Option<Vec<String>>,
+) -> Result<(), Error> {
+    let cache = if let Some(cache_file) = cache_file {
+        load_cache(cache_file)
+    } else {
+        Cache::default()
     };
 
-    let (files, outcome) = check_links(&ctx, &mut cache, &cfg, file_filter)?;
-    let diags = outcome.generate_diagnostics(&files, cfg.warning_policy);
-    report_errors(&files, &diags, colour)?;
+    tracing::info!("Started the link checker on \"{}\"", dir.display());
+    tracing::debug!("Selected file: {:?}", selected_files);
+
+    let index_file = chapter_index_file(cache_file, &cfg);
+    let mut builder = Checker::builder().source_dir(dir).config(cfg);
+    if let Some(ref index_file) = index_file {
+        builder = builder.chapter_index(load_cache(index_file));
+    }
+    if let Some(selected_files) = selected_files {
+        builder = builder.selected_files(selected_files);
+    }
+    if let Some(cache_file) = cache_file {
+        builder = builder.checkpoint_file(cache_file);
+    }
+
+    run_checker(
+        builder.build()?,
+        cache,
+        cache_file,
+        index_file.as_deref(),
+        dir,
+        None,
+        colour,
+    )
+}
+
+/// Check the book, then report the results and write any of the configured
+/// output files (which are resolved relative to `root`).
+fn run_checker(
+    checker: Checker,
+    cache: Cache,
+    cache_file: Option<&Path>,
+    index_file: Option<&Path>,
+    root: &Path,
+    rewrites_file: Option<&Path>,
+    colour: ColorChoice,
+) -> Result<(), Error> {
+    let metrics_file = checker.config().resolve_metrics_file(root)?;
+    let link_graph_file = checker.config().resolve_link_graph_file(root)?;
+    let history_file = checker.config().resolve_history_file(root)?;
+    let baseline_file = checker.config().resolve_baseline_file(root)?;
+
+    // used to find links which broke since last time
+    let previous_cache = if checker.config().notify_webhook.is_some()
+        || history_file.is_some()
+    {
+        Some(cache.clone())
+    } else {
+        None
+    };
+    let Report {
+        files,
+        mut outcome,
+        cache,
+        chapter_index,
+        ..
+    } = checker.check_with_cache(cache)?;
+    if let Some(ref baseline_file) = baseline_file {
+        let baseline = baseline::Baseline::load(baseline_file)?;
+        baseline.apply(&mut outcome, &files);
+    }
+    let diags = outcome.generate_diagnostics(&files, checker.config());
+    report_errors(&files, &diags, colour, checker.config().max_diagnostics)?;
+
+    if let Some(count) = checker.config().report_slow_links {
+        eprint!(
+            "{}",
+            metrics::slow_link_report(&outcome.request_timings, &files, count)
+        );
+    }
+    if checker.config().report_domains {
+        eprint!("{}", metrics::domain_report(&outcome));
+    }
 
     if let Some(cache_file) = cache_file {
         save_cache(cache_file, &cache);
     }
+    if let (Some(index_file), Some(chapter_index)) = (index_file, chapter_index)
+    {
+        save_cache(index_file, &chapter_index);
+    }
+    if let Some(ref metrics_file) = metrics_file {
+        metrics::write_metrics(metrics_file, &Metrics::from_outcome(&outcome))?;
+    }
+    if let (Some(history_file), Some(previous_cache)) =
+        (&history_file, &previous_cache)
+    {
+        let count = |severity: Severity| {
+            diags.iter().filter(|diag| diag.severity == severity).count()
+        };
+        let entry = history::HistoryEntry::new(
+            count(Severity::Error) + count(Severity::Bug),
+            count(Severity::Warning),
+            Metrics::from_outcome(&outcome),
+            notify::regressions(&outcome, previous_cache).count(),
+        );
+        if let Some(trend) = history::append(history_file, &entry)? {
+            eprintln!("{}", trend);
+        }
+    }
+    if let Some(ref link_graph_file) = link_graph_file {
+        let chapters: Vec<PathBuf> = checker
+            .book()
+            .iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) => ch.path.clone(),
+                _ => None,
+            })
+            .collect();
+        let graph = graph::LinkGraph::new(&chapters, &outcome, &files);
+        graph::write_link_graph(link_graph_file, &graph)?;
+    }
+    if let Some(rewrites_file) = rewrites_file {
+        suggested_rewrites::write_rewrites(
+            rewrites_file,
+            &outcome.permanent_redirects,
+        )?;
+    }
+    if let (Some(webhook), Some(previous_cache)) =
+        (&checker.config().notify_webhook, &previous_cache)
+    {
+        let cfg = checker.config();
+        let notified = notify::notify(
+            webhook,
+            cfg,
+            &outcome,
+            &diags,
+            &files,
+            previous_cache,
+            &cfg.client(),
+        );
+        if let Err(e) = notified {
+            tracing::warn!("Unable to send the notification: {:?}", e);
+        }
+    }
+    if let Some(ref review) = checker.config().github_review {
+        let posted = github::post_review(
+            review,
+            &diags,
+            &files,
+            checker.source_dir(),
+            checker.config(),
+            &checker.config().client(),
+            checker.file_provider(),
+        );
+        if let Err(e) = posted {
+            tracing::warn!("Unable to post the GitHub review: {:?}", e);
+        }
+    }
 
-    if diags.iter().any(|diag| diag.severity >= Severity::Error) {
-        log::info!("{} broken links found", outcome.invalid_links.len());
-        Err(Error::msg("One or more incorrect links"))
+    if outcome.invalid_links.is_empty() {
+        tracing::info!("No broken links found");
     } else {
-        log::info!("No broken links found");
-        Ok(())
+        tracing::info!("{} broken links found", outcome.invalid_links.len());
+    }
+
+    check_for_failures(&diags, checker.config())?;
+
+    if !outcome.unchecked.is_empty() || !outcome.cut_short.is_empty() {
+        return Err(Error::from(DeadlineExceeded {
+            unchecked: outcome.unchecked.len(),
+        }));
+    }
+
+    Ok(())
+}
+
+/// Check a book and automatically fix any links which can be safely updated
+/// (see [`Fix`]).
+///
+/// When `dry_run` is `true` a diff of the changes is printed instead of
+/// rewriting any files.
+pub fn fix(checker: &Checker, dry_run: bool) -> Result<(), Error> {
+    let report = checker.check()?;
+    let fixes = checker.find_fixes(&report)?;
+
+    if fixes.is_empty() {
+        tracing::info!("There was nothing to fix");
+    } else if dry_run {
+        print!("{}", preview_fixes(checker.source_dir(), &fixes)?);
+    } else {
+        let applied = apply_fixes(checker.source_dir(), &fixes)?;
+        tracing::info!("Fixed {} links", applied);
+    }
+
+    Ok(())
+}
+
+/// Where the [`ChapterIndex`] should be kept when running incrementally.
+fn chapter_index_file(
+    cache_file: Option<&Path>,
+    cfg: &Config,
+) -> Option<PathBuf> {
+    if cfg.incremental {
+        cache_file.map(|cache_file| cache_file.with_extension("chapters.json"))
+    } else {
+        None
+    }
+}
+
+/// Load the book at `root` (either a normal `mdbook` project or a plain
+/// directory of markdown files), returning its source directory, contents,
+/// and configuration.
+pub(crate) fn load_standalone(
+    root: &Path,
+) -> Result<(PathBuf, Book, Config), Error> {
+    if root.join("book.toml").exists() {
+        let book_cfg = mdbook::Config::from_disk(root.join("book.toml"))?;
+        let src_dir = root.join(&book_cfg.book.src);
+        if !src_dir.join("SUMMARY.md").exists() {
+            // mdbook can't load books with a SUMMARY.md for each language
+            if let Some(book) =
+                languages::load_book(&src_dir, &RealFileSystem)?
+            {
+                return Ok((src_dir, book, get_config(&book_cfg)?));
+            }
+        }
+
+        let md = MDBook::load_with_config(root, book_cfg)?;
+        let src_dir = md.source_dir();
+        let cfg = get_config(&md.config)?;
+        Ok((src_dir, md.book, cfg))
+    } else {
+        let checker = Checker::builder().source_dir(root).build()?;
+        // there's no book.toml, but the defaults should match a book's
+        let cfg = get_config(&mdbook::Config::default())?;
+        Ok((root.to_path_buf(), checker.book().clone(), cfg))
+    }
+}
+
+/// Decide whether the linkcheck failed, based on the diagnostics that were
+/// emitted.
+fn check_for_failures(
+    diags: &[Diagnostic<FileId>],
+    cfg: &Config,
+) -> Result<(), Error> {
+    if diags.iter().any(|diag| diag.severity >= Severity::Error) {
+        return Err(Error::msg("One or more incorrect links"));
+    }
+
+    let warnings = diags
+        .iter()
+        .filter(|diag| diag.severity == Severity::Warning)
+        .count();
+
+    match cfg.max_warnings {
+        Some(max_warnings) if warnings > max_warnings => {
+            Err(Error::msg(format!(
+                "Found {} warnings, but at most {} are allowed",
+                warnings, max_warnings
+            )))
+        },
+        _ => Ok(()),
     }
 }
 
 /// Get the configuration used by `mdbook-linkcheck`.
 pub fn get_config(cfg: &mdbook::Config) -> Result<Config, Error> {
-    match cfg.get("output.linkcheck") {
+    let mut config: Config = match cfg.get("output.linkcheck") {
         Some(raw) => raw
             .clone()
             .try_into()
-            .context("Unable to deserialize the `output.linkcheck` table.")
-            .map_err(Error::from),
-        None => Ok(Config::default()),
+            .context("Unable to deserialize the `output.linkcheck` table.")?,
+        None => Config::default(),
+    };
+
+    if config.site_url.is_none() {
+        // mdbook lets this be a plain path (e.g. "/my-book/"), which doesn't
+        // tell us which domain the book is hosted on
+        config.site_url = cfg
+            .get("output.html.site-url")
+            .and_then(|url| url.as_str())
+            .filter(|url| {
+                url.starts_with("http://") || url.starts_with("https://")
+            })
+            .map(String::from);
+    }
+    if config.default_language.is_none() {
+        config.default_language = cfg.book.language.clone();
     }
+
+    Ok(config)
 }
 
 /// Check whether this library is compatible with the provided version string.
@@ -153,7 +575,7 @@ where
         match item {
             BookItem::Chapter(ref ch) => {
                 if let Some(ref path) = ch.path {
-                    if filter(&path) {
+                    if filter(path) {
                         let id = dest.add(
                             path.display().to_string(),
                             ch.content.clone(),
@@ -169,90 +591,164 @@ where
     ids
 }
 
+/// The path (relative to the source directory) of every chapter in the
+/// [`Book`], which is what links are allowed to point at.
+pub(crate) fn chapter_names(book: &Book) -> Vec<OsString> {
+    book.iter()
+        .filter_map(|item| match item {
+            BookItem::Chapter(ch) => ch.path.as_ref(),
+            BookItem::Separator | BookItem::PartTitle(_) => None,
+        })
+        .map(|path| path.as_os_str().to_os_string())
+        .collect()
+}
+
+/// How many CPU-bound jobs (e.g. parsing chapters) to run at once.
+pub(crate) fn parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// How a diagnostic's severity is written in machine-readable output (JSON,
+/// review comments, `file:line:column` messages, etc.).
+pub(crate) fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
 fn report_errors(
     files: &Files<String>,
     diags: &[Diagnostic<FileId>],
     colour: ColorChoice,
+    max_diagnostics: Option<usize>,
 ) -> Result<(), Error> {
     let mut writer = StandardStream::stderr(colour);
     let cfg = codespan_reporting::term::Config::default();
+    let (shown, hidden) = truncate_diagnostics(diags, max_diagnostics);
+    let summary = hidden_diagnostics_summary(&hidden);
 
-    for diag in diags {
+    for diag in shown.into_iter().chain(&summary) {
         codespan_reporting::term::emit(&mut writer, &cfg, files, diag)?;
     }
 
     Ok(())
 }
 
-fn check_links<F>(
-    ctx: &RenderContext,
-    cache: &mut Cache,
-    cfg: &Config,
-    file_filter: F,
-) -> Result<(Files<String>, ValidationOutcome), Error>
-where
-    F: Fn(&Path) -> bool,
-{
-    log::info!("Scanning book for links");
-    let mut files = Files::new();
-    let file_ids =
-        crate::load_files_into_memory(&ctx.book, &mut files, file_filter);
-    let (links, incomplete_links) =
-        crate::extract_links(file_ids.clone(), &files);
-    log::info!(
-        "Found {} links ({} incomplete links)",
-        links.len(),
-        incomplete_links.len()
+/// Split the diagnostics into the ones to print and the ones to leave out
+/// because of [`Config::max_diagnostics`].
+///
+/// No more than `max_diagnostics` are printed, whatever their severity. When
+/// some need to be left out, the most severe ones are kept and printed first
+/// (otherwise the original order is used).
+fn truncate_diagnostics(
+    diags: &[Diagnostic<FileId>],
+    max_diagnostics: Option<usize>,
+) -> (Vec<&Diagnostic<FileId>>, Vec<&Diagnostic<FileId>>) {
+    let keep = max_diagnostics.unwrap_or(diags.len());
+    if keep >= diags.len() {
+        return (diags.iter().collect(), Vec::new());
+    }
+
+    // Note: the sort is stable, so diagnostics with the same severity stay in
+    // their original order
+    let mut shown: Vec<usize> = (0..diags.len()).collect();
+    shown.sort_by(|&a, &b| {
+        diags[b]
+            .severity
+            .partial_cmp(&diags[a].severity)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut hidden = shown.split_off(keep);
+    hidden.sort_unstable();
+
+    (
+        shown.into_iter().map(|ix| &diags[ix]).collect(),
+        hidden.into_iter().map(|ix| &diags[ix]).collect(),
+    )
+}
+
+/// Summarise the diagnostics left out because of [`Config::max_diagnostics`].
+fn hidden_diagnostics_summary(
+    hidden: &[&Diagnostic<FileId>],
+) -> Option<Diagnostic<FileId>> {
+    if hidden.is_empty() {
+        return None;
+    }
+
+    let count = |severity: Severity| {
+        hidden.iter().filter(|d| d.severity == severity).count()
+    };
+    let message = format!(
+        "{} more problems not shown ({} errors, {} warnings)",
+        hidden.len(),
+        count(Severity::Error) + count(Severity::Bug),
+        count(Severity::Warning)
     );
-    let src = dunce::canonicalize(ctx.source_dir())
-        .context("Unable to resolve the source directory")?;
-    let outcome = crate::validate(
-        &links,
-        &cfg,
-        &src,
-        cache,
-        &files,
-        &file_ids,
-        incomplete_links,
-    )?;
 
-    Ok((files, outcome))
+    Some(Diagnostic::note().with_message(message).with_notes(vec![
+        String::from("Increase `max-diagnostics` to see them"),
+    ]))
 }
 
-fn load_cache(filename: &Path) -> Cache {
-    log::debug!("Loading cache from {}", filename.display());
+fn load_cache<T>(filename: &Path) -> T
+where
+    T: DeserializeOwned + Default,
+{
+    let _span = tracing::debug_span!("load_cache", file = %filename.display())
+        .entered();
+    tracing::debug!("Loading cache from {}", filename.display());
 
     match File::open(filename) {
         Ok(f) => match serde_json::from_reader(f) {
             Ok(cache) => cache,
             Err(e) => {
-                log::warn!("Unable to deserialize the cache: {}", e);
-                Cache::default()
+                tracing::warn!("Unable to deserialize the cache: {}", e);
+                T::default()
             },
         },
         Err(e) => {
-            log::debug!("Unable to open the cache: {}", e);
-            Cache::default()
+            tracing::debug!("Unable to open the cache: {}", e);
+            T::default()
         },
     }
 }
 
-fn save_cache(filename: &Path, cache: &Cache) {
+pub(crate) fn save_cache<T: Serialize>(filename: &Path, cache: &T) {
+    let _span = tracing::debug_span!("save_cache", file = %filename.display())
+        .entered();
+
     if let Some(parent) = filename.parent() {
         if let Err(e) = std::fs::create_dir_all(parent) {
-            log::warn!("Unable to create the cache's directory: {}", e);
+            tracing::warn!("Unable to create the cache's directory: {}", e);
         }
     }
 
-    log::debug!("Saving the cache to {}", filename.display());
+    tracing::debug!("Saving the cache to {}", filename.display());
+
+    // Write to a temporary file first so being interrupted half way through
+    // (e.g. while saving a checkpoint) can't leave a corrupted cache behind
+    let temp = filename.with_extension("tmp");
 
-    match File::create(filename) {
+    match File::create(&temp) {
         Ok(f) => {
             if let Err(e) = serde_json::to_writer(f, cache) {
-                log::warn!("Saving the cache as JSON failed: {}", e);
+                tracing::warn!("Saving the cache as JSON failed: {}", e);
+                return;
             }
         },
-        Err(e) => log::warn!("Unable to create the cache file: {}", e),
+        Err(e) => {
+            tracing::warn!("Unable to create the cache file: {}", e);
+            return;
+        },
+    }
+
+    if let Err(e) = std::fs::rename(&temp, filename) {
+        tracing::warn!("Unable to save the cache file: {}", e);
     }
 }
 
@@ -260,6 +756,175 @@ fn save_cache(filename: &Path, cache: &Cache) {
 mod tests {
     use super::*;
 
+    fn warnings(count: usize) -> Vec<Diagnostic<FileId>> {
+        (0..count).map(|_| Diagnostic::warning()).collect()
+    }
+
+    #[test]
+    fn check_the_preprocessed_book() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("orphans");
+        let md = MDBook::load(&root).unwrap();
+
+        let ctx = preprocess(&md).unwrap();
+
+        let chapter_1 = ctx
+            .book
+            .iter()
+            .find_map(|item| match item {
+                BookItem::Chapter(ch) if ch.name == "Chapter 1" => Some(ch),
+                _ => None,
+            })
+            .unwrap();
+        assert!(!chapter_1.content.contains("{{#include"));
+    }
+
+    #[test]
+    fn check_files_outside_the_book() {
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("extra-files")
+            .join("src");
+
+        let report = Checker::builder()
+            .source_dir(&src_dir)
+            .config(Config {
+                extra_files: vec![
+                    String::from("../CHANGELOG.md"),
+                    String::from("../docs"),
+                ],
+                ..Default::default()
+            })
+            .check()
+            .unwrap();
+
+        let mut broken: Vec<_> = report
+            .outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| {
+                let file = report.files.name(invalid.link.file);
+                format!("{}: {}", file.to_string_lossy(), invalid.link.href)
+            })
+            .collect();
+        broken.sort();
+        assert_eq!(
+            broken,
+            vec![
+                "../CHANGELOG.md: ./old-guide.md",
+                "../docs/guide.md: ./nowhere.md",
+            ]
+        );
+        assert_eq!(report.outcome.valid_links.len(), 3);
+        // the changelog won't be copied into the rendered book
+        let unpublished: Vec<_> = report
+            .outcome
+            .unpublished_links
+            .iter()
+            .map(|link| link.href.as_str())
+            .collect();
+        assert_eq!(unpublished, vec!["../CHANGELOG.md"]);
+    }
+
+    #[test]
+    fn raw_sources_ignore_what_preprocessors_did() {
+        let generated = "[generated](./also-missing.md)";
+        let (mut files, book) =
+            file_provider::in_memory_book(&[("chapter_1.md", generated)]);
+        // what's on disk, before preprocessors got to it
+        files.insert("/book/src/chapter_1.md", "[raw](./missing.md)");
+
+        let checker = Checker::builder()
+            .source_dir("/book/src")
+            .config(Config {
+                raw_sources: true,
+                ..Default::default()
+            })
+            .book(book)
+            .file_provider(files)
+            .build()
+            .unwrap();
+
+        let (_, links) = checker.links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].link.href, "./missing.md");
+    }
+
+    #[test]
+    fn warnings_dont_fail_the_linkcheck_by_default() {
+        let cfg = Config::default();
+
+        assert!(check_for_failures(&warnings(100), &cfg).is_ok());
+    }
+
+    #[test]
+    fn fail_when_the_warning_budget_is_exceeded() {
+        let cfg = Config {
+            max_warnings: Some(2),
+            ..Default::default()
+        };
+
+        assert!(check_for_failures(&warnings(2), &cfg).is_ok());
+        assert!(check_for_failures(&warnings(3), &cfg).is_err());
+    }
+
+    #[test]
+    fn summarise_the_diagnostics_which_were_left_out() {
+        assert!(hidden_diagnostics_summary(&[]).is_none());
+
+        let mut hidden = warnings(3);
+        hidden.push(Diagnostic::error());
+        let hidden: Vec<_> = hidden.iter().collect();
+        let got = hidden_diagnostics_summary(&hidden).unwrap();
+
+        assert_eq!(got.severity, Severity::Note);
+        assert_eq!(
+            got.message,
+            "4 more problems not shown (1 errors, 3 warnings)"
+        );
+    }
+
+    #[test]
+    fn truncating_keeps_the_most_severe_diagnostics() {
+        let diags = vec![
+            Diagnostic::note().with_message("a"),
+            Diagnostic::warning().with_message("b"),
+            Diagnostic::error().with_message("c"),
+            Diagnostic::warning().with_message("d"),
+            Diagnostic::error().with_message("e"),
+        ];
+        let messages = |diags: Vec<&Diagnostic<FileId>>| {
+            diags.iter().map(|d| d.message.clone()).collect::<Vec<_>>()
+        };
+
+        let (shown, hidden) = truncate_diagnostics(&diags, Some(3));
+        assert_eq!(messages(shown), ["c", "e", "b"]);
+        assert_eq!(messages(hidden), ["a", "d"]);
+
+        // errors count towards the limit too
+        let (shown, hidden) = truncate_diagnostics(&diags, Some(1));
+        assert_eq!(messages(shown), ["c"]);
+        assert_eq!(messages(hidden), ["a", "b", "d", "e"]);
+
+        let (shown, hidden) = truncate_diagnostics(&diags, None);
+        assert_eq!(messages(shown), ["a", "b", "c", "d", "e"]);
+        assert!(hidden.is_empty());
+    }
+
+    #[test]
+    fn plain_directories_get_the_same_defaults_as_a_book() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("plain-directory");
+
+        let (_, _, cfg) = load_standalone(&root).unwrap();
+
+        let book_defaults = get_config(&mdbook::Config::default()).unwrap();
+        assert_eq!(cfg, book_defaults);
+        assert_eq!(cfg.default_language.as_deref(), Some("en"));
+    }
+
     #[test]
     fn always_stay_compatible_with_mdbook_dependency() {
         let got = version_check(mdbook::MDBOOK_VERSION);
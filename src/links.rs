@@ -1,10 +1,21 @@
+use crate::{HashedRegex, MarkdownConfig};
 use codespan::{FileId, Files, Span};
 use linkcheck::Link;
-use pulldown_cmark::{BrokenLink, CowStr};
-use std::{cell::RefCell, fmt::Debug};
+use once_cell::sync::Lazy;
+use pulldown_cmark::{BrokenLink, CowStr, Event, Options, Parser, Tag};
+use regex::Regex;
+use serde_derive::Serialize;
+use std::{fmt::Debug, ops::Range};
+
+/// A bare URL, the way GitHub's extended autolinks find them.
+static AUTOLINK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:^|[\s*_~(])((?:https?://|www\.)[^\s<]+)").unwrap()
+});
 
 /// Search every file in the [`Files`] and collate all the links that are
 /// found.
+///
+/// Links are always returned in the same order as `target_files`.
 pub fn extract<I>(
     target_files: I,
     files: &Files<String>,
@@ -12,50 +23,245 @@ pub fn extract<I>(
 where
     I: IntoIterator<Item = FileId>,
 {
+    extract_with(target_files, files, &[])
+}
+
+/// The same as [`extract()`], except the [`Extractor`]s are used to find
+/// any links the markdown parser doesn't know about.
+pub fn extract_with<I>(
+    target_files: I,
+    files: &Files<String>,
+    extractors: &[&dyn Extractor],
+) -> (Vec<Link>, Vec<IncompleteLink>)
+where
+    I: IntoIterator<Item = FileId>,
+{
+    let markdown = MarkdownConfig::default();
+    extract_with_options(target_files, files, extractors, markdown)
+}
+
+/// The same as [`extract_with()`], except the markdown parser uses the
+/// extensions from a [`MarkdownConfig`].
+pub(crate) fn extract_with_options<I>(
+    target_files: I,
+    files: &Files<String>,
+    extractors: &[&dyn Extractor],
+    markdown: MarkdownConfig,
+) -> (Vec<Link>, Vec<IncompleteLink>)
+where
+    I: IntoIterator<Item = FileId>,
+{
+    let target_files: Vec<FileId> = target_files.into_iter().collect();
+    let span =
+        tracing::debug_span!("extract_links", files = target_files.len());
+    let _guard = span.enter();
+
     let mut links = Vec::new();
-    let broken_links = RefCell::new(Vec::new());
-
-    for file_id in target_files {
-        let src = files.source(file_id);
-        log::debug!("Scanning {}", files.name(file_id).to_string_lossy());
-
-        links.extend(scan_links(file_id, &*src, &mut |broken_link| {
-            let BrokenLink {
-                reference, span, ..
-            } = broken_link;
-            log::debug!(
-                "Found a (possibly) broken link to [{}] at {:?}",
-                reference,
-                span
-            );
-
-            broken_links.borrow_mut().push(IncompleteLink {
-                reference: broken_link.reference.to_string(),
-                span: Span::new(span.start as u32, span.end as u32),
-                file: file_id,
-            });
-            None
-        }));
+    let mut broken_links = Vec::new();
+
+    for id in target_files {
+        let name = files.name(id).to_string_lossy();
+        let (found, broken) =
+            extract_file(id, &name, files.source(id), extractors, markdown);
+        links.extend(found);
+        broken_links.extend(broken);
     }
 
-    (links, broken_links.into_inner())
+    (links, broken_links)
 }
 
-fn scan_links<'a, F>(
+/// Find the links in a single file, given its name and source text.
+pub(crate) fn extract_file(
+    file_id: FileId,
+    name: &str,
+    src: &str,
+    extractors: &[&dyn Extractor],
+    markdown: MarkdownConfig,
+) -> (Vec<Link>, Vec<IncompleteLink>) {
+    let options = markdown.options();
+    let _span = tracing::debug_span!("scan_file", file = %name).entered();
+    tracing::debug!("Scanning {}", name);
+
+    let mut broken_links = Vec::new();
+    let mut on_broken_link = |broken_link: BrokenLink<'_>| {
+        let BrokenLink {
+            reference, span, ..
+        } = broken_link;
+        tracing::debug!(
+            "Found a (possibly) broken link to [{}] at {:?}",
+            reference,
+            span
+        );
+
+        broken_links.push(IncompleteLink {
+            reference: broken_link.reference.to_string(),
+            span: Span::new(span.start as u32, span.end as u32),
+            file: file_id,
+        });
+        None
+    };
+    let mut links = scan_markdown(
+        file_id,
+        src,
+        options,
+        markdown.autolinks,
+        &mut on_broken_link,
+    );
+
+    for extractor in extractors {
+        links.extend(extractor.extract(src).into_iter().map(
+            |(href, range)| {
+                tracing::debug!(?extractor, %href, "Found a link");
+                Link::new(
+                    href,
+                    Span::new(range.start as u32, range.end as u32),
+                    file_id,
+                )
+            },
+        ));
+    }
+
+    (links, broken_links)
+}
+
+/// Something which can find links written using custom syntax (e.g. a
+/// preprocessor's `{{#button href="..."}}` directive) that the markdown
+/// parser won't see.
+///
+/// A [`HashedRegex`] can be used as an [`Extractor`], taking the link from
+/// its `link` capture group (or the first capture group if there is no
+/// `link`). This is how [`crate::Config::link_patterns`] are implemented.
+pub trait Extractor: Debug + Send + Sync {
+    /// Find all the links in a chapter's source text, returning each link
+    /// and its location as a byte range.
+    fn extract(&self, src: &str) -> Vec<(String, Range<usize>)>;
+}
+
+impl Extractor for HashedRegex {
+    fn extract(&self, src: &str) -> Vec<(String, Range<usize>)> {
+        self.captures_iter(src)
+            .filter_map(|caps| caps.name("link").or_else(|| caps.get(1)))
+            .map(|m| (m.as_str().to_string(), m.range()))
+            .collect()
+    }
+}
+
+/// Find the links in a chapter's markdown, plus its bare URLs when
+/// `autolinks` is set (see [`MarkdownConfig::autolinks`]).
+///
+/// `pulldown_cmark` doesn't know about GitHub's extended autolinks, so we look
+/// for them in the text it reports, skipping anything already inside a link
+/// or a code block. Both are found in the same pass over the document.
+fn scan_markdown<'a, F>(
     file_id: FileId,
     src: &'a str,
+    options: Options,
+    autolinks: bool,
     cb: &'a mut F,
-) -> impl Iterator<Item = Link> + 'a
+) -> Vec<Link>
 where
     F: FnMut(BrokenLink<'_>) -> Option<(CowStr<'a>, CowStr<'a>)> + 'a,
 {
-    linkcheck::scanners::markdown_with_broken_link_callback(src, Some(cb))
-        .map(move |(link, span)| Link::new(link, span, file_id))
+    let mut links = Vec::new();
+    let mut texts: Vec<Range<usize>> = Vec::new();
+    let mut nesting = 0;
+    let mut after_text = false;
+
+    // Note: this is linkcheck::scanners::markdown_with_broken_link_callback(),
+    // except the parser's options can be changed and we take ownership of
+    // the destination instead of copying it
+    let parser = Parser::new_with_broken_link_callback(src, options, Some(cb));
+
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            // text with escapes or entities doesn't line up with the source
+            Event::Text(text)
+                if autolinks
+                    && nesting == 0
+                    && *text == src[range.clone()] =>
+            {
+                // the parser splits text at characters like `_`, so
+                // neighbouring pieces are glued back together
+                match texts.last_mut() {
+                    Some(last) if after_text && last.end == range.start => {
+                        last.end = range.end;
+                    },
+                    _ => texts.push(range),
+                }
+                after_text = true;
+                continue;
+            },
+            Event::Start(Tag::Link(_, dest, _))
+            | Event::Start(Tag::Image(_, dest, _)) => {
+                links.push(Link::new(
+                    dest.into_string(),
+                    Span::new(range.start as u32, range.end as u32),
+                    file_id,
+                ));
+                nesting += 1;
+            },
+            Event::Start(Tag::CodeBlock(_)) => nesting += 1,
+            Event::End(Tag::Link(..))
+            | Event::End(Tag::Image(..))
+            | Event::End(Tag::CodeBlock(_)) => nesting -= 1,
+            _ => {},
+        }
+        after_text = false;
+    }
+
+    links.extend(
+        texts
+            .into_iter()
+            .flat_map(|range| find_autolinks(file_id, src, range)),
+    );
+    links
+}
+
+fn find_autolinks(
+    file_id: FileId,
+    src: &str,
+    range: Range<usize>,
+) -> Vec<Link> {
+    AUTOLINK
+        .captures_iter(&src[range.clone()])
+        .filter_map(|caps| caps.get(1))
+        .map(|m| {
+            let url = trim_autolink(m.as_str());
+            let start = range.start + m.start();
+            let href = if url.starts_with("www.") {
+                format!("http://{}", url)
+            } else {
+                url.to_string()
+            };
+            Link::new(
+                href,
+                Span::new(start as u32, (start + url.len()) as u32),
+                file_id,
+            )
+        })
+        .collect()
+}
+
+/// Drop the trailing punctuation GitHub leaves out of an autolink, including
+/// a `)` which doesn't close a `(` in the URL.
+fn trim_autolink(mut url: &str) -> &str {
+    const TRAILING: &[char] =
+        &['?', '!', '.', ',', ':', '*', '_', '~', '\'', '"'];
+
+    loop {
+        let trimmed = url.trim_end_matches(TRAILING);
+        let opened = trimmed.matches('(').count();
+        let closed = trimmed.matches(')').count();
+        url = match trimmed.strip_suffix(')') {
+            Some(rest) if closed > opened => rest,
+            _ => return trimmed,
+        };
+    }
 }
 
 /// A potential link that has a broken reference (e.g `[foo]` when there is no
 /// `[foo]: ...` entry at the bottom).
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct IncompleteLink {
     /// The reference name (e.g. the `foo` in `[foo]`).
     pub reference: String,
@@ -64,3 +270,91 @@ pub struct IncompleteLink {
     /// Where this incomplete link occurred in the source text.
     pub span: Span,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_extractors_find_links_in_custom_syntax() {
+        let mut files = Files::new();
+        let src = r#"[normal](./chapter_1.md)
+
+{{#button text="Download" href="https://example.com/download"}}
+"#;
+        let id = files.add("chapter_1.md", String::from(src));
+        let pattern =
+            HashedRegex::new(r#"\{\{#button [^}]*href="(?P<link>[^"]+)""#)
+                .unwrap();
+
+        let (links, _) = extract_with(vec![id], &files, &[&pattern]);
+
+        let hrefs: Vec<_> = links.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(
+            hrefs,
+            vec!["./chapter_1.md", "https://example.com/download"]
+        );
+        let span = links[1].span;
+        assert_eq!(
+            &src[span.start().to_usize()..span.end().to_usize()],
+            "https://example.com/download"
+        );
+    }
+
+    #[test]
+    fn parser_extensions_change_which_links_are_found() {
+        let mut files = Files::new();
+        let src = "See the footnote[^1].\n\n[^1]: https://example.com/\n";
+        let id = files.add("chapter_1.md", String::from(src));
+        let commonmark = MarkdownConfig {
+            tables: false,
+            footnotes: false,
+            strikethrough: false,
+            tasklists: false,
+            smart_punctuation: false,
+            autolinks: false,
+        };
+
+        let (with_footnotes, _) = extract(vec![id], &files);
+        let (without_footnotes, _) =
+            extract_with_options(vec![id], &files, &[], commonmark);
+
+        // with footnotes, this is a footnote definition rather than a link
+        // reference definition
+        assert!(with_footnotes.is_empty(), "{:?}", with_footnotes);
+        let hrefs: Vec<_> =
+            without_footnotes.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(hrefs, vec!["https://example.com/"]);
+    }
+
+    #[test]
+    fn bare_urls_are_only_links_when_autolinks_are_enabled() {
+        let mut files = Files::new();
+        let src = "Visit https://example.com/a_(b)., www.example.com or\n\
+                   [this](https://example.com/c)\n\
+                   (see https://example.com/d)\n\n\
+                   ```\nhttps://example.com/code\n```\n";
+        let id = files.add("chapter_1.md", String::from(src));
+        let markdown = MarkdownConfig {
+            autolinks: true,
+            ..Default::default()
+        };
+
+        let (links, _) = extract_with_options(vec![id], &files, &[], markdown);
+
+        let hrefs: Vec<_> = links.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(hrefs, vec![
+            "https://example.com/c",
+            "https://example.com/a_(b)",
+            "http://www.example.com",
+            "https://example.com/d",
+        ]);
+        let span = links[2].span;
+        assert_eq!(
+            &src[span.start().to_usize()..span.end().to_usize()],
+            "www.example.com"
+        );
+        let (links, _) = extract(vec![id], &files);
+        assert_eq!(links.len(), 1);
+    }
+}
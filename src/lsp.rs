@@ -0,0 +1,588 @@
+use crate::{
+    json_rpc::{self, Framing, Handler},
+    Cache, Checker, Config,
+};
+use anyhow::Error;
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
+use mdbook::book::{Book, BookItem, Chapter};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread::Scope,
+};
+use tokio::runtime::{Builder, Handle};
+
+/// Run a [Language Server Protocol][lsp] server which reads requests from
+/// `input` and writes responses to `output` (normally stdin and stdout), until
+/// the client asks it to exit.
+///
+/// Every time a chapter is opened or changed its local links are re-checked,
+/// using the text in the editor rather than what is on disk. Web links are
+/// only checked when the chapter is saved, in the background, so typing is
+/// never held up by the network.
+///
+/// [lsp]: https://microsoft.github.io/language-server-protocol/
+pub fn serve_language_server<R, W>(input: R, output: W) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write + Send,
+{
+    let runtime = Builder::new_multi_thread().enable_all().build()?;
+    let output = Mutex::new(output);
+    let state = Mutex::new(State::default());
+
+    std::thread::scope(|scope| {
+        let mut server = Server {
+            output: &output,
+            root: None,
+            book: None,
+            runtime: runtime.handle().clone(),
+            state: &state,
+            scope,
+        };
+        json_rpc::run(input, &output, Framing::Headers, &mut server)
+    })
+}
+
+struct Server<'scope, 'env, W> {
+    output: &'env Mutex<W>,
+    root: Option<PathBuf>,
+    /// The book, loaded the first time it is needed and again whenever a
+    /// chapter is saved.
+    book: Option<LoadedBook>,
+    runtime: Handle,
+    state: &'env Mutex<State>,
+    /// Where the web checks are run.
+    scope: &'scope Scope<'scope, 'env>,
+}
+
+struct LoadedBook {
+    src_dir: PathBuf,
+    book: Book,
+    cfg: Config,
+}
+
+/// Everything which is shared with the web checks running in the background.
+#[derive(Default)]
+struct State {
+    /// The text of every document which is currently open in the editor.
+    documents: HashMap<Url, String>,
+    /// The latest diagnostics for each document's local links.
+    local: HashMap<Url, Vec<Found>>,
+    /// The diagnostics from the last time each document's web links were
+    /// checked.
+    web: HashMap<Url, Vec<Found>>,
+    /// Incremented every time a document is saved, so an old web check can't
+    /// overwrite the results from a newer one.
+    generations: HashMap<Url, u64>,
+    cache: Cache,
+}
+
+impl State {
+    /// All the diagnostics for a document, moving the ones from the last web
+    /// check to wherever their link is in the current text.
+    fn diagnostics(&self, uri: &Url) -> Vec<Value> {
+        let text = self.documents.get(uri).map(String::as_str).unwrap_or("");
+        let mut diagnostics: Vec<Value> = self
+            .local
+            .get(uri)
+            .into_iter()
+            .flatten()
+            .map(|found| found.to_lsp(found.range.clone(), text))
+            .collect();
+
+        let mut taken = HashSet::new();
+        for found in self.web.get(uri).into_iter().flatten() {
+            let start = text
+                .match_indices(found.snippet.as_str())
+                .map(|(ix, _)| ix)
+                .find(|&ix| !taken.contains(&(ix, &found.message)));
+
+            // the link was removed after the document was saved
+            if let Some(start) = start {
+                taken.insert((start, &found.message));
+                let range = start..start + found.snippet.len();
+                diagnostics.push(found.to_lsp(range, text));
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// A diagnostic for one of the links in a document.
+#[derive(Debug, Clone, PartialEq)]
+struct Found {
+    /// The diagnostic's location, as a byte range in the document.
+    range: Range<usize>,
+    /// The text at that location, so it can be found again after an edit.
+    snippet: String,
+    severity: u8,
+    code: Option<String>,
+    message: String,
+}
+
+impl Found {
+    fn to_lsp(&self, range: Range<usize>, text: &str) -> Value {
+        json!({
+            "range": {
+                "start": position(text, range.start),
+                "end": position(text, range.end),
+            },
+            "severity": self.severity,
+            "source": env!("CARGO_PKG_NAME"),
+            "code": self.code,
+            "message": self.message,
+        })
+    }
+}
+
+impl<'scope, 'env, W: Write + Send> Handler for Server<'scope, 'env, W> {
+    fn handle(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Option<Result<Value, Error>> {
+        match method {
+            "initialize" => {
+                self.root = workspace_root(params);
+                Some(Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": {
+                            "openClose": true,
+                            // we always want the full text
+                            "change": 1,
+                            "save": { "includeText": true },
+                        },
+                    },
+                    "serverInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                })))
+            },
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let text = document["text"].as_str().unwrap_or_default();
+                Some(self.update(&document["uri"], Some(text)))
+            },
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                Some(self.update(&params["textDocument"]["uri"], text))
+            },
+            "textDocument/didSave" => Some(self.save(params)),
+            "textDocument/didClose" => Some(self.close(params)),
+            "shutdown" => Some(Ok(Value::Null)),
+            _ => None,
+        }
+    }
+}
+
+impl<'scope, 'env, W: Write + Send> Server<'scope, 'env, W> {
+    fn save(&mut self, params: &Value) -> Result<Value, Error> {
+        // SUMMARY.md or book.toml may have changed
+        self.book = None;
+        let text = params["text"].as_str();
+        self.update(&params["textDocument"]["uri"], text)?;
+        if let Some(uri) = parse_uri(&params["textDocument"]["uri"]) {
+            if let Err(e) = self.check_web_links(&uri) {
+                tracing::warn!(
+                    "Unable to check the web links in \"{}\": {:?}",
+                    uri,
+                    e
+                );
+            }
+        }
+
+        Ok(Value::Null)
+    }
+
+    fn close(&mut self, params: &Value) -> Result<Value, Error> {
+        if let Some(uri) = parse_uri(&params["textDocument"]["uri"]) {
+            let mut state = self.state.lock().unwrap();
+            state.documents.remove(&uri);
+            state.local.remove(&uri);
+            state.web.remove(&uri);
+            state.generations.remove(&uri);
+            drop(state);
+            publish(self.output, &uri, Vec::new())?;
+        }
+
+        Ok(Value::Null)
+    }
+
+    /// Record the latest text for a document, check its local links, and
+    /// publish its diagnostics.
+    fn update(
+        &mut self,
+        uri: &Value,
+        text: Option<&str>,
+    ) -> Result<Value, Error> {
+        let uri = match parse_uri(uri) {
+            Some(uri) => uri,
+            None => return Ok(Value::Null),
+        };
+
+        if let Some(text) = text {
+            let mut state = self.state.lock().unwrap();
+            state.documents.insert(uri.clone(), text.to_string());
+        }
+
+        let local = match self.check_local_links(&uri) {
+            Ok(local) => local,
+            Err(e) => {
+                tracing::warn!("Unable to check \"{}\": {:?}", uri, e);
+                Vec::new()
+            },
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.local.insert(uri.clone(), local);
+        let diagnostics = state.diagnostics(&uri);
+        drop(state);
+
+        publish(self.output, &uri, diagnostics)?;
+        Ok(Value::Null)
+    }
+
+    /// Check the links in a single open document without sending any web
+    /// requests.
+    fn check_local_links(&mut self, uri: &Url) -> Result<Vec<Found>, Error> {
+        let path = uri
+            .to_file_path()
+            .map_err(|_| Error::msg("Only local files can be checked"))?;
+        let text = self.state.lock().unwrap().documents.get(uri).cloned();
+        let loaded = self.load(&path)?;
+        let chapter = match path.strip_prefix(&loaded.src_dir) {
+            Ok(chapter) => chapter.to_path_buf(),
+            // not part of the book, so there's nothing to check
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        let checker = loaded.checker(
+            &chapter,
+            text.as_deref(),
+            loaded.cfg.without_web_checks(),
+        )?;
+        let report =
+            self.runtime.block_on(checker.check_async(Cache::default()))?;
+
+        Ok(found_in(&report, &chapter))
+    }
+
+    /// Check everything in a saved document on a background thread, then
+    /// publish its diagnostics again when the web links have been checked.
+    fn check_web_links(&mut self, uri: &Url) -> Result<(), Error> {
+        let path = uri
+            .to_file_path()
+            .map_err(|_| Error::msg("Only local files can be checked"))?;
+        let (text, cache, local, generation) = {
+            let mut state = self.state.lock().unwrap();
+            let generation = state.generations.entry(uri.clone()).or_default();
+            *generation += 1;
+            let generation = *generation;
+            (
+                state.documents.get(uri).cloned(),
+                state.cache.clone(),
+                state.local.get(uri).cloned().unwrap_or_default(),
+                generation,
+            )
+        };
+        let loaded = self.load(&path)?;
+        let chapter = match path.strip_prefix(&loaded.src_dir) {
+            Ok(chapter) => chapter.to_path_buf(),
+            Err(_) => return Ok(()),
+        };
+        let checker =
+            loaded.checker(&chapter, text.as_deref(), loaded.cfg.clone())?;
+
+        let handle = self.runtime.clone();
+        let state = self.state;
+        let output = self.output;
+        let uri = uri.clone();
+
+        self.scope.spawn(move || {
+            let report = match handle.block_on(checker.check_async(cache)) {
+                Ok(report) => report,
+                Err(e) => {
+                    tracing::warn!("Unable to check \"{}\": {:?}", uri, e);
+                    return;
+                },
+            };
+
+            let mut state = state.lock().unwrap();
+            for (url, entry) in report.cache.iter() {
+                state.cache.insert(url.clone(), *entry);
+            }
+            if state.generations.get(&uri) != Some(&generation) {
+                // the document was saved again or closed in the meantime
+                return;
+            }
+            // everything else was already found by the local check
+            let web = found_in(&report, &chapter)
+                .into_iter()
+                .filter(|found| !local.contains(found))
+                .collect();
+            state.web.insert(uri.clone(), web);
+            let diagnostics = state.diagnostics(&uri);
+            drop(state);
+
+            if let Err(e) = publish(output, &uri, diagnostics) {
+                tracing::warn!("Unable to publish diagnostics: {:?}", e);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Get the book the document belongs to, loading it if necessary.
+    fn load(&mut self, path: &Path) -> Result<&LoadedBook, Error> {
+        if self.book.is_none() {
+            let root = match self.root {
+                Some(ref root) => root.clone(),
+                None => path.parent().unwrap_or(path).to_path_buf(),
+            };
+            let (src_dir, book, cfg) = crate::load_standalone(&root)?;
+            self.book = Some(LoadedBook { src_dir, book, cfg });
+        }
+
+        Ok(self.book.as_ref().expect("The book was just loaded"))
+    }
+}
+
+impl LoadedBook {
+    /// A [`Checker`] for a single chapter, using the (possibly unsaved) text
+    /// from the editor.
+    fn checker(
+        &self,
+        chapter: &Path,
+        text: Option<&str>,
+        cfg: Config,
+    ) -> Result<Checker, Error> {
+        let mut book = self.book.clone();
+        if let Some(text) = text {
+            use_editor_contents(&mut book, chapter, text);
+        }
+
+        Checker::builder()
+            .source_dir(&self.src_dir)
+            .config(cfg)
+            .book(book)
+            .selected_files(vec![chapter.to_path_buf()])
+            .build()
+    }
+}
+
+fn publish<W: Write>(
+    output: &Mutex<W>,
+    uri: &Url,
+    diagnostics: Vec<Value>,
+) -> Result<(), Error> {
+    let params = json!({
+        "uri": uri.as_str(),
+        "diagnostics": diagnostics,
+    });
+    json_rpc::notify(
+        output,
+        Framing::Headers,
+        "textDocument/publishDiagnostics",
+        params,
+    )
+}
+
+fn parse_uri(raw: &Value) -> Option<Url> { raw.as_str()?.parse().ok() }
+
+fn workspace_root(params: &Value) -> Option<PathBuf> {
+    if let Some(uri) = parse_uri(&params["rootUri"]) {
+        return uri.to_file_path().ok();
+    }
+
+    params["rootPath"].as_str().map(PathBuf::from)
+}
+
+/// Replace a chapter's contents with the (possibly unsaved) text from the
+/// editor.
+fn use_editor_contents(book: &mut Book, chapter: &Path, text: &str) {
+    let mut found = false;
+
+    book.for_each_mut(|item| {
+        if let BookItem::Chapter(ref mut ch) = item {
+            if ch.path.as_deref() == Some(chapter) {
+                ch.content = text.to_string();
+                found = true;
+            }
+        }
+    });
+
+    if !found {
+        let name = chapter.display().to_string();
+        book.push_item(Chapter::new(
+            &name,
+            text.to_string(),
+            chapter,
+            Vec::new(),
+        ));
+    }
+}
+
+/// The diagnostics for a chapter.
+fn found_in(report: &crate::Report, chapter: &Path) -> Vec<Found> {
+    let name = chapter.display().to_string();
+
+    report
+        .diagnostics()
+        .iter()
+        .filter_map(|diag| to_found(diag, &report.files))
+        .filter(|(file_id, _)| report.files.name(*file_id) == name.as_str())
+        .map(|(_, found)| found)
+        .collect()
+}
+
+fn to_found(
+    diag: &Diagnostic<FileId>,
+    files: &Files<String>,
+) -> Option<(FileId, Found)> {
+    let label = diag
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary)?;
+    let source = files.source(label.file_id);
+
+    let severity = match diag.severity {
+        Severity::Bug | Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+        Severity::Help => 4,
+    };
+
+    let found = Found {
+        range: label.range.clone(),
+        snippet: source.get(label.range.clone())?.to_string(),
+        severity,
+        code: diag.code.clone(),
+        message: diag.message.clone(),
+    };
+    Some((label.file_id, found))
+}
+
+/// Convert a byte offset into a LSP position (a zero-based line number and a
+/// character offset measured in UTF-16 code units).
+fn position(src: &str, offset: usize) -> Value {
+    let before = &src[..offset.min(src.len())];
+    let line_start = before.rfind('\n').map(|ix| ix + 1).unwrap_or(0);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+
+    json!({ "line": line, "character": character })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serve_messages(messages: &[Value]) -> Vec<Value> {
+        let input: String = messages
+            .iter()
+            .map(|msg| {
+                let body = msg.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect();
+        let mut output = Vec::new();
+
+        serve_language_server(input.as_bytes(), &mut output).unwrap();
+
+        let raw = String::from_utf8(output).unwrap();
+        raw.split("Content-Length: ")
+            .filter_map(|msg| msg.split_once("\r\n\r\n"))
+            .map(|(_, body)| serde_json::from_str(body).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn only_local_links_are_checked_while_typing() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("all-green");
+        let chapter = root.join("src").join("chapter_1.md");
+        let uri = Url::from_file_path(&chapter).unwrap();
+        let text = "[sibling](./nested/sibling.md) [missing](./missing.md) \
+                    [web](https://example.invalid/)";
+        let messages = vec![
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": {
+                    "rootUri": Url::from_file_path(&root).unwrap().as_str(),
+                },
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": uri.as_str() },
+                    "contentChanges": [{ "text": text }],
+                },
+            }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ];
+
+        let got = serve_messages(&messages);
+
+        assert_eq!(got.len(), 2, "{:#?}", got);
+        let diagnostics = got[1]["params"]["diagnostics"].as_array().unwrap();
+        // the web link is skipped, and the link to another chapter is fine
+        // even though only this chapter was checked
+        assert_eq!(diagnostics.len(), 1, "{:#?}", diagnostics);
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("missing.md"));
+    }
+
+    #[test]
+    fn web_diagnostics_follow_their_link_after_an_edit() {
+        let web = Found {
+            range: 0..5,
+            snippet: String::from("[web]"),
+            severity: 1,
+            code: None,
+            message: String::from("Broken"),
+        };
+        let uri: Url = "file:///book/src/chapter.md".parse().unwrap();
+        let mut state = State::default();
+        state
+            .documents
+            .insert(uri.clone(), String::from("# Heading\n\n[web]"));
+        state.web.insert(uri.clone(), vec![web]);
+
+        let got = state.diagnostics(&uri);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(
+            got[0]["range"]["start"],
+            json!({ "line": 2, "character": 0 })
+        );
+    }
+
+    #[test]
+    fn positions_are_measured_in_utf16() {
+        let src = "# Heading\n\n😀 [link](./missing.md)";
+        let offset = src.find('[').unwrap();
+
+        let got = position(src, offset);
+
+        // the emoji is 4 bytes in UTF-8, but 2 code units in UTF-16
+        assert_eq!(got, json!({ "line": 2, "character": 3 }));
+    }
+}
@@ -0,0 +1,527 @@
+use crate::{RequestTiming, ValidationOutcome};
+use anyhow::{Context as _, Error};
+use codespan::Files;
+use reqwest::Url;
+use serde_derive::Serialize;
+use std::{
+    cmp::Reverse, collections::BTreeMap, fmt::Write as _, path::Path,
+    time::Duration,
+};
+
+/// Machine-readable statistics about a link-checking run, for tracking the
+/// health of a book's links over time.
+///
+/// See [`crate::Config::metrics_file`] for more.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Metrics {
+    /// The total number of links which were found (not including
+    /// [`Metrics::incomplete_links`]).
+    pub total_links: usize,
+    /// The number of valid links.
+    pub valid_links: usize,
+    /// The number of broken links.
+    pub invalid_links: usize,
+    /// The number of links which were skipped.
+    pub ignored_links: usize,
+    /// The number of links we didn't know how to check.
+    pub unknown_category: usize,
+    /// The number of potentially incomplete links (e.g. `[foo]` with no
+    /// matching `[foo]: ...`).
+    pub incomplete_links: usize,
+    /// The number of web links which were answered from the cache.
+    pub cache_hits: usize,
+    /// The number of HTTP requests which were sent.
+    pub requests: usize,
+    /// The number of bytes downloaded in response bodies. Links are checked
+    /// with `HEAD` requests, so this is usually small.
+    pub bytes_fetched: u64,
+    /// The fraction of web links which were answered from the cache, if any
+    /// web links were checked.
+    pub cache_hit_ratio: Option<f64>,
+    /// How long the HTTP requests took, if any were sent.
+    pub latency: Option<Latency>,
+}
+
+/// A summary of how long HTTP requests took, in milliseconds.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Latency {
+    /// The time spent on all requests.
+    pub total_ms: f64,
+    /// The mean request duration.
+    pub average_ms: f64,
+    /// The median request duration.
+    pub p50_ms: f64,
+    /// The 90th percentile request duration.
+    pub p90_ms: f64,
+    /// The 99th percentile request duration.
+    pub p99_ms: f64,
+}
+
+impl Metrics {
+    /// Calculate metrics from the results of link checking.
+    pub fn from_outcome(outcome: &ValidationOutcome) -> Self {
+        let valid_links = outcome.valid_links.len();
+        let invalid_links = outcome.invalid_links.len();
+        let ignored_links = outcome.ignored.len();
+        let unknown_category = outcome.unknown_category.len();
+        let cache_hits = outcome.cache_hits;
+        let requests = outcome.request_timings.len();
+        let durations: Vec<Duration> = outcome
+            .request_timings
+            .iter()
+            .map(|timing| timing.duration)
+            .collect();
+
+        let cache_hit_ratio = if cache_hits + requests > 0 {
+            Some(cache_hits as f64 / (cache_hits + requests) as f64)
+        } else {
+            None
+        };
+
+        Metrics {
+            total_links: valid_links
+                + invalid_links
+                + ignored_links
+                + unknown_category,
+            valid_links,
+            invalid_links,
+            ignored_links,
+            unknown_category,
+            incomplete_links: outcome.incomplete_links.len(),
+            cache_hits,
+            requests,
+            bytes_fetched: outcome.bytes_fetched,
+            cache_hit_ratio,
+            latency: Latency::from_durations(&durations),
+        }
+    }
+
+    /// Format the metrics as a JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self)
+            .expect("Serializing to JSON should never fail")
+    }
+
+    /// Format the metrics using the [OpenMetrics][om] text format (which is
+    /// also understood by Prometheus).
+    ///
+    /// [om]: https://openmetrics.io/
+    pub fn to_openmetrics(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# TYPE linkcheck_links gauge\n");
+        out.push_str("# HELP linkcheck_links The number of links found.\n");
+        for (outcome, count) in [
+            ("valid", self.valid_links),
+            ("invalid", self.invalid_links),
+            ("ignored", self.ignored_links),
+            ("unknown", self.unknown_category),
+            ("incomplete", self.incomplete_links),
+        ] {
+            let _ = writeln!(
+                out,
+                "linkcheck_links{{outcome=\"{}\"}} {}",
+                outcome, count
+            );
+        }
+
+        out.push_str("# TYPE linkcheck_cache_hits gauge\n");
+        let _ = writeln!(out, "linkcheck_cache_hits {}", self.cache_hits);
+        out.push_str("# TYPE linkcheck_requests gauge\n");
+        let _ = writeln!(out, "linkcheck_requests {}", self.requests);
+        out.push_str("# TYPE linkcheck_fetched_bytes gauge\n");
+        out.push_str("# UNIT linkcheck_fetched_bytes bytes\n");
+        let _ = writeln!(out, "linkcheck_fetched_bytes {}", self.bytes_fetched);
+
+        if let Some(latency) = self.latency {
+            out.push_str("# TYPE linkcheck_request_duration_seconds summary\n");
+            out.push_str("# UNIT linkcheck_request_duration_seconds seconds\n");
+            for (quantile, ms) in [
+                ("0.5", latency.p50_ms),
+                ("0.9", latency.p90_ms),
+                ("0.99", latency.p99_ms),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "linkcheck_request_duration_seconds{{quantile=\"{}\"}} {}",
+                    quantile,
+                    ms / 1000.0
+                );
+            }
+            let _ = writeln!(
+                out,
+                "linkcheck_request_duration_seconds_sum {}",
+                latency.total_ms / 1000.0
+            );
+            let _ = writeln!(
+                out,
+                "linkcheck_request_duration_seconds_count {}",
+                self.requests
+            );
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+impl Latency {
+    fn from_durations(durations: &[Duration]) -> Option<Latency> {
+        if durations.is_empty() {
+            return None;
+        }
+
+        let mut ms: Vec<f64> =
+            durations.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        ms.sort_by(|a, b| a.total_cmp(b));
+        let total_ms: f64 = ms.iter().sum();
+
+        Some(Latency {
+            total_ms,
+            average_ms: total_ms / ms.len() as f64,
+            p50_ms: percentile(&ms, 50.0),
+            p90_ms: percentile(&ms, 90.0),
+            p99_ms: percentile(&ms, 99.0),
+        })
+    }
+}
+
+/// Get a percentile from a sorted list using the nearest-rank method.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Format a list of the `count` slowest web links, followed by the average
+/// response time for each domain.
+pub(crate) fn slow_link_report(
+    timings: &[RequestTiming],
+    files: &Files<String>,
+    count: usize,
+) -> String {
+    let mut out = String::new();
+    if timings.is_empty() || count == 0 {
+        return out;
+    }
+
+    let mut slowest: Vec<&RequestTiming> = timings.iter().collect();
+    slowest.sort_by_key(|timing| Reverse(timing.duration));
+
+    out.push_str("Slowest web links:\n");
+    for timing in slowest.iter().take(count) {
+        let location = files
+            .location(timing.link.file, timing.link.span.start())
+            .map(|loc| {
+                format!(
+                    "{}:{}",
+                    files.name(timing.link.file).to_string_lossy(),
+                    loc.line.number()
+                )
+            })
+            .unwrap_or_else(|_| {
+                files.name(timing.link.file).to_string_lossy().into_owned()
+            });
+        let _ = writeln!(
+            out,
+            "    {:>8.2?}  {} ({})",
+            timing.duration, timing.link.href, location
+        );
+    }
+
+    let mut domains: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+    for timing in timings {
+        let domain = timing
+            .link
+            .href
+            .parse::<Url>()
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+        domains.entry(domain).or_default().push(timing.duration);
+    }
+
+    let mut averages: Vec<(String, Duration, usize)> = domains
+        .into_iter()
+        .map(|(domain, durations)| {
+            let total: Duration = durations.iter().sum();
+            (domain, total / durations.len() as u32, durations.len())
+        })
+        .collect();
+    averages.sort_by_key(|(_, average, _)| Reverse(*average));
+
+    out.push_str("Average response time per domain:\n");
+    for (domain, average, requests) in averages {
+        let _ = writeln!(
+            out,
+            "    {:>8.2?}  {} ({} requests)",
+            average, domain, requests
+        );
+    }
+
+    out
+}
+
+/// How the links to a single domain fared, for [`domain_report()`].
+#[derive(Debug, Clone, PartialEq)]
+struct DomainSummary {
+    domain: String,
+    links: usize,
+    failures: usize,
+    requests: u32,
+    total_duration: Duration,
+    rate_limited: usize,
+}
+
+impl DomainSummary {
+    /// The mean request duration, if any requests were sent.
+    fn average(&self) -> Option<Duration> {
+        self.total_duration.checked_div(self.requests)
+    }
+}
+
+/// Collect a [`DomainSummary`] for every domain that was linked to, with the
+/// flakiest (the most failures, then the most rate limiting) first.
+fn domain_summaries(outcome: &ValidationOutcome) -> Vec<DomainSummary> {
+    let mut domains: BTreeMap<String, DomainSummary> = BTreeMap::new();
+
+    for link in &outcome.valid_links {
+        if let Some(summary) = domain_entry(&mut domains, &link.href) {
+            summary.links += 1;
+        }
+    }
+    for invalid in &outcome.invalid_links {
+        if let Some(summary) = domain_entry(&mut domains, &invalid.link.href) {
+            summary.links += 1;
+            summary.failures += 1;
+        }
+    }
+    for timing in &outcome.request_timings {
+        if let Some(summary) = domain_entry(&mut domains, &timing.link.href) {
+            summary.requests += 1;
+            summary.total_duration += timing.duration;
+        }
+    }
+
+    for (domain, &count) in &outcome.rate_limited {
+        if let Some(summary) = domains.get_mut(domain) {
+            summary.rate_limited = count;
+        }
+    }
+
+    let mut summaries: Vec<DomainSummary> = domains.into_values().collect();
+    summaries.sort_by_key(|s| Reverse((s.failures, s.rate_limited)));
+    summaries
+}
+
+/// Get the [`DomainSummary`] for a web link's domain, creating it if this is
+/// the first link to that domain.
+fn domain_entry<'a>(
+    domains: &'a mut BTreeMap<String, DomainSummary>,
+    href: &str,
+) -> Option<&'a mut DomainSummary> {
+    let domain = href
+        .parse::<Url>()
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .and_then(|url| url.host_str().map(String::from))?;
+
+    Some(domains.entry(domain.clone()).or_insert(DomainSummary {
+        domain,
+        links: 0,
+        failures: 0,
+        requests: 0,
+        total_duration: Duration::ZERO,
+        rate_limited: 0,
+    }))
+}
+
+/// Format a table showing how many links pointed at each domain, how many
+/// of them were broken, the average response time, and how often the domain
+/// asked us to slow down (see [`crate::Config::report_domains`]).
+pub(crate) fn domain_report(outcome: &ValidationOutcome) -> String {
+    let summaries = domain_summaries(outcome);
+    let mut out = String::new();
+    if summaries.is_empty() {
+        return out;
+    }
+
+    let width = summaries
+        .iter()
+        .map(|s| s.domain.len())
+        .chain(std::iter::once("Domain".len()))
+        .max()
+        .unwrap_or_default();
+
+    out.push_str("Results per domain:\n");
+    let _ = writeln!(
+        out,
+        "    {:<width$}  {:>6}  {:>8}  {:>10}  {:>12}",
+        "Domain",
+        "Links",
+        "Failures",
+        "Average",
+        "Rate limited",
+        width = width
+    );
+    for summary in summaries {
+        let average = summary
+            .average()
+            .map(|average| format!("{:.2?}", average))
+            .unwrap_or_else(|| String::from("-"));
+        let _ = writeln!(
+            out,
+            "    {:<width$}  {:>6}  {:>8}  {:>10}  {:>12}",
+            summary.domain,
+            summary.links,
+            summary.failures,
+            average,
+            summary.rate_limited,
+            width = width
+        );
+    }
+
+    out
+}
+
+/// Write the metrics to a file, using the OpenMetrics format unless the file
+/// has a `*.json` extension.
+pub(crate) fn write_metrics(
+    path: &Path,
+    metrics: &Metrics,
+) -> Result<(), Error> {
+    let is_json = path.extension().is_some_and(|ext| ext == "json");
+    let text = if is_json {
+        metrics.to_json()
+    } else {
+        metrics.to_openmetrics()
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!("Unable to create \"{}\"", parent.display())
+        })?;
+    }
+
+    tracing::debug!("Writing metrics to {}", path.display());
+    std::fs::write(path, text).with_context(|| {
+        format!("Unable to write metrics to \"{}\"", path.display())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+    use linkcheck::{
+        validation::{InvalidLink, Reason},
+        Link,
+    };
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let durations: Vec<Duration> =
+            (1..=10).map(|i| Duration::from_millis(i * 10)).collect();
+
+        let got = Latency::from_durations(&durations).unwrap();
+
+        assert_eq!(got.p50_ms, 50.0);
+        assert_eq!(got.p90_ms, 90.0);
+        assert_eq!(got.p99_ms, 100.0);
+        assert_eq!(got.average_ms, 55.0);
+    }
+
+    #[test]
+    fn openmetrics_output() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let outcome = ValidationOutcome {
+            cache_hits: 3,
+            bytes_fetched: 512,
+            request_timings: vec![RequestTiming {
+                link: Link::new("https://example.com/", Span::default(), id),
+                duration: Duration::from_millis(250),
+            }],
+            ..Default::default()
+        };
+
+        let got = Metrics::from_outcome(&outcome);
+
+        assert_eq!(got.cache_hit_ratio, Some(0.75));
+        let text = got.to_openmetrics();
+        assert!(text.contains("linkcheck_cache_hits 3\n"));
+        assert!(text.contains("linkcheck_fetched_bytes 512\n"));
+        assert!(text.contains(
+            "linkcheck_request_duration_seconds{quantile=\"0.5\"} 0.25\n"
+        ));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn slowest_links_come_first() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from("[a](x) [b](y)"));
+        let timing = |href: &str, ms| RequestTiming {
+            link: Link::new(href, Span::new(0, 1), id),
+            duration: Duration::from_millis(ms),
+        };
+        let timings = vec![
+            timing("https://fast.example.com/", 10),
+            timing("https://slow.example.com/a", 3000),
+            timing("https://slow.example.com/b", 1000),
+        ];
+
+        let got = slow_link_report(&timings, &files, 2);
+
+        let should_be = "Slowest web links:
+       3.00s  https://slow.example.com/a (chapter_1.md:1)
+       1.00s  https://slow.example.com/b (chapter_1.md:1)
+Average response time per domain:
+       2.00s  slow.example.com (2 requests)
+     10.00ms  fast.example.com (1 requests)
+";
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn flakiest_domains_come_first() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let link = |href: &str| Link::new(href, Span::new(0, 1), id);
+        let mut rate_limited = BTreeMap::new();
+        rate_limited.insert(String::from("flaky.example.com"), 3);
+        let outcome = ValidationOutcome {
+            valid_links: vec![
+                link("https://fine.example.com/"),
+                link("https://flaky.example.com/a"),
+                link("./chapter_2.md"),
+            ],
+            invalid_links: vec![InvalidLink {
+                link: link("https://flaky.example.com/b"),
+                reason: Reason::Io(std::io::Error::other("broken")),
+            }],
+            request_timings: vec![
+                RequestTiming {
+                    link: link("https://flaky.example.com/a"),
+                    duration: Duration::from_millis(100),
+                },
+                RequestTiming {
+                    link: link("https://flaky.example.com/b"),
+                    duration: Duration::from_millis(300),
+                },
+            ],
+            rate_limited,
+            ..Default::default()
+        };
+
+        let got = domain_report(&outcome);
+
+        let should_be = "Results per domain:
+    Domain              Links  Failures     Average  Rate limited
+    flaky.example.com       2         1    200.00ms             3
+    fine.example.com        1         0           -             0
+";
+        assert_eq!(got, should_be);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::data_uri::percent_decode;
+use linkcheck::{validation::Outcomes, Link};
+use reqwest::Url;
+use std::path::PathBuf;
+
+/// Normalize a web link's URL so equivalent links (e.g. `http://host:80/a/`
+/// and `http://host/a`) are checked once and cached under the same key.
+///
+/// The URL parser already lowercases the scheme and host and drops default
+/// ports. On top of that we:
+///
+/// - strip the fragment, because it doesn't affect whether the page exists
+/// - strip trailing slashes from the path (apart from the root)
+/// - uppercase percent-encoded bytes (`%2f` becomes `%2F`)
+/// - drop empty queries (`?`)
+pub(crate) fn normalize(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    if url.cannot_be_a_base() {
+        return url;
+    }
+
+    let path = uppercase_percent_encoding(url.path());
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    let path = path.to_string();
+    url.set_path(&path);
+
+    match url.query().map(uppercase_percent_encoding) {
+        Some(query) if query.is_empty() => url.set_query(None),
+        Some(query) => url.set_query(Some(&query)),
+        None => {},
+    }
+
+    url
+}
+
+/// Get a copy of the link which uses its [`normalize()`]d URL, if it is a web
+/// link.
+pub(crate) fn normalize_link(link: &Link) -> Link {
+    match link.href.parse::<Url>() {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            Link::new(normalize(&url).to_string(), link.span, link.file)
+        },
+        _ => link.clone(),
+    }
+}
+
+/// Put the original link back into the [`Outcomes`] for checking its
+/// [`normalize_link()`]ed version, so diagnostics show what the author wrote.
+pub(crate) fn restore(mut outcomes: Outcomes, original: &Link) -> Outcomes {
+    for link in outcomes
+        .valid
+        .iter_mut()
+        .chain(outcomes.ignored.iter_mut())
+        .chain(outcomes.unknown_category.iter_mut())
+        .chain(outcomes.invalid.iter_mut().map(|invalid| &mut invalid.link))
+    {
+        link.href = original.href.clone();
+    }
+
+    outcomes
+}
+
+/// Is this a link to a local file (as opposed to a web link, a link to
+/// somewhere in the current page, or some other scheme like `mailto:`)?
+pub(crate) fn is_local(href: &str) -> bool {
+    !href.is_empty() && !href.starts_with('#') && href.parse::<Url>().is_err()
+}
+
+/// The file a local link points to, the same way a browser would find it
+/// (i.e. without the query or fragment, and with escapes decoded).
+pub(crate) fn local_path(href: &str) -> PathBuf {
+    let path = href.split(['#', '?']).next().unwrap_or_default();
+    PathBuf::from(String::from_utf8_lossy(&percent_decode(path)).into_owned())
+}
+
+fn uppercase_percent_encoding(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(ix) = rest.find('%') {
+        normalized.push_str(&rest[..=ix]);
+        rest = &rest[ix + 1..];
+
+        if let Some(hex) = rest.get(..2) {
+            if hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                normalized.push_str(&hex.to_ascii_uppercase());
+                rest = &rest[2..];
+            }
+        }
+    }
+    normalized.push_str(rest);
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equivalent_urls_are_normalized_the_same() {
+        let inputs = vec![
+            ("http://host:80/a/", "http://host/a"),
+            ("http://HOST/a", "http://host/a"),
+            ("https://host:443/a#section", "https://host/a"),
+            ("https://host/a%2fb%3a", "https://host/a%2Fb%3A"),
+            ("https://host/?", "https://host/"),
+            ("https://host/a?q=%e2%9c%93", "https://host/a?q=%E2%9C%93"),
+            ("https://host//", "https://host/"),
+            ("https://host/100%zz", "https://host/100%zz"),
+            ("https://host:8080/a/", "https://host:8080/a"),
+            ("mailto:someone@example.com", "mailto:someone@example.com"),
+        ];
+
+        for (url, should_be) in inputs {
+            let url: Url = url.parse().unwrap();
+            assert_eq!(normalize(&url).as_str(), should_be, "{}", url);
+        }
+    }
+
+    #[test]
+    fn decode_local_paths() {
+        let inputs = vec![
+            ("./chapter_1.md", true, "./chapter_1.md"),
+            ("path with spaces.md#intro", true, "path with spaces.md"),
+            ("path%20with%20spaces.md?q=1", true, "path with spaces.md"),
+            ("foo(1).md", true, "foo(1).md"),
+            ("#heading", false, ""),
+            ("https://example.com/a%20b", false, "https://example.com/a b"),
+        ];
+
+        for (href, local, path) in inputs {
+            assert_eq!(is_local(href), local, "{}", href);
+            assert_eq!(local_path(href), PathBuf::from(path), "{}", href);
+        }
+    }
+}
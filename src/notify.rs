@@ -0,0 +1,221 @@
+use crate::{
+    config::interpolate_env, http_client, Cache, Config, HttpClient, Metrics,
+    ValidationOutcome,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::{Diagnostic, Severity};
+use linkcheck::Link;
+use http::header::{HeaderValue, CONTENT_TYPE};
+use reqwest::{Method, Request, Url};
+use serde::Serialize;
+use tokio::runtime::Builder;
+
+/// The JSON sent to [`Config::notify_webhook`] when a run completes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Summary {
+    /// A one-line description, so chat tools which only understand a `text`
+    /// field (e.g. Slack and Teams incoming webhooks) show something useful.
+    text: String,
+    errors: usize,
+    warnings: usize,
+    #[serde(flatten)]
+    metrics: Metrics,
+    /// Broken web links which were fine the last time they were checked.
+    regressions: Vec<Regression>,
+    report_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Regression {
+    href: String,
+    file: String,
+    line: usize,
+}
+
+/// Send a summary of the run to [`Config::notify_webhook`].
+///
+/// `previous` is the cache from before the run, which is used to figure out
+/// which broken links used to work.
+pub(crate) fn notify(
+    webhook: &str,
+    cfg: &Config,
+    outcome: &ValidationOutcome,
+    diags: &[Diagnostic<FileId>],
+    files: &Files<String>,
+    previous: &Cache,
+    http: &dyn HttpClient,
+) -> Result<(), Error> {
+    let webhook: Url = interpolate_env(webhook)
+        .context("Unable to expand the notify-webhook URL")?
+        .parse()
+        .context("The notify-webhook isn't a valid URL")?;
+    let report_url = cfg
+        .report_url
+        .as_deref()
+        .map(interpolate_env)
+        .transpose()
+        .context("Unable to expand the report-url")?;
+    let summary = summarize(outcome, diags, files, previous, report_url);
+
+    let mut request = Request::new(Method::POST, webhook);
+    request
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    *request.body_mut() = Some(serde_json::to_string(&summary)?.into());
+
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async {
+        http_client::send(http, cfg, request)
+            .await?
+            .error_for_status()?;
+        tracing::info!("Sent a summary to the notify-webhook");
+
+        Ok(())
+    })
+}
+
+/// Broken web links which were fine the last time they were checked,
+/// according to the `previous` cache.
+pub(crate) fn regressions<'a>(
+    outcome: &'a ValidationOutcome,
+    previous: &'a Cache,
+) -> impl Iterator<Item = &'a Link> + 'a {
+    outcome
+        .invalid_links
+        .iter()
+        .map(|invalid| &invalid.link)
+        .filter(move |link| {
+            link.href
+                .parse::<Url>()
+                .ok()
+                .map(|url| crate::normalize::normalize(&url))
+                .and_then(|url| previous.lookup(&url).map(|entry| entry.valid))
+                .unwrap_or(false)
+        })
+}
+
+fn summarize(
+    outcome: &ValidationOutcome,
+    diags: &[Diagnostic<FileId>],
+    files: &Files<String>,
+    previous: &Cache,
+    report_url: Option<String>,
+) -> Summary {
+    let count = |severity: Severity| {
+        diags.iter().filter(|diag| diag.severity == severity).count()
+    };
+    let errors = count(Severity::Error) + count(Severity::Bug);
+    let warnings = count(Severity::Warning);
+
+    let regressions: Vec<Regression> = regressions(outcome, previous)
+        .map(|link| {
+            let line = files
+                .location(link.file, link.span.start())
+                .map(|location| location.line.number().to_usize())
+                .unwrap_or(0);
+
+            Regression {
+                href: link.href.clone(),
+                file: files.name(link.file).to_string_lossy().into_owned(),
+                line,
+            }
+        })
+        .collect();
+
+    let mut text = format!(
+        "{}: {} errors and {} warnings",
+        env!("CARGO_PKG_NAME"),
+        errors,
+        warnings
+    );
+    if !regressions.is_empty() {
+        text.push_str(&format!(
+            " ({} links broke since the last run)",
+            regressions.len()
+        ));
+    }
+    if let Some(ref url) = report_url {
+        text.push_str(&format!(". See {}", url));
+    }
+
+    Summary {
+        text,
+        errors,
+        warnings,
+        metrics: Metrics::from_outcome(outcome),
+        regressions,
+        report_url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+    use codespan_reporting::diagnostic::Label;
+    use linkcheck::validation::{CacheEntry, InvalidLink, Reason};
+    use std::time::SystemTime;
+
+    #[test]
+    fn links_which_used_to_work_are_regressions() {
+        let mut files = Files::new();
+        let id = files.add(
+            "chapter_1.md",
+            String::from("# Chapter 1\n\n[a](https://a.example/)\n"),
+        );
+        let broken = |href: &str| InvalidLink {
+            link: Link::new(href, Span::new(13, 14), id),
+            reason: Reason::Io(std::io::Error::other("broken")),
+        };
+        let outcome = ValidationOutcome {
+            invalid_links: vec![
+                broken("https://a.example/"),
+                broken("https://b.example/"),
+            ],
+            ..Default::default()
+        };
+        let diags = vec![
+            Diagnostic::error()
+                .with_labels(vec![Label::primary(id, 13..14)]),
+            Diagnostic::warning()
+                .with_labels(vec![Label::primary(id, 13..14)]),
+        ];
+        let mut previous = Cache::default();
+        previous.insert(
+            "https://a.example/".parse().unwrap(),
+            CacheEntry::new(SystemTime::now(), true),
+        );
+        previous.insert(
+            "https://b.example/".parse().unwrap(),
+            CacheEntry::new(SystemTime::now(), false),
+        );
+
+        let got = summarize(
+            &outcome,
+            &diags,
+            &files,
+            &previous,
+            Some(String::from("https://ci.example/job/1")),
+        );
+
+        assert_eq!(got.errors, 1);
+        assert_eq!(got.warnings, 1);
+        assert_eq!(got.metrics.invalid_links, 2);
+        assert_eq!(
+            got.regressions,
+            vec![Regression {
+                href: String::from("https://a.example/"),
+                file: String::from("chapter_1.md"),
+                line: 3,
+            }]
+        );
+        assert_eq!(
+            got.text,
+            "mdbook-linkcheck: 1 errors and 1 warnings (1 links broke since \
+             the last run). See https://ci.example/job/1"
+        );
+    }
+}
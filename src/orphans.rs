@@ -0,0 +1,122 @@
+use crate::{changes::local_target, FileProvider};
+use mdbook::book::{Book, BookItem};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// Find markdown files under `src_dir` which aren't in `SUMMARY.md` and
+/// aren't linked to (or included) from any chapter. These are usually
+/// forgotten content which should either be linked to or deleted.
+///
+/// Paths are relative to `src_dir` and sorted.
+pub(crate) fn find_orphans(
+    book: &Book,
+    src_dir: &Path,
+    files: &dyn FileProvider,
+) -> Vec<PathBuf> {
+    let mut referenced = HashSet::new();
+    referenced.insert(PathBuf::from("SUMMARY.md"));
+
+    for item in book.iter() {
+        let ch = match item {
+            BookItem::Chapter(ch) => ch,
+            BookItem::Separator | BookItem::PartTitle(_) => continue,
+        };
+        let path = match ch.source_path.as_ref().or(ch.path.as_ref()) {
+            Some(path) => path,
+            None => continue,
+        };
+        referenced.insert(path.clone());
+
+        for (href, _) in linkcheck::scanners::markdown(&ch.content) {
+            referenced.extend(local_target(path, &href));
+        }
+
+        // mdbook has already expanded any includes, so we need to look at
+        // the original source
+        if let Ok(raw) = files.read_to_string(&src_dir.join(path)) {
+            for included in crate::includes::included_files(&raw) {
+                referenced.extend(local_target(path, &included));
+            }
+        }
+    }
+
+    let mut orphans: Vec<PathBuf> = markdown_files(src_dir, files)
+        .into_iter()
+        .filter(|path| !referenced.contains(path))
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+/// Every markdown file under `src_dir`, relative to `src_dir`.
+fn markdown_files(src_dir: &Path, files: &dyn FileProvider) -> Vec<PathBuf> {
+    fn visit(
+        root: &Path,
+        dir: &Path,
+        files: &dyn FileProvider,
+        found: &mut Vec<PathBuf>,
+    ) {
+        let entries = match files.read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::debug!("Unable to read \"{}\": {}", dir.display(), e);
+                return;
+            },
+        };
+
+        for path in entries {
+            let hidden = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'));
+
+            if hidden {
+                continue;
+            } else if files.is_dir(&path) {
+                visit(root, &path, files, found);
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    found.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    visit(src_dir, src_dir, files, &mut found);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RealFileSystem;
+    use mdbook::book::Chapter;
+
+    #[test]
+    fn files_which_are_linked_to_arent_orphans() {
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("orphans")
+            .join("src");
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Chapter 1",
+            String::from("[appendix](./appendix.md)"),
+            "chapter_1.md",
+            Vec::new(),
+        ));
+
+        let got = find_orphans(&book, &src_dir, &RealFileSystem);
+
+        assert_eq!(
+            got,
+            vec![
+                PathBuf::from("forgotten.md"),
+                Path::new("nested").join("old.md")
+            ]
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{
+    validate::SharedFailure, DraftChapter, MalformedDataUri, MalformedDoi,
+    MalformedPhoneNumber, MissingAnchor, MissingCrate, NotInSummary,
+    UnreadableFile,
+};
+use codespan::FileId;
+use codespan_reporting::diagnostic::Diagnostic;
+use linkcheck::validation::{InvalidLink, Reason};
+use serde::{ser::SerializeSeq, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+/// Why a link is broken, in a form that can be matched on (or serialized)
+/// instead of parsing the error message.
+///
+/// New variants may be added as more checks are introduced, so there should
+/// always be a fallback arm when matching.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum FailureReason {
+    /// The link goes outside the book's source directory.
+    TraversesParentDirectories,
+    /// The file being linked to doesn't exist.
+    FileNotFound,
+    /// The file exists, but doesn't have the anchor being linked to (see
+    /// [`MissingAnchor`]).
+    MissingAnchor {
+        /// The file being linked to.
+        path: PathBuf,
+        /// The anchor which couldn't be found.
+        anchor: String,
+    },
+    /// The file exists, but isn't in `SUMMARY.md` (see [`NotInSummary`]).
+    NotInSummary {
+        /// The file's full path.
+        path: PathBuf,
+    },
+    /// The file doesn't exist because it belongs to a draft chapter (see
+    /// [`DraftChapter`]).
+    DraftChapter {
+        /// The chapter's name in `SUMMARY.md`.
+        name: String,
+    },
+    /// The file exists, but can't be read (see [`UnreadableFile`]).
+    UnreadableFile {
+        /// The file's full path.
+        path: PathBuf,
+        /// Why the file couldn't be read.
+        message: String,
+    },
+    /// A link to `docs.rs` or `crates.io` refers to a crate (or version)
+    /// which doesn't exist (see [`MissingCrate`]).
+    MissingCrate {
+        /// The crate's name.
+        name: String,
+        /// The version, if the link pinned one.
+        version: Option<String>,
+    },
+    /// A `data:` URI is malformed (see [`MalformedDataUri`]).
+    MalformedDataUri {
+        /// What was wrong with it.
+        reason: String,
+    },
+    /// A link to the DOI resolver doesn't contain a valid DOI (see
+    /// [`MalformedDoi`]).
+    MalformedDoi {
+        /// The supposed DOI.
+        doi: String,
+    },
+    /// A `tel:` link doesn't contain a valid phone number (see
+    /// [`MalformedPhoneNumber`]).
+    MalformedPhoneNumber {
+        /// The phone number, without any visual separators.
+        number: String,
+    },
+    /// The server responded with an error status code.
+    HttpStatus {
+        /// The status code (e.g. `404`).
+        status: u16,
+        /// The URL which was requested.
+        url: Option<String>,
+    },
+    /// The request timed out.
+    Timeout {
+        /// The URL which was requested.
+        url: Option<String>,
+    },
+    /// Any other problem sending a request (e.g. the domain doesn't exist or
+    /// the connection was refused).
+    Web {
+        /// The URL which was requested.
+        url: Option<String>,
+        /// The error message.
+        message: String,
+    },
+    /// Any other problem checking a file.
+    Io {
+        /// The error message.
+        message: String,
+    },
+}
+
+impl FailureReason {
+    /// Figure out why a link is broken from the error `linkcheck` gave us.
+    pub fn from_reason(reason: &Reason) -> Self {
+        match reason {
+            Reason::TraversesParentDirectories => {
+                FailureReason::TraversesParentDirectories
+            },
+            Reason::Io(io) => match io.get_ref() {
+                Some(inner) => {
+                    FailureReason::from_error(inner).unwrap_or_else(|| {
+                        FailureReason::from_io(io)
+                    })
+                },
+                None => FailureReason::from_io(io),
+            },
+            Reason::Web(web) => {
+                let url = web.url().map(|url| url.to_string());
+
+                if let Some(status) = web.status() {
+                    FailureReason::HttpStatus {
+                        status: status.as_u16(),
+                        url,
+                    }
+                } else if web.is_timeout() {
+                    FailureReason::Timeout { url }
+                } else {
+                    FailureReason::Web {
+                        url,
+                        message: web.to_string(),
+                    }
+                }
+            },
+            _ => FailureReason::Io {
+                message: reason.to_string(),
+            },
+        }
+    }
+
+    fn from_io(io: &std::io::Error) -> Self {
+        if io.kind() == std::io::ErrorKind::NotFound {
+            FailureReason::FileNotFound
+        } else {
+            FailureReason::Io {
+                message: io.to_string(),
+            }
+        }
+    }
+
+    /// Recognise the errors we wrap in a [`std::io::Error`].
+    fn from_error(
+        error: &(dyn std::error::Error + Send + Sync + 'static),
+    ) -> Option<Self> {
+        if let Some(e) = error.downcast_ref::<SharedFailure>() {
+            Some(e.reason.clone())
+        } else if let Some(e) = error.downcast_ref::<MissingAnchor>() {
+            Some(FailureReason::MissingAnchor {
+                path: e.path.clone(),
+                anchor: e.anchor.clone(),
+            })
+        } else if let Some(e) = error.downcast_ref::<NotInSummary>() {
+            Some(FailureReason::NotInSummary {
+                path: e.path.clone(),
+            })
+        } else if let Some(e) = error.downcast_ref::<DraftChapter>() {
+            Some(FailureReason::DraftChapter {
+                name: e.name.clone(),
+            })
+        } else if let Some(e) = error.downcast_ref::<UnreadableFile>() {
+            Some(FailureReason::UnreadableFile {
+                path: e.path.clone(),
+                message: e.error.to_string(),
+            })
+        } else if let Some(e) = error.downcast_ref::<MissingCrate>() {
+            Some(FailureReason::MissingCrate {
+                name: e.name.clone(),
+                version: e.version.clone(),
+            })
+        } else if let Some(e) = error.downcast_ref::<MalformedDataUri>() {
+            Some(FailureReason::MalformedDataUri {
+                reason: e.reason.clone(),
+            })
+        } else if let Some(e) = error.downcast_ref::<MalformedDoi>() {
+            Some(FailureReason::MalformedDoi { doi: e.doi.clone() })
+        } else {
+            error.downcast_ref::<MalformedPhoneNumber>().map(|e| {
+                FailureReason::MalformedPhoneNumber {
+                    number: e.number.clone(),
+                }
+            })
+        }
+    }
+}
+
+impl From<&Reason> for FailureReason {
+    fn from(reason: &Reason) -> Self { FailureReason::from_reason(reason) }
+}
+
+/// The kind of problem a [`Diagnostic`] from
+/// [`crate::ValidationOutcome::generate_diagnostics`] is about.
+///
+/// Each category's [`DiagnosticCategory::code()`] is used as the
+/// diagnostic's code, so embedders can tell diagnostics apart without
+/// looking at their messages. New categories may be added as more checks are
+/// introduced.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum DiagnosticCategory {
+    /// A link which failed validation.
+    BrokenLink,
+    /// A reference-style link without a matching definition.
+    IncompleteLink,
+    /// A link to an absolute path, which will break if the book is hosted
+    /// somewhere other than the root of a domain.
+    AbsoluteLink,
+    /// A plain `http://` link (see [`crate::Config::forbid_http`]).
+    ForbiddenHttp,
+    /// A link to a private network (see
+    /// [`crate::Config::private_network_links`]).
+    PrivateNetwork,
+    /// A link to a denied domain (see [`crate::Config::deny_domains`]).
+    DeniedDomain,
+    /// A domain which could be mistaken for another one.
+    Homograph,
+    /// A `data:` URI which is bigger than [`crate::Config::max_data_uri_size`].
+    OversizedDataUri,
+    /// A `javascript:` link.
+    JavascriptLinks,
+    /// A `file://` link.
+    FileLinks,
+    /// A link using a URL scheme the link checker doesn't know about.
+    UnknownScheme,
+    /// A link to a file which won't be in the rendered book.
+    UnpublishedFile,
+    /// A link to a file mdbook reads but never renders (e.g. `SUMMARY.md`).
+    SpecialFile,
+    /// A link to an empty file.
+    EmptyFile,
+    /// A link which was changed by [`crate::Config::rewrites`].
+    RewrittenLink,
+    /// A `{{#include}}` with missing or unbalanced `ANCHOR` markers.
+    BrokenInclude,
+    /// A problem with some HTML embedded in a chapter.
+    Html,
+    /// An image without any alt text.
+    MissingAltText,
+    /// A link to the chapter it is in.
+    SelfReferentialLink,
+    /// A remote image served with the wrong content type.
+    WrongContentType,
+    /// A web link which redirects to an unrelated domain.
+    CrossDomainRedirect,
+    /// A HTTPS link to a server which only supports outdated versions of TLS.
+    OutdatedTls,
+    /// A HTTPS link to a server whose certificate expires soon.
+    CertificateExpiry,
+    /// A problem with a link to a downloadable asset.
+    Asset,
+    /// A link to a yanked or outdated version of a crate.
+    Crate,
+    /// Web links which weren't checked because
+    /// [`crate::Config::max_runtime`] was exceeded.
+    UncheckedLinks,
+    /// A link to a translation which doesn't exist.
+    MissingTranslation,
+    /// A markdown file which isn't in `SUMMARY.md` and isn't linked to.
+    Orphan,
+}
+
+impl DiagnosticCategory {
+    /// Every category.
+    pub const ALL: &'static [DiagnosticCategory] = &[
+        DiagnosticCategory::BrokenLink,
+        DiagnosticCategory::IncompleteLink,
+        DiagnosticCategory::AbsoluteLink,
+        DiagnosticCategory::ForbiddenHttp,
+        DiagnosticCategory::PrivateNetwork,
+        DiagnosticCategory::DeniedDomain,
+        DiagnosticCategory::Homograph,
+        DiagnosticCategory::OversizedDataUri,
+        DiagnosticCategory::JavascriptLinks,
+        DiagnosticCategory::FileLinks,
+        DiagnosticCategory::UnknownScheme,
+        DiagnosticCategory::UnpublishedFile,
+        DiagnosticCategory::SpecialFile,
+        DiagnosticCategory::EmptyFile,
+        DiagnosticCategory::RewrittenLink,
+        DiagnosticCategory::BrokenInclude,
+        DiagnosticCategory::Html,
+        DiagnosticCategory::MissingAltText,
+        DiagnosticCategory::SelfReferentialLink,
+        DiagnosticCategory::WrongContentType,
+        DiagnosticCategory::CrossDomainRedirect,
+        DiagnosticCategory::OutdatedTls,
+        DiagnosticCategory::CertificateExpiry,
+        DiagnosticCategory::Asset,
+        DiagnosticCategory::Crate,
+        DiagnosticCategory::UncheckedLinks,
+        DiagnosticCategory::MissingTranslation,
+        DiagnosticCategory::Orphan,
+    ];
+
+    /// The code attached to diagnostics in this category (e.g.
+    /// `broken-link`).
+    pub const fn code(self) -> &'static str {
+        match self {
+            DiagnosticCategory::BrokenLink => "broken-link",
+            DiagnosticCategory::IncompleteLink => "incomplete-link",
+            DiagnosticCategory::AbsoluteLink => "absolute-link",
+            DiagnosticCategory::ForbiddenHttp => "forbidden-http",
+            DiagnosticCategory::PrivateNetwork => "private-network",
+            DiagnosticCategory::DeniedDomain => "denied-domain",
+            DiagnosticCategory::Homograph => "homograph",
+            DiagnosticCategory::OversizedDataUri => "oversized-data-uri",
+            DiagnosticCategory::JavascriptLinks => "javascript-links",
+            DiagnosticCategory::FileLinks => "file-links",
+            DiagnosticCategory::UnknownScheme => "unknown-scheme",
+            DiagnosticCategory::UnpublishedFile => "unpublished-file",
+            DiagnosticCategory::SpecialFile => "special-file",
+            DiagnosticCategory::EmptyFile => "empty-file",
+            DiagnosticCategory::RewrittenLink => "rewritten-link",
+            DiagnosticCategory::BrokenInclude => "broken-include",
+            DiagnosticCategory::Html => "html",
+            DiagnosticCategory::MissingAltText => "missing-alt-text",
+            DiagnosticCategory::SelfReferentialLink => {
+                "self-referential-link"
+            },
+            DiagnosticCategory::WrongContentType => "wrong-content-type",
+            DiagnosticCategory::CrossDomainRedirect => {
+                "cross-domain-redirect"
+            },
+            DiagnosticCategory::OutdatedTls => "outdated-tls",
+            DiagnosticCategory::CertificateExpiry => "certificate-expiry",
+            DiagnosticCategory::Asset => "asset",
+            DiagnosticCategory::Crate => "crate",
+            DiagnosticCategory::UncheckedLinks => "unchecked-links",
+            DiagnosticCategory::MissingTranslation => "missing-translation",
+            DiagnosticCategory::Orphan => "orphan",
+        }
+    }
+
+    /// Look up a category by its [`DiagnosticCategory::code()`].
+    pub fn from_code(code: &str) -> Option<Self> {
+        DiagnosticCategory::ALL
+            .iter()
+            .copied()
+            .find(|category| category.code() == code)
+    }
+
+    /// Which category does this diagnostic belong to?
+    pub fn of(diag: &Diagnostic<FileId>) -> Option<Self> {
+        diag.code.as_deref().and_then(DiagnosticCategory::from_code)
+    }
+}
+
+impl Display for DiagnosticCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Serialize broken links as a list of `{ link, reason, message }` objects,
+/// because `linkcheck`'s errors can't be serialized directly.
+pub(crate) fn serialize_invalid_links<S>(
+    invalid_links: &[InvalidLink],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    struct Serialized<'a> {
+        link: &'a linkcheck::Link,
+        reason: FailureReason,
+        message: String,
+    }
+
+    let mut seq = serializer.serialize_seq(Some(invalid_links.len()))?;
+    for invalid in invalid_links {
+        seq.serialize_element(&Serialized {
+            link: &invalid.link,
+            reason: FailureReason::from_reason(&invalid.reason),
+            message: crate::validate::most_specific_error_message(invalid),
+        })?;
+    }
+    seq.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::{Files, Span};
+    use linkcheck::Link;
+    use std::path::Path;
+
+    #[test]
+    fn recognise_our_own_errors() {
+        let missing_anchor = Reason::Io(std::io::Error::other(MissingAnchor {
+            path: PathBuf::from("chapter_1.md"),
+            anchor: String::from("intro"),
+        }));
+        let not_found = Reason::Io(std::io::ErrorKind::NotFound.into());
+        let other = Reason::Io(std::io::Error::other("broken"));
+
+        assert_eq!(
+            FailureReason::from(&missing_anchor),
+            FailureReason::MissingAnchor {
+                path: PathBuf::from("chapter_1.md"),
+                anchor: String::from("intro"),
+            }
+        );
+        assert_eq!(
+            FailureReason::from(&Reason::TraversesParentDirectories),
+            FailureReason::TraversesParentDirectories
+        );
+        assert_eq!(
+            FailureReason::from(&not_found),
+            FailureReason::FileNotFound
+        );
+        assert_eq!(
+            FailureReason::from(&other),
+            FailureReason::Io {
+                message: String::from("broken")
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(FailureReason::from(&missing_anchor))
+                .unwrap(),
+            serde_json::json!({
+                "kind": "missing-anchor",
+                "path": Path::new("chapter_1.md"),
+                "anchor": "intro",
+            })
+        );
+    }
+
+    #[test]
+    fn categories_round_trip_through_their_codes() {
+        for &category in DiagnosticCategory::ALL {
+            let code = serde_json::to_value(category).unwrap();
+
+            assert_eq!(code, category.code());
+            assert_eq!(
+                DiagnosticCategory::from_code(category.code()),
+                Some(category)
+            );
+        }
+
+        assert_eq!(DiagnosticCategory::from_code("not-a-category"), None);
+    }
+
+    #[test]
+    fn serialize_an_outcome() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from("[broken](./a.md)"));
+        let link = Link::new("./a.md", Span::new(0, 16), id);
+        let outcome = crate::ValidationOutcome {
+            invalid_links: vec![InvalidLink {
+                link,
+                reason: Reason::Io(std::io::ErrorKind::NotFound.into()),
+            }],
+            ..Default::default()
+        };
+
+        let got = serde_json::to_value(&outcome).unwrap();
+
+        let broken = &got["invalid-links"][0];
+        assert_eq!(broken["link"]["href"], "./a.md");
+        assert_eq!(broken["reason"]["kind"], "file-not-found");
+        assert_eq!(broken["message"], "File not found: ./a.md");
+        let diags = outcome.generate_diagnostics(&files, &Default::default());
+        assert_eq!(
+            DiagnosticCategory::of(&diags[0]),
+            Some(DiagnosticCategory::BrokenLink)
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{
+    validate::{broken_link_severity, most_specific_error_message},
+    Checker, Config,
+};
+use anyhow::Error;
+use mdbook::{
+    book::{Book, BookItem},
+    preprocess::{Preprocessor, PreprocessorContext},
+};
+use std::{collections::HashMap, path::Path};
+
+/// A `mdbook` preprocessor which adds a visible warning next to every broken
+/// link, so people reviewing a preview build can see problems in context.
+///
+/// It uses the same `[output.linkcheck]` configuration as the backend, and
+/// is enabled by adding this to `book.toml`:
+///
+/// ```toml
+/// [preprocessor.linkcheck]
+/// command = "mdbook-linkcheck preprocess"
+/// ```
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LinkcheckPreprocessor;
+
+impl Preprocessor for LinkcheckPreprocessor {
+    fn name(&self) -> &str { "linkcheck" }
+
+    fn run(
+        &self,
+        ctx: &PreprocessorContext,
+        book: Book,
+    ) -> Result<Book, mdbook::errors::Error> {
+        let cfg = crate::get_config(&ctx.config)?;
+        let src_dir = ctx.root.join(&ctx.config.book.src);
+
+        annotate_book(&src_dir, cfg, book)
+    }
+
+    // the annotations are HTML, so they'd only be noise for other renderers
+    fn supports_renderer(&self, renderer: &str) -> bool { renderer == "html" }
+}
+
+/// Check the book and insert a warning after each broken link.
+fn annotate_book(
+    src_dir: &Path,
+    cfg: Config,
+    mut book: Book,
+) -> Result<Book, Error> {
+    let report = Checker::builder()
+        .source_dir(src_dir)
+        .config(cfg.clone())
+        .book(book.clone())
+        .check()?;
+
+    let mut annotations: HashMap<&Path, Vec<(usize, String)>> = HashMap::new();
+    for invalid in &report.outcome.invalid_links {
+        // links whose policy is "ignore" wouldn't be reported either
+        if broken_link_severity(invalid, &cfg, &report.files).is_none() {
+            continue;
+        }
+
+        let link = &invalid.link;
+        annotations
+            .entry(Path::new(report.files.name(link.file)))
+            .or_default()
+            .push((
+                link.span.end().to_usize(),
+                most_specific_error_message(invalid),
+            ));
+    }
+
+    book.for_each_mut(|item| {
+        if let BookItem::Chapter(ref mut ch) = item {
+            let chapter_annotations =
+                ch.path.as_deref().and_then(|path| annotations.get(path));
+
+            if let Some(chapter_annotations) = chapter_annotations {
+                ch.content = annotate(&ch.content, chapter_annotations);
+            }
+        }
+    });
+
+    Ok(book)
+}
+
+/// Insert a warning message at each offset.
+fn annotate(content: &str, annotations: &[(usize, String)]) -> String {
+    let mut annotations = annotations.to_vec();
+    annotations.sort_by_key(|(offset, _)| *offset);
+
+    let mut annotated = content.to_string();
+
+    for (offset, message) in annotations.iter().rev() {
+        if !annotated.is_char_boundary(*offset) {
+            continue;
+        }
+
+        let warning = format!(
+            r#" <mark class="linkcheck-broken-link" title="{0}">⚠️ Broken link: {0}</mark>"#,
+            escape_html(message)
+        );
+        annotated.insert_str(*offset, &warning);
+    }
+
+    annotated
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LocalConfig, WarningPolicy};
+    use mdbook::config::BuildConfig;
+    use std::path::PathBuf;
+
+    fn broken_links_book() -> (PathBuf, Book) {
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("broken-links")
+            .join("src");
+        let build_cfg = BuildConfig {
+            create_missing: false,
+            ..Default::default()
+        };
+        let book = mdbook::book::load_book(&src_dir, &build_cfg).unwrap();
+
+        (src_dir, book)
+    }
+
+    #[test]
+    fn insert_warnings_after_each_link() {
+        let content = "[first](a.md) and [second](b.md)";
+        let annotations = vec![
+            (32, String::from("File not found: b.md")),
+            (13, String::from("File <not> found")),
+        ];
+
+        let got = annotate(content, &annotations);
+
+        assert_eq!(
+            got,
+            r#"[first](a.md) <mark class="linkcheck-broken-link" title="File &lt;not&gt; found">⚠️ Broken link: File &lt;not&gt; found</mark> and [second](b.md) <mark class="linkcheck-broken-link" title="File not found: b.md">⚠️ Broken link: File not found: b.md</mark>"#
+        );
+    }
+
+    #[test]
+    fn annotate_broken_links_in_a_book() {
+        let (src_dir, book) = broken_links_book();
+
+        let got = annotate_book(&src_dir, Config::default(), book).unwrap();
+
+        let sibling = got
+            .iter()
+            .find_map(|item| match item {
+                BookItem::Chapter(ch)
+                    if ch.path.as_deref()
+                        == Some(Path::new("second/directory.md")) =>
+                {
+                    Some(ch.content.clone())
+                },
+                _ => None,
+            })
+            .unwrap();
+        assert!(sibling.contains("(sibling.md) <mark"), "{}", sibling);
+    }
+
+    #[test]
+    fn links_which_are_ignored_arent_annotated() {
+        let (src_dir, book) = broken_links_book();
+        let cfg = Config {
+            local: LocalConfig {
+                severity: Some(WarningPolicy::Ignore),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let got = annotate_book(&src_dir, cfg, book).unwrap();
+
+        got.iter().for_each(|item| {
+            if let BookItem::Chapter(ch) = item {
+                assert!(!ch.content.contains("<mark"), "{}", ch.content);
+            }
+        });
+    }
+}
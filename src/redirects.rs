@@ -0,0 +1,131 @@
+use crate::config::is_web_link;
+use linkcheck::Link;
+use reqwest::Url;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+/// A web link which redirects to a different domain, which often means the
+/// page moved or the old domain was bought by someone else.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrossDomainRedirect {
+    /// The link.
+    pub link: Link,
+    /// The domain being linked to.
+    pub from: String,
+    /// The domain we ended up on.
+    pub to: String,
+}
+
+/// Look for web links in `valid_links` which ended up on an unrelated domain,
+/// going by where each (normalized) URL was redirected to while it was being
+/// checked.
+///
+/// We stop at the last URL we could reach, so a redirect to a domain which
+/// has since gone offline is still reported.
+pub(crate) fn cross_domain_redirects(
+    valid_links: &[Link],
+    final_destinations: &BTreeMap<String, String>,
+) -> Vec<CrossDomainRedirect> {
+    let mut redirects: Vec<_> = valid_links
+        .iter()
+        .filter(|link| is_web_link(&link.href))
+        .filter_map(|link| {
+            let url = link.href.parse::<Url>().ok()?;
+            let url = crate::normalize::normalize(&url);
+            let destination = final_destinations
+                .get(url.as_str())?
+                .parse::<Url>()
+                .ok()?;
+            let from = url.host_str()?.to_lowercase();
+            let to = destination.host_str()?.to_lowercase();
+            if same_site(&from, &to) {
+                return None;
+            }
+
+            Some(CrossDomainRedirect {
+                link: link.clone(),
+                from,
+                to,
+            })
+        })
+        .collect();
+
+    redirects.sort_by_key(|r| (r.link.file, r.link.span));
+    redirects
+}
+
+/// Are these hosts part of the same site (e.g. `example.com` and
+/// `docs.example.com`)?
+fn same_site(from: &str, to: &str) -> bool {
+    let from = from.trim_start_matches("www.");
+    let to = to.trim_start_matches("www.");
+    let is_subdomain = |sub: &str, parent: &str| {
+        sub.strip_suffix(parent).is_some_and(|s| s.ends_with('.'))
+    };
+
+    from == to || is_subdomain(from, to) || is_subdomain(to, from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http_client::Canned, validate::validate_with, Config, WarningPolicy,
+        WebConfig,
+    };
+    use codespan::{Files, Span};
+    use std::sync::Arc;
+
+    #[test]
+    fn redirects_within_a_site_are_fine() {
+        let inputs = vec![
+            ("example.com", "example.com", true),
+            ("example.com", "www.example.com", true),
+            ("www.example.com", "docs.example.com", true),
+            ("docs.example.com", "example.com", true),
+            ("example.com", "notexample.com", false),
+            ("example.com", "example.net", false),
+            ("old-project.org", "parked-domains.example", false),
+        ];
+
+        for (from, to, should_be) in inputs {
+            assert_eq!(same_site(from, to), should_be, "{} -> {}", from, to);
+        }
+    }
+
+    #[test]
+    fn detect_redirects_to_another_domain() {
+        let client = Canned::default()
+            .respond("http://old-project.org/old", 301, &[(
+                "Location",
+                "http://parked.invalid/",
+            )])
+            .respond("http://parked.invalid/", 200, &[]);
+        let requested = Arc::clone(&client.requested);
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let link = Link::new("http://old-project.org/old", Span::new(0, 1), id);
+        let cfg = Config {
+            follow_web_links: true,
+            web: WebConfig {
+                cross_domain_redirects: Some(WarningPolicy::Warn),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let got =
+            validate_with(&client, std::slice::from_ref(&link), &cfg, &files);
+
+        assert_eq!(
+            got.cross_domain_redirects,
+            vec![CrossDomainRedirect {
+                link,
+                from: String::from("old-project.org"),
+                to: String::from("parked.invalid"),
+            }]
+        );
+        // the redirect was recorded during the normal check
+        assert_eq!(requested.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{
+    anchors::AnchorIndex, normalize, Cache, Config, FileProvider,
+    RealFileSystem, ValidationOutcome,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files, Span};
+use codespan_reporting::term::termcolor::ColorChoice;
+use linkcheck::Link;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Url;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\s(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+static BASE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"<base\s[^>]*href\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+
+/// Check the links in a book's rendered HTML (e.g. the `book/` directory)
+/// instead of its markdown source, to catch problems introduced by
+/// preprocessors and themes.
+///
+/// Every `href` and `src` attribute in each page is checked, and links to a
+/// section of a page are checked against the `id`s in the generated HTML.
+///
+/// If `root` contains a `book.toml`, its configuration is used and the HTML
+/// is looked for in the build directory (unless `html_dir` says otherwise).
+/// Otherwise `root` is treated as the directory of HTML files itself.
+pub fn check_rendered(
+    root: &Path,
+    html_dir: Option<&Path>,
+    cache_file: Option<&Path>,
+    colour: ColorChoice,
+) -> Result<(), Error> {
+    let (default_dir, cfg) = locate(root)?;
+    let html_dir = html_dir.map(Path::to_path_buf).unwrap_or(default_dir);
+    tracing::info!("Checking the rendered book in \"{}\"", html_dir.display());
+
+    let mut cache: Cache = match cache_file {
+        Some(cache_file) => crate::load_cache(cache_file),
+        None => Cache::default(),
+    };
+    let (files, outcome) =
+        check_html_directory(&html_dir, &cfg, &mut cache, &RealFileSystem)?;
+    let diags = outcome.generate_diagnostics(&files, &cfg);
+    crate::report_errors(&files, &diags, colour, cfg.max_diagnostics)?;
+
+    if let Some(cache_file) = cache_file {
+        crate::save_cache(cache_file, &cache);
+    }
+
+    crate::check_for_failures(&diags, &cfg)
+}
+
+/// Find the directory the HTML renderer writes to and the configuration to
+/// use.
+fn locate(root: &Path) -> Result<(PathBuf, Config), Error> {
+    let book_toml = root.join("book.toml");
+    if !book_toml.exists() {
+        return Ok((root.to_path_buf(), Config::default()));
+    }
+
+    let book_cfg = mdbook::Config::from_disk(&book_toml)?;
+    let mut cfg = crate::get_config(&book_cfg)?;
+    if cfg.deploy_prefix.is_none() {
+        // mdbook uses this for the 404 page's <base> tag
+        cfg.deploy_prefix = book_cfg
+            .get("output.html.site-url")
+            .and_then(|url| url.as_str())
+            .map(String::from);
+    }
+
+    // mdbook only uses a subdirectory when there are multiple renderers
+    let build_dir = root.join(&book_cfg.build.build_dir);
+    let html_dir = if build_dir.join("html").join("index.html").is_file() {
+        build_dir.join("html")
+    } else {
+        build_dir
+    };
+
+    Ok((html_dir, cfg))
+}
+
+/// Load every page in the directory and check the links they contain.
+pub(crate) fn check_html_directory(
+    html_dir: &Path,
+    cfg: &Config,
+    cache: &mut Cache,
+    file_provider: &dyn FileProvider,
+) -> Result<(Files<String>, ValidationOutcome), Error> {
+    let html_dir = file_provider.canonicalize(html_dir).with_context(|| {
+        let dir = html_dir.display();
+        format!("Unable to find the rendered book in \"{}\"", dir)
+    })?;
+
+    let mut files = Files::new();
+    let mut file_ids = Vec::new();
+    for path in html_files(&html_dir, file_provider)? {
+        let src = file_provider
+            .read_to_string(&path)
+            .with_context(|| format!("Unable to read \"{}\"", path.display()))?;
+        let name = path.strip_prefix(&html_dir).unwrap_or(&path);
+        file_ids.push(files.add(name, src));
+    }
+
+    let links: Vec<Link> = file_ids
+        .iter()
+        .flat_map(|&id| links_in_page(id, &files, &html_dir, file_provider))
+        .collect();
+    tracing::debug!("Found {} links in {} pages", links.len(), file_ids.len());
+
+    let pages: Vec<OsString> = file_ids
+        .iter()
+        .map(|&id| files.name(id).to_os_string())
+        .collect();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let (outcome, _) = runtime.block_on(crate::validate::validate_batches(
+        futures::stream::iter(std::iter::once((links, Vec::new()))),
+        cfg,
+        &html_dir,
+        cache,
+        &files,
+        &file_ids,
+        &pages,
+        &crate::drafts::Drafts::default(),
+        &crate::front_matter::Aliases::default(),
+        None,
+        &AnchorIndex::new(cfg.slugs.clone()),
+        None,
+        file_provider,
+        None,
+    ))?;
+
+    Ok((files, outcome))
+}
+
+/// Every `*.html` file under a directory, in a consistent order.
+fn html_files(
+    dir: &Path,
+    file_provider: &dyn FileProvider,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut entries = file_provider
+        .read_dir(dir)
+        .with_context(|| format!("Unable to read \"{}\"", dir.display()))?;
+    entries.sort();
+
+    let mut pages = Vec::new();
+    for path in entries {
+        if file_provider.is_dir(&path) {
+            pages.extend(html_files(&path, file_provider)?);
+        } else if path.extension().is_some_and(|ext| ext == "html") {
+            pages.push(path);
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Find the `href` and `src` attributes in a page, turning them into links
+/// the normal validation can follow.
+fn links_in_page(
+    id: FileId,
+    files: &Files<String>,
+    html_dir: &Path,
+    file_provider: &dyn FileProvider,
+) -> Vec<Link> {
+    let src = files.source(id);
+    let page = Path::new(files.name(id));
+    let base = base_href(src);
+
+    ATTRIBUTE
+        .captures_iter(src)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        // the page's own <base> tag isn't a link
+        .filter(|m| base.as_ref().map(|(_, range)| range) != Some(&m.range()))
+        .map(|m| {
+            let href = unescape(m.as_str());
+            let base = base.as_ref().map(|(href, _)| href.as_str());
+            let href =
+                resolve_href(&href, page, base, html_dir, file_provider);
+            Link::new(href, Span::new(m.start() as u32, m.end() as u32), id)
+        })
+        .collect()
+}
+
+/// The URL in the page's `<base href="...">` tag (which relative links are
+/// resolved against) and where it is.
+fn base_href(src: &str) -> Option<(String, std::ops::Range<usize>)> {
+    let m = BASE
+        .captures(src)
+        .and_then(|caps| caps.get(1).or_else(|| caps.get(2)))?;
+
+    Some((unescape(m.as_str()), m.range()))
+}
+
+/// Turn a link from a page into something the link checker understands,
+/// taking into account that browsers go to `index.html` when a link points
+/// at a directory.
+fn resolve_href(
+    href: &str,
+    page: &Path,
+    base: Option<&str>,
+    html_dir: &Path,
+    file_provider: &dyn FileProvider,
+) -> String {
+    if href.starts_with('#') {
+        // anchors are checked against the rendered page, not the markdown
+        let name = page.file_name().unwrap_or_default().to_string_lossy();
+        return format!("{}{}", name, href);
+    }
+    if !normalize::is_local(href) {
+        return href.to_string();
+    }
+
+    let href = match base {
+        Some(base) if !href.starts_with('/') && base.starts_with('/') => {
+            let site = Url::parse("http://localhost/").unwrap();
+            match site.join(base).and_then(|base| base.join(href)) {
+                Ok(url) => {
+                    let mut resolved = url.path().to_string();
+                    if let Some(query) = url.query() {
+                        resolved.push('?');
+                        resolved.push_str(query);
+                    }
+                    if let Some(fragment) = url.fragment() {
+                        resolved.push('#');
+                        resolved.push_str(fragment);
+                    }
+                    resolved
+                },
+                Err(_) => href.to_string(),
+            }
+        },
+        _ => href.to_string(),
+    };
+
+    let end = href.find(['?', '#']).unwrap_or(href.len());
+    let (path, rest) = href.split_at(end);
+    let target = if path.starts_with('/') {
+        html_dir.join(path.trim_start_matches('/'))
+    } else {
+        html_dir.join(page.parent().unwrap_or(Path::new(""))).join(path)
+    };
+
+    if path.is_empty() {
+        // e.g. "?query", which is the page itself
+        let name = page.file_name().unwrap_or_default().to_string_lossy();
+        format!("{}{}", name, rest)
+    } else if path.ends_with('/') {
+        format!("{}index.html{}", path, rest)
+    } else if file_provider.is_dir(&target) {
+        format!("{}/index.html{}", path, rest)
+    } else {
+        href
+    }
+}
+
+/// Undo the HTML escaping which is allowed in attribute values.
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_are_resolved_like_a_browser_would() {
+        let html_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rendered");
+        let page = Path::new("index.html");
+        let inputs = vec![
+            ("#intro", None, "index.html#intro"),
+            ("nested/", None, "nested/index.html"),
+            ("nested#x", None, "nested/index.html#x"),
+            ("chapter_1.html?a=1", None, "chapter_1.html?a=1"),
+            ("https://example.com/", None, "https://example.com/"),
+            ("chapter_1.html", Some("/"), "/chapter_1.html"),
+            ("../css/style.css", Some("/book/a/"), "/book/css/style.css"),
+        ];
+
+        for (href, base, should_be) in inputs {
+            let got =
+                resolve_href(href, page, base, &html_dir, &RealFileSystem);
+            assert_eq!(got, should_be, "{} (base {:?})", href, base);
+        }
+    }
+
+    #[test]
+    fn check_a_rendered_book() {
+        let html_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("rendered");
+
+        let cfg = Config {
+            check_anchors: true,
+            ..Default::default()
+        };
+
+        let (files, outcome) = check_html_directory(
+            &html_dir,
+            &cfg,
+            &mut Cache::default(),
+            &RealFileSystem,
+        )
+        .unwrap();
+
+        let mut broken: Vec<_> = outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| {
+                let file = files.name(invalid.link.file).to_string_lossy();
+                format!("{}: {}", file, invalid.link.href)
+            })
+            .collect();
+        broken.sort();
+        assert_eq!(
+            broken,
+            vec![
+                "index.html: chapter_1.html#missing-section",
+                "index.html: images/missing.png",
+                "index.html: missing.html",
+            ]
+        );
+        let web = outcome.ignored.iter().find(|link| link.href.contains('&'));
+        assert_eq!(web.unwrap().href, "https://example.com/?a=1&b=2");
+    }
+
+    #[test]
+    fn pages_are_read_through_the_file_provider() {
+        let mut pages = crate::InMemoryFiles::new();
+        pages.insert(
+            "/book/index.html",
+            r#"<a href="nested/">x</a> <a href="missing.html">y</a>"#,
+        );
+        pages.insert("/book/nested/index.html", "");
+
+        let (_, outcome) = check_html_directory(
+            Path::new("/book"),
+            &Config::default(),
+            &mut Cache::default(),
+            &pages,
+        )
+        .unwrap();
+
+        let valid: Vec<_> =
+            outcome.valid_links.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(valid, ["nested/index.html"]);
+        assert_eq!(outcome.invalid_links.len(), 1);
+        assert_eq!(outcome.invalid_links[0].link.href, "missing.html");
+    }
+}
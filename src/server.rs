@@ -0,0 +1,312 @@
+use crate::{
+    anchors::AnchorIndex,
+    drafts::Drafts,
+    front_matter::Aliases,
+    json_rpc::{self, Framing, Handler},
+    Cache, Checker, Config, RealFileSystem,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files, Span};
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
+use linkcheck::Link;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::runtime::{Builder, Runtime};
+
+/// The name given to the links sent with a `checkLinks` request, which are
+/// resolved relative to the book's source directory.
+pub(crate) const REQUEST_FILE: &str = "<request>";
+
+/// Serve [JSON-RPC 2.0][rpc] requests to check the book in `root`, reading
+/// one request per line from `input` and writing one response per line to
+/// `output` (normally stdin and stdout), until `input` is closed or the
+/// client sends an `exit` notification.
+///
+/// Unlike running the link checker once per check, the cache of web results
+/// and the HTTP connection pool are kept between requests, so tools like
+/// editor plugins and build daemons can cheaply check a handful of files or
+/// links at a time. The supported methods are:
+///
+/// - `check`, which checks the chapters listed in the optional `files`
+///   parameter (paths relative to the source directory), or the whole book,
+///   and returns the `diagnostics` along with how many `errors` and
+///   `warnings` there were
+/// - `checkLinks`, which checks each href in the `links` parameter (relative
+///   links are resolved against the source directory) and returns whether
+///   each was `valid`, `invalid`, `ignored`, or `unknown`
+/// - `shutdown` and `exit`, which stop the server
+///
+/// The book and its configuration are reloaded for every request, so changes
+/// on disk are always picked up.
+///
+/// [rpc]: https://www.jsonrpc.org/specification
+pub fn serve<R, W>(root: &Path, input: R, output: W) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut server = Server {
+        root: root.to_path_buf(),
+        runtime: Builder::new_multi_thread().enable_all().build()?,
+        cache: Cache::default(),
+        client: None,
+    };
+    json_rpc::run(input, &Mutex::new(output), Framing::Lines, &mut server)
+}
+
+struct Server {
+    root: PathBuf,
+    runtime: Runtime,
+    cache: Cache,
+    /// The [`Client`] (and the [`Config`] it was created from) shared by
+    /// every request, so connections are reused.
+    client: Option<(Config, Client)>,
+}
+
+impl Handler for Server {
+    fn handle(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Option<Result<Value, Error>> {
+        match method {
+            "check" => Some(self.check(params)),
+            "checkLinks" => Some(self.check_links(params)),
+            "shutdown" => Some(Ok(Value::Null)),
+            _ => None,
+        }
+    }
+
+    fn exit_on_shutdown(&self) -> bool { true }
+}
+
+impl Server {
+    /// Check some (or all) of the book's chapters.
+    fn check(&mut self, params: &Value) -> Result<Value, Error> {
+        let (src_dir, book, cfg) = crate::load_standalone(&self.root)?;
+        let client = self.client(&cfg);
+        let builder = Checker::builder()
+            .source_dir(src_dir)
+            .config(cfg)
+            .book(book)
+            .client(client);
+        let builder = match params.get("files") {
+            Some(files) => builder.selected_files(string_array(files)?),
+            None => builder,
+        };
+        let checker = builder.build()?;
+
+        let cache = std::mem::take(&mut self.cache);
+        let report = self.runtime.block_on(checker.check_async(cache))?;
+        self.cache = report.cache.clone();
+
+        let diags = report.diagnostics();
+        let count = |severity: Severity| {
+            diags.iter().filter(|diag| diag.severity == severity).count()
+        };
+        let diagnostics: Vec<Value> = diags
+            .iter()
+            .filter_map(|diag| to_json(diag, &report.files))
+            .collect();
+
+        Ok(json!({
+            "errors": count(Severity::Error) + count(Severity::Bug),
+            "warnings": count(Severity::Warning),
+            "diagnostics": diagnostics,
+        }))
+    }
+
+    /// Check a list of hrefs, as if they were written in a chapter at the
+    /// root of the book's source directory.
+    fn check_links(&mut self, params: &Value) -> Result<Value, Error> {
+        let hrefs = string_array(&params["links"])?;
+        let (src_dir, book, cfg) = crate::load_standalone(&self.root)?;
+        let client = self.client(&cfg);
+
+        let mut files = Files::new();
+        // the chapters are needed to tell whether a link points into the book
+        let file_ids =
+            crate::load_files_into_memory(&book, &mut files, |_| true);
+        let id = files.add(REQUEST_FILE, hrefs.join("\n"));
+        let links = link_per_line(id, &hrefs);
+
+        let (outcome, _) = self.runtime.block_on(
+            crate::validate::validate_batches(
+                futures::stream::iter(std::iter::once((links, Vec::new()))),
+                &cfg,
+                &src_dir,
+                &mut self.cache,
+                &files,
+                &file_ids,
+                &crate::chapter_names(&book),
+                &Drafts::new(&book, &src_dir, &RealFileSystem),
+                &Aliases::new(&book, &src_dir, &RealFileSystem),
+                None,
+                &AnchorIndex::new(cfg.slugs.clone()),
+                Some(&client),
+                &RealFileSystem,
+                None,
+            ),
+        )?;
+
+        let status = |href: &str| {
+            if outcome.valid_links.iter().any(|link| link.href == href) {
+                json!({ "href": href, "status": "valid" })
+            } else if let Some(invalid) = outcome
+                .invalid_links
+                .iter()
+                .find(|invalid| invalid.link.href == href)
+            {
+                json!({
+                    "href": href,
+                    "status": "invalid",
+                    "reason": invalid.reason.to_string(),
+                })
+            } else if outcome.ignored.iter().any(|link| link.href == href) {
+                json!({ "href": href, "status": "ignored" })
+            } else {
+                json!({ "href": href, "status": "unknown" })
+            }
+        };
+
+        Ok(Value::Array(hrefs.iter().map(|href| status(href)).collect()))
+    }
+
+    /// Get the shared [`Client`], creating a new one if the configuration
+    /// has changed since it was last used.
+    fn client(&mut self, cfg: &Config) -> Client {
+        match self.client {
+            Some((ref previous, ref client)) if previous == cfg => {
+                client.clone()
+            },
+            _ => {
+                let client = cfg.client();
+                self.client = Some((cfg.clone(), client.clone()));
+                client
+            },
+        }
+    }
+}
+
+fn string_array(value: &Value) -> Result<Vec<String>, Error> {
+    value
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(String::from))
+                .collect()
+        })
+        .context("Expected a list of strings")
+}
+
+/// Create a [`Link`] for each href, where the `n`'th href is on line `n` of
+/// the file.
+fn link_per_line(file: FileId, hrefs: &[String]) -> Vec<Link> {
+    let mut start = 0;
+
+    hrefs
+        .iter()
+        .map(|href| {
+            let end = start + href.len();
+            let link = Link::new(
+                href.as_str(),
+                Span::new(start as u32, end as u32),
+                file,
+            );
+            start = end + 1;
+            link
+        })
+        .collect()
+}
+
+fn to_json(diag: &Diagnostic<FileId>, files: &Files<String>) -> Option<Value> {
+    let label = diag
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary)?;
+    let location = files
+        .location(label.file_id, label.range.start as u32)
+        .ok()?;
+
+    Some(json!({
+        "file": files.name(label.file_id).to_string_lossy(),
+        "line": location.line.number().to_usize(),
+        "column": location.column.to_usize() + 1,
+        "severity": crate::severity_name(diag.severity),
+        "code": diag.code,
+        "message": diag.message,
+        "notes": diag.notes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn serve_requests(root: &Path, requests: &[Value]) -> Vec<Value> {
+        let input: String = requests
+            .iter()
+            .map(|request| format!("{}\n", request))
+            .collect();
+        let mut output = Vec::new();
+
+        serve(root, Cursor::new(input), &mut output).unwrap();
+
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn check_files_and_links_over_stdin() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("broken-links");
+        let requests = vec![
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "check",
+                "params": { "files": ["chapter_1.md"] },
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "checkLinks",
+                "params": { "links": ["./chapter_1.md", "./missing.md"] },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "frobnicate" }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "id": 5, "method": "check" }),
+        ];
+
+        let got = serve_requests(&root, &requests);
+
+        assert_eq!(got.len(), 4, "{:#?}", got);
+        let check = &got[0]["result"];
+        assert!(check["errors"].as_u64().unwrap() > 0, "{:#}", check);
+        assert!(check["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|diag| diag["file"] == "chapter_1.md"));
+        let statuses: Vec<_> = got[1]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|link| link["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(statuses, vec!["valid", "invalid"]);
+        assert_eq!(got[2]["error"]["code"], -32601);
+        assert_eq!(got[3]["id"], 4);
+    }
+}
@@ -0,0 +1,96 @@
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::Diagnostic;
+use std::fmt::Debug;
+
+/// Something which is told about each broken link as soon as it is found,
+/// instead of waiting until the whole book has been checked.
+///
+/// Give one to [`crate::CheckerBuilder::diagnostic_sink()`] to show problems
+/// while a long run is still going (e.g. in an editor or a web UI). Each
+/// diagnostic is the same one that ends up in the final report, so they
+/// don't need to be kept around.
+pub trait DiagnosticSink: Debug + Send + Sync {
+    /// Emit the diagnostic for a broken link.
+    fn emit(&self, diag: &Diagnostic<FileId>, files: &Files<String>);
+}
+
+/// Print each diagnostic to stderr in the same `file:line:column` form
+/// compilers use (see [`crate::Config::stream_failures`]).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Stderr;
+
+impl DiagnosticSink for Stderr {
+    fn emit(&self, diag: &Diagnostic<FileId>, files: &Files<String>) {
+        eprintln!("{}", one_line(diag, files));
+    }
+}
+
+fn one_line(diag: &Diagnostic<FileId>, files: &Files<String>) -> String {
+    let severity = crate::severity_name(diag.severity);
+    let label = match diag.labels.first() {
+        Some(label) => label,
+        None => return format!("{}: {}", severity, diag.message),
+    };
+    let name = files.name(label.file_id).to_string_lossy();
+    let start = label.range.start;
+    // codespan panics when asked about something past the end of the file
+    let location = if start <= files.source(label.file_id).len() {
+        files.location(label.file_id, start as u32).ok()
+    } else {
+        None
+    };
+    let location = match location {
+        Some(location) => format!(
+            "{}:{}:{}",
+            name,
+            location.line.number(),
+            location.column.number()
+        ),
+        None => name.into_owned(),
+    };
+
+    format!("{}: {}: {}", severity, location, diag.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file_provider::in_memory_book, Checker};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    impl DiagnosticSink for Collect {
+        fn emit(&self, diag: &Diagnostic<FileId>, files: &Files<String>) {
+            self.0.lock().unwrap().push(one_line(diag, files));
+        }
+    }
+
+    #[test]
+    fn broken_links_are_emitted_as_they_are_found() {
+        let src = "# Intro\n\n[fine](./intro.md) [gone](./gone.md)\n";
+        let (files, book) = in_memory_book(&[("intro.md", src)]);
+        let sink = Collect::default();
+        let emitted = Arc::clone(&sink.0);
+
+        let report = Checker::builder()
+            .source_dir("/book/src")
+            .book(book)
+            .file_provider(files)
+            .diagnostic_sink(sink)
+            .check()
+            .unwrap();
+
+        assert_eq!(*emitted.lock().unwrap(), vec![
+            "error: intro.md:3:20: File not found: ./gone.md"
+        ]);
+        // the same diagnostic is in the final report
+        let diags = report.diagnostics();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            one_line(&diags[0], &report.files),
+            emitted.lock().unwrap()[0]
+        );
+    }
+}
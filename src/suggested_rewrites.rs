@@ -0,0 +1,255 @@
+use crate::http_client::MAX_REDIRECTS;
+use anyhow::{Context as _, Error};
+use http::StatusCode;
+use linkcheck::Link;
+use reqwest::Url;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use toml_edit::{value, Document, Item, Table};
+
+/// The name of the file [`Config::suggest_rewrites`] writes to the output
+/// directory.
+///
+/// [`Config::suggest_rewrites`]: crate::Config::suggest_rewrites
+pub(crate) const FILE_NAME: &str = "rewrites.toml";
+
+/// Remembers every redirect which is followed while checking links.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RedirectRecorder {
+    /// Only the permanent redirects (`301 Moved Permanently` or
+    /// `308 Permanent Redirect`).
+    redirects: Arc<Mutex<BTreeMap<String, String>>>,
+    /// Every redirect, including the temporary ones.
+    followed: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl RedirectRecorder {
+    /// Remember a redirect which was followed, keyed by the normalized URL it
+    /// came from.
+    pub(crate) fn record(&self, from: &Url, to: &Url, status: StatusCode) {
+        let from = crate::normalize::normalize(from).to_string();
+        if is_permanent(status) {
+            self.redirects
+                .lock()
+                .expect("Lock was poisoned")
+                .insert(from.clone(), to.to_string());
+        }
+        self.followed
+            .lock()
+            .expect("Lock was poisoned")
+            .insert(from, to.to_string());
+    }
+
+    /// Figure out where each of these web links ends up after following
+    /// permanent redirects, keyed by their normalized URL. Links which
+    /// weren't permanently redirected are left out.
+    ///
+    /// A temporary redirect part way through a chain stops us from going any
+    /// further, because it may change.
+    pub(crate) fn destinations(
+        &self,
+        links: &[Link],
+    ) -> BTreeMap<String, String> {
+        let redirects = self.redirects.lock().expect("Lock was poisoned");
+        follow_chains(&redirects, links)
+    }
+
+    /// Like [`RedirectRecorder::destinations()`], except every redirect is
+    /// followed, so we get the page each link really ended up on.
+    pub(crate) fn final_destinations(
+        &self,
+        links: &[Link],
+    ) -> BTreeMap<String, String> {
+        let followed = self.followed.lock().expect("Lock was poisoned");
+        follow_chains(&followed, links)
+    }
+}
+
+fn follow_chains(
+    redirects: &BTreeMap<String, String>,
+    links: &[Link],
+) -> BTreeMap<String, String> {
+    let mut destinations = BTreeMap::new();
+
+    for link in links {
+        let url = match link.href.parse::<Url>() {
+            Ok(url) => normalized(url.as_str()),
+            Err(_) => continue,
+        };
+        let mut destination = None;
+        let mut key = url.clone();
+        for _ in 0..MAX_REDIRECTS {
+            match redirects.get(&key) {
+                Some(next) if normalized(next) != url => {
+                    key = normalized(next);
+                    destination = Some(next);
+                },
+                _ => break,
+            }
+        }
+
+        if let Some(destination) = destination {
+            destinations.insert(url, destination.clone());
+        }
+    }
+
+    destinations
+}
+
+fn normalized(url: &str) -> String {
+    match url.parse::<Url>() {
+        Ok(url) => crate::normalize::normalize(&url).to_string(),
+        Err(_) => url.to_string(),
+    }
+}
+
+fn is_permanent(status: StatusCode) -> bool {
+    status == StatusCode::MOVED_PERMANENTLY
+        || status == StatusCode::PERMANENT_REDIRECT
+}
+
+/// Write the permanent redirects to a TOML file with a single `[rewrites]`
+/// table, mapping each old URL to where it ends up.
+pub(crate) fn write_rewrites(
+    path: &Path,
+    redirects: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!("Unable to create \"{}\"", parent.display())
+        })?;
+    }
+
+    tracing::debug!(
+        "Writing {} suggested rewrites to {}",
+        redirects.len(),
+        path.display()
+    );
+    std::fs::write(path, to_toml(redirects)).with_context(|| {
+        format!("Unable to write the rewrites to \"{}\"", path.display())
+    })
+}
+
+fn to_toml(redirects: &BTreeMap<String, String>) -> String {
+    let mut table = Table::new();
+    for (from, to) in redirects {
+        table.insert(from, value(to.as_str()));
+    }
+
+    let mut doc = Document::new();
+    doc.insert("rewrites", Item::Table(table));
+
+    format!(
+        "# Web links which permanently redirect somewhere else, found by \
+         {}.\n{}",
+        env!("CARGO_PKG_NAME"),
+        doc
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http_client::{head_following_redirects, Canned},
+        Config,
+    };
+    use codespan::{Files, Span};
+    use http::HeaderMap;
+
+    #[test]
+    fn record_redirects_while_following_them() {
+        let original = "https://example.com/original";
+        let client = Canned::default()
+            .respond(original, 301, &[("Location", "/moved")])
+            .respond("https://example.com/moved", 302, &[(
+                "Location",
+                "/temporary",
+            )]);
+        let recorder = RedirectRecorder::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let response = runtime
+            .block_on(head_following_redirects(
+                &client,
+                &Config::default(),
+                original.parse().unwrap(),
+                |_| HeaderMap::new(),
+                |from, to, status| recorder.record(from, to, status),
+            ))
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(client.requested.lock().unwrap().as_slice(), [
+            original,
+            "https://example.com/moved",
+            "https://example.com/temporary",
+        ]);
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let link = Link::new(original, Span::new(0, 1), id);
+        let got = recorder.destinations(std::slice::from_ref(&link));
+        // the temporary redirect isn't followed
+        assert_eq!(
+            got.get(original).map(String::as_str),
+            Some("https://example.com/moved")
+        );
+        let got = recorder.final_destinations(&[link]);
+        assert_eq!(
+            got.get(original).map(String::as_str),
+            Some("https://example.com/temporary")
+        );
+    }
+
+    #[test]
+    fn follow_chains_of_permanent_redirects() {
+        let recorder = RedirectRecorder::default();
+        {
+            let mut redirects = recorder.redirects.lock().unwrap();
+            for (from, to) in [
+                ("http://old.example.com/", "https://old.example.com/"),
+                ("https://old.example.com/", "https://new.example.com/"),
+                ("https://loop.example.com/a", "https://loop.example.com/b"),
+                ("https://loop.example.com/b", "https://loop.example.com/a"),
+            ] {
+                redirects.insert(String::from(from), String::from(to));
+            }
+        }
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links: Vec<Link> = [
+            "http://old.example.com#intro",
+            "https://loop.example.com/a",
+            "https://fine.example.com/",
+        ]
+        .iter()
+        .map(|href| Link::new(*href, Span::new(0, 1), id))
+        .collect();
+
+        let got = recorder.destinations(&links);
+
+        let mut should_be = BTreeMap::new();
+        should_be.insert(
+            String::from("http://old.example.com/"),
+            String::from("https://new.example.com/"),
+        );
+        should_be.insert(
+            String::from("https://loop.example.com/a"),
+            String::from("https://loop.example.com/b"),
+        );
+        assert_eq!(got, should_be);
+        assert_eq!(
+            to_toml(&should_be),
+            "# Web links which permanently redirect somewhere else, found by \
+             mdbook-linkcheck.\n\
+             [rewrites]\n\
+             \"http://old.example.com/\" = \"https://new.example.com/\"\n\
+             \"https://loop.example.com/a\" = \"https://loop.example.com/b\"\n"
+        );
+    }
+}
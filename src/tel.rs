@@ -0,0 +1,107 @@
+use crate::{data_uri::percent_decode, Config};
+use linkcheck::validation::Reason;
+use regex::Regex;
+use std::fmt::{self, Display, Formatter};
+
+/// A loose version of the [E.164](https://en.wikipedia.org/wiki/E.164)
+/// format, used when [`Config::tel_pattern`] isn't set.
+const DEFAULT_PATTERN: &str = r"^\+?[0-9]{3,15}$";
+
+/// Is this a `tel:` link (e.g. `tel:+1-555-123-4567`)?
+pub(crate) fn is_tel_link(link: &str) -> bool {
+    link.get(..4)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("tel:"))
+}
+
+/// Make sure the phone number in a `tel:` link matches the
+/// [`Config::tel_pattern`].
+pub(crate) fn check_tel_link(link: &str, cfg: &Config) -> Result<(), Reason> {
+    let number = phone_number(link);
+    let matches = match cfg.tel_pattern {
+        Some(ref pattern) => pattern.is_match(&number),
+        None => Regex::new(DEFAULT_PATTERN)
+            .expect("The default pattern is valid")
+            .is_match(&number),
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(Reason::Io(std::io::Error::other(MalformedPhoneNumber {
+            number,
+        })))
+    }
+}
+
+/// Get the phone number from a `tel:` link, without any parameters (e.g.
+/// `;ext=123`) or the visual separators allowed by
+/// [RFC 3966](https://www.rfc-editor.org/rfc/rfc3966).
+fn phone_number(link: &str) -> String {
+    let number = link[4..].split(';').next().unwrap_or_default();
+
+    String::from_utf8_lossy(&percent_decode(number))
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect()
+}
+
+/// An error that is emitted when a `tel:` link doesn't contain a valid phone
+/// number.
+#[derive(Debug)]
+pub struct MalformedPhoneNumber {
+    /// The phone number, without any visual separators.
+    pub number: String,
+}
+
+impl Display for MalformedPhoneNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\" doesn't look like a phone number", self.number)
+    }
+}
+
+impl std::error::Error for MalformedPhoneNumber {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashedRegex;
+
+    #[test]
+    fn detect_malformed_phone_numbers() {
+        let cfg = Config::default();
+        let inputs = vec![
+            ("tel:+61412345678", true),
+            ("tel:+1-555-123-4567", true),
+            ("TEL:+1.(555).123.4567;ext=42", true),
+            ("tel:+1%20555%20123%204567", true),
+            ("tel:911", true),
+            ("tel:", false),
+            ("tel:+1-555-CALL-NOW", false),
+            ("tel:+1234567890123456", false),
+            ("tel:555-1234,ext", false),
+        ];
+
+        for (link, should_be) in inputs {
+            assert!(is_tel_link(link));
+            assert_eq!(
+                check_tel_link(link, &cfg).is_ok(),
+                should_be,
+                "{}",
+                link
+            );
+        }
+
+        assert!(!is_tel_link("./telephone.md"));
+    }
+
+    #[test]
+    fn use_a_custom_pattern() {
+        let cfg = Config {
+            tel_pattern: Some(HashedRegex::new(r"^\+44[0-9]{10}$").unwrap()),
+            ..Default::default()
+        };
+
+        assert!(check_tel_link("tel:+44 20 7946 0958", &cfg).is_ok());
+        assert!(check_tel_link("tel:+1-555-123-4567", &cfg).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use http::{header::RETRY_AFTER, HeaderMap, StatusCode};
+use reqwest::Url;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// How many requests are sent at once by default (the same as `linkcheck`).
+pub(crate) const DEFAULT_CONCURRENCY: usize = 64;
+
+/// How many times a request is retried after the server says it is
+/// overloaded.
+pub(crate) const MAX_RETRIES: u32 = 5;
+
+/// The longest we'll wait before sending another request to a host.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Keeps track of how many requests each host can handle at once, slowing
+/// down when a host says it is overloaded (`429 Too Many Requests` or
+/// `503 Service Unavailable`) instead of reporting the rest of its links as
+/// broken.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    concurrency: usize,
+    hosts: Mutex<HashMap<String, Arc<Host>>>,
+}
+
+impl Throttle {
+    /// Create a [`Throttle`] where each host starts off being sent up to
+    /// `concurrency` requests at a time.
+    pub(crate) fn new(concurrency: usize) -> Self {
+        Throttle {
+            concurrency: concurrency.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the [`Host`] a request to this URL would be sent to.
+    pub(crate) fn host(&self, url: &Url) -> Arc<Host> {
+        let name = url.host_str().unwrap_or_default().to_string();
+        let concurrency = self.concurrency;
+        let mut hosts = self.hosts.lock().expect("Lock was poisoned");
+        let host = hosts
+            .entry(name.clone())
+            .or_insert_with(|| Arc::new(Host::new(name, concurrency)));
+
+        Arc::clone(host)
+    }
+
+    /// How many times each host said it was overloaded, leaving out the ones
+    /// which never did.
+    pub(crate) fn rate_limited(&self) -> BTreeMap<String, usize> {
+        let hosts = self.hosts.lock().expect("Lock was poisoned");
+
+        hosts
+            .iter()
+            .map(|(name, host)| (name.clone(), host.state().backoffs as usize))
+            .filter(|(_, backoffs)| *backoffs > 0)
+            .collect()
+    }
+}
+
+/// Does this status code mean the server wants us to slow down?
+pub(crate) fn is_overloaded(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// The requests being sent to a single host.
+#[derive(Debug)]
+pub(crate) struct Host {
+    name: String,
+    permits: Semaphore,
+    state: Mutex<HostState>,
+}
+
+#[derive(Debug)]
+struct HostState {
+    concurrency: usize,
+    /// Permits which should be dropped instead of handed back to the
+    /// semaphore, because the concurrency was reduced while they were in use.
+    debt: usize,
+    backoffs: u32,
+    resume_at: Option<Instant>,
+}
+
+impl Host {
+    fn new(name: String, concurrency: usize) -> Self {
+        Host {
+            name,
+            permits: Semaphore::new(concurrency),
+            state: Mutex::new(HostState {
+                concurrency,
+                debt: 0,
+                backoffs: 0,
+                resume_at: None,
+            }),
+        }
+    }
+
+    /// Wait until we're allowed to send this host another request.
+    pub(crate) async fn acquire(&self) -> Permit<'_> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("The semaphore is never closed");
+
+        let resume_at = self.state().resume_at;
+        if let Some(resume_at) = resume_at {
+            tokio::time::sleep_until(resume_at.into()).await;
+        }
+
+        Permit {
+            host: self,
+            permit: Some(permit),
+        }
+    }
+
+    /// The host said it was overloaded, so halve the number of requests we
+    /// send it at a time and wait a bit before sending the next one.
+    pub(crate) fn back_off(&self, headers: &HeaderMap) {
+        let mut state = self.state();
+        state.backoffs += 1;
+
+        let delay = retry_after(headers).unwrap_or_else(|| {
+            Duration::from_secs(1 << state.backoffs.min(6))
+        });
+        let delay = delay.min(MAX_BACKOFF);
+        let resume_at = Instant::now() + delay;
+        state.resume_at = Some(match state.resume_at {
+            Some(previous) => previous.max(resume_at),
+            None => resume_at,
+        });
+
+        let reduced = (state.concurrency / 2).max(1);
+        if reduced < state.concurrency {
+            let surplus = state.concurrency - reduced;
+            let mut forgotten = 0;
+            while forgotten < surplus {
+                match self.permits.try_acquire() {
+                    Ok(permit) => permit.forget(),
+                    // the rest are in use
+                    Err(_) => break,
+                }
+                forgotten += 1;
+            }
+            state.debt += surplus - forgotten;
+            state.concurrency = reduced;
+        }
+
+        tracing::info!(
+            host = %self.name,
+            concurrency = state.concurrency,
+            ?delay,
+            "The server is overloaded, backing off"
+        );
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, HostState> {
+        self.state.lock().expect("Lock was poisoned")
+    }
+}
+
+/// Permission to send a [`Host`] a request.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    host: &'a Host,
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.host.state();
+
+        if let Some(permit) = self.permit.take() {
+            if state.debt > 0 {
+                state.debt -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
+
+/// Parse a `Retry-After` header given in seconds.
+///
+/// It may also be a HTTP date, but servers overwhelmingly send a number of
+/// seconds and we fall back to exponential backoff anyway.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn overloaded_hosts_get_fewer_requests() {
+        let throttle = Throttle::new(8);
+        let url: Url = "https://example.com/".parse().unwrap();
+        let host = throttle.host(&url);
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("0"));
+
+        host.back_off(&headers);
+        assert_eq!(host.permits.available_permits(), 4);
+
+        for _ in 0..5 {
+            host.back_off(&headers);
+        }
+        assert_eq!(host.permits.available_permits(), 1);
+
+        // other hosts aren't affected
+        let other = throttle.host(&"https://example.org/".parse().unwrap());
+        assert_eq!(other.permits.available_permits(), 8);
+    }
+
+    #[test]
+    fn permits_in_use_are_dropped_after_backing_off() {
+        let host = Host::new(String::from("example.com"), 2);
+        let first = futures::executor::block_on(host.acquire());
+        let second = futures::executor::block_on(host.acquire());
+
+        host.back_off(&HeaderMap::new());
+        assert_eq!(host.state().debt, 1);
+
+        drop(first);
+        drop(second);
+        assert_eq!(host.permits.available_permits(), 1);
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{
+    http_client::{self, describe, headers_for},
+    Config, HttpClient,
+};
+use anyhow::{Context as _, Error};
+use chrono::NaiveDateTime;
+use futures::StreamExt;
+use linkcheck::{validation::Reason, Link};
+use reqwest::{
+    tls::{TlsInfo, Version},
+    Client, Method, Request, Response, Url,
+};
+use serde_derive::Serialize;
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::Instrument;
+
+/// The maximum number of hosts being checked at once.
+const CONCURRENCY: usize = 16;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A HTTPS link to a server which only accepts versions of TLS older than
+/// 1.2, which browsers have stopped supporting.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutdatedTls {
+    /// The link.
+    pub link: Link,
+    /// The server being linked to.
+    pub host: String,
+}
+
+/// A HTTPS link to a server whose certificate expires soon, so the link will
+/// stop working unless the certificate is renewed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExpiringCertificate {
+    /// The first link to the server.
+    pub link: Link,
+    /// How many other links go to the same server.
+    pub other_links: usize,
+    /// The server being linked to.
+    pub host: String,
+    /// The number of whole days left until the certificate expires.
+    pub days_left: u64,
+}
+
+/// Look for HTTPS links in `valid_links` whose server can't negotiate TLS 1.2
+/// or newer (see [`crate::WebConfig::outdated_tls`]).
+///
+/// Each server is only asked once, by repeating the request for one of its
+/// links with a client which refuses anything older than TLS 1.2, and then
+/// with `http` to make sure the server is reachable at all. The TLS
+/// library doesn't let us see which cipher suite was negotiated, so only the
+/// protocol version is checked.
+pub(crate) async fn outdated_tls(
+    valid_links: &[Link],
+    cfg: &Config,
+    http: &dyn HttpClient,
+) -> Vec<OutdatedTls> {
+    let strict = cfg
+        .client_builder()
+        .min_tls_version(Version::TLS_1_2)
+        .build();
+    let strict = match strict {
+        Ok(strict) => strict,
+        Err(e) => {
+            tracing::warn!("Unable to create a HTTP client: {}", e);
+            return Vec::new();
+        },
+    };
+
+    let hosts = https_links_by_host(valid_links);
+    let outdated: Vec<String> = futures::stream::iter(hosts.iter())
+        .map(|(host, (url, _))| {
+            let strict = &strict;
+            let span = tracing::debug_span!("check_tls", %host);
+
+            async move {
+                let outdated = needs_outdated_tls(http, strict, cfg, url).await;
+                Some(host.clone()).filter(|_| outdated)
+            }
+            .instrument(span)
+        })
+        .buffer_unordered(CONCURRENCY)
+        .filter_map(futures::future::ready)
+        // erasing the stream's type keeps the caller's future Send
+        .boxed()
+        .collect()
+        .await;
+
+    let mut problems: Vec<OutdatedTls> = outdated
+        .into_iter()
+        .flat_map(|host| {
+            hosts[&host].1.iter().map(move |link| OutdatedTls {
+                link: (*link).clone(),
+                host: host.clone(),
+            })
+        })
+        .collect();
+    problems.sort_by_key(|p| (p.link.file, p.link.span));
+    problems
+}
+
+/// Look for HTTPS links in `valid_links` to servers whose certificate expires
+/// within [`crate::WebConfig::certificate_expiry`] days.
+///
+/// Each server is only sent one request, through the same client as the
+/// link check (so its proxy, timeout and DNS cache apply), and the
+/// certificate is read from that connection. Servers whose certificate can't
+/// be read are skipped (a certificate which has already expired makes the
+/// link itself fail).
+pub(crate) async fn expiring_certificates(
+    valid_links: &[Link],
+    cfg: &Config,
+    http: &dyn HttpClient,
+) -> Vec<ExpiringCertificate> {
+    let threshold = match cfg.web.certificate_expiry {
+        Some(days) => days,
+        None => return Vec::new(),
+    };
+    let headers = cfg.interpolate_headers(cfg.warning_policy);
+
+    let hosts = https_links_by_host(valid_links);
+    let mut expiring: Vec<_> = futures::stream::iter(hosts)
+        .map(|(host, (url, links))| {
+            let headers = &headers;
+
+            async move {
+                let mut request = Request::new(Method::HEAD, url.clone());
+                *request.headers_mut() = headers_for(headers, &url);
+                let expiry = http_client::send(http, cfg, request)
+                    .await
+                    .map_err(|reason| Error::msg(describe(&reason)))
+                    .and_then(|response| certificate_expiry(&response));
+
+                let expires = match expiry {
+                    Ok(expires) => expires,
+                    Err(e) => {
+                        tracing::debug!(
+                            %host,
+                            "Unable to get the certificate: {:#}",
+                            e
+                        );
+                        return None;
+                    },
+                };
+                let remaining = expires
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                let days_left = remaining.as_secs() / SECONDS_PER_DAY;
+                if days_left >= threshold {
+                    return None;
+                }
+
+                Some(ExpiringCertificate {
+                    link: links[0].clone(),
+                    other_links: links.len() - 1,
+                    host,
+                    days_left,
+                })
+            }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .filter_map(futures::future::ready)
+        .boxed()
+        .collect()
+        .await;
+
+    expiring.sort_by_key(|e| (e.link.file, e.link.span));
+    expiring
+}
+
+/// Find out when the certificate for the connection a response came over
+/// expires.
+///
+/// This needs a client created with [`reqwest::ClientBuilder::tls_info()`],
+/// which [`Config::client_builder()`] turns on when
+/// [`crate::WebConfig::certificate_expiry`] is set.
+fn certificate_expiry(response: &Response) -> Result<SystemTime, Error> {
+    let certificate = response
+        .extensions()
+        .get::<TlsInfo>()
+        .context("The HTTP client didn't say which certificate was used")?
+        .peer_certificate()
+        .context("The server didn't send a certificate")?;
+
+    not_after(certificate)
+        .context("Unable to read the certificate's expiry date")
+}
+
+/// Read the end of a X.509 certificate's validity period.
+///
+/// This isn't a full DER parser, it only knows enough to skip over the
+/// fields before the validity period.
+fn not_after(der: &[u8]) -> Option<SystemTime> {
+    let (_, certificate, _) = der_element(der)?;
+    let (_, tbs_certificate, _) = der_element(certificate)?;
+
+    // the version is optional and tagged [0], then comes the serial number
+    let (tag, _, mut rest) = der_element(tbs_certificate)?;
+    if tag == 0xa0 {
+        rest = der_element(rest)?.2;
+    }
+    // the signature algorithm and issuer
+    for _ in 0..2 {
+        rest = der_element(rest)?.2;
+    }
+
+    let (_, validity, _) = der_element(rest)?;
+    let (_, _not_before, rest) = der_element(validity)?;
+    let (tag, not_after, _) = der_element(rest)?;
+
+    parse_time(tag, std::str::from_utf8(not_after).ok()?)
+}
+
+/// Split a DER element into its tag and contents, along with whatever comes
+/// after it.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let len = rest[..octets]
+            .iter()
+            .fold(0, |len, &b| (len << 8) | usize::from(b));
+        (len, &rest[octets..])
+    };
+
+    if rest.len() < len {
+        return None;
+    }
+
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// Parse a `UTCTime` (`YYMMDDHHMMSSZ`) or `GeneralizedTime`
+/// (`YYYYMMDDHHMMSSZ`).
+fn parse_time(tag: u8, time: &str) -> Option<SystemTime> {
+    let time = match tag {
+        // two digit years from 50 onwards are in the 1900s (RFC 5280)
+        0x17 => {
+            let year: u32 = time.get(..2)?.parse().ok()?;
+            let century = if year >= 50 { "19" } else { "20" };
+            format!("{}{}", century, time)
+        },
+        0x18 => time.to_string(),
+        _ => return None,
+    };
+    let time = NaiveDateTime::parse_from_str(&time, "%Y%m%d%H%M%SZ").ok()?;
+    let seconds = u64::try_from(time.timestamp()).ok()?;
+
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Group the HTTPS links by the server they point to (including the port),
+/// along with the first link's URL.
+fn https_links_by_host(links: &[Link]) -> BTreeMap<String, (Url, Vec<&Link>)> {
+    let mut hosts: BTreeMap<String, (Url, Vec<&Link>)> = BTreeMap::new();
+
+    for link in links {
+        let url = match link.href.parse::<Url>() {
+            Ok(url) if url.scheme() == "https" => url,
+            _ => continue,
+        };
+        let host = match (url.host_str(), url.port_or_known_default()) {
+            (Some(host), Some(443)) => host.to_lowercase(),
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            _ => continue,
+        };
+
+        hosts
+            .entry(host)
+            .or_insert_with(|| (url, Vec::new()))
+            .1
+            .push(link);
+    }
+
+    hosts
+}
+
+/// Can the server only be reached when older versions of TLS are allowed?
+///
+/// The strict request failing isn't enough on its own, because the server
+/// may just be having a bad day, so it is only blamed on TLS when the same
+/// request goes through without the restriction.
+async fn needs_outdated_tls(
+    lenient: &dyn HttpClient,
+    strict: &Client,
+    cfg: &Config,
+    url: &Url,
+) -> bool {
+    let head = || Request::new(Method::HEAD, url.clone());
+
+    match http_client::send(strict, cfg, head()).await {
+        Err(Reason::Web(e)) if e.is_connect() => {
+            tracing::debug!(%url, error = %e, "Unable to connect with TLS 1.2");
+            http_client::send(lenient, cfg, head()).await.is_ok()
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::{Files, Span};
+
+    /// Encode a DER element.
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut element = vec![tag];
+        if contents.len() < 0x80 {
+            element.push(contents.len() as u8);
+        } else {
+            element.extend([0x82, (contents.len() >> 8) as u8]);
+            element.push(contents.len() as u8);
+        }
+        element.extend(contents);
+        element
+    }
+
+    #[test]
+    fn read_when_a_certificate_expires() {
+        let validity = [
+            der(0x17, b"240101000000Z"),
+            der(0x18, b"20300315123000Z"),
+        ]
+        .concat();
+        let tbs_certificate = [
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[1]),
+            der(0x30, &[]),
+            der(0x30, &[0x13; 200]),
+            der(0x30, &validity),
+        ]
+        .concat();
+        let certificate = der(0x30, &der(0x30, &tbs_certificate));
+
+        let got = not_after(&certificate).unwrap();
+
+        // 2030-03-15T12:30:00Z
+        let should_be = UNIX_EPOCH + Duration::from_secs(1_899_808_200);
+        assert_eq!(got, should_be);
+        assert_eq!(parse_time(0x17, "700101000000Z"), Some(UNIX_EPOCH));
+        assert!(parse_time(0x17, "491231235959Z").is_some());
+        assert!(parse_time(0x17, "241301000000Z").is_none());
+        assert!(not_after(&certificate[..20]).is_none());
+    }
+
+    #[test]
+    fn servers_without_a_readable_certificate_are_skipped() {
+        // the canned responses didn't come over a TLS connection
+        let client = crate::http_client::Canned::default();
+        let mut files = Files::new();
+        let file = files.add("chapter_1.md", String::new());
+        let links: Vec<_> = ["https://example.com/a", "https://example.com/b"]
+            .iter()
+            .map(|href| Link::new(*href, Span::new(0, 1), file))
+            .collect();
+        let mut cfg = Config::default();
+        cfg.web.certificate_expiry = Some(14);
+
+        let got = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(expiring_certificates(&links, &cfg, &client));
+
+        assert!(got.is_empty());
+        assert_eq!(client.requested.lock().unwrap().as_slice(), [
+            "https://example.com/a"
+        ]);
+    }
+
+    #[test]
+    fn each_server_is_only_checked_once() {
+        let mut files = Files::new();
+        let file = files.add("chapter_1.md", String::new());
+        let links: Vec<_> = [
+            "https://example.com/a",
+            "https://EXAMPLE.com/b",
+            "https://example.com:8443/",
+            "http://example.com/",
+            "./chapter_2.md",
+        ]
+        .iter()
+        .map(|href| Link::new(*href, Span::new(0, 1), file))
+        .collect();
+
+        let hosts = https_links_by_host(&links);
+
+        let got: Vec<_> = hosts
+            .iter()
+            .map(|(host, (url, links))| {
+                (host.as_str(), url.path(), links.len())
+            })
+            .collect();
+        assert_eq!(
+            got,
+            vec![("example.com", "/a", 2), ("example.com:8443", "/", 1)]
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{
+    baseline::{Baseline, BaselineEntry},
+    config::is_web_link,
+    normalize,
+    validate::most_specific_error_message,
+    Checker, Fix,
+};
+use anyhow::{Context as _, Error};
+use codespan::Files;
+use std::{
+    ffi::OsStr,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use toml_edit::{Array, Document, Item, Table};
+
+/// Where links are added to the baseline when [`crate::Config::baseline_file`]
+/// isn't set, relative to the book's root.
+const DEFAULT_BASELINE_FILE: &str = "linkcheck-baseline.json";
+
+/// A broken link, as presented to the user during triage.
+#[derive(Debug, Clone, PartialEq)]
+struct Problem {
+    href: String,
+    location: String,
+    reason: String,
+    fix: Option<Fix>,
+    target: Target,
+    baseline: BaselineEntry,
+}
+
+/// What "open" shows the user.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Web(String),
+    /// The file a local link points to.
+    Local(PathBuf),
+}
+
+/// What the user decided to do with each [`Problem`].
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Decisions {
+    fixes: Vec<Fix>,
+    exclusions: Vec<String>,
+    baseline: Vec<BaselineEntry>,
+}
+
+/// Step through every broken link, letting the user decide what to do with
+/// each one.
+///
+/// Accepted fixes are written to the book's source, new exclusions are added
+/// to the `[output.linkcheck]` table in `book_toml`, and links the user
+/// accepts as known problems are added to the baseline (see
+/// [`crate::Config::baseline_file`]) once every link has been triaged (or the
+/// user quits). Links which are already in the baseline aren't shown.
+pub fn triage<R, W>(
+    checker: &Checker,
+    book_toml: Option<&Path>,
+    input: R,
+    mut output: W,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+{
+    let root = book_toml
+        .and_then(Path::parent)
+        .unwrap_or_else(|| checker.source_dir());
+    let baseline_file = checker.config().resolve_baseline_file(root)?;
+    let mut report = checker.check()?;
+    if let Some(ref baseline_file) = baseline_file {
+        let baseline = Baseline::load(baseline_file)?;
+        baseline.apply(&mut report.outcome, &report.files);
+    }
+    let fixes = checker.find_fixes(&report)?;
+    let problems = problems(
+        &report.outcome.invalid_links,
+        fixes,
+        &report.files,
+        checker.source_dir(),
+    );
+
+    if problems.is_empty() {
+        writeln!(output, "No broken links found")?;
+        return Ok(());
+    }
+
+    let decisions = ask(&problems, input, &mut output)?;
+
+    if !decisions.fixes.is_empty() {
+        let applied =
+            crate::apply_fixes(checker.source_dir(), &decisions.fixes)?;
+        writeln!(output, "Fixed {} links", applied)?;
+    }
+
+    if !decisions.exclusions.is_empty() {
+        match book_toml {
+            Some(book_toml) => {
+                add_exclusions(book_toml, &decisions.exclusions)?;
+                writeln!(
+                    output,
+                    "Added {} exclusions to {}",
+                    decisions.exclusions.len(),
+                    book_toml.display()
+                )?;
+            },
+            None => {
+                writeln!(output, "Add these to your `exclude` list:")?;
+                for pattern in &decisions.exclusions {
+                    writeln!(output, "    '{}'", pattern)?;
+                }
+            },
+        }
+    }
+
+    if !decisions.baseline.is_empty() {
+        let path = baseline_file
+            .clone()
+            .unwrap_or_else(|| root.join(DEFAULT_BASELINE_FILE));
+        let mut baseline = Baseline::load(&path)?;
+        for entry in &decisions.baseline {
+            baseline.insert(entry.clone());
+        }
+        baseline.save(&path)?;
+        writeln!(
+            output,
+            "Added {} links to {}",
+            decisions.baseline.len(),
+            path.display()
+        )?;
+
+        match (&baseline_file, book_toml) {
+            (Some(_), _) => {},
+            (None, Some(book_toml)) => {
+                set_baseline_file(book_toml, DEFAULT_BASELINE_FILE)?;
+                writeln!(
+                    output,
+                    "Set `baseline-file` in {}",
+                    book_toml.display()
+                )?;
+            },
+            (None, None) => writeln!(
+                output,
+                "Set `baseline-file = \"{}\"` to stop reporting them",
+                DEFAULT_BASELINE_FILE
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+fn problems(
+    invalid_links: &[linkcheck::validation::InvalidLink],
+    fixes: Vec<Fix>,
+    files: &Files<String>,
+    source_dir: &Path,
+) -> Vec<Problem> {
+    invalid_links
+        .iter()
+        .map(|invalid| {
+            let link = &invalid.link;
+            let location = files
+                .location(link.file, link.span.start())
+                .map(|loc| {
+                    format!(
+                        "{}:{}:{}",
+                        files.name(link.file).to_string_lossy(),
+                        loc.line.number(),
+                        loc.column.number()
+                    )
+                })
+                .unwrap_or_else(|_| {
+                    files.name(link.file).to_string_lossy().into_owned()
+                });
+            let fix = fixes
+                .iter()
+                .find(|fix| {
+                    fix.chapter.as_os_str() == files.name(link.file)
+                        && fix.original == link.href
+                        && fix.range.start >= link.span.start().to_usize()
+                        && fix.range.end <= link.span.end().to_usize()
+                })
+                .cloned();
+
+            let target = if is_web_link(&link.href) {
+                Target::Web(link.href.clone())
+            } else {
+                let chapter = Path::new(files.name(link.file));
+                Target::Local(local_target(source_dir, chapter, &link.href))
+            };
+
+            Problem {
+                href: link.href.clone(),
+                location,
+                reason: most_specific_error_message(invalid),
+                fix,
+                target,
+                baseline: BaselineEntry::for_link(link, files),
+            }
+        })
+        .collect()
+}
+
+fn ask<R, W>(
+    problems: &[Problem],
+    mut input: R,
+    output: &mut W,
+) -> Result<Decisions, Error>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut decisions = Decisions::default();
+
+    for (i, problem) in problems.iter().enumerate() {
+        writeln!(output)?;
+        writeln!(
+            output,
+            "[{}/{}] {} ({})",
+            i + 1,
+            problems.len(),
+            problem.href,
+            problem.location
+        )?;
+        writeln!(output, "    {}", problem.reason)?;
+        if let Some(ref fix) = problem.fix {
+            writeln!(
+                output,
+                "    Suggested fix ({}): {}",
+                fix.reason, fix.replacement
+            )?;
+        }
+
+        loop {
+            let mut prompt = String::from("[o]pen, ");
+            if problem.fix.is_some() {
+                prompt.push_str("[f]ix, ");
+            }
+            prompt.push_str("[e]xclude, [b]aseline, [s]kip, [q]uit? ");
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                // treat EOF the same as quitting
+                return Ok(decisions);
+            }
+
+            match answer.trim().to_lowercase().as_str() {
+                "o" | "open" => open(&problem.target, output)?,
+                "f" | "fix" if problem.fix.is_some() => {
+                    decisions.fixes.extend(problem.fix.clone());
+                    break;
+                },
+                "e" | "exclude" => {
+                    decisions
+                        .exclusions
+                        .push(format!("^{}$", regex::escape(&problem.href)));
+                    break;
+                },
+                "b" | "baseline" => {
+                    decisions.baseline.push(problem.baseline.clone());
+                    break;
+                },
+                "s" | "skip" | "" => break,
+                "q" | "quit" => return Ok(decisions),
+                other => writeln!(output, "Unknown action \"{}\"", other)?,
+            }
+        }
+    }
+
+    Ok(decisions)
+}
+
+/// The file a local link in `chapter` points to (or the chapter itself, for
+/// links to somewhere else in the same chapter).
+fn local_target(source_dir: &Path, chapter: &Path, href: &str) -> PathBuf {
+    let path = normalize::local_path(href);
+    let chapter = source_dir.join(chapter);
+
+    if path.as_os_str().is_empty() {
+        chapter
+    } else if let Ok(relative) = path.strip_prefix("/") {
+        source_dir.join(relative)
+    } else {
+        chapter.parent().unwrap_or(source_dir).join(path)
+    }
+}
+
+/// Try to open a web link in the user's browser, or a local file with
+/// whatever normally opens it.
+fn open<W: Write>(target: &Target, output: &mut W) -> Result<(), Error> {
+    let target = match target {
+        Target::Web(href) => OsStr::new(href),
+        Target::Local(path) if !path.exists() => {
+            writeln!(output, "\"{}\" doesn't exist", path.display())?;
+            return Ok(());
+        },
+        Target::Local(path) => path.as_os_str(),
+    };
+
+    let mut command = if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    if let Err(e) = command.arg(target).spawn() {
+        let target = target.to_string_lossy();
+        writeln!(output, "Unable to open \"{}\": {}", target, e)?;
+    }
+
+    Ok(())
+}
+
+/// Add patterns to the `exclude` list in `book.toml`, leaving the rest of
+/// the file (comments, formatting, etc.) untouched.
+fn add_exclusions(book_toml: &Path, patterns: &[String]) -> Result<(), Error> {
+    edit_book_toml(book_toml, |original| with_exclusions(original, patterns))
+}
+
+/// Point `baseline-file` in `book.toml` at `path`, leaving the rest of the
+/// file untouched.
+fn set_baseline_file(book_toml: &Path, path: &str) -> Result<(), Error> {
+    edit_book_toml(book_toml, |original| with_baseline_file(original, path))
+}
+
+fn edit_book_toml<F>(book_toml: &Path, edit: F) -> Result<(), Error>
+where
+    F: FnOnce(&str) -> Result<String, Error>,
+{
+    let original = std::fs::read_to_string(book_toml).with_context(|| {
+        format!("Unable to read \"{}\"", book_toml.display())
+    })?;
+    let updated = edit(&original)?;
+    std::fs::write(book_toml, updated).with_context(|| {
+        format!("Unable to write to \"{}\"", book_toml.display())
+    })?;
+
+    Ok(())
+}
+
+fn with_exclusions(
+    original: &str,
+    patterns: &[String],
+) -> Result<String, Error> {
+    let mut doc: Document =
+        original.parse().context("Unable to parse book.toml")?;
+
+    let exclude = linkcheck_table(&mut doc)?
+        .entry("exclude")
+        .or_insert_with(|| toml_edit::value(Array::new()))
+        .as_array_mut()
+        .context("`output.linkcheck.exclude` isn't an array")?;
+
+    for pattern in patterns {
+        if !exclude
+            .iter()
+            .any(|existing| existing.as_str() == Some(pattern))
+        {
+            exclude.push(pattern.as_str());
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+fn with_baseline_file(original: &str, path: &str) -> Result<String, Error> {
+    let mut doc: Document =
+        original.parse().context("Unable to parse book.toml")?;
+
+    linkcheck_table(&mut doc)?.insert("baseline-file", toml_edit::value(path));
+
+    Ok(doc.to_string())
+}
+
+/// The `[output.linkcheck]` table, creating it if necessary.
+fn linkcheck_table(doc: &mut Document) -> Result<&mut Table, Error> {
+    let output = doc
+        .entry("output")
+        .or_insert_with(implicit_table)
+        .as_table_mut()
+        .context("`output` isn't a table")?;
+
+    output
+        .entry("linkcheck")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_mut()
+        .context("`output.linkcheck` isn't a table")
+}
+
+fn implicit_table() -> Item {
+    let mut table = Table::new();
+    table.set_implicit(true);
+    Item::Table(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FixReason;
+    use std::path::PathBuf;
+
+    fn problem(href: &str, fix: Option<&str>) -> Problem {
+        Problem {
+            href: href.to_string(),
+            location: String::from("chapter.md:1:1"),
+            reason: String::from("File not found"),
+            target: Target::Web(href.to_string()),
+            baseline: BaselineEntry {
+                chapter: String::from("chapter.md"),
+                href: href.to_string(),
+            },
+            fix: fix.map(|replacement| Fix {
+                chapter: PathBuf::from("chapter.md"),
+                range: 0..href.len(),
+                original: href.to_string(),
+                replacement: replacement.to_string(),
+                reason: FixReason::MovedFile,
+            }),
+        }
+    }
+
+    #[test]
+    fn record_what_the_user_chose() {
+        let problems = vec![
+            problem("./moved.md", Some("./new/moved.md")),
+            problem("https://example.com/flaky", None),
+            problem("./missing.md", None),
+            problem("./known.md", None),
+            problem("./never-asked.md", None),
+        ];
+        // "f" isn't valid for the second problem, so it gets asked again
+        let input = "f\nf\ne\ns\nb\nq\n";
+        let mut output = Vec::new();
+
+        let got = ask(&problems, input.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(got.fixes.len(), 1);
+        assert_eq!(got.fixes[0].replacement, "./new/moved.md");
+        assert_eq!(got.exclusions, vec![r"^https://example\.com/flaky$"]);
+        assert_eq!(got.baseline.len(), 1);
+        assert_eq!(got.baseline[0].href, "./known.md");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Unknown action \"f\""));
+    }
+
+    #[test]
+    fn exclusions_are_added_without_reformatting() {
+        let original = r#"[book]
+title = "My Book" # a comment
+
+[output.linkcheck]
+# keep this comment
+exclude = ['google\.com']
+"#;
+
+        let got =
+            with_exclusions(original, &["^localhost$".to_string()]).unwrap();
+
+        assert!(got.contains("title = \"My Book\" # a comment"));
+        assert!(got.contains("# keep this comment"));
+        assert!(got.contains(r#"exclude = ['google\.com', "^localhost$"]"#));
+    }
+
+    #[test]
+    fn local_links_open_the_file_they_point_to() {
+        let src = Path::new("/book/src");
+        let chapter = Path::new("nested/chapter.md");
+        let target = |href| local_target(src, chapter, href);
+
+        assert_eq!(target("./other.md#intro"), src.join("nested/other.md"));
+        assert_eq!(
+            target("../with%20space.md"),
+            src.join("nested/../with space.md")
+        );
+        assert_eq!(target("/root.md"), src.join("root.md"));
+        assert_eq!(target("#intro"), src.join("nested/chapter.md"));
+    }
+
+    #[test]
+    fn the_baseline_file_is_added_without_reformatting() {
+        let original = "[book]\ntitle = \"My Book\" # a comment\n";
+
+        let got = with_baseline_file(original, "baseline.json").unwrap();
+
+        assert!(got.contains("title = \"My Book\" # a comment"));
+        assert!(got.contains(
+            "[output.linkcheck]\nbaseline-file = \"baseline.json\""
+        ));
+    }
+}
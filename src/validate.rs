@@ -1,72 +1,232 @@
-use crate::{Config, Context, IncompleteLink, WarningPolicy};
+use crate::{
+    anchors::{self, AnchorIndex},
+    assets::{human_size, AssetProblem, AssetProblemKind},
+    changes::glob_to_regex,
+    config::is_web_link,
+    data_uri::{check_data_uri, is_data_uri},
+    dns::DnsCache,
+    drafts::{DraftChapter, Drafts},
+    front_matter::Aliases,
+    http_client::Counting,
+    normalize,
+    sink::{DiagnosticSink, Stderr},
+    suggested_rewrites::RedirectRecorder,
+    tel::{check_tel_link, is_tel_link},
+    web::Source,
+    throttle::{Throttle, DEFAULT_CONCURRENCY},
+    languages::Languages,
+    workspace::{Workspace, WorkspaceLinks},
+    BrokenInclude, Cache, ChapterOverride, Config, Context, CrateProblem,
+    CrateProblemKind, CrossDomainRedirect, DiagnosticCategory,
+    ExpiringCertificate, FailureReason, FileProvider, HtmlProblem,
+    HtmlProblemKind, HttpClient, IncludeProblem, IncompleteLink, OutdatedTls,
+    RealFileSystem, WarningPolicy, WrongContentType,
+};
 use anyhow::Error;
 use codespan::{FileId, Files};
-use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
+use futures::{Stream, StreamExt};
 use linkcheck::{
-    validation::{Cache, InvalidLink, Options, Outcomes, Reason},
+    validation::{InvalidLink, Outcomes, Reason},
     Link,
 };
+use regex::Regex;
+use reqwest::Url;
+use serde_derive::Serialize;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::{OsStr, OsString},
     fmt::{self, Display, Formatter},
     path::{Component, Path, PathBuf},
     sync::Mutex,
+    time::{Duration, Instant},
 };
 use tokio::runtime::Builder;
+use tracing::Instrument;
 
-fn lc_validate(
-    links: &[Link],
+/// Check links as they are found, where `batches` yields the (already
+/// rewritten) links from a handful of chapters at a time.
+#[allow(clippy::too_many_arguments)]
+async fn lc_validate<S>(
+    batches: S,
     cfg: &Config,
     src_dir: &Path,
     cache: &mut Cache,
     files: &Files<String>,
     file_ids: &[FileId],
-) -> Outcomes {
-    let file_names = file_ids
-        .iter()
-        .map(|id| files.name(*id).to_os_string())
-        .collect();
-
-    let options = Options::default()
-        .with_root_directory(src_dir)
-        .expect("The source directory doesn't exist?")
-        .set_alternate_extensions(vec![(
-            "html".to_string(),
-            vec!["md".to_string()],
-        )])
-        .set_links_may_traverse_the_root_directory(
-            cfg.traverse_parent_directories,
-        )
-        // take into account the `index` preprocessor which rewrites `README.md`
-        // to `index.md` (which tne gets rendered as `index.html`)
-        .set_default_file("README.md")
-        .set_custom_validation(ensure_included_in_book(src_dir, file_names));
+    chapters: &[OsString],
+    drafts: &Drafts,
+    aliases: &Aliases,
+    checkpoint_file: Option<&Path>,
+    anchor_index: &AnchorIndex,
+    http: &dyn HttpClient,
+    dns_cache: Option<&DnsCache>,
+    file_provider: &dyn FileProvider,
+    sink: Option<&dyn DiagnosticSink>,
+    deadline: Option<tokio::time::Instant>,
+) -> (Outcomes, RequestStats, Vec<Link>)
+where
+    S: Stream<Item = Vec<Link>>,
+{
+    let local =
+        LocalFiles::new(cfg, src_dir, chapters.to_vec(), file_provider)
+            .with_drafts(drafts)
+            .with_aliases(aliases);
 
     let interpolated_headers = cfg.interpolate_headers(cfg.warning_policy);
 
     let ctx = Context {
+        http,
+        dns_cache,
         client: cfg.client(),
-        filesystem_options: options,
+        redirects: RedirectRecorder::default(),
+        content_types: Mutex::new(BTreeMap::new()),
+        local,
         cfg,
-        src_dir,
         cache: Mutex::new(cache.clone()),
-        files,
         interpolated_headers,
+        failed: tokio::sync::watch::channel(false).0,
+        // a host can't get more requests than are in flight overall
+        throttle: Throttle::new(DEFAULT_CONCURRENCY),
+        chapter_overrides: chapter_overrides(cfg, files, file_ids),
     };
-    let links = collate_links(links, src_dir, files);
+    // Web links don't depend on which directory they came from, so requests
+    // are sent as soon as each link is found. Everything else waits until the
+    // whole book has been scanned, because local links need to know about
+    // every chapter (e.g. to check links into other books in the workspace).
+    let (web_links, web_stream) = futures::channel::mpsc::unbounded();
+    let context = &ctx;
+    let split = async move {
+        let mut offline_links = Vec::new();
+        let mut current_page_links = Vec::new();
+        let mut local_links = Vec::new();
+        let mut duplicates = Duplicates::default();
+        futures::pin_mut!(batches);
 
-    let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
-    let got = runtime.block_on(async {
-        let mut outcomes = Outcomes::default();
+        while let Some(batch) = batches.next().await {
+            for link in batch {
+                if is_data_uri(&link.href) || is_tel_link(&link.href) {
+                    offline_links.push(link);
+                } else if link.href.starts_with('#') {
+                    current_page_links.push(link);
+                } else if is_web_link(&link.href)
+                    && !context.should_ignore(&link)
+                    && duplicates.is_duplicate(&link)
+                {
+                    tracing::trace!(href = %link.href, "Already checking");
+                } else if is_web_link(&link.href) {
+                    if let (Some(dns), Ok(url)) =
+                        (context.dns_cache, link.href.parse::<Url>())
+                    {
+                        dns.prefetch(&url);
+                    }
+                    web_links
+                        .unbounded_send(link)
+                        .expect("The receiver outlives the sender");
+                } else {
+                    local_links.push(link);
+                }
+            }
 
-        for (current_dir, links) in links {
-            outcomes
-                .merge(linkcheck::validate(&current_dir, links, &ctx).await);
+            // give the web requests a chance to start before scanning more
+            tokio::task::yield_now().await;
         }
 
-        outcomes
-    });
+        (offline_links, current_page_links, local_links, duplicates)
+    };
+
+    let checkpoint = checkpoint_file.map(Checkpoint::new);
+    // Note: web links are resolved relative to the book's root
+    let web_stream = web_stream.map(|link| (src_dir.to_path_buf(), link));
+    let web =
+        validate_each(web_stream, files, &ctx, deadline, checkpoint, sink)
+            .instrument(tracing::debug_span!("check_web_links"));
+    let local = async {
+        let (offline_links, current_page_links, local_links, duplicates) =
+            split.await;
+        let mut got = check_offline_links(offline_links, cfg);
+        got.merge(check_current_page_links(
+            current_page_links,
+            cfg,
+            src_dir,
+            files,
+            file_provider,
+        ));
+        let mut stats = RequestStats::default();
+
+        let workspace = Workspace::load(cfg, src_dir);
+        let WorkspaceLinks {
+            outcomes: workspace_outcomes,
+            remaining: local_links,
+            missing_translations,
+        } = if workspace.is_empty() {
+            WorkspaceLinks {
+                remaining: local_links,
+                ..Default::default()
+            }
+        } else {
+            workspace.check_links(
+                local_links,
+                cfg,
+                src_dir,
+                files,
+                &ctx.local,
+                anchor_index,
+            )
+        };
+        got.merge(workspace_outcomes);
+
+        // the local timeout starts once the whole book has been scanned
+        let local_deadline = cfg
+            .local
+            .timeout
+            .map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs))
+            .into_iter()
+            .chain(deadline)
+            .min();
+        let links = collate_links(&local_links, src_dir, files).flat_map(
+            |(current_dir, links)| {
+                links.into_iter().map(move |link| (current_dir.clone(), link))
+            },
+        );
+        let (local, local_stats) = validate_each(
+            futures::stream::iter(links),
+            files,
+            &ctx,
+            local_deadline,
+            None,
+            sink,
+        )
+        .await;
+        stats.merge(local_stats);
+
+        let local = check_fragments(
+            local,
+            cfg,
+            &ctx.local,
+            src_dir,
+            files,
+            anchor_index,
+        )
+        .instrument(tracing::debug_span!("check_fragments"))
+        .await;
+        got.merge(local);
+        (got, stats, missing_translations, duplicates)
+    }
+    .instrument(tracing::debug_span!("check_local_links"));
+    let (
+        (mut got, mut stats),
+        (local, local_stats, missing_translations, duplicates),
+    ) = futures::join!(web, local);
+    got.merge(local);
+    stats.merge(local_stats);
+    duplicates.fan_out(&mut got, &mut stats);
+    stats.rate_limited = ctx.throttle.rate_limited();
+    stats.permanent_redirects = ctx.redirects.destinations(&got.valid);
+    stats.final_destinations = ctx.redirects.final_destinations(&got.valid);
+    stats.content_types = std::mem::take(
+        &mut *ctx.content_types.lock().expect("Lock was poisoned"),
+    );
 
     // move the cache out of ctx. We'd get a borrowing error if anything was
     // using it
@@ -75,222 +235,2716 @@ fn lc_validate(
     *cache = updated_cache
         .into_inner()
         .expect("We statically know this isn't used");
-    got
+    (got, stats, missing_translations)
 }
 
-fn ensure_included_in_book(
-    src_dir: &Path,
-    file_names: Vec<OsString>,
-) -> impl Fn(&Path, Option<&str>) -> Result<(), Reason> {
-    let src_dir = src_dir.to_path_buf();
-
-    move |resolved_link, _| {
-        let resolved_link = match resolved_link.strip_prefix(&src_dir) {
-            Ok(path) => path,
-            // Not part of the book.
-            Err(_) => return Ok(()),
-        };
-        let was_included_in_summary =
-            file_names.iter().any(|summary_path| {
-                let summary_path = Path::new(summary_path);
-                if summary_path.parent() != resolved_link.parent() {
-                    return false;
-                }
-                match (summary_path.file_name(), resolved_link.file_name()) {
-                    (a, b) if a == b => true,
-                    (Some(summary), Some(resolved)) => {
-                        // index preprocessor rewrites summary paths before we get to them.
-                        summary == Path::new("index.md") && resolved == Path::new("README.md")
-                    }
-                    _ => false,
-                }
-            });
-        let ext = resolved_link.extension();
-        let is_markdown = ext == Some(OsStr::new("md"));
+/// Web links pointing at the same URL (after normalizing it) as one which is
+/// already being checked, so they can share its result instead of sending
+/// another request.
+#[derive(Debug, Default)]
+struct Duplicates {
+    seen: HashSet<String>,
+    links: Vec<(String, Link)>,
+}
+
+impl Duplicates {
+    /// Remember this link, returning `true` if its URL was seen before.
+    fn is_duplicate(&mut self, link: &Link) -> bool {
+        let url = crate::normalize::normalize_link(link).href;
 
-        if was_included_in_summary || !is_markdown {
-            Ok(())
+        if self.seen.contains(&url) {
+            self.links.push((url, link.clone()));
+            true
         } else {
-            use std::io::{Error, ErrorKind};
+            self.seen.insert(url);
+            false
+        }
+    }
 
-            Err(Reason::Io(Error::new(
-                ErrorKind::Other,
-                NotInSummary {
-                    path: resolved_link.to_path_buf(),
-                },
-            )))
+    /// Copy the result for each URL to the links which weren't checked
+    /// because they were duplicates.
+    fn fan_out(self, got: &mut Outcomes, stats: &mut RequestStats) {
+        if self.links.is_empty() {
+            return;
+        }
+        tracing::debug!(
+            "Sharing results with {} duplicate web links",
+            self.links.len()
+        );
+
+        let url = |link: &Link| crate::normalize::normalize_link(link).href;
+        let valid: HashSet<String> = got.valid.iter().map(url).collect();
+        let invalid: HashMap<String, SharedFailure> = got
+            .invalid
+            .iter()
+            .map(|i| (url(&i.link), SharedFailure::new(i)))
+            .collect();
+        let unchecked: HashSet<String> =
+            stats.unchecked.iter().map(url).collect();
+
+        for (url, link) in self.links {
+            if valid.contains(&url) {
+                got.valid.push(link);
+            } else if let Some(failure) = invalid.get(&url) {
+                got.invalid.push(InvalidLink {
+                    link,
+                    reason: failure.to_reason(),
+                });
+            } else if unchecked.contains(&url) {
+                stats.unchecked.push(link);
+            }
+            // otherwise the check was cancelled by fail-fast
         }
     }
 }
 
-/// An error that is emitted if something links to a file that exists on disk,
-/// but isn't included in the book.
-#[derive(Debug)]
-pub struct NotInSummary {
-    /// The file's full path.
-    pub path: PathBuf,
+/// Why a link is broken, copied from another link to the same URL which was
+/// checked instead (see [`Duplicates`]).
+#[derive(Debug, Clone)]
+pub(crate) struct SharedFailure {
+    pub(crate) reason: FailureReason,
+    kind: std::io::ErrorKind,
+    message: String,
 }
 
-impl Display for NotInSummary {
+impl SharedFailure {
+    fn new(invalid: &InvalidLink) -> Self {
+        let kind = match invalid.reason {
+            Reason::Io(ref io) => io.kind(),
+            _ => std::io::ErrorKind::Other,
+        };
+
+        SharedFailure {
+            reason: FailureReason::from(&invalid.reason),
+            kind,
+            message: most_specific_error_message(invalid),
+        }
+    }
+
+    fn to_reason(&self) -> Reason {
+        Reason::Io(std::io::Error::new(self.kind, self.clone()))
+    }
+}
+
+impl Display for SharedFailure {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "It looks like \"{}\" wasn't included in SUMMARY.md",
-            self.path.display()
-        )
+        f.write_str(&self.message)
     }
 }
 
-impl std::error::Error for NotInSummary {}
+impl std::error::Error for SharedFailure {}
 
-fn collate_links<'a>(
-    links: &'a [Link],
-    src_dir: &Path,
-    files: &'a Files<String>,
-) -> impl Iterator<Item = (PathBuf, Vec<linkcheck::Link>)> {
-    let mut links_by_directory: HashMap<PathBuf, Vec<linkcheck::Link>> =
-        HashMap::new();
+/// The equivalent of [`linkcheck::validate()`], except each link gets its own
+/// span so people can see how long it took to check.
+///
+/// Each link comes with the directory it is resolved relative to, so links
+/// from every chapter can share the same stream of requests.
+///
+/// Any links which haven't been checked by the `deadline` are cancelled and
+/// recorded in [`RequestStats::unchecked`]. Everything is cancelled without
+/// being recorded when [`Config::fail_fast`] is enabled and a link is broken.
+async fn validate_each<S>(
+    links: S,
+    files: &Files<String>,
+    ctx: &Context<'_>,
+    deadline: Option<tokio::time::Instant>,
+    mut checkpoint: Option<Checkpoint<'_>>,
+    sink: Option<&dyn DiagnosticSink>,
+) -> (Outcomes, RequestStats)
+where
+    S: Stream<Item = (PathBuf, Link)>,
+{
+    // links are only taken from the stream when there is room for another
+    // request, so keep track of the ones which were started
+    let started = Mutex::new(Vec::new());
+    let mut finished = HashSet::new();
+    let links = links.fuse();
+    futures::pin_mut!(links);
+    let deadline = async move {
+        match deadline {
+            Some(deadline) => {
+                tokio::time::sleep_until(deadline).await;
+                tracing::warn!("The deadline was exceeded");
+            },
+            None => futures::future::pending().await,
+        }
+    };
+    let stop = futures::future::select(
+        Box::pin(deadline),
+        Box::pin(ctx.failure()),
+    );
+
+    let (outcomes, mut stats) =
+        links
+            .as_mut()
+            .map(|(current_dir, link)| {
+                let ix = {
+                    let mut started = started.lock().unwrap();
+                    started.push(link.clone());
+                    started.len() - 1
+                };
+                let span = tracing::debug_span!(
+                    "validate_link",
+                    href = %link.href,
+                    file = %files.name(link.file).to_string_lossy(),
+                );
+                async move {
+                    let stripped = strip_link(&link, ctx.cfg);
+                    let start = Instant::now();
+                    let doi = if ctx.cfg.check_dois {
+                        crate::doi::doi_in_link(&stripped.href)
+                    } else {
+                        None
+                    };
+                    let (got, source) = match doi {
+                        Some(doi) => {
+                            crate::doi::check_doi(stripped, &doi, ctx).await
+                        },
+                        None if crate::web::is_web_link(&stripped) => {
+                            crate::web::check_web(stripped, ctx).await
+                        },
+                        None if normalize::is_local(&stripped.href) => {
+                            (check_local(&current_dir, stripped, ctx), None)
+                        },
+                        None => (check_other(stripped, ctx).await, None),
+                    };
+                    let got = crate::normalize::restore(got, &link);
+                    let timing = RequestTiming {
+                        link,
+                        duration: start.elapsed(),
+                    };
+                    (ix, got, source, timing)
+                }
+                .instrument(span)
+            })
+            .buffer_unordered(ctx.concurrency())
+            .take_until(stop)
+            .fold(
+                (Outcomes::default(), RequestStats::default()),
+                |(mut outcomes, mut stats),
+                 (ix, got, source, timing)| {
+                    finished.insert(ix);
+                    for invalid in &got.invalid {
+                        stream_failure(invalid, files, ctx.cfg, sink);
+                    }
+                    if ctx.cfg.fail_fast && has_errors(&got, ctx) {
+                        tracing::info!("Stopping at the first broken link");
+                        ctx.fail();
+                    }
+                    outcomes.merge(got);
+                    match source {
+                        Some(Source::Network) => stats.timings.push(timing),
+                        Some(Source::Cache) => stats.cache_hits += 1,
+                        None => {},
+                    }
+                    if let Some(ref mut checkpoint) = checkpoint {
+                        checkpoint.save_if_due(&ctx.cache);
+                    }
+                    futures::future::ready((outcomes, stats))
+                },
+            )
+            .await;
+
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.finish().await;
+    }
+
+    if ctx.has_failed() {
+        return (outcomes, stats);
+    }
+
+    stats.unchecked = started
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .enumerate()
+        .filter(|(ix, _)| !finished.contains(ix))
+        .map(|(_, link)| link)
+        .collect();
+    // anything we never got to
+    stats
+        .unchecked
+        .extend(links.map(|(_, link)| link).collect::<Vec<_>>().await);
+
+    (outcomes, stats)
+}
+
+/// Apply [`Config::strip`] to a link, then point it back at the book's source
+/// if it is under the [`Config::deploy_prefix`]. The result is only used for
+/// checking, and the original link is put back afterwards.
+fn strip_link(link: &Link, cfg: &Config) -> Link {
+    let stripped = cfg.strip(&link.href);
+    let href = stripped.as_deref().unwrap_or(&link.href);
+
+    match cfg.strip_deploy_prefix(href).or(stripped) {
+        Some(href) => {
+            tracing::debug!("Checking \"{}\" as \"{}\"", link.href, href);
+            Link::new(href, link.span, link.file)
+        },
+        None => link.clone(),
+    }
+}
+
+/// The [`Config::overrides`] for each of the chapters, leaving out the
+/// chapters without any.
+fn chapter_overrides<'a>(
+    cfg: &'a Config,
+    files: &Files<String>,
+    file_ids: &[FileId],
+) -> HashMap<FileId, Vec<&'a ChapterOverride>> {
+    if cfg.overrides.is_empty() {
+        return HashMap::new();
+    }
+
+    file_ids
+        .iter()
+        .map(|&id| (id, cfg.overrides_for(Path::new(files.name(id)))))
+        .filter(|(_, overrides)| !overrides.is_empty())
+        .collect()
+}
+
+/// Would any of these broken links be reported as errors?
+fn has_errors(outcomes: &Outcomes, ctx: &Context<'_>) -> bool {
+    outcomes.invalid.iter().any(|invalid| {
+        let overrides = ctx.overrides_for(&invalid.link);
+        ctx.cfg
+            .broken_link_policy_in(&invalid.link.href, overrides)
+            .to_severity()
+            == Some(Severity::Error)
+    })
+}
+
+/// `data:` URIs and `tel:` links don't point anywhere we can check, so the
+/// best we can do is make sure they are well-formed.
+fn check_offline_links(links: Vec<Link>, cfg: &Config) -> Outcomes {
+    let mut outcomes = Outcomes::default();
 
     for link in links {
-        let mut path = src_dir.join(files.name(link.file));
-        path.pop();
-        links_by_directory
-            .entry(path)
-            .or_default()
-            .push(link.clone());
+        if cfg.should_skip(&link.href) {
+            outcomes.ignored.push(link);
+            continue;
+        }
+
+        let result = if is_tel_link(&link.href) {
+            check_tel_link(&link.href, cfg)
+        } else {
+            check_data_uri(&link.href)
+        };
+
+        match result {
+            Ok(()) => outcomes.valid.push(link),
+            Err(reason) => outcomes.invalid.push(InvalidLink { link, reason }),
+        }
     }
 
-    links_by_directory.into_iter()
+    outcomes
 }
 
-fn merge_outcomes(
-    outcomes: Outcomes,
-    incomplete_links: Vec<IncompleteLink>,
-) -> ValidationOutcome {
-    // Note: we want to sort all outcomes by file and then its location in that
-    // file.
-    //
-    // That way, when we emit diagnostics they'll be emitted for each file in
-    // the order that it is listed in `SUMMARY.md`, then individual diagnostics
-    // will be emitted from the start of each file to the end.
-    fn sorted<T, F>(mut items: Vec<T>, mut key: F) -> Vec<T>
-    where
-        F: FnMut(&T) -> &Link,
-    {
-        items.sort_by_key(|item| {
-            let link = key(item);
-            (link.file, link.span)
-        });
-        items
+/// Check a link to a local file, looking it up using the context's
+/// [`FileProvider`] instead of going through `linkcheck` (which only knows
+/// about the real filesystem, and would misread links containing spaces or
+/// percent-encoded characters).
+fn check_local(current_dir: &Path, link: Link, ctx: &Context<'_>) -> Outcomes {
+    let mut outcomes = Outcomes::default();
+
+    if ctx.should_ignore(&link) {
+        outcomes.ignored.push(link);
+        return outcomes;
     }
-    fn sorted_link(items: Vec<Link>) -> Vec<Link> { sorted(items, |link| link) }
 
-    ValidationOutcome {
-        invalid_links: sorted(outcomes.invalid, |l| &l.link),
-        ignored: sorted_link(outcomes.ignored),
-        valid_links: sorted_link(outcomes.valid),
-        unknown_category: sorted_link(outcomes.unknown_category),
-        incomplete_links,
+    let path = normalize::local_path(&link.href);
+    match ctx.local.check(current_dir, &path) {
+        Ok(()) => outcomes.valid.push(link),
+        Err(reason) => outcomes.invalid.push(InvalidLink { link, reason }),
     }
+
+    outcomes
 }
 
-/// Try to validate the provided [`Link`]s.
-pub fn validate(
-    links: &[Link],
+/// Check a link which isn't a web link or a link to a local file, the same
+/// way [`linkcheck::validate()`] would (e.g. `mailto:` links are ignored).
+async fn check_other(link: Link, ctx: &Context<'_>) -> Outcomes {
+    let mut outcomes = Outcomes::default();
+
+    if ctx.should_ignore(&link) {
+        outcomes.ignored.push(link);
+        return outcomes;
+    }
+
+    if link.href.is_empty() {
+        outcomes.unknown_category.push(link);
+        return outcomes;
+    }
+
+    let url = match link.href.parse::<Url>() {
+        Ok(url) if url.scheme() != "mailto" => url,
+        // mailto: links and links to the current file
+        _ => {
+            outcomes.ignored.push(link);
+            return outcomes;
+        },
+    };
+
+    let headers = ctx.url_specific_headers(&url);
+    match linkcheck::validation::head(&ctx.client, url, headers).await {
+        Ok(()) => outcomes.valid.push(link),
+        Err(e) => outcomes.invalid.push(InvalidLink {
+            link,
+            reason: Reason::Web(e),
+        }),
+    }
+
+    outcomes
+}
+
+/// Check links to somewhere else in the same chapter (e.g. `#configuration`)
+/// against the anchors in that chapter.
+///
+/// `linkcheck` ignores these, so we do it ourselves. Each chapter's anchors
+/// are only worked out once, and a broken link's span is narrowed down to
+/// just the `#fragment` so the diagnostic points at the right thing.
+fn check_current_page_links(
+    links: Vec<Link>,
     cfg: &Config,
     src_dir: &Path,
-    cache: &mut Cache,
     files: &Files<String>,
-    file_ids: &[FileId],
-    incomplete_links: Vec<IncompleteLink>,
-) -> Result<ValidationOutcome, Error> {
-    let got = lc_validate(links, cfg, src_dir, cache, files, file_ids);
-    Ok(merge_outcomes(got, incomplete_links))
+    file_provider: &dyn FileProvider,
+) -> Outcomes {
+    let mut outcomes = Outcomes::default();
+    let mut anchors_by_file: HashMap<FileId, HashSet<String>> =
+        HashMap::new();
+
+    for link in links {
+        let fragment = link.href.trim_start_matches('#');
+
+        if cfg.should_skip(&link.href)
+            || cfg.should_skip_anchor(&link.href, fragment)
+        {
+            outcomes.ignored.push(link);
+            continue;
+        }
+        // an empty fragment and "#top" both go to the top of the page
+        if !cfg.check_anchors
+            || fragment.is_empty()
+            || fragment.eq_ignore_ascii_case("top")
+            || cfg.is_known_anchor(&link.href, fragment)
+        {
+            outcomes.valid.push(link);
+            continue;
+        }
+
+        let anchors = anchors_by_file.entry(link.file).or_insert_with(|| {
+            let chapter = src_dir.join(files.name(link.file));
+            let src = crate::includes::expand_includes(
+                files.source(link.file),
+                chapter.parent().unwrap_or(src_dir),
+                file_provider,
+            );
+            anchors::anchors_in_markdown(&src, &cfg.slugs)
+        });
+
+        if anchors.contains(fragment) {
+            outcomes.valid.push(link);
+        } else {
+            let reason = Reason::Io(std::io::Error::other(MissingAnchor {
+                path: PathBuf::from(files.name(link.file)),
+                anchor: fragment.to_string(),
+            }));
+            let link = narrow_to_href(link, files);
+            outcomes.invalid.push(InvalidLink { link, reason });
+        }
+    }
+
+    outcomes
 }
 
-/// The outcome of validating a set of links.
-#[derive(Debug, Default)]
-pub struct ValidationOutcome {
-    /// Valid links.
-    pub valid_links: Vec<Link>,
-    /// Links where validation failed.
-    pub invalid_links: Vec<InvalidLink>,
-    /// Links which have been ignored (e.g. due to
-    /// [`Config::follow_web_links`]).
-    pub ignored: Vec<Link>,
-    /// Links which we don't know how to handle.
-    pub unknown_category: Vec<Link>,
-    /// Potentially incomplete links.
-    pub incomplete_links: Vec<IncompleteLink>,
+/// Shrink a link's span (which normally covers the whole `[text](href)`) so
+/// it only covers the href itself.
+fn narrow_to_href(mut link: Link, files: &Files<String>) -> Link {
+    let src = files.source(link.file);
+    let start = link.span.start().to_usize();
+    let end = link.span.end().to_usize();
+
+    if let Some(ix) = src
+        .get(start..end)
+        .and_then(|text| text.rfind(link.href.as_str()))
+    {
+        let href_start = start + ix;
+        link.span = codespan::Span::new(
+            href_start as u32,
+            (href_start + link.href.len()) as u32,
+        );
+    }
+
+    link
 }
 
-impl ValidationOutcome {
-    /// Generate a list of [`Diagnostic`] messages from this
-    /// [`ValidationOutcome`].
-    pub fn generate_diagnostics(
-        &self,
-        files: &Files<String>,
-        warning_policy: WarningPolicy,
-    ) -> Vec<Diagnostic<FileId>> {
-        let mut diags = Vec::new();
+/// Periodically saves the [`Cache`] while web links are being checked, so
+/// the results aren't lost if the run is interrupted (e.g. by Ctrl-C or a CI
+/// timeout) and the next run can pick up where this one left off.
+#[derive(Debug)]
+struct Checkpoint<'a> {
+    path: &'a Path,
+    interval: Duration,
+    last_saved: Instant,
+    /// The checkpoint currently being written in the background.
+    saving: Option<tokio::task::JoinHandle<()>>,
+}
 
-        self.add_invalid_link_diagnostics(&mut diags);
-        self.add_incomplete_link_diagnostics(warning_policy, &mut diags);
-        self.warn_on_absolute_links(warning_policy, &mut diags, files);
+impl<'a> Checkpoint<'a> {
+    const INTERVAL: Duration = Duration::from_secs(5);
 
-        diags
+    fn new(path: &'a Path) -> Self {
+        Checkpoint {
+            path,
+            interval: Checkpoint::INTERVAL,
+            last_saved: Instant::now(),
+            saving: None,
+        }
     }
 
-    fn add_incomplete_link_diagnostics(
-        &self,
-        warning_policy: WarningPolicy,
-        diags: &mut Vec<Diagnostic<FileId>>,
-    ) {
-        let severity = match warning_policy {
-            WarningPolicy::Error => Severity::Error,
-            WarningPolicy::Warn => Severity::Warning,
-            WarningPolicy::Ignore => return,
-        };
-
-        for incomplete in &self.incomplete_links {
-            let IncompleteLink {
-                ref reference,
-                file,
-                span,
-            } = incomplete;
+    fn save_if_due(&mut self, cache: &Mutex<Cache>) {
+        if self.last_saved.elapsed() < self.interval
+            || self.saving.as_ref().is_some_and(|saving| !saving.is_finished())
+        {
+            return;
+        }
 
-            let msg =
-                format!("Did you forget to define a URL for `{0}`?", reference);
-            let label = Label::primary(*file, *span).with_message(msg);
-            let note = format!(
-                "hint: declare the link's URL. For example: `[{}]: http://example.com/`",
-                reference
-            );
+        tracing::debug!("Saving a checkpoint of the cache");
+        // only hold the lock while copying the cache, so web checks aren't
+        // held up by serializing it and writing it to disk
+        let cache = cache.lock().expect("Lock was poisoned").clone();
+        let path = self.path.to_path_buf();
+        self.saving = Some(tokio::task::spawn_blocking(move || {
+            crate::save_cache(&path, &cache)
+        }));
+        self.last_saved = Instant::now();
+    }
 
-            let diag = Diagnostic::new(severity)
-                .with_message("Potential incomplete link")
-                .with_labels(vec![label])
-                .with_notes(vec![note]);
-            diags.push(diag)
+    /// Wait for the last checkpoint to be written, so it can't overwrite the
+    /// cache which is saved at the end of the run.
+    async fn finish(self) {
+        if let Some(saving) = self.saving {
+            if let Err(e) = saving.await {
+                tracing::warn!("Unable to save a checkpoint: {}", e);
+            }
         }
     }
+}
 
-    fn add_invalid_link_diagnostics(
+/// How a broken link should be reported, taking the chapter's
+/// [`Config::overrides`] into account (`None` means it shouldn't be).
+pub(crate) fn broken_link_severity(
+    broken_link: &InvalidLink,
+    cfg: &Config,
+    files: &Files<String>,
+) -> Option<Severity> {
+    let link = &broken_link.link;
+    let overrides = cfg.overrides_for(Path::new(files.name(link.file)));
+    cfg.broken_link_policy_in(&link.href, &overrides).to_severity()
+}
+
+/// The diagnostic for a broken link, if its policy says to report it.
+fn broken_link_diagnostic(
+    broken_link: &InvalidLink,
+    cfg: &Config,
+    files: &Files<String>,
+) -> Option<Diagnostic<FileId>> {
+    let link = &broken_link.link;
+    let severity = broken_link_severity(broken_link, cfg, files)?;
+    let msg = most_specific_error_message(broken_link);
+    let mut diag = Diagnostic::new(severity)
+        .with_code(DiagnosticCategory::BrokenLink.code())
+        .with_message(msg.clone())
+        .with_labels(vec![
+            Label::primary(link.file, link.span).with_message(msg)
+        ]);
+    if let Some(draft) = draft_chapter(&broken_link.reason) {
+        diag = diag.with_notes(vec![draft.note()]);
+    }
+
+    Some(diag)
+}
+
+/// Tell the [`DiagnosticSink`] about a broken link as soon as it is found,
+/// printing it too if [`Config::stream_failures`] is enabled.
+fn stream_failure(
+    invalid: &InvalidLink,
+    files: &Files<String>,
+    cfg: &Config,
+    sink: Option<&dyn DiagnosticSink>,
+) {
+    if sink.is_none() && !cfg.stream_failures {
+        return;
+    }
+    let diag = match broken_link_diagnostic(invalid, cfg, files) {
+        Some(diag) => diag,
+        None => return,
+    };
+
+    if cfg.stream_failures {
+        Stderr.emit(&diag, files);
+    }
+    if let Some(sink) = sink {
+        sink.emit(&diag, files);
+    }
+}
+
+/// Statistics about the HTTP requests made while validating.
+#[derive(Debug, Default)]
+struct RequestStats {
+    timings: Vec<RequestTiming>,
+    cache_hits: usize,
+    /// Links which were cancelled because [`Config::max_runtime`] was
+    /// exceeded.
+    unchecked: Vec<Link>,
+    rate_limited: BTreeMap<String, usize>,
+    permanent_redirects: BTreeMap<String, String>,
+    /// Where each redirected web link ended up, temporary redirects included.
+    final_destinations: BTreeMap<String, String>,
+    /// The `Content-Type` each web link was served with.
+    content_types: BTreeMap<String, String>,
+}
+
+impl RequestStats {
+    fn merge(&mut self, other: RequestStats) {
+        self.timings.extend(other.timings);
+        self.cache_hits += other.cache_hits;
+        self.unchecked.extend(other.unchecked);
+        for (host, count) in other.rate_limited {
+            *self.rate_limited.entry(host).or_default() += count;
+        }
+        self.permanent_redirects.extend(other.permanent_redirects);
+        self.final_destinations.extend(other.final_destinations);
+        self.content_types.extend(other.content_types);
+    }
+}
+
+fn ensure_included_in_book(
+    src_dir: &Path,
+    file_names: &[OsString],
+    resolved_link: &Path,
+) -> Result<(), Reason> {
+    let resolved_link = match resolved_link.strip_prefix(src_dir) {
+        Ok(path) => path,
+        // Not part of the book.
+        Err(_) => return Ok(()),
+    };
+    if is_special_file(resolved_link) {
+        // reported separately, according to Config::special_files
+        return Ok(());
+    }
+    let was_included_in_summary = file_names.iter().any(|summary_path| {
+        let summary_path = Path::new(summary_path);
+        if summary_path.parent() != resolved_link.parent() {
+            return false;
+        }
+        match (summary_path.file_name(), resolved_link.file_name()) {
+            (a, b) if a == b => true,
+            (Some(summary), Some(resolved)) => {
+                // index preprocessor rewrites summary paths before we get to
+                // them.
+                summary == Path::new("index.md")
+                    && resolved == Path::new("README.md")
+            },
+            _ => false,
+        }
+    });
+    let ext = resolved_link.extension();
+    let is_markdown = ext == Some(OsStr::new("md"));
+
+    if was_included_in_summary || !is_markdown {
+        Ok(())
+    } else {
+        use std::io::Error;
+
+        Err(Reason::Io(Error::other(NotInSummary {
+            path: resolved_link.to_path_buf(),
+        })))
+    }
+}
+
+/// Make sure the file a link points to can actually be read. Otherwise it
+/// would still be broken for readers.
+pub(crate) fn ensure_readable(
+    path: &Path,
+    files: &dyn FileProvider,
+) -> Result<(), Reason> {
+    use std::io::Error;
+
+    if files.is_dir(path) {
+        return Ok(());
+    }
+
+    let unreadable = |error: Error| {
+        Reason::Io(Error::new(
+            error.kind(),
+            UnreadableFile {
+                path: path.to_path_buf(),
+                error,
+            },
+        ))
+    };
+
+    files.file_size(path).map(|_| ()).map_err(unreadable)
+}
+
+/// Which files outside the source directory links are allowed to point to,
+/// according to [`Config::external_roots`],
+/// [`Config::allowed_external_paths`], [`Config::extra_files`], and
+/// [`Config::max_parent_depth`].
+#[derive(Debug, Clone)]
+struct ParentDirectoryPolicy {
+    src_dir: PathBuf,
+    roots: Vec<PathBuf>,
+    /// Patterns matched against the link's path relative to `src_dir`.
+    allowed: Vec<Regex>,
+    max_depth: Option<usize>,
+}
+
+impl ParentDirectoryPolicy {
+    /// Returns `None` when links may go anywhere, or nowhere (in which case
+    /// `linkcheck` will reject them for us).
+    fn new(
+        cfg: &Config,
+        src_dir: &Path,
+        files: &dyn FileProvider,
+    ) -> Option<Self> {
+        if cfg.traverse_parent_directories || !cfg.may_leave_source_directory()
+        {
+            return None;
+        }
+
+        let src_dir = files
+            .canonicalize(src_dir)
+            .unwrap_or_else(|_| src_dir.to_path_buf());
+        let roots = cfg
+            .external_roots
+            .iter()
+            .chain(&cfg.extra_files)
+            .filter_map(|raw| match files.canonicalize(&src_dir.join(raw)) {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    tracing::warn!(
+                        "Unable to find \"{}\", so links to it will be rejected: {}",
+                        raw,
+                        e
+                    );
+                    None
+                },
+            })
+            .collect();
+        let allowed = cfg
+            .allowed_external_paths
+            .iter()
+            .filter_map(|pattern| match glob_to_regex(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    tracing::warn!("Ignoring allowed-external-paths: {:#}", e);
+                    None
+                },
+            })
+            .collect();
+
+        Some(ParentDirectoryPolicy {
+            src_dir,
+            roots,
+            allowed,
+            max_depth: cfg.max_parent_depth,
+        })
+    }
+
+    fn check(&self, resolved_link: &Path) -> Result<(), Reason> {
+        if resolved_link.starts_with(&self.src_dir)
+            || self
+                .roots
+                .iter()
+                .any(|root| resolved_link.starts_with(root))
+        {
+            return Ok(());
+        }
+
+        let common = self
+            .src_dir
+            .components()
+            .zip(resolved_link.components())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let depth = self.src_dir.components().count() - common;
+
+        // the link's path relative to the source directory (e.g.
+        // "../shared/intro.md"), which is what the patterns are written as
+        let mut relative = vec![".."; depth].join("/");
+        for component in resolved_link.components().skip(common) {
+            relative.push('/');
+            relative.push_str(&component.as_os_str().to_string_lossy());
+        }
+        if self.allowed.iter().any(|re| re.is_match(&relative)) {
+            return Ok(());
+        }
+
+        match self.max_depth {
+            Some(max_depth) if depth <= max_depth => Ok(()),
+            _ => Err(Reason::TraversesParentDirectories),
+        }
+    }
+}
+
+/// Resolves links to local files the same way `linkcheck` does, except files
+/// are looked up using a [`FileProvider`] so books don't need to be on disk,
+/// then makes sure the file may actually be linked to.
+#[derive(Debug)]
+pub(crate) struct LocalFiles<'a> {
+    files: &'a dyn FileProvider,
+    src_dir: PathBuf,
+    /// The canonical source directory, which absolute links (e.g.
+    /// `/chapter_1.md`) are relative to.
+    root: PathBuf,
+    may_leave_root: bool,
+    parent_directories: Option<ParentDirectoryPolicy>,
+    languages: Languages,
+    /// The chapters in `SUMMARY.md`.
+    file_names: Vec<OsString>,
+    drafts: Drafts,
+    aliases: Aliases,
+}
+
+impl<'a> LocalFiles<'a> {
+    /// Links to a directory go to this file inside it, taking into account the
+    /// `index` preprocessor which rewrites `README.md` to `index.md` (which
+    /// then gets rendered as `index.html`).
+    const DEFAULT_FILE: &'static str = "README.md";
+
+    pub(crate) fn new(
+        cfg: &Config,
+        src_dir: &Path,
+        file_names: Vec<OsString>,
+        files: &'a dyn FileProvider,
+    ) -> Self {
+        let root = files
+            .canonicalize(src_dir)
+            .unwrap_or_else(|_| src_dir.to_path_buf());
+
+        LocalFiles {
+            files,
+            src_dir: src_dir.to_path_buf(),
+            // when this is more specific than "everything", the
+            // ParentDirectoryPolicy takes care of it
+            may_leave_root: cfg.may_leave_source_directory(),
+            parent_directories: ParentDirectoryPolicy::new(cfg, src_dir, files),
+            languages: Languages::new(cfg, &root, &file_names, files),
+            root,
+            file_names,
+            drafts: Drafts::default(),
+            aliases: Aliases::default(),
+        }
+    }
+
+    /// Explain that links to missing files which belong to one of the book's
+    /// draft chapters are broken because the chapter hasn't been written.
+    pub(crate) fn with_drafts(mut self, drafts: &Drafts) -> Self {
+        self.drafts = drafts.clone();
+        self
+    }
+
+    /// Treat links to a chapter's old URLs (its `redirect_from` front
+    /// matter) as links to the chapter.
+    pub(crate) fn with_aliases(mut self, aliases: &Aliases) -> Self {
+        self.aliases = aliases.clone();
+        self
+    }
+
+    /// Figure out which file a link from `current_dir` points to.
+    pub(crate) fn resolve(
+        &self,
+        current_dir: &Path,
+        link: &Path,
+    ) -> Result<PathBuf, Reason> {
+        let joined = if link.has_root() {
+            // absolute links are relative to the source directory (or the
+            // chapter's language, in a translated book)
+            let mut joined = self.languages.root_for(&self.root, current_dir);
+            joined.extend(link.components().skip_while(|c| {
+                matches!(c, Component::Prefix(_) | Component::RootDir)
+            }));
+            joined
+        } else {
+            current_dir.join(link)
+        };
+
+        // markdown files can be linked to using their HTML extension
+        let is_html = joined
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("html"));
+        // untranslated pages are rendered using the default language
+        let fallback = self.languages.fallback(&self.root, &joined);
+        let mut candidates = Vec::new();
+        for target in std::iter::once(joined).chain(fallback) {
+            let markdown = target.with_extension("md");
+            candidates.push(target);
+            if is_html {
+                candidates.push(markdown);
+            }
+        }
+
+        let alias = candidates
+            .iter()
+            .find_map(|candidate| self.aliases.target(candidate))
+            .map(Path::to_path_buf);
+
+        for candidate in candidates.into_iter().chain(alias) {
+            if let Ok(mut canonical) = self.files.canonicalize(&candidate) {
+                if self.files.is_dir(&canonical) {
+                    canonical.push(Self::DEFAULT_FILE);
+                    canonical = self.files.canonicalize(&canonical)?;
+                }
+                if !self.may_leave_root && !canonical.starts_with(&self.root)
+                {
+                    return Err(Reason::TraversesParentDirectories);
+                }
+                return Ok(canonical);
+            }
+        }
+
+        Err(Reason::Io(std::io::ErrorKind::NotFound.into()))
+    }
+
+    /// Make sure a link from `current_dir` points to a file which is allowed
+    /// to be linked to and can be read.
+    pub(crate) fn check(
+        &self,
+        current_dir: &Path,
+        link: &Path,
+    ) -> Result<(), Reason> {
+        let resolved_link = self
+            .resolve(current_dir, link)
+            .map_err(|reason| self.drafts.explain(reason, link))?;
+
+        if let Some(ref policy) = self.parent_directories {
+            policy.check(&resolved_link)?;
+        }
+        ensure_readable(&resolved_link, self.files)?;
+        ensure_included_in_book(&self.src_dir, &self.file_names, &resolved_link)
+    }
+}
+
+/// `linkcheck` doesn't know how to check the `#fragment` in a link to another
+/// file, so we go back through the links it said were valid and make sure
+/// the anchor being linked to actually exists.
+///
+/// This means reading and parsing every file being linked to, so it is done
+/// in parallel and each file's anchors are remembered in the [`AnchorIndex`].
+async fn check_fragments(
+    mut outcomes: Outcomes,
+    cfg: &Config,
+    local: &LocalFiles<'_>,
+    src_dir: &Path,
+    files: &Files<String>,
+    anchor_index: &AnchorIndex,
+) -> Outcomes {
+    if !cfg.check_anchors {
+        return outcomes;
+    }
+
+    let valid = std::mem::take(&mut outcomes.valid);
+    let targets: Vec<_> = valid
+        .iter()
+        .map(|link| fragment_target(link, cfg, local, src_dir, files))
+        .collect();
+
+    let linked_files: HashSet<&Path> =
+        targets.iter().flatten().map(|(path, _)| path.as_path()).collect();
+    futures::stream::iter(linked_files)
+        .map(|path| anchor_index.prefetch(path, local.files))
+        .buffer_unordered(crate::parallelism())
+        // erasing the stream's type keeps the caller's future Send
+        .boxed()
+        .collect::<Vec<()>>()
+        .await;
+
+    for (link, target) in valid.into_iter().zip(targets) {
+        let reason = target.and_then(|(resolved, fragment)| {
+            missing_anchor(&resolved, &fragment, local, src_dir, anchor_index)
+        });
+        match reason {
+            Some(reason) => outcomes.invalid.push(InvalidLink { link, reason }),
+            None => outcomes.valid.push(link),
+        }
+    }
+
+    outcomes
+}
+
+/// The file a link points into and the anchor it needs, if the anchor should
+/// be checked.
+fn fragment_target(
+    link: &Link,
+    cfg: &Config,
+    local: &LocalFiles<'_>,
+    src_dir: &Path,
+    files: &Files<String>,
+) -> Option<(PathBuf, String)> {
+    if is_web_link(&link.href) {
+        return None;
+    }
+
+    let (path, fragment) = match link.href.split_once('#') {
+        Some((path, fragment)) if !path.is_empty() && !fragment.is_empty() => {
+            (path, fragment)
+        },
+        _ => return None,
+    };
+
+    if cfg.should_skip_anchor(&link.href, fragment) {
+        tracing::debug!("Not checking the fragment in \"{}\"", link.href);
+        return None;
+    }
+    if cfg.is_known_anchor(&link.href, fragment) {
+        return None;
+    }
+
+    let mut current_dir = src_dir.join(files.name(link.file));
+    current_dir.pop();
+    let path = normalize::local_path(path);
+    let resolved = local.resolve(&current_dir, &path).ok()?;
+
+    Some((resolved, fragment.to_string()))
+}
+
+fn missing_anchor(
+    resolved: &Path,
+    fragment: &str,
+    local: &LocalFiles<'_>,
+    src_dir: &Path,
+    anchor_index: &AnchorIndex,
+) -> Option<Reason> {
+    let anchors = anchor_index.anchors(resolved, local.files)?;
+
+    if anchors.contains(fragment) {
+        return None;
+    }
+
+    let path = resolved.strip_prefix(src_dir).unwrap_or(resolved);
+    Some(Reason::Io(std::io::Error::other(MissingAnchor {
+        path: path.to_path_buf(),
+        anchor: fragment.to_string(),
+    })))
+}
+
+/// An error that is emitted when a link points to a section of a document
+/// (e.g. `./chapter_1.md#some-heading`) which doesn't exist.
+#[derive(Debug)]
+pub struct MissingAnchor {
+    /// The file being linked to.
+    pub path: PathBuf,
+    /// The name of the anchor that could not be found.
+    pub anchor: String,
+}
+
+impl Display for MissingAnchor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "There is no \"#{}\" anchor in \"{}\"",
+            self.anchor,
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for MissingAnchor {}
+
+/// An error that is emitted if something links to a file that exists on disk,
+/// but isn't included in the book.
+#[derive(Debug)]
+pub struct NotInSummary {
+    /// The file's full path.
+    pub path: PathBuf,
+}
+
+impl Display for NotInSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "It looks like \"{}\" wasn't included in SUMMARY.md",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for NotInSummary {}
+
+/// An error that is emitted when a link points to a file which exists, but
+/// can't be read (e.g. because of its permissions).
+#[derive(Debug)]
+pub struct UnreadableFile {
+    /// The file's full path.
+    pub path: PathBuf,
+    /// Why the file couldn't be read.
+    pub error: std::io::Error,
+}
+
+impl Display for UnreadableFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to read \"{}\": {}",
+            self.path.display(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for UnreadableFile {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The error returned when [`Config::max_runtime`] was exceeded before every
+/// link could be checked.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeadlineExceeded {
+    /// The number of links which weren't checked.
+    pub unchecked: usize,
+}
+
+impl DeadlineExceeded {
+    /// The exit code used when the deadline is exceeded, so CI can tell a
+    /// partial run apart from broken links.
+    pub const EXIT_CODE: i32 = 3;
+}
+
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.unchecked == 0 {
+            write!(f, "The deadline was exceeded before every check finished")
+        } else {
+            write!(
+                f,
+                "The deadline was exceeded before {} links could be checked",
+                self.unchecked
+            )
+        }
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+fn collate_links<'a>(
+    links: &'a [Link],
+    src_dir: &Path,
+    files: &'a Files<String>,
+) -> impl Iterator<Item = (PathBuf, Vec<linkcheck::Link>)> {
+    let mut links_by_directory: HashMap<PathBuf, Vec<linkcheck::Link>> =
+        HashMap::new();
+
+    for link in links {
+        let mut path = src_dir.join(files.name(link.file));
+        path.pop();
+        links_by_directory
+            .entry(path)
+            .or_default()
+            .push(link.clone());
+    }
+
+    links_by_directory.into_iter()
+}
+
+fn merge_outcomes(
+    outcomes: Outcomes,
+    incomplete_links: Vec<IncompleteLink>,
+) -> ValidationOutcome {
+    // Note: we want to sort all outcomes by file and then its location in that
+    // file.
+    //
+    // That way, when we emit diagnostics they'll be emitted for each file in
+    // the order that it is listed in `SUMMARY.md`, then individual diagnostics
+    // will be emitted from the start of each file to the end.
+    fn sorted<T, F>(mut items: Vec<T>, mut key: F) -> Vec<T>
+    where
+        F: FnMut(&T) -> &Link,
+    {
+        items.sort_by_key(|item| {
+            let link = key(item);
+            (link.file, link.span)
+        });
+        items
+    }
+    fn sorted_link(items: Vec<Link>) -> Vec<Link> { sorted(items, |link| link) }
+
+    ValidationOutcome {
+        invalid_links: sorted(outcomes.invalid, |l| &l.link),
+        ignored: sorted_link(outcomes.ignored),
+        valid_links: sorted_link(outcomes.valid),
+        unknown_category: sorted_link(outcomes.unknown_category),
+        incomplete_links,
+        ..Default::default()
+    }
+}
+
+/// Try to validate the provided [`Link`]s.
+///
+/// This will block the current thread until validation is complete. Use
+/// [`validate_async()`] if you are already inside an async runtime.
+pub fn validate(
+    links: &[Link],
+    cfg: &Config,
+    src_dir: &Path,
+    cache: &mut Cache,
+    files: &Files<String>,
+    file_ids: &[FileId],
+    incomplete_links: Vec<IncompleteLink>,
+) -> Result<ValidationOutcome, Error> {
+    let runtime = Builder::new_multi_thread().enable_all().build()?;
+
+    runtime.block_on(validate_async(
+        links,
+        cfg,
+        src_dir,
+        cache,
+        files,
+        file_ids,
+        incomplete_links,
+    ))
+}
+
+/// The `async` equivalent of [`validate()`], for use inside an existing
+/// `tokio` runtime.
+pub async fn validate_async(
+    links: &[Link],
+    cfg: &Config,
+    src_dir: &Path,
+    cache: &mut Cache,
+    files: &Files<String>,
+    file_ids: &[FileId],
+    incomplete_links: Vec<IncompleteLink>,
+) -> Result<ValidationOutcome, Error> {
+    let batch = (links.to_vec(), incomplete_links);
+    let chapters: Vec<OsString> = file_ids
+        .iter()
+        .map(|id| files.name(*id).to_os_string())
+        .collect();
+    let (outcome, _) = validate_batches(
+        futures::stream::iter(std::iter::once(batch)),
+        cfg,
+        src_dir,
+        cache,
+        files,
+        file_ids,
+        &chapters,
+        &Drafts::default(),
+        &Aliases::default(),
+        None,
+        &AnchorIndex::new(cfg.slugs.clone()),
+        None,
+        &RealFileSystem,
+        None,
+    )
+    .await?;
+
+    Ok(outcome)
+}
+
+/// Check `links` (from the one chapter in `files`) using a canned
+/// [`HttpClient`] instead of the network.
+#[cfg(test)]
+pub(crate) fn validate_with(
+    client: &dyn HttpClient,
+    links: &[Link],
+    cfg: &Config,
+    files: &Files<String>,
+) -> ValidationOutcome {
+    let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+
+    validate_on(runtime, client, links, cfg, files)
+}
+
+/// The same as [`validate_with()`], using the provided runtime (e.g. one
+/// where time is paused).
+#[cfg(test)]
+pub(crate) fn validate_on(
+    runtime: tokio::runtime::Runtime,
+    client: &dyn HttpClient,
+    links: &[Link],
+    cfg: &Config,
+    files: &Files<String>,
+) -> ValidationOutcome {
+    let mut file_ids: Vec<FileId> =
+        links.iter().map(|link| link.file).collect();
+    file_ids.sort();
+    file_ids.dedup();
+    let batch = (links.to_vec(), Vec::new());
+
+    let (outcome, _) = runtime
+        .block_on(validate_batches(
+            futures::stream::iter(std::iter::once(batch)),
+            cfg,
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            &mut Cache::default(),
+            files,
+            &file_ids,
+            &[],
+            &Drafts::default(),
+            &Aliases::default(),
+            None,
+            &AnchorIndex::new(cfg.slugs.clone()),
+            Some(client),
+            &RealFileSystem,
+            None,
+        ))
+        .unwrap();
+
+    outcome
+}
+
+/// The same as [`validate_async()`], except links are checked as each batch
+/// (e.g. the links from a handful of chapters) arrives, so web requests can be
+/// sent while the rest of the book is still being scanned. Every link that
+/// was found is returned alongside the [`ValidationOutcome`].
+///
+/// Only the links in `batches` are checked, but `chapters` should list every
+/// chapter in `SUMMARY.md` (relative to `src_dir`), including the ones which
+/// aren't being checked, so links to them aren't mistaken for links to
+/// files that were left out of the book.
+///
+/// The [`Cache`] is regularly saved to `checkpoint_file` while web links are
+/// being checked, and anchors are looked up in the provided [`AnchorIndex`].
+/// Web requests are sent using `client` when provided, so its connection pool
+/// can be reused between runs, and local files are looked up using the
+/// `file_provider`. Each broken link is given to the `sink` as soon as it is
+/// found.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn validate_batches<S>(
+    batches: S,
+    cfg: &Config,
+    src_dir: &Path,
+    cache: &mut Cache,
+    files: &Files<String>,
+    file_ids: &[FileId],
+    chapters: &[OsString],
+    drafts: &Drafts,
+    aliases: &Aliases,
+    checkpoint_file: Option<&Path>,
+    anchor_index: &AnchorIndex,
+    client: Option<&dyn HttpClient>,
+    file_provider: &dyn FileProvider,
+    sink: Option<&dyn DiagnosticSink>,
+) -> Result<(ValidationOutcome, Vec<Link>), Error>
+where
+    S: Stream<Item = (Vec<Link>, Vec<IncompleteLink>)>,
+{
+    let default_client = cfg.client();
+    let dns_cache = DnsCache::new(cfg, default_client.clone());
+    let dns_cache = Some(&dns_cache).filter(|dns| dns.is_enabled());
+    let (http, dns_cache): (&dyn HttpClient, _) = match (client, dns_cache) {
+        (Some(client), _) => (client, None),
+        (None, Some(dns_cache)) => (dns_cache, Some(dns_cache)),
+        (None, None) => (&default_client, None),
+    };
+    let counting = Counting::new(http);
+    let http: &dyn HttpClient = &counting;
+
+    // the deadline covers the whole run, not just checking web links
+    let deadline = cfg.max_runtime.map(|secs| {
+        tokio::time::Instant::now() + Duration::from_secs(secs)
+    });
+    let mut cut_short = Vec::new();
+
+    let found = Mutex::new(Found::default());
+    let batches = batches.map(|(links, incomplete_links)| {
+        let mut found = found.lock().unwrap();
+        let rewritten = apply_rewrites(&links, cfg, &mut found.rewritten_links);
+        found.links.extend(links);
+        found.incomplete_links.extend(incomplete_links);
+        rewritten
+    });
+    let (mut got, stats, missing_translations) = lc_validate(
+        batches,
+        cfg,
+        src_dir,
+        cache,
+        files,
+        file_ids,
+        chapters,
+        drafts,
+        aliases,
+        checkpoint_file,
+        anchor_index,
+        http,
+        dns_cache,
+        file_provider,
+        sink,
+        deadline,
+    )
+    .await;
+    let Found {
+        links,
+        incomplete_links,
+        rewritten_links,
+    } = found.into_inner().unwrap();
+    let file_links = if cfg.file_links.and_then(|p| p.to_severity()).is_some()
+    {
+        find_file_links(&links, src_dir, files, file_provider)
+    } else {
+        Vec::new()
+    };
+    let special_file_links = if cfg.special_files.to_severity().is_some() {
+        find_special_file_links(&got.valid, files, chapters)
+    } else {
+        Vec::new()
+    };
+    let empty_file_links = if cfg.empty_files.to_severity().is_some() {
+        let local =
+            LocalFiles::new(cfg, src_dir, chapters.to_vec(), file_provider)
+                .with_aliases(aliases);
+        find_empty_file_links(&got.valid, &local, src_dir, files)
+    } else {
+        Vec::new()
+    };
+    let unpublished_links = if cfg.unpublished_files.to_severity().is_some()
+        && cfg.may_leave_source_directory()
+    {
+        let local =
+            LocalFiles::new(cfg, src_dir, chapters.to_vec(), file_provider)
+                .with_aliases(aliases);
+        find_unpublished_links(&got.valid, cfg, &local, src_dir, files)
+    } else {
+        Vec::new()
+    };
+    let crate_problems = if cfg.check_crates {
+        let check = crate::crates::check_crates(&mut got, cfg, http)
+            .instrument(tracing::debug_span!("check_crates"));
+        before_deadline("crates", deadline, &mut cut_short, check).await
+    } else {
+        Vec::new()
+    };
+    let wrong_content_types = if cfg.web.image_content_type.is_some() {
+        let check = crate::images::wrong_content_types(
+            &got.valid,
+            &stats.content_types,
+            cfg,
+            files,
+            file_ids,
+            cache,
+            http,
+        )
+        .instrument(tracing::debug_span!("check_image_content_types"));
+        before_deadline("image content types", deadline, &mut cut_short, check)
+            .await
+    } else {
+        Vec::new()
+    };
+    let check = crate::assets::asset_problems(
+        &got.valid,
+        cfg,
+        src_dir,
+        files,
+        file_provider,
+        http,
+    )
+    .instrument(tracing::debug_span!("check_assets"));
+    let asset_problems =
+        before_deadline("assets", deadline, &mut cut_short, check).await;
+    let cross_domain_redirects = if cfg.web.cross_domain_redirects.is_some() {
+        crate::redirects::cross_domain_redirects(
+            &got.valid,
+            &stats.final_destinations,
+        )
+    } else {
+        Vec::new()
+    };
+    let outdated_tls = if cfg.web.outdated_tls.is_some() {
+        let check = crate::tls::outdated_tls(&got.valid, cfg, http)
+            .instrument(tracing::debug_span!("check_outdated_tls"));
+        before_deadline("outdated TLS", deadline, &mut cut_short, check).await
+    } else {
+        Vec::new()
+    };
+    let check = crate::tls::expiring_certificates(&got.valid, cfg, http)
+        .instrument(tracing::debug_span!("check_certificate_expiry"));
+    let expiring_certificates =
+        before_deadline("certificate expiry", deadline, &mut cut_short, check)
+            .await;
+
+    let outcome = ValidationOutcome {
+        rewritten_links,
+        file_links,
+        unpublished_links,
+        special_file_links,
+        empty_file_links,
+        wrong_content_types,
+        cross_domain_redirects,
+        outdated_tls,
+        expiring_certificates,
+        asset_problems,
+        crate_problems,
+        request_timings: stats.timings,
+        cache_hits: stats.cache_hits,
+        bytes_fetched: counting.bytes(),
+        unchecked: stats.unchecked,
+        cut_short,
+        rate_limited: stats.rate_limited,
+        permanent_redirects: stats.permanent_redirects,
+        missing_translations,
+        ..merge_outcomes(got, incomplete_links)
+    };
+
+    Ok((outcome, links))
+}
+
+/// Run one of the checks which happen after every link has been checked,
+/// giving up when the [`Config::max_runtime`] deadline is reached and
+/// remembering its `name` so the report can say it didn't finish.
+async fn before_deadline<F>(
+    name: &str,
+    deadline: Option<tokio::time::Instant>,
+    cut_short: &mut Vec<String>,
+    check: F,
+) -> F::Output
+where
+    F: std::future::Future,
+    F::Output: Default,
+{
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return check.await,
+    };
+
+    match tokio::time::timeout_at(deadline, check).await {
+        Ok(got) => got,
+        Err(_) => {
+            tracing::warn!("The deadline was exceeded while checking {}", name);
+            cut_short.push(name.to_string());
+            F::Output::default()
+        },
+    }
+}
+
+/// Everything found while the book was being scanned (see
+/// [`validate_batches()`]).
+#[derive(Debug, Default)]
+struct Found {
+    links: Vec<Link>,
+    incomplete_links: Vec<IncompleteLink>,
+    rewritten_links: Vec<RewrittenLink>,
+}
+
+/// Apply [`Config::rewrites`] to every link, keeping track of which ones were
+/// changed.
+fn apply_rewrites(
+    links: &[Link],
+    cfg: &Config,
+    rewritten_links: &mut Vec<RewrittenLink>,
+) -> Vec<Link> {
+    links
+        .iter()
+        .map(|link| match cfg.rewrite(&link.href) {
+            Some(rewritten) => {
+                tracing::debug!(
+                    "Rewrote \"{}\" to \"{}\"",
+                    link.href,
+                    rewritten
+                );
+                rewritten_links.push(RewrittenLink {
+                    original: link.clone(),
+                    rewritten: rewritten.clone(),
+                });
+                Link::new(rewritten, link.span, link.file)
+            },
+            None => link.clone(),
+        })
+        .collect()
+}
+
+/// Is this a file mdbook reads while building the book, but never renders?
+fn is_special_file(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    name == "SUMMARY.md" || name == "book.toml" || name.ends_with(".hbs")
+}
+
+/// Find the links to files mdbook never renders (see
+/// [`Config::special_files`]), suggesting the first chapter instead of
+/// `SUMMARY.md`.
+fn find_special_file_links(
+    valid: &[Link],
+    files: &Files<String>,
+    chapters: &[OsString],
+) -> Vec<SpecialFileLink> {
+    let first_chapter = chapters.first();
+
+    valid
+        .iter()
+        .filter(|link| normalize::is_local(&link.href))
+        .filter_map(|link| {
+            let path = normalize::local_path(&link.href);
+            if !is_special_file(&path) {
+                return None;
+            }
+
+            let suggestion = first_chapter
+                .filter(|_| path.ends_with("SUMMARY.md"))
+                .and_then(|chapter| {
+                    relative_path_to_file(
+                        files.name(link.file),
+                        Path::new("/").join(chapter),
+                    )
+                });
+
+            Some(SpecialFileLink {
+                link: link.clone(),
+                suggestion,
+            })
+        })
+        .collect()
+}
+
+/// Find the links from a chapter to a file outside the source directory,
+/// which mdbook won't copy into the rendered book (see
+/// [`Config::unpublished_files`]).
+fn find_unpublished_links(
+    valid: &[Link],
+    cfg: &Config,
+    local: &LocalFiles<'_>,
+    src_dir: &Path,
+    files: &Files<String>,
+) -> Vec<Link> {
+    let workspace = Workspace::load(cfg, src_dir);
+    let src_dir = crate::file_provider::normalize(src_dir);
+
+    valid
+        .iter()
+        .filter(|link| normalize::is_local(&link.href))
+        // these get their own diagnostic
+        .filter(|link| !is_special_file(&normalize::local_path(&link.href)))
+        .filter(|link| {
+            let chapter = src_dir.join(files.name(link.file));
+            let current_dir =
+                crate::file_provider::normalize(chapter.parent().unwrap());
+            // links in Config::extra_files aren't published either
+            if !current_dir.starts_with(&src_dir) {
+                return false;
+            }
+
+            let path = normalize::local_path(&link.href);
+            match local.resolve(&current_dir, &path) {
+                Ok(target) => {
+                    !target.starts_with(&local.root)
+                        && !workspace.contains(&target)
+                },
+                Err(_) => false,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// Find the local links to an empty file (see [`Config::empty_files`]).
+fn find_empty_file_links(
+    valid: &[Link],
+    local: &LocalFiles<'_>,
+    src_dir: &Path,
+    files: &Files<String>,
+) -> Vec<Link> {
+    valid
+        .iter()
+        .filter(|link| normalize::is_local(&link.href))
+        .filter(|link| {
+            let chapter = src_dir.join(files.name(link.file));
+            let current_dir = match chapter.parent() {
+                Some(parent) => crate::file_provider::normalize(parent),
+                None => return false,
+            };
+            let path = normalize::local_path(&link.href);
+
+            match local.resolve(&current_dir, &path) {
+                Ok(target) => {
+                    !local.files.is_dir(&target)
+                        && local.files.file_size(&target).ok() == Some(0)
+                },
+                Err(_) => false,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// Find all the `file://` links, suggesting a relative link instead when they
+/// point to something inside the book.
+fn find_file_links(
+    links: &[Link],
+    src_dir: &Path,
+    files: &Files<String>,
+    file_provider: &dyn FileProvider,
+) -> Vec<FileLink> {
+    let src_dir = file_provider
+        .canonicalize(src_dir)
+        .unwrap_or_else(|_| src_dir.to_path_buf());
+
+    links
+        .iter()
+        .filter_map(|link| {
+            let url = Url::parse(&link.href).ok()?;
+            if url.scheme() != "file" {
+                return None;
+            }
+
+            let suggestion = url
+                .to_file_path()
+                .ok()
+                .and_then(|path| file_provider.canonicalize(&path).ok())
+                .and_then(|path| {
+                    let inside_book = path.strip_prefix(&src_dir).ok()?;
+                    relative_path_to_file(
+                        files.name(link.file),
+                        Path::new("/").join(inside_book),
+                    )
+                })
+                .map(|relative| match url.fragment() {
+                    Some(fragment) => format!("{}#{}", relative, fragment),
+                    None => relative,
+                });
+
+            Some(FileLink {
+                link: link.clone(),
+                suggestion,
+            })
+        })
+        .collect()
+}
+
+/// The outcome of validating a set of links.
+///
+/// This can be serialized (e.g. to JSON), with each broken link's
+/// [`crate::FailureReason`] alongside its error message.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ValidationOutcome {
+    /// Valid links.
+    pub valid_links: Vec<Link>,
+    /// Links where validation failed.
+    #[serde(serialize_with = "crate::outcome::serialize_invalid_links")]
+    pub invalid_links: Vec<InvalidLink>,
+    /// Links which have been ignored (e.g. due to
+    /// [`Config::follow_web_links`]).
+    pub ignored: Vec<Link>,
+    /// Links which we don't know how to handle.
+    pub unknown_category: Vec<Link>,
+    /// Potentially incomplete links.
+    pub incomplete_links: Vec<IncompleteLink>,
+    /// Links which were changed by [`Config::rewrites`] before being checked.
+    pub rewritten_links: Vec<RewrittenLink>,
+    /// How long each HTTP request took. Links which were answered from the
+    /// cache aren't included.
+    pub request_timings: Vec<RequestTiming>,
+    /// The number of web links which were answered from the cache.
+    pub cache_hits: usize,
+    /// The number of bytes downloaded in response bodies.
+    pub bytes_fetched: u64,
+    /// Links which weren't checked because [`Config::max_runtime`] was
+    /// exceeded.
+    pub unchecked: Vec<Link>,
+    /// The checks (e.g. `"assets"`) which were cancelled because
+    /// [`Config::max_runtime`] was exceeded before they finished.
+    pub cut_short: Vec<String>,
+    /// How many times each host told us to slow down (with a `429 Too Many
+    /// Requests` or `503 Service Unavailable` response).
+    pub rate_limited: BTreeMap<String, usize>,
+    /// Web links (after being normalized) which permanently redirect
+    /// somewhere else, mapped to where they end up. Only redirects which
+    /// were followed while checking links are included, so links answered
+    /// from the cache are left out.
+    pub permanent_redirects: BTreeMap<String, String>,
+    /// `{{#include}}` directives with missing or unbalanced `ANCHOR` markers.
+    pub broken_includes: Vec<BrokenInclude>,
+    /// Links to a chapter's counterpart in another one of the
+    /// [`Config::translations`] which doesn't exist.
+    pub missing_translations: Vec<Link>,
+    /// Markdown files (relative to the source directory) which aren't in
+    /// `SUMMARY.md` and aren't linked to from any chapter. These are only
+    /// looked for when [`Config::orphan_chapters`] is set.
+    pub orphans: Vec<PathBuf>,
+    /// `file://` links, which only work on the author's machine.
+    pub file_links: Vec<FileLink>,
+    /// Links to files mdbook never renders, like `SUMMARY.md` and
+    /// `book.toml`.
+    pub special_file_links: Vec<SpecialFileLink>,
+    /// Links to empty files (see [`Config::empty_files`]).
+    pub empty_file_links: Vec<Link>,
+    /// Links to files outside the source directory, which won't be in the
+    /// rendered book. These are only looked for when links are allowed to
+    /// leave the source directory.
+    pub unpublished_links: Vec<Link>,
+    /// Problems with the HTML embedded in each chapter. These are only looked
+    /// for when [`Config::unsafe_target_blank`] or [`Config::mixed_content`]
+    /// is set.
+    pub html_problems: Vec<HtmlProblem>,
+    /// Markdown images without any alt text. These are only looked for when
+    /// [`Config::missing_alt_text`] is set.
+    pub missing_alt_text: Vec<Link>,
+    /// Remote images which weren't served with an `image/*` content type.
+    /// These are only looked for when
+    /// [`crate::WebConfig::image_content_type`] is set.
+    pub wrong_content_types: Vec<WrongContentType>,
+    /// Web links which redirect to an unrelated domain. These are only
+    /// looked for when [`crate::WebConfig::cross_domain_redirects`] is set.
+    pub cross_domain_redirects: Vec<CrossDomainRedirect>,
+    /// HTTPS links to servers which only support TLS versions older than
+    /// 1.2. These are only looked for when [`crate::WebConfig::outdated_tls`]
+    /// is set.
+    pub outdated_tls: Vec<OutdatedTls>,
+    /// HTTPS links to servers whose certificate expires soon. These are only
+    /// looked for when [`crate::WebConfig::certificate_expiry`] is set.
+    pub expiring_certificates: Vec<ExpiringCertificate>,
+    /// Links to downloads which are too big or served as a HTML page. These
+    /// are only looked for when [`Config::max_asset_size`] or
+    /// [`crate::WebConfig::asset_content_type`] is set.
+    pub asset_problems: Vec<AssetProblem>,
+    /// Links to `docs.rs` or `crates.io` pinning a yanked or outdated version
+    /// of a crate. These are only looked for when [`Config::check_crates`] is
+    /// set.
+    pub crate_problems: Vec<CrateProblem>,
+}
+
+/// How long it took to check a web link.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestTiming {
+    /// The link which was checked.
+    pub link: Link,
+    /// How long it took to get a response.
+    pub duration: Duration,
+}
+
+/// A link which was changed by one of the [`Config::rewrites`] rules.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RewrittenLink {
+    /// The link, as it was written in the source.
+    pub original: Link,
+    /// What the link was rewritten to.
+    pub rewritten: String,
+}
+
+/// A `file://` link (see [`Config::file_links`]).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileLink {
+    /// The link.
+    pub link: Link,
+    /// A relative link to use instead, if the file is inside the book.
+    pub suggestion: Option<String>,
+}
+
+/// A link to a file mdbook never renders (see [`Config::special_files`]).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpecialFileLink {
+    /// The link.
+    pub link: Link,
+    /// A chapter to link to instead, if there is an obvious one.
+    pub suggestion: Option<String>,
+}
+
+impl ValidationOutcome {
+    /// Generate a list of [`Diagnostic`] messages from this
+    /// [`ValidationOutcome`].
+    pub fn generate_diagnostics(
+        &self,
+        files: &Files<String>,
+        cfg: &Config,
+    ) -> Vec<Diagnostic<FileId>> {
+        let warning_policy = cfg.warning_policy;
+        let mut diags = Vec::new();
+
+        self.add_invalid_link_diagnostics(cfg, &mut diags, files);
+        self.add_incomplete_link_diagnostics(warning_policy, &mut diags);
+        self.warn_on_absolute_links(warning_policy, &mut diags, files);
+        self.add_forbidden_http_diagnostics(cfg, &mut diags);
+        self.add_private_network_diagnostics(cfg, &mut diags);
+        self.add_denied_domain_diagnostics(cfg, &mut diags);
+        self.add_homograph_diagnostics(cfg, &mut diags);
+        self.add_oversized_data_uri_diagnostics(cfg, &mut diags);
+        self.add_javascript_link_diagnostics(cfg, &mut diags);
+        self.add_file_link_diagnostics(cfg, &mut diags);
+        self.add_unknown_scheme_diagnostics(cfg, &mut diags);
+        self.add_unpublished_link_diagnostics(cfg, &mut diags);
+        self.add_special_file_diagnostics(cfg, &mut diags);
+        self.add_empty_file_diagnostics(cfg, &mut diags);
+        self.add_rewritten_link_diagnostics(cfg, &mut diags);
+        self.add_broken_include_diagnostics(cfg, &mut diags);
+        self.add_html_diagnostics(cfg, &mut diags);
+        self.add_missing_alt_text_diagnostics(cfg, &mut diags);
+        self.add_self_referential_link_diagnostics(cfg, &mut diags, files);
+        self.add_wrong_content_type_diagnostics(cfg, &mut diags);
+        self.add_cross_domain_redirect_diagnostics(cfg, &mut diags);
+        self.add_outdated_tls_diagnostics(cfg, &mut diags);
+        self.add_expiring_certificate_diagnostics(&mut diags);
+        self.add_asset_diagnostics(cfg, &mut diags);
+        self.add_crate_problem_diagnostics(cfg, &mut diags);
+
+        sort_diagnostics(&mut diags, files);
+        // this is a summary for the whole run, so it goes at the end
+        self.add_unchecked_link_diagnostics(cfg, &mut diags);
+        self.add_missing_translation_diagnostics(&mut diags, files);
+        self.add_orphan_diagnostics(cfg, &mut diags);
+        diags
+    }
+
+    fn add_incomplete_link_diagnostics(
+        &self,
+        warning_policy: WarningPolicy,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match warning_policy {
+            WarningPolicy::Error => Severity::Error,
+            WarningPolicy::Warn => Severity::Warning,
+            WarningPolicy::Ignore => return,
+        };
+
+        for incomplete in &self.incomplete_links {
+            let IncompleteLink {
+                ref reference,
+                file,
+                span,
+            } = incomplete;
+
+            let msg =
+                format!("Did you forget to define a URL for `{0}`?", reference);
+            let label = Label::primary(*file, *span).with_message(msg);
+            let note = format!(
+                "hint: declare the link's URL. For example: `[{}]: http://example.com/`",
+                reference
+            );
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::IncompleteLink.code())
+                .with_message("Potential incomplete link")
+                .with_labels(vec![label])
+                .with_notes(vec![note]);
+            diags.push(diag)
+        }
+    }
+
+    fn add_broken_include_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for broken in &self.broken_includes {
+            let (severity, note) = match broken.problem {
+                IncludeProblem::MissingAnchor => {
+                    let severity =
+                        match cfg.missing_include_anchors.to_severity() {
+                            Some(severity) => severity,
+                            None => continue,
+                        };
+                    let note = format!(
+                        "hint: add \"ANCHOR: {0}\" and \"ANCHOR_END: {0}\" comments around the code to include",
+                        broken.anchor
+                    );
+                    (severity, note)
+                },
+                IncludeProblem::UnterminatedAnchor => {
+                    let severity = match cfg.warning_policy.to_severity() {
+                        Some(severity) => severity,
+                        None => continue,
+                    };
+                    let note = format!(
+                        "hint: everything up to the end of the file will be included unless there is an \"ANCHOR_END: {}\" comment",
+                        broken.anchor
+                    );
+                    (severity, note)
+                },
+            };
+
+            let msg = broken.to_string();
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::BrokenInclude.code())
+                .with_message(msg.clone())
+                .with_labels(vec![
+                    Label::primary(broken.file, broken.span).with_message(msg)
+                ])
+                .with_notes(vec![note]);
+            diags.push(diag);
+        }
+    }
+
+    fn add_missing_alt_text_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity =
+            match cfg.missing_alt_text.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => return,
+            };
+
+        for link in &self.missing_alt_text {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::MissingAltText.code())
+                .with_message(format!(
+                    "The image \"{}\" doesn't have any alt text",
+                    link.href
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Screen readers can't describe this image")])
+                .with_notes(vec![format!(
+                    "hint: describe the image between the square brackets, or \
+                     add `<!-- linkcheck-allow: {} -->` to the chapter if it is \
+                     purely decorative",
+                    crate::alt_text::LINT
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_html_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for problem in &self.html_problems {
+            let (policy, note) = match problem.kind {
+                HtmlProblemKind::UnsafeTargetBlank => (
+                    cfg.unsafe_target_blank,
+                    "hint: add rel=\"noopener noreferrer\" so the new page can't access this one",
+                ),
+                HtmlProblemKind::MixedContent { .. } => (
+                    cfg.mixed_content,
+                    "hint: load it over HTTPS instead",
+                ),
+            };
+            let severity = match policy.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => continue,
+            };
+
+            let msg = problem.to_string();
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::Html.code())
+                .with_message(msg.clone())
+                .with_labels(vec![Label::primary(problem.file, problem.span)
+                    .with_message(msg)])
+                .with_notes(vec![String::from(note)]);
+            diags.push(diag);
+        }
+    }
+
+    fn add_invalid_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+        files: &Files<String>,
+    ) {
+        diags.extend(self.invalid_links.iter().filter_map(|broken_link| {
+            broken_link_diagnostic(broken_link, cfg, files)
+        }));
+    }
+
+    /// Every link we found, regardless of whether it was valid or not.
+    fn all_links(&self) -> impl Iterator<Item = &Link> + '_ {
+        self.valid_links
+            .iter()
+            .chain(self.invalid_links.iter().map(|invalid| &invalid.link))
+            .chain(&self.ignored)
+            .chain(&self.unknown_category)
+    }
+
+    fn add_forbidden_http_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.forbid_http.and_then(|p| p.to_severity()) {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in self.all_links() {
+            if !cfg.is_forbidden_http(&link.href) {
+                continue;
+            }
+
+            let suggestion = format!(
+                "Suggestion: change the link to \"https{}\"",
+                &link.href[4..]
+            );
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::ForbiddenHttp.code())
+                .with_message("Plain HTTP links are forbidden")
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This link should use HTTPS")])
+                .with_notes(vec![suggestion]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_private_network_diagnostics(
         &self,
+        cfg: &Config,
         diags: &mut Vec<Diagnostic<FileId>>,
     ) {
-        for broken_link in &self.invalid_links {
-            let link = &broken_link.link;
-            let msg = most_specific_error_message(&broken_link);
-            let diag = Diagnostic::error()
-                .with_message(msg.clone())
-                .with_labels(vec![
-                    Label::primary(link.file, link.span).with_message(msg)
-                ]);
+        let severity =
+            match cfg.private_network_links.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => return,
+            };
+
+        for link in self.all_links() {
+            if !cfg.is_private_network_link(&link.href) {
+                continue;
+            }
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::PrivateNetwork.code())
+                .with_message("Link to a local or private network address")
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This won't work for readers")])
+                .with_notes(vec![String::from(
+                    "hint: add it to `private-network-links-exclude` if this is an intentional example",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_homograph_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity =
+            match cfg.homograph_domains.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => return,
+            };
+
+        for link in self.all_links() {
+            let homograph = match crate::idn::homograph(&link.href) {
+                Some(homograph) => homograph,
+                None => continue,
+            };
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::Homograph.code())
+                .with_message(homograph.to_string())
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This may be a typo or a spoofed domain")])
+                .with_notes(vec![format!(
+                    "The domain is actually \"{}\"",
+                    homograph.ascii
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_denied_domain_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.denied_domain_policy.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in self.all_links() {
+            let domain = match cfg.denied_domain(&link.href) {
+                Some(domain) => domain,
+                None => continue,
+            };
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::DeniedDomain.code())
+                .with_message(format!("Links to \"{}\" aren't allowed", domain))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This domain is in `deny-domains`")]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_javascript_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.javascript_links.and_then(|p| p.to_severity())
+        {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in self.all_links().filter(|l| is_javascript_link(&l.href)) {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::JavascriptLinks.code())
+                .with_message("Links shouldn't run JavaScript")
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This is a javascript: link")])
+                .with_notes(vec![String::from(
+                    "hint: link to a page instead, or remove `javascript-links` if this is intentional",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_file_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.file_links.and_then(|p| p.to_severity()) {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for FileLink { link, suggestion } in &self.file_links {
+            let notes = suggestion
+                .iter()
+                .map(|suggestion| {
+                    format!("Suggestion: change the link to \"{}\"", suggestion)
+                })
+                .collect();
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::FileLinks.code())
+                .with_message("file:// links only work on the author's machine")
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This is a file:// link")])
+                .with_notes(notes);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_unknown_scheme_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.unknown_scheme.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in self.all_links() {
+            let scheme = match cfg.unrecognised_scheme(&link.href) {
+                Some(scheme) => scheme,
+                None => continue,
+            };
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::UnknownScheme.code())
+                .with_message(format!(
+                    "Unable to check links using the \"{}\" scheme",
+                    scheme
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message(format!("This is a {}: link", scheme))])
+                .with_notes(vec![format!(
+                    "hint: add \"{}\" to `skip-schemes` if this is intentional",
+                    scheme
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_unpublished_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.unpublished_files.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in &self.unpublished_links {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::UnpublishedFile.code())
+                .with_message(format!(
+                    "\"{}\" won't be in the rendered book",
+                    link.href
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This file is outside the source directory")])
+                .with_notes(vec![String::from(
+                    "hint: move the file into the source directory or link to where it is published",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_special_file_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.special_files.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for SpecialFileLink { link, suggestion } in &self.special_file_links {
+            let note = match suggestion {
+                Some(suggestion) => {
+                    format!("Suggestion: change the link to \"{}\"", suggestion)
+                },
+                None => String::from("hint: link to a chapter instead"),
+            };
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::SpecialFile.code())
+                .with_message("mdbook doesn't render this file into the book")
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This will be broken in the published book")])
+                .with_notes(vec![note]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_empty_file_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.empty_files.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in &self.empty_file_links {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::EmptyFile.code())
+                .with_message(format!("\"{}\" is empty", link.href))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This links to a blank page")])
+                .with_notes(vec![String::from(
+                    "hint: set `empty-files = \"ignore\"` if the file is a \
+                     placeholder",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_oversized_data_uri_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let max_size = match cfg.max_data_uri_size {
+            Some(max_size) => max_size,
+            None => return,
+        };
+        let severity = match cfg.warning_policy.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for link in self.all_links() {
+            if !is_data_uri(&link.href) || link.href.len() <= max_size {
+                continue;
+            }
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::OversizedDataUri.code())
+                .with_message(format!(
+                    "This data: URI is {} bytes, more than the {} byte limit",
+                    link.href.len(),
+                    max_size
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Large data: URI")])
+                .with_notes(vec![String::from(
+                    "hint: save the contents to a file and link to that instead",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_self_referential_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+        files: &Files<String>,
+    ) {
+        let severity =
+            match cfg.self_referential_links.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => return,
+            };
+
+        for link in self.all_links() {
+            let path = match cfg.self_referential_path(&link.href) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let mut notes = Vec::new();
+            if let Some(suggested_change) = relative_link_to_chapter(
+                Path::new(files.name(link.file)),
+                &path,
+            ) {
+                notes.push(format!(
+                    "Suggestion: change the link to \"{}\"",
+                    suggested_change
+                ));
+            }
+
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::SelfReferentialLink.code())
+                .with_message("Link to the book's own site should be relative")
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message(
+                        "This won't work in previews or when viewed offline",
+                    )])
+                .with_notes(notes);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_wrong_content_type_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity =
+            match cfg.web.image_content_type.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => return,
+            };
+
+        for WrongContentType { link, content_type } in &self.wrong_content_types
+        {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::WrongContentType.code())
+                .with_message(format!(
+                    "Expected an image but \"{}\" is \"{}\"",
+                    link.href, content_type
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This will be shown as a broken image")]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_asset_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for AssetProblem { link, kind } in &self.asset_problems {
+            let diag = match kind {
+                AssetProblemKind::TooLarge { size } => {
+                    let severity = match cfg.warning_policy.to_severity() {
+                        Some(severity) => severity,
+                        None => continue,
+                    };
+                    let max_size = cfg.max_asset_size.unwrap_or_default();
+                    Diagnostic::new(severity)
+                        .with_code(DiagnosticCategory::Asset.code())
+                        .with_message(format!(
+                            "\"{}\" is {}, more than the {} limit",
+                            link.href,
+                            human_size(*size),
+                            human_size(max_size)
+                        ))
+                        .with_labels(vec![Label::primary(link.file, link.span)
+                            .with_message("Large download")])
+                },
+                AssetProblemKind::WrongContentType { content_type } => {
+                    let severity = match cfg
+                        .web
+                        .asset_content_type
+                        .and_then(|p| p.to_severity())
+                    {
+                        Some(severity) => severity,
+                        None => continue,
+                    };
+                    Diagnostic::new(severity)
+                        .with_code(DiagnosticCategory::Asset.code())
+                        .with_message(format!(
+                            "Expected a download but \"{}\" is \"{}\"",
+                            link.href, content_type
+                        ))
+                        .with_labels(vec![Label::primary(link.file, link.span)
+                            .with_message("This is probably an error page")])
+                },
+            };
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_cross_domain_redirect_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg
+            .web
+            .cross_domain_redirects
+            .and_then(|p| p.to_severity())
+        {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for redirect in &self.cross_domain_redirects {
+            let CrossDomainRedirect { link, from, to } = redirect;
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::CrossDomainRedirect.code())
+                .with_message(format!(
+                    "\"{}\" redirects from {} to {}",
+                    link.href, from, to
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Redirects to a different domain")])
+                .with_notes(vec![String::from(
+                    "The page may have moved, or the domain may have changed \
+                     hands",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_outdated_tls_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity =
+            match cfg.web.outdated_tls.and_then(|p| p.to_severity()) {
+                Some(severity) => severity,
+                None => return,
+            };
+
+        for OutdatedTls { link, host } in &self.outdated_tls {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::OutdatedTls.code())
+                .with_message(format!(
+                    "{} only supports TLS versions older than 1.2",
+                    host
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This server uses outdated TLS")])
+                .with_notes(vec![String::from(
+                    "Most browsers refuse to connect to servers which don't \
+                     support TLS 1.2 or newer",
+                )]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_expiring_certificate_diagnostics(
+        &self,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for expiring in &self.expiring_certificates {
+            let ExpiringCertificate {
+                link,
+                other_links,
+                host,
+                days_left,
+            } = expiring;
+            let when = match days_left {
+                0 => String::from("in less than a day"),
+                1 => String::from("tomorrow"),
+                n => format!("in {} days", n),
+            };
+            let mut notes = vec![String::from(
+                "Let the site's owners know, before readers start seeing \
+                 security errors",
+            )];
+            if *other_links > 0 {
+                notes.push(format!(
+                    "There are {} other links to {}",
+                    other_links, host
+                ));
+            }
+
+            let diag = Diagnostic::warning()
+                .with_code(DiagnosticCategory::CertificateExpiry.code())
+                .with_message(format!(
+                    "The certificate for {} expires {}",
+                    host, when
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("This server's certificate expires soon")])
+                .with_notes(notes);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_crate_problem_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.outdated_crates.to_severity() {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for problem in &self.crate_problems {
+            let label = match problem.kind {
+                CrateProblemKind::Yanked => "This version was yanked",
+                CrateProblemKind::Outdated { .. } => {
+                    "There is a newer, incompatible version"
+                },
+            };
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::Crate.code())
+                .with_message(problem.to_string())
+                .with_labels(vec![Label::primary(
+                    problem.link.file,
+                    problem.link.span,
+                )
+                .with_message(label)]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_rewritten_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.report_rewrites.and_then(|p| p.to_severity()) {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for RewrittenLink {
+            original,
+            rewritten,
+        } in &self.rewritten_links
+        {
+            let suggestion =
+                format!("Suggestion: change the link to \"{}\"", rewritten);
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::RewrittenLink.code())
+                .with_message("This link was rewritten before being checked")
+                .with_labels(vec![Label::primary(original.file, original.span)
+                    .with_message(format!("Rewritten to \"{}\"", rewritten))])
+                .with_notes(vec![suggestion]);
+
+            diags.push(diag);
+        }
+    }
+
+    fn add_unchecked_link_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        if self.unchecked.is_empty() && self.cut_short.is_empty() {
+            return;
+        }
+
+        let message = if self.unchecked.is_empty() {
+            String::from("Some checks didn't finish (deadline)")
+        } else {
+            format!("{} links not checked (deadline)", self.unchecked.len())
+        };
+        let mut notes = vec![format!(
+            "The {} second `max-runtime` was exceeded, so any outstanding \
+             checks were cancelled",
+            cfg.max_runtime.unwrap_or_default()
+        )];
+        if !self.cut_short.is_empty() {
+            notes.push(format!(
+                "These checks didn't finish: {}",
+                self.cut_short.join(", ")
+            ));
+        }
+        diags.push(
+            Diagnostic::warning()
+                .with_code(DiagnosticCategory::UncheckedLinks.code())
+                .with_message(message)
+                .with_notes(notes),
+        );
+    }
+
+    fn add_orphan_diagnostics(
+        &self,
+        cfg: &Config,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        let severity = match cfg.orphan_chapters.and_then(|p| p.to_severity()) {
+            Some(severity) => severity,
+            None => return,
+        };
+
+        for orphan in &self.orphans {
+            let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::Orphan.code())
+                .with_message(format!(
+                    "\"{}\" isn't in SUMMARY.md or linked to from any chapter",
+                    orphan.display()
+                ))
+                .with_notes(vec![String::from(
+                    "hint: link to it from another chapter, add it to SUMMARY.md, or delete it",
+                )]);
             diags.push(diag);
         }
     }
 
+    fn add_missing_translation_diagnostics(
+        &self,
+        diags: &mut Vec<Diagnostic<FileId>>,
+        files: &Files<String>,
+    ) {
+        if self.missing_translations.is_empty() {
+            return;
+        }
+
+        let chapters: HashSet<FileId> = self
+            .missing_translations
+            .iter()
+            .map(|link| link.file)
+            .collect();
+
+        let notes = self
+            .missing_translations
+            .iter()
+            .map(|link| {
+                format!(
+                    "{} -> {}",
+                    files.name(link.file).to_string_lossy(),
+                    link.href
+                )
+            })
+            .collect();
+
+        diags.push(
+            Diagnostic::note()
+                .with_code(DiagnosticCategory::MissingTranslation.code())
+                .with_message(format!(
+                    "{} chapters link to translations which don't exist",
+                    chapters.len()
+                ))
+                .with_notes(notes),
+        );
+    }
+
     /// As shown in https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues/33
     /// absolute links are actually a bit of a foot gun when the document is
     /// being read directly from the filesystem.
@@ -300,7 +2954,7 @@ impl ValidationOutcome {
         diags: &mut Vec<Diagnostic<FileId>>,
         files: &Files<String>,
     ) {
-        const WARNING_MESSAGE: &'static str = r#"When viewing a document directly from the file system and click on an
+        const WARNING_MESSAGE: &str = r#"When viewing a document directly from the file system and click on an
 absolute link (e.g. `/index.md`), the browser will try to navigate to
 `/index.md` on the current file system (i.e. the `index.md` file inside
 `/` or `C:\`) instead of the `index.md` file at book's base directory as
@@ -349,6 +3003,7 @@ For more details, see https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues
             }
 
             let diag = Diagnostic::new(severity)
+                .with_code(DiagnosticCategory::AbsoluteLink.code())
                 .with_message("Absolute link should be made relative")
                 .with_notes(notes)
                 .with_labels(vec![Label::primary(link.file, link.span)
@@ -359,15 +3014,63 @@ For more details, see https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues
     }
 }
 
+fn is_javascript_link(link: &str) -> bool {
+    link.trim_start()
+        .get(..11)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("javascript:"))
+}
+
+/// Sort diagnostics by file path, then by where they occur in that file, then
+/// by their message (which usually contains the URL), so the report is always
+/// emitted in the same order.
+fn sort_diagnostics(diags: &mut [Diagnostic<FileId>], files: &Files<String>) {
+    let key = |diag: &Diagnostic<FileId>| {
+        let location = diag
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| {
+                let name = files.name(label.file_id).to_os_string();
+                (name, label.range.start, label.range.end)
+            });
+
+        (location, diag.message.clone())
+    };
+
+    // Note: this is a stable sort, so diagnostics for the same link keep the
+    // order they were generated in
+    diags.sort_by_cached_key(key);
+}
+
+/// Turn a path relative to the book's root (as it would appear in the
+/// rendered site, e.g. `intro/setup.html#linux`) into a relative link to the
+/// corresponding markdown file from `chapter`.
+fn relative_link_to_chapter(chapter: &Path, path: &str) -> Option<String> {
+    let (path, suffix) = match path.find(['?', '#']) {
+        Some(ix) => path.split_at(ix),
+        None => (path, ""),
+    };
+    let markdown = match path.strip_suffix(".html") {
+        Some(stem) => format!("/{}.md", stem),
+        None => format!("/{}", path),
+    };
+
+    relative_path_to_file(chapter, markdown)
+        .map(|relative| format!("{}{}", relative, suffix))
+}
+
 // Path diffing, copied from https://crates.io/crates/pathdiff with some tweaks
-fn relative_path_to_file<S, D>(start: S, destination: D) -> Option<String>
+pub(crate) fn relative_path_to_file<S, D>(
+    start: S,
+    destination: D,
+) -> Option<String>
 where
     S: AsRef<Path>,
     D: AsRef<Path>,
 {
     let destination = destination.as_ref();
     let start = start.as_ref();
-    log::debug!(
+    tracing::debug!(
         "Trying to find the relative path from \"{}\" to \"{}\"",
         start.display(),
         destination.display()
@@ -392,8 +3095,8 @@ where
             },
             (None, _) => comps.push(Component::ParentDir),
             (Some(a), Some(b)) if comps.is_empty() && a == b => (),
-            (Some(a), Some(b)) if b == Component::CurDir => comps.push(a),
-            (Some(_), Some(b)) if b == Component::ParentDir => return None,
+            (Some(a), Some(Component::CurDir)) => comps.push(a),
+            (Some(_), Some(Component::ParentDir)) => return None,
             (Some(a), Some(_)) => {
                 comps.push(Component::ParentDir);
                 for _ in itb {
@@ -416,7 +3119,18 @@ where
     Some(path.display().to_string().replace('\\', "/"))
 }
 
-fn most_specific_error_message(link: &InvalidLink) -> String {
+/// The draft chapter a link was pointing to, if that's why it is broken.
+fn draft_chapter(reason: &Reason) -> Option<&DraftChapter> {
+    match reason {
+        Reason::Io(io) => io.get_ref()?.downcast_ref::<DraftChapter>(),
+        _ => None,
+    }
+}
+
+pub(crate) fn most_specific_error_message(link: &InvalidLink) -> String {
+    if let Some(draft) = draft_chapter(&link.reason) {
+        return draft.to_string();
+    }
     if link.reason.file_not_found() {
         return format!("File not found: {}", link.link.href);
     }
@@ -452,6 +3166,1071 @@ fn most_specific_error_message(link: &InvalidLink) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{file_provider::in_memory_book, HashedRegex};
+    use codespan::Span;
+
+    fn outcome_with_valid_links(
+        hrefs: &[&str],
+    ) -> (Files<String>, ValidationOutcome) {
+        let mut files = Files::new();
+        let file = files.add("chapter_1.md", String::from("# Chapter 1"));
+        let valid_links = hrefs
+            .iter()
+            .map(|href| Link::new(*href, Span::new(0, 1), file))
+            .collect();
+        let outcome = ValidationOutcome {
+            valid_links,
+            ..Default::default()
+        };
+
+        (files, outcome)
+    }
+
+    #[test]
+    fn diagnostics_are_sorted_by_location() {
+        let mut files = Files::new();
+        // files are sorted by path, not by the order they were loaded in
+        let second = files.add("chapter_2.md", String::from("[c]"));
+        let first = files.add("chapter_1.md", String::from("[a] [b](./b.md)"));
+        let outcome = ValidationOutcome {
+            invalid_links: vec![InvalidLink {
+                link: Link::new("./b.md", Span::new(4, 15), first),
+                reason: Reason::TraversesParentDirectories,
+            }],
+            incomplete_links: vec![
+                IncompleteLink {
+                    reference: String::from("c"),
+                    file: second,
+                    span: Span::new(0, 3),
+                },
+                IncompleteLink {
+                    reference: String::from("a"),
+                    file: first,
+                    span: Span::new(0, 3),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+
+        let locations: Vec<_> = diags
+            .iter()
+            .map(|diag| (diag.labels[0].file_id, diag.labels[0].range.start))
+            .collect();
+        assert_eq!(locations, vec![(first, 0), (first, 4), (second, 0)]);
+    }
+
+    #[test]
+    fn chapters_missing_translations_are_only_counted_once() {
+        let mut files = Files::new();
+        let first = files.add("fr/chapter_1.md", String::new());
+        let second = files.add("fr/chapter_2.md", String::new());
+        let outcome = ValidationOutcome {
+            missing_translations: vec![
+                Link::new("../en/a.md", Span::default(), first),
+                Link::new("../en/b.md", Span::default(), second),
+                Link::new("../en/c.md", Span::default(), first),
+            ],
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+
+        assert_eq!(
+            diags[0].message,
+            "2 chapters link to translations which don't exist"
+        );
+        assert_eq!(diags[0].notes.len(), 3);
+    }
+
+    #[test]
+    fn report_plain_http_links_when_forbidden() {
+        let (files, outcome) = outcome_with_valid_links(&[
+            "http://example.com/",
+            "https://example.com/",
+            "http://localhost/",
+        ]);
+        let cfg = Config {
+            forbid_http: Some(WarningPolicy::Error),
+            forbid_http_exclude: vec!["localhost".parse().unwrap()],
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert!(diags[0].notes[0].contains("https://example.com/"));
+    }
+
+    #[test]
+    fn report_links_to_denied_domains() {
+        let (files, outcome) = outcome_with_valid_links(&[
+            "https://bit.ly/abc123",
+            "https://example.com/",
+        ]);
+        let cfg = Config {
+            deny_domains: vec![String::from("bit.ly")],
+            // make sure it doesn't follow the general warning policy
+            warning_policy: WarningPolicy::Ignore,
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert_eq!(diags[0].code.as_deref(), Some("denied-domain"));
+
+        let cfg = Config {
+            denied_domain_policy: WarningPolicy::Warn,
+            ..cfg
+        };
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn report_javascript_links() {
+        let (files, outcome) = outcome_with_valid_links(&[
+            "javascript:alert(1)",
+            " JavaScript:void(0)",
+            "https://example.com/javascript:",
+        ]);
+
+        let cfg = Config {
+            javascript_links: Some(WarningPolicy::Warn),
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(diags.len(), 2);
+        assert!(diags.iter().all(|diag| diag.severity == Severity::Warning));
+        assert_eq!(diags[0].code.as_deref(), Some("javascript-links"));
+
+        // they are allowed by default
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn report_unknown_schemes() {
+        let (files, outcome) = outcome_with_valid_links(&[
+            "ftp://example.com/file.txt",
+            "irc://irc.libera.chat/rust",
+            "https://example.com/",
+        ]);
+        let cfg = Config {
+            skip_schemes: vec![String::from("irc")],
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert_eq!(diags[0].code.as_deref(), Some("unknown-scheme"));
+        assert!(diags[0].message.contains("\"ftp\""), "{}", diags[0].message);
+
+        let cfg = Config {
+            unknown_scheme: WarningPolicy::Error,
+            ..cfg
+        };
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+        assert_eq!(diags[0].severity, Severity::Error);
+        let cfg = Config {
+            unknown_scheme: WarningPolicy::Ignore,
+            ..cfg
+        };
+        assert!(outcome.generate_diagnostics(&files, &cfg).is_empty());
+    }
+
+    #[test]
+    fn report_links_to_special_files() {
+        let mut files = Files::new();
+        let nested = files.add("nested/chapter_2.md", String::new());
+        let chapters =
+            [OsString::from("intro.md"), "nested/chapter_2.md".into()];
+        let valid = vec![
+            Link::new("../SUMMARY.md", Span::new(0, 1), nested),
+            Link::new("../../book.toml#L3", Span::new(1, 2), nested),
+            Link::new("../theme/index.hbs", Span::new(2, 3), nested),
+            Link::new("./chapter_2.md", Span::new(3, 4), nested),
+            Link::new("https://example.com/book.toml", Span::new(4, 5), nested),
+        ];
+
+        let got = find_special_file_links(&valid, &files, &chapters);
+
+        let hrefs: Vec<_> = got.iter().map(|l| l.link.href.as_str()).collect();
+        assert_eq!(
+            hrefs,
+            vec!["../SUMMARY.md", "../../book.toml#L3", "../theme/index.hbs"]
+        );
+        assert_eq!(got[0].suggestion.as_deref(), Some("../intro.md"));
+        assert_eq!(got[1].suggestion, None);
+        assert!(
+            ensure_included_in_book(
+                Path::new("/src"),
+                &[],
+                Path::new("/src/SUMMARY.md")
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn suggest_relative_paths_for_file_links() {
+        let src_dir = Path::new("/book/src");
+        let mut file_provider = crate::InMemoryFiles::new();
+        file_provider.insert(src_dir.join("chapter_1.md"), "# Chapter 1");
+        let chapter_1 = Url::from_file_path(src_dir.join("chapter_1.md"))
+            .unwrap()
+            .to_string();
+        let mut files = Files::new();
+        let id = files.add("nested/README.md", String::new());
+        let links = vec![
+            Link::new(format!("{}#heading", chapter_1), Span::new(0, 1), id),
+            Link::new("file:///nonexistent/file.md", Span::new(2, 3), id),
+            Link::new("https://example.com/", Span::new(4, 5), id),
+        ];
+
+        let got = find_file_links(&links, src_dir, &files, &file_provider);
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(
+            got[0].suggestion.as_deref(),
+            Some("../chapter_1.md#heading")
+        );
+        assert_eq!(got[1].suggestion, None);
+    }
+
+    #[test]
+    fn links_are_rewritten_before_being_checked() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links = vec![
+            Link::new("https://internal.corp/page", Span::new(0, 1), id),
+            Link::new("./chapter_2.md", Span::new(2, 3), id),
+        ];
+        let cfg = Config {
+            rewrites: vec![(
+                HashedRegex::new("^https://internal.corp/(.*)$").unwrap(),
+                String::from("./mirror/$1.md"),
+            )]
+            .into_iter()
+            .collect(),
+            report_rewrites: Some(WarningPolicy::Warn),
+            ..Default::default()
+        };
+
+        let mut rewritten = Vec::new();
+        let got = apply_rewrites(&links, &cfg, &mut rewritten);
+
+        assert_eq!(got[0].href, "./mirror/page.md");
+        assert_eq!(got[1], links[1]);
+        assert_eq!(rewritten.len(), 1);
+        assert_eq!(rewritten[0].original, links[0]);
+
+        let outcome = ValidationOutcome {
+            rewritten_links: rewritten,
+            ..Default::default()
+        };
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn links_under_the_deploy_prefix_are_checked_against_the_source() {
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("all-green")
+            .join("src");
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links = vec![
+            Link::new("/docs/book/chapter_1.md", Span::new(0, 1), id),
+            Link::new("/docs/book/missing.md", Span::new(2, 3), id),
+        ];
+        let cfg = Config {
+            deploy_prefix: Some(String::from("/docs/book/")),
+            ..Default::default()
+        };
+
+        let got = validate(
+            &links,
+            &cfg,
+            &src_dir,
+            &mut Cache::default(),
+            &files,
+            &[id],
+            Vec::new(),
+        )
+        .unwrap();
+
+        // diagnostics still point at what the author wrote
+        assert_eq!(got.valid_links, &links[..1]);
+        assert_eq!(got.invalid_links.len(), 1);
+        assert_eq!(got.invalid_links[0].link, links[1]);
+    }
+
+    #[test]
+    fn equivalent_web_links_share_a_cache_entry() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links: Vec<_> =
+            ["http://example.com:80/a/", "http://EXAMPLE.com/a#section"]
+                .iter()
+                .map(|href| Link::new(*href, Span::new(0, 1), id))
+                .collect();
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let mut cache = Cache::default();
+        cache.insert(
+            "http://example.com/a".parse().unwrap(),
+            linkcheck::validation::CacheEntry::new(
+                std::time::SystemTime::now(),
+                true,
+            ),
+        );
+
+        let got = validate(
+            &links,
+            &cfg,
+            Path::new(env!("CARGO_MANIFEST_DIR")),
+            &mut cache,
+            &files,
+            &[id],
+            Vec::new(),
+        )
+        .unwrap();
+
+        // the second link shares the first one's result
+        assert_eq!(got.cache_hits, 1);
+        assert!(got.request_timings.is_empty());
+        assert!(got.invalid_links.is_empty());
+        // diagnostics still point at what the author wrote
+        assert_eq!(got.valid_links, links);
+    }
+
+    #[test]
+    fn cached_links_remember_their_redirects() {
+        let src = "[old](http://example.com/old)";
+        let (files, book) = in_memory_book(&[("chapter_1.md", src)]);
+        let check = |client, cache| {
+            crate::Checker::builder()
+                .source_dir("/book/src")
+                .config(Config {
+                    follow_web_links: true,
+                    ..Default::default()
+                })
+                .book(book.clone())
+                .file_provider(files.clone())
+                .http_client(client)
+                .build()
+                .unwrap()
+                .check_with_cache(cache)
+                .unwrap()
+        };
+        let client = crate::http_client::Canned::default().respond(
+            "http://example.com/old",
+            301,
+            &[("Location", "https://example.com/new")],
+        );
+        let first = check(client, Cache::default());
+        let client = crate::http_client::Canned::default();
+        let requested = std::sync::Arc::clone(&client.requested);
+
+        let second = check(client, first.cache.clone());
+
+        assert!(requested.lock().unwrap().is_empty());
+        assert_eq!(second.outcome.cache_hits, 1);
+        assert_eq!(
+            second.outcome.permanent_redirects,
+            first.outcome.permanent_redirects
+        );
+        assert_eq!(
+            second.outcome.permanent_redirects["http://example.com/old"],
+            "https://example.com/new"
+        );
+    }
+
+    #[test]
+    fn each_url_is_only_requested_once() {
+        let src = "[a](https://example.com/missing) \
+                   [b](https://example.com/missing/) \
+                   [c](https://example.com/missing#section) \
+                   [d](https://example.com/) [e](https://example.com)";
+        let (files, book) = in_memory_book(&[("chapter_1.md", src)]);
+        let client = crate::http_client::Canned::default();
+        let requested = std::sync::Arc::clone(&client.requested);
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        let report = crate::Checker::builder()
+            .source_dir("/book/src")
+            .config(cfg)
+            .book(book)
+            .file_provider(files)
+            .http_client(client)
+            .check()
+            .unwrap();
+
+        assert_eq!(requested.lock().unwrap().len(), 2);
+        assert_eq!(report.outcome.valid_links.len(), 2);
+        let invalid = &report.outcome.invalid_links;
+        assert_eq!(invalid.len(), 3);
+        // the duplicates keep the original link's reason
+        for broken in invalid {
+            let reason = FailureReason::from(&broken.reason);
+            assert!(
+                matches!(reason, FailureReason::HttpStatus { status: 404, .. }),
+                "{:?}",
+                reason
+            );
+        }
+    }
+
+    #[test]
+    fn query_strings_can_be_stripped_before_checking() {
+        let href = "https://example.com/page?session=expired";
+        // the server rejects the expired session
+        let client =
+            crate::http_client::Canned::default().respond(href, 400, &[]);
+        let requested = std::sync::Arc::clone(&client.requested);
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links = vec![Link::new(href, Span::new(0, 1), id)];
+        let cfg = Config {
+            follow_web_links: true,
+            strip_query: vec![HashedRegex::new("session=").unwrap()],
+            ..Default::default()
+        };
+
+        let got = validate_with(&client, &links, &cfg, &files);
+
+        assert!(got.invalid_links.is_empty(), "{:?}", got.invalid_links);
+        assert_eq!(*requested.lock().unwrap(), vec![
+            "https://example.com/page"
+        ]);
+        // diagnostics still point at what the author wrote
+        assert_eq!(got.valid_links, links);
+    }
+
+    #[test]
+    fn outstanding_requests_are_cancelled_after_the_deadline() {
+        // a server which never responds
+        let client = crate::http_client::Canned::default();
+        let url = "https://example.com/hang";
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links = vec![Link::new(url, Span::new(0, 1), id)];
+        let cfg = Config {
+            follow_web_links: true,
+            max_runtime: Some(600),
+            ..Default::default()
+        };
+
+        let got = validate_on(paused_runtime(), &client, &links, &cfg, &files);
+
+        assert_eq!(got.unchecked, links);
+        assert!(got.cut_short.is_empty());
+        let diags = got.generate_diagnostics(&files, &cfg);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "1 links not checked (deadline)");
+    }
+
+    #[test]
+    fn the_deadline_covers_checks_after_the_links() {
+        let client = crate::http_client::Canned::default();
+        let src = "[fine](https://example.com/)
+
+\
+                   <img src=\"https://example.com/hang\">\n";
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::from(src));
+        let links =
+            vec![Link::new("https://example.com/", Span::new(7, 27), id)];
+        let mut cfg = Config {
+            follow_web_links: true,
+            max_runtime: Some(600),
+            ..Default::default()
+        };
+        cfg.web.image_content_type = Some(WarningPolicy::Warn);
+
+        let got = validate_on(paused_runtime(), &client, &links, &cfg, &files);
+
+        assert!(got.unchecked.is_empty());
+        assert_eq!(got.cut_short, vec!["image content types"]);
+        let diags = got.generate_diagnostics(&files, &cfg);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "Some checks didn't finish (deadline)");
+        assert_eq!(
+            diags[0].notes[1],
+            "These checks didn't finish: image content types"
+        );
+    }
+
+    /// A runtime where time only passes when everything is waiting on a
+    /// timer, so deadlines can be tested without sleeping.
+    fn paused_runtime() -> tokio::runtime::Runtime {
+        Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn stop_at_the_first_error_when_failing_fast() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links: Vec<_> = (0..10)
+            .map(|i| {
+                Link::new(format!("./missing-{}.md", i), Span::new(0, 1), id)
+            })
+            .collect();
+        let cfg = Config {
+            fail_fast: true,
+            ..Default::default()
+        };
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+        let got = validate(
+            &links,
+            &cfg,
+            src_dir,
+            &mut Cache::default(),
+            &files,
+            &[id],
+            Vec::new(),
+        )
+        .unwrap();
+
+        assert_eq!(got.invalid_links.len(), 1);
+        assert!(got.unchecked.is_empty());
+
+        // broken links which are only warnings don't count
+        let cfg = Config {
+            local: crate::LocalConfig {
+                severity: Some(WarningPolicy::Warn),
+                ..Default::default()
+            },
+            ..cfg
+        };
+        let got = validate(
+            &links,
+            &cfg,
+            src_dir,
+            &mut Cache::default(),
+            &files,
+            &[id],
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(got.invalid_links.len(), links.len());
+    }
+
+    #[test]
+    fn give_up_on_local_links_after_the_local_timeout() {
+        /// A filesystem where every lookup takes a while.
+        #[derive(Debug)]
+        struct Slow;
+
+        impl FileProvider for Slow {
+            fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+                std::thread::sleep(Duration::from_millis(50));
+                RealFileSystem.canonicalize(path)
+            }
+
+            fn is_dir(&self, path: &Path) -> bool { path.is_dir() }
+
+            fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+                RealFileSystem.read(path)
+            }
+
+            fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+                RealFileSystem.read_dir(path)
+            }
+        }
+
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links: Vec<_> = (0..40)
+            .map(|i| {
+                Link::new(format!("./missing-{}.md", i), Span::new(0, 1), id)
+            })
+            .collect();
+        let mut cfg = Config::default();
+        cfg.local.timeout = Some(1);
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+
+        let (got, _) = runtime
+            .block_on(validate_batches(
+                futures::stream::iter(vec![(links.clone(), Vec::new())]),
+                &cfg,
+                Path::new(env!("CARGO_MANIFEST_DIR")),
+                &mut Cache::default(),
+                &files,
+                &[id],
+                &[],
+                &Drafts::default(),
+                &Aliases::default(),
+                None,
+                &AnchorIndex::new(cfg.slugs.clone()),
+                None,
+                &Slow,
+                None,
+            ))
+            .unwrap();
+
+        assert!(!got.unchecked.is_empty());
+        assert_eq!(
+            got.invalid_links.len() + got.unchecked.len(),
+            links.len()
+        );
+    }
+
+    #[test]
+    fn destinations_with_spaces_and_escapes() {
+        let src_dir = std::env::temp_dir().join(format!(
+            "mdbook-linkcheck-destinations-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("my notes.md"), "# Intro\n").unwrap();
+        std::fs::write(src_dir.join("foo(1).md"), "# Foo\n").unwrap();
+        let mut files = Files::new();
+        let id = files.add(
+            "chapter_1.md",
+            String::from(
+                "[a](<my notes.md>) [b](my%20notes.md#intro) \
+                 [c](foo\\(1\\).md) [d](<missing file.md>) \
+                 [e](<my notes.md#outro>)",
+            ),
+        );
+        let notes = files.add("my notes.md", String::from("# Intro\n"));
+        let foo = files.add("foo(1).md", String::from("# Foo\n"));
+        let (links, _) = crate::links::extract(vec![id], &files);
+        let cfg = Config {
+            check_anchors: true,
+            ..Default::default()
+        };
+
+        let got = validate(
+            &links,
+            &cfg,
+            &src_dir,
+            &mut Cache::default(),
+            &files,
+            &[id, notes, foo],
+            Vec::new(),
+        )
+        .unwrap();
+
+        let valid: Vec<_> =
+            got.valid_links.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(
+            valid,
+            vec!["my notes.md", "my%20notes.md#intro", "foo(1).md"]
+        );
+        let invalid: Vec<_> = got
+            .invalid_links
+            .iter()
+            .map(|i| i.link.href.as_str())
+            .collect();
+        assert_eq!(invalid, vec!["missing file.md", "my notes.md#outro"]);
+        std::fs::remove_dir_all(&src_dir).unwrap();
+    }
+
+    #[test]
+    fn links_are_collected_from_every_batch() {
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let incomplete = IncompleteLink {
+            reference: String::from("foo"),
+            file: id,
+            span: Span::new(4, 5),
+        };
+        let batches = vec![
+            (
+                vec![
+                    Link::new("https://example.com/", Span::new(0, 1), id),
+                    Link::new("./missing.md", Span::new(1, 2), id),
+                ],
+                Vec::new(),
+            ),
+            (
+                vec![Link::new("data:,Hello", Span::new(2, 3), id)],
+                vec![incomplete.clone()],
+            ),
+        ];
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let runtime =
+            Builder::new_current_thread().enable_all().build().unwrap();
+
+        let (got, links) = runtime
+            .block_on(validate_batches(
+                futures::stream::iter(batches),
+                &Config::default(),
+                src_dir,
+                &mut Cache::default(),
+                &files,
+                &[id],
+                &[],
+                &Drafts::default(),
+                &Aliases::default(),
+                None,
+                &AnchorIndex::default(),
+                None,
+                &RealFileSystem,
+                None,
+            ))
+            .unwrap();
+
+        let hrefs: Vec<_> = links.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(
+            hrefs,
+            vec!["https://example.com/", "./missing.md", "data:,Hello"]
+        );
+        assert_eq!(got.ignored[0].href, "https://example.com/");
+        assert_eq!(got.invalid_links[0].link.href, "./missing.md");
+        assert_eq!(got.valid_links[0].href, "data:,Hello");
+        assert_eq!(got.incomplete_links, vec![incomplete]);
+    }
+
+    #[test]
+    fn checkpoints_are_only_saved_once_the_interval_elapses() {
+        let path = std::env::temp_dir().join(format!(
+            "mdbook-linkcheck-checkpoint-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let cache = Mutex::new(Cache::default());
+        let mut checkpoint = Checkpoint::new(&path);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            checkpoint.save_if_due(&cache);
+            assert!(checkpoint.saving.is_none());
+
+            checkpoint.interval = Duration::from_secs(0);
+            checkpoint.save_if_due(&cache);
+            checkpoint.finish().await;
+        });
+        assert!(path.exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn suggest_relative_links_to_the_books_own_site() {
+        let (files, outcome) = outcome_with_valid_links(&[
+            "https://example.com/my-book/nested/setup.html#linux",
+            "https://example.com/other-site/",
+            "https://docs.rs/",
+        ]);
+        let cfg = Config {
+            site_url: Some(String::from("https://example.com/my-book/")),
+            self_referential_links: Some(WarningPolicy::Warn),
+            ..Default::default()
+        };
+
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].notes,
+            vec![String::from(
+                "Suggestion: change the link to \"nested/setup.md#linux\""
+            )]
+        );
+    }
+
+    #[test]
+    fn links_within_the_same_chapter_are_checked() {
+        let mut files = Files::new();
+        let src = "# Chapter 1\n\n## Configuration\n\n\
+                   [ok](#configuration) [top](#top) [bad](#usage)\n";
+        let id = files.add("chapter_1.md", String::from(src));
+        let link = |href: &str| {
+            let text = format!("]({})", href);
+            let end = src.find(&text).unwrap() + text.len();
+            let start = src[..end].rfind('[').unwrap();
+            Link::new(href, Span::new(start as u32, end as u32), id)
+        };
+        let links = vec![link("#configuration"), link("#top"), link("#usage")];
+        let cfg = Config {
+            check_anchors: true,
+            ..Default::default()
+        };
+
+        let got =
+            check_current_page_links(
+                links,
+                &cfg,
+                Path::new("."),
+                &files,
+                &RealFileSystem,
+            );
+
+        let valid: Vec<_> = got.valid.iter().map(|l| l.href.as_str()).collect();
+        assert_eq!(valid, vec!["#configuration", "#top"]);
+        assert_eq!(got.invalid.len(), 1);
+        let invalid = &got.invalid[0];
+        let span = invalid.link.span;
+        assert_eq!(
+            &src[span.start().to_usize()..span.end().to_usize()],
+            "#usage"
+        );
+        let err = match &invalid.reason {
+            Reason::Io(io) => io.to_string(),
+            other => panic!("Unexpected reason: {:?}", other),
+        };
+        assert_eq!(err, "There is no \"#usage\" anchor in \"chapter_1.md\"");
+
+        // anchors are only checked when asked for
+        let links = vec![link("#usage")];
+        let got = check_current_page_links(
+            links,
+            &Config::default(),
+            Path::new("."),
+            &files,
+            &RealFileSystem,
+        );
+        assert_eq!(got.valid.len(), 1);
+    }
+
+    #[test]
+    fn only_some_links_may_leave_the_source_directory() {
+        let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let src_dir = tests.join("all-green").join("src");
+        let cfg = Config {
+            external_roots: vec![String::from("../../broken-links")],
+            max_parent_depth: Some(1),
+            ..Default::default()
+        };
+        let policy =
+            ParentDirectoryPolicy::new(&cfg, &src_dir, &RealFileSystem)
+                .unwrap();
+        let canonical = |path: PathBuf| dunce::canonicalize(path).unwrap();
+
+        let inside = canonical(src_dir.join("chapter_1.md"));
+        assert!(policy.check(&inside).is_ok());
+        let one_level_up = canonical(tests.join("all-green").join("book.toml"));
+        assert!(policy.check(&one_level_up).is_ok());
+        let allowed = canonical(tests.join("broken-links").join("book.toml"));
+        assert!(policy.check(&allowed).is_ok());
+        let too_far = canonical(tests.join("smoke_tests.rs"));
+        assert!(matches!(
+            policy.check(&too_far),
+            Err(Reason::TraversesParentDirectories)
+        ));
+    }
+
+    #[test]
+    fn allow_external_paths_matching_a_pattern() {
+        let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let src_dir = tests.join("all-green").join("src");
+        let cfg = Config {
+            allowed_external_paths: vec![
+                String::from("../../broken-links/**/*.md"),
+                String::from("./../../smoke_tests.rs"),
+            ],
+            ..Default::default()
+        };
+        let policy =
+            ParentDirectoryPolicy::new(&cfg, &src_dir, &RealFileSystem)
+                .unwrap();
+        let canonical = |path: PathBuf| dunce::canonicalize(path).unwrap();
+
+        let inside = canonical(src_dir.join("chapter_1.md"));
+        assert!(policy.check(&inside).is_ok());
+        let other_book = tests.join("broken-links");
+        let markdown = canonical(other_book.join("src").join("SUMMARY.md"));
+        assert!(policy.check(&markdown).is_ok());
+        let exact = canonical(tests.join("smoke_tests.rs"));
+        assert!(policy.check(&exact).is_ok());
+        let not_markdown = canonical(other_book.join("book.toml"));
+        assert!(matches!(
+            policy.check(&not_markdown),
+            Err(Reason::TraversesParentDirectories)
+        ));
+        let one_level_up = canonical(tests.join("all-green").join("book.toml"));
+        assert!(policy.check(&one_level_up).is_err());
+    }
+
+    #[test]
+    fn unreadable_files_are_reported() {
+        let dir = std::env::temp_dir()
+            .join(format!("mdbook-linkcheck-readable-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.md");
+        std::fs::write(&empty, "").unwrap();
+        let full = dir.join("full.md");
+        std::fs::write(&full, "# Hello").unwrap();
+
+        assert!(ensure_readable(&full, &RealFileSystem).is_ok());
+        assert!(ensure_readable(&dir, &RealFileSystem).is_ok());
+        // reported separately, depending on Config::empty_files
+        assert!(ensure_readable(&empty, &RealFileSystem).is_ok());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let private = dir.join("private.md");
+            std::fs::write(&private, "secrets").unwrap();
+            std::fs::set_permissions(
+                &private,
+                std::fs::Permissions::from_mode(0o000),
+            )
+            .unwrap();
+
+            // root can read anything, so only check when it matters
+            if std::fs::File::open(&private).is_err() {
+                match ensure_readable(&private, &RealFileSystem).unwrap_err() {
+                    Reason::Io(io) => {
+                        assert!(io.to_string().starts_with("Unable to read"))
+                    },
+                    other => panic!("Unexpected reason: {:?}", other),
+                }
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn links_to_empty_files_follow_their_policy() {
+        let chapters = [
+            ("intro.md", "# Intro\n\n[later](./placeholder.md)"),
+            ("placeholder.md", ""),
+        ];
+        let (files, book) = in_memory_book(&chapters);
+        let check = |empty_files| {
+            let cfg = Config {
+                empty_files,
+                ..Default::default()
+            };
+            crate::Checker::builder()
+                .source_dir("/book/src")
+                .config(cfg)
+                .book(book.clone())
+                .file_provider(files.clone())
+                .check()
+                .unwrap()
+        };
+
+        let report = check(WarningPolicy::Warn);
+
+        assert!(report.outcome.invalid_links.is_empty());
+        let hrefs: Vec<_> = report
+            .outcome
+            .empty_file_links
+            .iter()
+            .map(|link| link.href.as_str())
+            .collect();
+        assert_eq!(hrefs, vec!["./placeholder.md"]);
+        let diags = report.diagnostics();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert_eq!(diags[0].code.as_deref(), Some("empty-file"));
+        assert!(!report.has_errors());
+
+        let report = check(WarningPolicy::Ignore);
+
+        assert!(report.outcome.empty_file_links.is_empty());
+        assert!(report.diagnostics().is_empty());
+    }
+
+    /// An in-memory book where `changed.md` links to `unchanged.md`, and
+    /// `unchanged.md` links to `changed.md` and something that's missing.
+    fn book_with_two_chapters() -> (crate::InMemoryFiles, mdbook::book::Book)
+    {
+        in_memory_book(&[
+            ("SUMMARY.md", ""),
+            ("changed.md", "# Changed\n\n[next](./unchanged.md#unchanged)"),
+            ("unchanged.md", "# Unchanged\n\n[a](./changed.md) [b](./gone.md)"),
+        ])
+    }
+
+    #[test]
+    fn links_to_chapters_which_are_not_being_checked_are_fine() {
+        let (files, book) = book_with_two_chapters();
+
+        let report = crate::Checker::builder()
+            .source_dir("/book/src")
+            .config(Config::default())
+            .book(book)
+            .file_provider(files)
+            .selected_files(vec![PathBuf::from("changed.md")])
+            .check()
+            .unwrap();
+
+        assert!(report.outcome.invalid_links.is_empty());
+        assert_eq!(report.outcome.valid_links.len(), 1);
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn overrides_apply_to_matching_chapters() {
+        let chapters = [
+            ("intro.md", "[a](./missing.md) [b](https://internal.invalid/)"),
+            (
+                "appendix/generated.md",
+                "[a](./missing.md) [b](https://internal.invalid/)",
+            ),
+        ];
+        let (mut files, book) = in_memory_book(&chapters);
+        files.insert("/book/book.toml", "");
+        let cfg = Config {
+            overrides: vec![crate::ChapterOverride {
+                path: String::from("src/appendix/**"),
+                severity: Some(WarningPolicy::Warn),
+                exclude: vec![HashedRegex::new("internal").unwrap()],
+                // the exclusion wins, so this still isn't checked
+                follow_web_links: Some(true),
+            }],
+            ..Default::default()
+        };
+
+        let report = crate::Checker::builder()
+            .source_dir("/book/src")
+            .config(cfg)
+            .book(book)
+            .file_provider(files)
+            .check()
+            .unwrap();
+
+        let mut got: Vec<_> = report
+            .diagnostics()
+            .iter()
+            .map(|diag| {
+                let file = report.files.name(diag.labels[0].file_id);
+                (file.to_string_lossy().into_owned(), diag.severity)
+            })
+            .collect();
+        got.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            got,
+            vec![
+                (String::from("appendix/generated.md"), Severity::Warning),
+                (String::from("intro.md"), Severity::Error),
+            ]
+        );
+        let ignored = &report.outcome.ignored;
+        assert_eq!(ignored.len(), 2);
+    }
 
     #[test]
     fn check_some_simple_relative_paths() {
@@ -0,0 +1,191 @@
+use crate::{Cache, Checker, Report};
+use anyhow::Error;
+use codespan_reporting::term::termcolor::ColorChoice;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Keep checking a book (or directory of markdown files), re-validating the
+/// chapters that have changed every time a file is saved.
+///
+/// Only the chapters which were modified, plus any chapters linking to them,
+/// are checked after the initial run. This function never returns unless an
+/// error occurs while loading the book.
+pub fn watch(
+    root: &Path,
+    colour: ColorChoice,
+    interval: Duration,
+) -> Result<(), Error> {
+    let root = dunce::canonicalize(root)?;
+    let mut cache = Cache::default();
+
+    let (src_dir, book, cfg) = crate::load_standalone(&root)?;
+    let checker = Checker::builder()
+        .source_dir(&src_dir)
+        .config(cfg)
+        .book(book)
+        .build()?;
+    check(&checker, &mut cache, colour);
+
+    let mut previous = snapshot(&src_dir);
+    tracing::info!("Watching \"{}\" for changes", src_dir.display());
+
+    loop {
+        std::thread::sleep(interval);
+
+        let current = snapshot(&src_dir);
+        let changed = changed_files(&previous, &current);
+        previous = current;
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        tracing::info!("Files changed: {:?}", changed);
+
+        let (src_dir, book, cfg) = match crate::load_standalone(&root) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                tracing::error!("Unable to reload the book: {}", e);
+                continue;
+            },
+        };
+        let affected = crate::changes::affected_chapters(&book, &changed);
+
+        if affected.is_empty() {
+            tracing::info!("No chapters were affected");
+            continue;
+        }
+
+        let checker = Checker::builder()
+            .source_dir(&src_dir)
+            .config(cfg)
+            .book(book)
+            .selected_files(affected)
+            .build()?;
+        check(&checker, &mut cache, colour);
+    }
+}
+
+/// Run the checker and print any diagnostics, updating the cache.
+///
+/// The cache is left alone if the book couldn't be checked, so the next run
+/// doesn't need to request every web link again.
+fn check(checker: &Checker, cache: &mut Cache, colour: ColorChoice) {
+    let Report {
+        files,
+        outcome,
+        cache: updated,
+        ..
+    } = match checker.check_with_cache(cache.clone()) {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::error!("Unable to check the book: {}", e);
+            return;
+        },
+    };
+    *cache = updated;
+
+    let diags = outcome.generate_diagnostics(&files, checker.config());
+    let max_diagnostics = checker.config().max_diagnostics;
+    if let Err(e) =
+        crate::report_errors(&files, &diags, colour, max_diagnostics)
+    {
+        tracing::warn!("Unable to print diagnostics: {}", e);
+    }
+
+    match crate::check_for_failures(&diags, checker.config()) {
+        Ok(()) => tracing::info!("No broken links found"),
+        Err(e) => tracing::warn!("{}", e),
+    }
+}
+
+/// Record the last modified time of every file under a directory.
+fn snapshot(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    fn visit(root: &Path, dir: &Path, dest: &mut HashMap<PathBuf, SystemTime>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::debug!("Unable to read \"{}\": {}", dir.display(), e);
+                return;
+            },
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let hidden = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with('.'));
+
+            if hidden {
+                continue;
+            } else if path.is_dir() {
+                visit(root, &path, dest);
+            } else if let Ok(modified) =
+                entry.metadata().and_then(|m| m.modified())
+            {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                dest.insert(relative.to_path_buf(), modified);
+            }
+        }
+    }
+
+    let mut files = HashMap::new();
+    visit(dir, dir, &mut files);
+    files
+}
+
+/// Find every file which was added, removed, or modified between two
+/// snapshots.
+fn changed_files(
+    previous: &HashMap<PathBuf, SystemTime>,
+    current: &HashMap<PathBuf, SystemTime>,
+) -> BTreeSet<PathBuf> {
+    let modified = current
+        .iter()
+        .filter(|(path, modified)| previous.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone());
+    let removed = previous
+        .keys()
+        .filter(|path| !current.contains_key(*path))
+        .cloned();
+
+    modified.chain(removed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_added_removed_and_modified_files() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(1);
+        let previous: HashMap<_, _> = vec![
+            (PathBuf::from("unchanged.md"), now),
+            (PathBuf::from("modified.md"), now),
+            (PathBuf::from("removed.md"), now),
+        ]
+        .into_iter()
+        .collect();
+        let current: HashMap<_, _> = vec![
+            (PathBuf::from("unchanged.md"), now),
+            (PathBuf::from("modified.md"), later),
+            (PathBuf::from("added.md"), now),
+        ]
+        .into_iter()
+        .collect();
+
+        let got = changed_files(&previous, &current);
+
+        let should_be: BTreeSet<_> =
+            vec!["added.md", "modified.md", "removed.md"]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect();
+        assert_eq!(got, should_be);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::{
+    cache::Redirect,
+    cache_control::{cache_control, CacheControl},
+    http_client::headers_for,
+    http_trace,
+    normalize::normalize,
+    suggested_rewrites::RedirectRecorder,
+    throttle::{is_overloaded, Throttle, DEFAULT_CONCURRENCY, MAX_RETRIES},
+    Config, Context, HashedRegex, HttpClient,
+};
+use futures::StreamExt;
+use http::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    StatusCode,
+};
+use linkcheck::{
+    validation::{InvalidLink, Outcomes, Reason},
+    Link,
+};
+use reqwest::{Response, Url};
+use std::{sync::Mutex, time::Duration};
+use tracing::Instrument;
+
+/// Is this a link which [`check_web()`] knows how to check?
+pub(crate) fn is_web_link(link: &Link) -> bool {
+    link.href
+        .parse::<Url>()
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Where the result for a web link came from (see [`crate::Metrics`]).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Source {
+    /// The cache says the link is still valid.
+    Cache,
+    /// A request was sent.
+    Network,
+}
+
+/// The equivalent of linkcheck's web link check, except:
+///
+/// - requests to a host which says it is overloaded are retried, and that
+///   host is sent fewer requests from then on (see [`crate::throttle`])
+/// - the result is cached for as long as the server's `Cache-Control` header
+///   says when [`crate::Config::respect_cache_control`] is enabled
+///
+/// The request goes to the URL the author wrote, while the result is cached
+/// under its [`normalize()`]d form.
+///
+/// Ignored links don't have a [`Source`].
+pub(crate) async fn check_web(
+    link: Link,
+    ctx: &Context<'_>,
+) -> (Outcomes, Option<Source>) {
+    let mut outcomes = Outcomes::default();
+
+    if ctx.should_ignore(&link) {
+        outcomes.ignored.push(link);
+        return (outcomes, None);
+    }
+
+    let url: Url = link
+        .href
+        .parse()
+        .expect("is_web_link() already parsed the URL");
+    let key = normalize(&url);
+    if is_cached(&key, ctx) {
+        // the redirects aren't followed this time, so use the ones from when
+        // the result was cached
+        for redirect in ctx.lock_cache().redirects(&key) {
+            if let Some((from, to, status)) = redirect.parse() {
+                ctx.redirects.record(&from, &to, status);
+            }
+        }
+        outcomes.valid.push(link);
+        return (outcomes, Some(Source::Cache));
+    }
+
+    match request(&url, &key, ctx).await {
+        Ok(()) => outcomes.valid.push(link),
+        Err(reason) => outcomes.invalid.push(InvalidLink { link, reason }),
+    }
+
+    (outcomes, Some(Source::Network))
+}
+
+/// Does the cache say this URL was valid recently enough to skip the
+/// request?
+pub(crate) fn is_cached(url: &Url, ctx: &Context<'_>) -> bool {
+    ctx.lock_cache()
+        .url_is_still_valid(url, ctx.cache_timeout())
+}
+
+/// Send the request to `url`, caching the result under `key`.
+async fn request(
+    url: &Url,
+    key: &Url,
+    ctx: &Context<'_>,
+) -> Result<(), Reason> {
+    let longest = ctx.cache_timeout();
+    let followed = Mutex::new(Vec::new());
+    let result = send(url, ctx, &followed).await.and_then(|response| {
+        response.error_for_status().map_err(Reason::from)
+    });
+
+    let content_type = result
+        .as_ref()
+        .ok()
+        .filter(|_| ctx.cfg.web.image_content_type.is_some())
+        .and_then(|response| response.headers().get(CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok());
+    if let Some(content_type) = content_type {
+        ctx.content_types
+            .lock()
+            .expect("Lock was poisoned")
+            .insert(key.to_string(), content_type.to_string());
+    }
+
+    let directive = result
+        .as_ref()
+        .ok()
+        .filter(|_| ctx.cfg.respect_cache_control)
+        .and_then(|response| cache_control(response.headers()));
+    let ttl = match directive {
+        Some(CacheControl::NoStore) => {
+            tracing::debug!(%url, "Not caching the result (no-store)");
+            return result.map(|_| ());
+        },
+        // stored, but checked again next time
+        Some(CacheControl::NoCache) => Duration::ZERO,
+        Some(CacheControl::MaxAge(ttl)) => ttl,
+        None => Duration::from_secs(ctx.cfg.cache_timeout),
+    };
+    let ttl = ttl.min(longest);
+    tracing::debug!(%url, ?ttl, "Caching the result");
+
+    let mut cache = ctx.lock_cache();
+    cache.insert_fresh_for(key.clone(), result.is_ok(), ttl);
+    cache.set_redirects(key, followed.into_inner().expect("Lock was poisoned"));
+
+    result.map(|_| ())
+}
+
+/// Send a `HEAD` request, backing off and trying again while the host says
+/// it is overloaded. Each redirect is added to `followed`, so it can be
+/// cached along with the result.
+async fn send(
+    url: &Url,
+    ctx: &Context<'_>,
+    followed: &Mutex<Vec<Redirect>>,
+) -> Result<Response, Reason> {
+    send_with_retries(
+        ctx.http,
+        ctx.cfg,
+        &ctx.throttle,
+        url,
+        |url| ctx.url_specific_headers(url),
+        |from, to, status| {
+            ctx.redirects.record(from, to, status);
+            let redirect = Redirect::new(from, to, status);
+            let mut followed = followed.lock().expect("Lock was poisoned");
+            // retrying a request follows the same redirects again
+            if !followed.contains(&redirect) {
+                followed.push(redirect);
+            }
+        },
+    )
+    .await
+}
+
+async fn send_with_retries<H, R>(
+    http: &dyn HttpClient,
+    cfg: &Config,
+    throttle: &Throttle,
+    url: &Url,
+    headers_for: H,
+    on_redirect: R,
+) -> Result<Response, Reason>
+where
+    H: Fn(&Url) -> HeaderMap,
+    R: Fn(&Url, &Url, StatusCode),
+{
+    let host = throttle.host(url);
+    let mut attempt = 0;
+
+    loop {
+        let permit = host.acquire().await;
+        let span = tracing::debug_span!("attempt", attempt);
+        let response = crate::http_client::head_following_redirects(
+            http,
+            cfg,
+            url.clone(),
+            &headers_for,
+            &on_redirect,
+        )
+        .instrument(span)
+        .await;
+        drop(permit);
+        let response = response?;
+        let headers: Vec<_> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name, http_trace::redacted(name, value)))
+            .collect();
+        tracing::debug!(
+            url = %http_trace::redacted_url(url),
+            status = %response.status(),
+            ?headers,
+            "Received a response"
+        );
+
+        if !is_overloaded(response.status()) || attempt >= MAX_RETRIES {
+            return Ok(response);
+        }
+
+        host.back_off(response.headers());
+        attempt += 1;
+    }
+}
+
+/// Sends the `HEAD` requests for the checks which run after the links
+/// themselves were checked (e.g. looking at the `Content-Type` of assets),
+/// the same way [`check_web()`] does.
+#[derive(Debug)]
+pub(crate) struct Prober<'a> {
+    cfg: &'a Config,
+    http: &'a dyn HttpClient,
+    headers: Vec<(HashedRegex, Vec<(HeaderName, HeaderValue)>)>,
+    throttle: Throttle,
+    /// The redirects followed so far.
+    pub(crate) redirects: RedirectRecorder,
+}
+
+impl<'a> Prober<'a> {
+    pub(crate) fn new(cfg: &'a Config, http: &'a dyn HttpClient) -> Self {
+        Prober {
+            cfg,
+            http,
+            headers: cfg.interpolate_headers(cfg.warning_policy),
+            throttle: Throttle::new(DEFAULT_CONCURRENCY),
+            redirects: RedirectRecorder::default(),
+        }
+    }
+
+    /// Send a `HEAD` request to this URL, following redirects.
+    pub(crate) async fn head(&self, url: &Url) -> Result<Response, Reason> {
+        send_with_retries(
+            self.http,
+            self.cfg,
+            &self.throttle,
+            url,
+            |url| headers_for(&self.headers, url),
+            |from, to, status| self.redirects.record(from, to, status),
+        )
+        .await
+    }
+
+    /// Send a `HEAD` request to each web link, passing the responses to
+    /// `inspect`.
+    ///
+    /// Links where the request fails are skipped, giving them the benefit of
+    /// the doubt.
+    pub(crate) async fn head_each<'l, I, T, F>(
+        &self,
+        links: I,
+        inspect: F,
+    ) -> Vec<T>
+    where
+        I: IntoIterator<Item = &'l Link>,
+        I::IntoIter: Send,
+        F: Fn(&'l Link, Response) -> Option<T> + Sync,
+        T: Send,
+    {
+        let links = links
+            .into_iter()
+            .filter_map(|link| Some((link, link.href.parse::<Url>().ok()?)));
+        let inspect = &inspect;
+
+        futures::stream::iter(links)
+            .map(|(link, url)| async move {
+                let response = self.head(&url).await.ok()?;
+                inspect(link, response)
+            })
+            .buffer_unordered(DEFAULT_CONCURRENCY)
+            .filter_map(futures::future::ready)
+            // erasing the stream's type keeps the caller's future Send
+            .boxed()
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        file_provider::in_memory_book, http_client::Canned,
+        validate::validate_with, Config,
+    };
+    use codespan::{Files, Span};
+    use linkcheck::Link;
+
+    #[test]
+    fn retry_when_the_server_is_overloaded() {
+        let url = "https://example.com/";
+        let client =
+            Canned::default().respond(url, 429, &[("Retry-After", "0")]);
+        let requested = std::sync::Arc::clone(&client.requested);
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links = vec![Link::new(url, Span::new(0, 1), id)];
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        let got = validate_with(&client, &links, &cfg, &files);
+
+        assert!(got.invalid_links.is_empty(), "{:?}", got.invalid_links);
+        assert_eq!(got.valid_links, links);
+        assert_eq!(requested.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn the_url_is_requested_as_written() {
+        let url = "https://example.com/dir/";
+        let client = Canned::default();
+        let requested = std::sync::Arc::clone(&client.requested);
+        let mut files = Files::new();
+        let id = files.add("chapter_1.md", String::new());
+        let links = vec![
+            Link::new(url, Span::new(0, 1), id),
+            Link::new("https://example.com/dir", Span::new(2, 3), id),
+        ];
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        let got = validate_with(&client, &links, &cfg, &files);
+
+        assert!(got.invalid_links.is_empty(), "{:?}", got.invalid_links);
+        assert_eq!(got.valid_links.len(), 2);
+        // the duplicate shares the first link's result
+        assert_eq!(requested.lock().unwrap().as_slice(), [url]);
+    }
+
+    #[test]
+    fn results_are_cached_the_way_the_server_says() {
+        let src = "[a](https://example.com/no-store) \
+                   [b](https://example.com/no-cache) \
+                   [c](https://example.com/max-age)";
+        let (files, book) = in_memory_book(&[("chapter_1.md", src)]);
+        let client = Canned::default()
+            .respond("https://example.com/no-store", 200, &[(
+                "Cache-Control",
+                "no-store",
+            )])
+            .respond("https://example.com/no-cache", 200, &[(
+                "Cache-Control",
+                "no-cache",
+            )])
+            .respond("https://example.com/max-age", 200, &[(
+                "Cache-Control",
+                "max-age=60",
+            )]);
+        let cfg = Config {
+            follow_web_links: true,
+            respect_cache_control: true,
+            ..Default::default()
+        };
+
+        let report = crate::Checker::builder()
+            .source_dir("/book/src")
+            .config(cfg.clone())
+            .book(book)
+            .file_provider(files)
+            .http_client(client)
+            .check()
+            .unwrap();
+
+        let timeout = cfg.longest_cache_timeout();
+        let url = |path: &str| -> reqwest::Url {
+            format!("https://example.com/{}", path).parse().unwrap()
+        };
+        assert!(report.cache.lookup(&url("no-store")).is_none());
+        let no_cache = report.cache.lookup(&url("no-cache")).unwrap();
+        assert!(no_cache.valid);
+        assert!(!report.cache.url_is_still_valid(&url("no-cache"), timeout));
+        assert!(report.cache.url_is_still_valid(&url("max-age"), timeout));
+    }
+}
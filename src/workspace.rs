@@ -0,0 +1,356 @@
+use crate::{
+    anchors::AnchorIndex, changes::local_target, validate::LocalFiles, Config,
+    MissingAnchor, RealFileSystem,
+};
+use codespan::Files;
+use linkcheck::{
+    validation::{InvalidLink, Outcomes, Reason},
+    Link,
+};
+use std::{
+    ffi::OsString,
+    io::Error,
+    path::{Path, PathBuf},
+};
+
+/// Other books living alongside this one (see [`Config::workspace_books`]),
+/// which chapters are allowed to link into.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Workspace {
+    books: Vec<SiblingBook>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SiblingBook {
+    root: PathBuf,
+    src_dir: PathBuf,
+    /// The sibling book's configuration and the chapters in its
+    /// `SUMMARY.md`, or `None` for one of the [`Config::translations`],
+    /// which belong to this book.
+    book: Option<(Config, Vec<OsString>)>,
+}
+
+/// The result of [`Workspace::check_links()`].
+#[derive(Debug, Default)]
+pub(crate) struct WorkspaceLinks {
+    /// Outcomes for the links which point into another book or translation.
+    pub(crate) outcomes: Outcomes,
+    /// Links which still need to be checked normally.
+    pub(crate) remaining: Vec<Link>,
+    /// Links to a counterpart in another translation which doesn't exist.
+    pub(crate) missing_translations: Vec<Link>,
+}
+
+impl Workspace {
+    /// Find the sibling books and translations for the book with this source
+    /// directory.
+    ///
+    /// Paths in [`Config::workspace_books`] are relative to the source
+    /// directory, like [`Config::external_roots`], while
+    /// [`Config::translations`] are relative to the directory containing
+    /// `book.toml` (or the source directory itself, when checking a plain
+    /// directory of markdown files).
+    pub(crate) fn load(cfg: &Config, src_dir: &Path) -> Workspace {
+        let book_root = src_dir
+            .ancestors()
+            .find(|dir| dir.join("book.toml").is_file())
+            .unwrap_or(src_dir);
+        let find = |base: &Path, raw: &String, what: &str| {
+            match dunce::canonicalize(base.join(raw)) {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    tracing::warn!(
+                        "Unable to find the \"{}\" {}: {}",
+                        raw,
+                        what,
+                        e
+                    );
+                    None
+                },
+            }
+        };
+
+        let books = cfg
+            .workspace_books
+            .iter()
+            .filter_map(|raw| find(src_dir, raw, "workspace book"))
+            .filter_map(|root| match crate::load_standalone(&root) {
+                Ok((src_dir, book, cfg)) => Some(SiblingBook {
+                    src_dir: dunce::canonicalize(&src_dir).unwrap_or(src_dir),
+                    root,
+                    book: Some((cfg, crate::chapter_names(&book))),
+                }),
+                Err(e) => {
+                    tracing::warn!(
+                        "Unable to load the workspace book in \"{}\": {:?}",
+                        root.display(),
+                        e
+                    );
+                    None
+                },
+            });
+        let translations = cfg
+            .translations
+            .iter()
+            .filter_map(|raw| find(book_root, raw, "translation"))
+            .map(|root| SiblingBook {
+                src_dir: root.clone(),
+                root,
+                book: None,
+            });
+
+        Workspace {
+            books: books.chain(translations).collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool { self.books.is_empty() }
+
+    /// Is this path inside one of the sibling books or translations?
+    pub(crate) fn contains(&self, path: &Path) -> bool {
+        self.books.iter().any(|book| path.starts_with(&book.root))
+    }
+
+    /// Does this link point into one of the sibling books or translations? If
+    /// so, where should it be resolved in that book's source?
+    fn resolve(
+        &self,
+        src_dir: &Path,
+        link: &Link,
+        files: &Files<String>,
+    ) -> Option<(PathBuf, &SiblingBook)> {
+        if link.href.starts_with('/') {
+            return None;
+        }
+
+        let chapter = src_dir.join(files.name(link.file));
+        let target = local_target(&chapter, &link.href)?;
+
+        self.books
+            .iter()
+            // links within the same translation are checked normally
+            .filter(|book| !chapter.starts_with(&book.root))
+            .find_map(|book| {
+                let relative = target.strip_prefix(&book.root).ok()?;
+
+                if target.starts_with(&book.src_dir) {
+                    Some((target.clone(), book))
+                } else {
+                    // the link skipped over the `src/` directory (i.e. it's
+                    // written relative to the rendered book), so look in the
+                    // source
+                    Some((book.src_dir.join(relative), book))
+                }
+            })
+    }
+
+    /// Check any links which point into a sibling book or another
+    /// translation, the same way as links within this book (`local`).
+    pub(crate) fn check_links(
+        &self,
+        links: Vec<Link>,
+        cfg: &Config,
+        src_dir: &Path,
+        files: &Files<String>,
+        local: &LocalFiles<'_>,
+        anchor_index: &AnchorIndex,
+    ) -> WorkspaceLinks {
+        let mut got = WorkspaceLinks::default();
+        let src_dir = dunce::canonicalize(src_dir)
+            .unwrap_or_else(|_| src_dir.to_path_buf());
+
+        for link in links {
+            match self.resolve(&src_dir, &link, files) {
+                Some((target, book)) => match book.check_target(
+                    &target,
+                    &link,
+                    cfg,
+                    local,
+                    anchor_index,
+                ) {
+                    Ok(()) => got.outcomes.valid.push(link),
+                    Err(reason) => {
+                        let translation = book.book.is_none();
+                        if translation && reason.file_not_found() {
+                            got.missing_translations.push(link.clone());
+                        }
+                        got.outcomes.invalid.push(InvalidLink { link, reason })
+                    },
+                },
+                None => got.remaining.push(link),
+            }
+        }
+
+        got
+    }
+}
+
+impl SiblingBook {
+    /// Check a link into this book, which resolved to `target`, with the
+    /// same rules as links within the book itself (the file must exist, be
+    /// in `SUMMARY.md`, etc.).
+    fn check_target(
+        &self,
+        target: &Path,
+        link: &Link,
+        cfg: &Config,
+        local: &LocalFiles<'_>,
+        anchor_index: &AnchorIndex,
+    ) -> Result<(), Reason> {
+        let sibling;
+        let local = match self.book {
+            Some((ref book_cfg, ref file_names)) => {
+                sibling = LocalFiles::new(
+                    book_cfg,
+                    &self.src_dir,
+                    file_names.clone(),
+                    &RealFileSystem,
+                );
+                &sibling
+            },
+            None => local,
+        };
+
+        // LocalFiles resolves links relative to the linking chapter's
+        // directory
+        let (current_dir, path) = match (target.parent(), target.file_name())
+        {
+            (Some(dir), Some(name)) => (dir, Path::new(name)),
+            _ => (target, Path::new(".")),
+        };
+        local.check(current_dir, path)?;
+
+        let fragment = match link.href.split_once('#') {
+            Some((_, fragment)) if !fragment.is_empty() => fragment,
+            _ => return Ok(()),
+        };
+
+        if !cfg.check_anchors
+            || cfg.should_skip_anchor(&link.href, fragment)
+            || cfg.is_known_anchor(&link.href, fragment)
+        {
+            return Ok(());
+        }
+
+        let target = local.resolve(current_dir, path)?;
+        match anchor_index.anchors(&target, &RealFileSystem) {
+            Some(anchors) if !anchors.contains(fragment) => {
+                Err(Reason::Io(Error::other(MissingAnchor {
+                    path: target,
+                    anchor: fragment.to_string(),
+                })))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+
+    #[test]
+    fn links_into_a_sibling_book_are_checked_against_its_source() {
+        let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let src_dir = tests.join("all-green").join("src");
+        let cfg = Config {
+            workspace_books: vec![String::from("../../broken-links")],
+            ..Default::default()
+        };
+        let workspace = Workspace::load(&cfg, &src_dir);
+        let mut files = Files::new();
+        let file = files.add("chapter_1.md", String::new());
+        let links = vec![
+            // relative to the other book's root
+            Link::new(
+                "../../broken-links/chapter_1.html",
+                Span::default(),
+                file,
+            ),
+            // relative to the other book's source
+            Link::new(
+                "../../broken-links/src/second/directory.md",
+                Span::default(),
+                file,
+            ),
+            Link::new("../../broken-links/missing.md", Span::default(), file),
+            // exists, but isn't in the other book's SUMMARY.md
+            Link::new(
+                "../../broken-links/src/second/sibling.md",
+                Span::default(),
+                file,
+            ),
+            Link::new("./chapter_1.md", Span::default(), file),
+        ];
+        let local =
+            LocalFiles::new(&cfg, &src_dir, Vec::new(), &RealFileSystem);
+
+        let got = workspace.check_links(
+            links,
+            &cfg,
+            &src_dir,
+            &files,
+            &local,
+            &AnchorIndex::default(),
+        );
+
+        assert_eq!(got.outcomes.valid.len(), 2);
+        assert_eq!(got.outcomes.invalid.len(), 2);
+        assert!(got.outcomes.invalid[0].reason.file_not_found());
+        let not_in_summary = match got.outcomes.invalid[1].reason {
+            Reason::Io(ref e) => e.to_string(),
+            ref other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            not_in_summary,
+            "It looks like \"second/sibling.md\" wasn't included in \
+             SUMMARY.md"
+        );
+        assert!(got.missing_translations.is_empty());
+        assert_eq!(got.remaining.len(), 1);
+        assert_eq!(got.remaining[0].href, "./chapter_1.md");
+    }
+
+    #[test]
+    fn links_between_translations() {
+        let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let src_dir = tests.join("translations").join("src");
+        let cfg = Config {
+            translations: vec![String::from("src/en"), String::from("src/fr")],
+            ..Default::default()
+        };
+        let workspace = Workspace::load(&cfg, &src_dir);
+        let mut files = Files::new();
+        let file = files.add("fr/chapter_1.md", String::new());
+        let links = vec![
+            Link::new("../en/chapter_1.md", Span::default(), file),
+            Link::new("../en/chapter_2.md", Span::default(), file),
+            Link::new("./chapter_2.md", Span::default(), file),
+        ];
+        let file_names = vec![
+            OsString::from("en/chapter_1.md"),
+            OsString::from("fr/chapter_1.md"),
+            OsString::from("fr/chapter_2.md"),
+        ];
+        let local =
+            LocalFiles::new(&cfg, &src_dir, file_names, &RealFileSystem);
+
+        let got = workspace.check_links(
+            links,
+            &cfg,
+            &src_dir,
+            &files,
+            &local,
+            &AnchorIndex::default(),
+        );
+
+        assert_eq!(got.outcomes.valid.len(), 1);
+        assert_eq!(got.outcomes.invalid.len(), 1);
+        assert_eq!(got.missing_translations.len(), 1);
+        assert_eq!(got.missing_translations[0].href, "../en/chapter_2.md");
+        // links within a translation are checked normally
+        assert_eq!(got.remaining.len(), 1);
+        assert_eq!(got.remaining[0].href, "./chapter_2.md");
+    }
+}
@@ -3,9 +3,14 @@ extern crate pretty_assertions;
 
 use anyhow::Error;
 use codespan::{FileId, Files};
-use linkcheck::validation::{Cache, Reason};
+use codespan_reporting::term::termcolor::ColorChoice;
+use linkcheck::validation::Reason;
 use mdbook::{renderer::{RenderContext, Renderer}, MDBook};
-use mdbook_linkcheck::{Config, HashedRegex, ValidationOutcome, WarningPolicy};
+use mdbook_linkcheck::{
+    Cache, Config, HashedRegex, ValidationOutcome, WarningPolicy,
+};
+use reqwest::Url;
+use serde_json::{json, Value};
 use std::{cell::Cell, collections::HashMap, convert::TryInto, iter::FromIterator, path::{Path, PathBuf}};
 
 fn test_dir() -> PathBuf { Path::new(env!("CARGO_MANIFEST_DIR")).join("tests") }
@@ -86,14 +91,57 @@ fn detect_when_a_linked_file_isnt_in_summary_md() {
     ));
 }
 
+#[test]
+fn detect_links_to_missing_anchors() {
+    let root = test_dir().join("broken-links");
+    let config = Config {
+        check_anchors: true,
+        ..Default::default()
+    };
+
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    let broken_link = output
+        .invalid_links
+        .iter()
+        .find(|invalid| {
+            invalid.link.href == "./second/directory.md#nonexistent-section"
+        })
+        .unwrap();
+
+    assert!(is_specific_error::<mdbook_linkcheck::MissingAnchor>(
+        &broken_link.reason
+    ));
+}
+
+#[test]
+fn excluded_anchors_are_not_checked() {
+    let root = test_dir().join("broken-links");
+    let config = Config {
+        check_anchors: true,
+        exclude_anchors: vec![r"^nonexistent-".parse().unwrap()],
+        ..Default::default()
+    };
+
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    assert!(output
+        .valid_links
+        .iter()
+        .any(|link| link.href == "./second/directory.md#nonexistent-section"));
+}
+
 #[test]
 fn emit_valid_suggestions_on_absolute_links() {
     let root = test_dir().join("absolute-links");
 
     TestRun::new(root)
         .after_validation(|files, outcome, _| {
-            let diags =
-                outcome.generate_diagnostics(files, WarningPolicy::Error);
+            let cfg = Config {
+                warning_policy: WarningPolicy::Error,
+                ..Default::default()
+            };
+            let diags = outcome.generate_diagnostics(files, &cfg);
 
             let suggestions = vec![
                 "\"chapter_1.md\"",
@@ -102,9 +150,7 @@ fn emit_valid_suggestions_on_absolute_links() {
             ];
             assert_eq!(suggestions.len(), diags.len());
 
-            for (diag, suggestion) in
-                diags.into_iter().zip(suggestions.into_iter())
-            {
+            for (diag, suggestion) in diags.into_iter().zip(suggestions) {
                 assert!(
                     diag.notes.iter().any(|note| note.contains(suggestion)),
                     "It should have suggested {} for {:?}",
@@ -167,6 +213,305 @@ fn skip_web_links() {
     );
 }
 
+#[test]
+fn check_a_book_using_the_library_api() {
+    let src = test_dir().join("external-links").join("src");
+
+    let report = mdbook_linkcheck::Checker::builder()
+        .source_dir(&src)
+        .config(Config::default())
+        .check()
+        .unwrap();
+
+    assert!(
+        report.outcome.invalid_links.is_empty(),
+        "Found invalid links: {:?}",
+        report.outcome.invalid_links
+    );
+    assert_eq!(report.outcome.valid_links.len(), 10);
+    assert_eq!(report.outcome.ignored.len(), 3);
+    assert!(!report.has_errors());
+}
+
+#[test]
+fn check_a_book_from_inside_an_async_runtime() {
+    let src = test_dir().join("external-links").join("src");
+    let checker = mdbook_linkcheck::Checker::builder()
+        .source_dir(&src)
+        .build()
+        .unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let report = runtime
+        .block_on(checker.check_async(Cache::default()))
+        .unwrap();
+
+    assert!(report.outcome.invalid_links.is_empty());
+    assert_eq!(report.outcome.valid_links.len(), 10);
+}
+
+#[test]
+fn only_check_the_selected_files() {
+    let src = test_dir().join("broken-links").join("src");
+
+    let report = mdbook_linkcheck::Checker::builder()
+        .source_dir(&src)
+        .selected_files(vec!["second/directory.md"])
+        .check()
+        .unwrap();
+
+    let broken: Vec<_> = report
+        .outcome
+        .invalid_links
+        .iter()
+        .map(|invalid| invalid.link.href.to_string())
+        .collect();
+    assert_same_links(broken, ["sibling.md"]);
+}
+
+#[test]
+fn check_a_directory_without_a_summary() {
+    let dir = test_dir().join("plain-directory");
+
+    let report = mdbook_linkcheck::Checker::builder()
+        .source_dir(&dir)
+        .check()
+        .unwrap();
+
+    let valid: Vec<_> = report
+        .outcome
+        .valid_links
+        .iter()
+        .map(|link| link.href.to_string())
+        .collect();
+    assert_same_links(
+        valid,
+        [
+            "docs/guide.md",
+            "docs/guide.md#getting-started",
+            "../README.md",
+        ],
+    );
+    let broken: Vec<_> = report
+        .outcome
+        .invalid_links
+        .iter()
+        .map(|invalid| invalid.link.href.to_string())
+        .collect();
+    assert_same_links(broken, ["./faq.md"]);
+}
+
+#[test]
+fn publish_diagnostics_over_the_language_server_protocol() {
+    let root = test_dir().join("plain-directory");
+    let guide = root.join("docs").join("guide.md");
+    let root_uri = Url::from_directory_path(&root).unwrap();
+    let guide_uri = Url::from_file_path(&guide).unwrap();
+    let messages = [
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": { "rootUri": root_uri },
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": guide_uri,
+                    "languageId": "markdown",
+                    "version": 1,
+                    "text": "[Unsaved](./unsaved.md)",
+                },
+            },
+        }),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ];
+    let input: String = messages
+        .iter()
+        .map(|msg| {
+            let body = msg.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        })
+        .collect();
+    let mut output = Vec::new();
+
+    mdbook_linkcheck::serve_language_server(input.as_bytes(), &mut output)
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let published: Value = output
+        .split("Content-Length: ")
+        .filter_map(|msg| msg.split_once("\r\n\r\n"))
+        .map(|(_, body)| serde_json::from_str::<Value>(body).unwrap())
+        .find(|msg| msg["method"] == "textDocument/publishDiagnostics")
+        .unwrap();
+    assert_eq!(published["params"]["uri"], guide_uri.as_str());
+    let diagnostics = published["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 0, "character": 23 },
+        })
+    );
+}
+
+#[test]
+fn only_check_chapters_affected_by_git_changes() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("git-changes");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    let plain = test_dir().join("plain-directory");
+    for file in &["README.md", "docs/guide.md"] {
+        std::fs::copy(plain.join(file), dir.join(file)).unwrap();
+    }
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "Initial commit"]);
+
+    // deleting the guide affects the README, which links to it
+    std::fs::remove_file(dir.join("docs").join("guide.md")).unwrap();
+    std::fs::write(dir.join("docs").join("new.md"), "[README](../README.md)")
+        .unwrap();
+    let book = mdbook_linkcheck::Checker::builder()
+        .source_dir(&dir)
+        .build()
+        .unwrap()
+        .book()
+        .clone();
+
+    let got = mdbook_linkcheck::changed_chapters(&book, &dir, "HEAD").unwrap();
+
+    assert_eq!(
+        got,
+        vec![PathBuf::from("README.md"), PathBuf::from("docs/new.md")]
+    );
+}
+
+/// A directory where `README.md` links to `docs/guide.md`, which has a broken
+/// link of its own. Checking just the README should pass.
+fn partially_broken_directory(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    std::fs::write(dir.join("README.md"), "[The guide](docs/guide.md)\n")
+        .unwrap();
+    std::fs::write(dir.join("docs").join("guide.md"), "[Gone](./gone.md)\n")
+        .unwrap();
+
+    dir
+}
+
+fn check_directory(dir: &Path, selected: Vec<PathBuf>) -> Result<(), Error> {
+    let selected = selected
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
+    mdbook_linkcheck::run_directory(
+        dir,
+        Config::default(),
+        None,
+        ColorChoice::Never,
+        Some(selected),
+    )
+}
+
+#[test]
+fn chapters_selected_with_patterns_can_link_to_the_rest_of_the_book() {
+    let dir = partially_broken_directory("selected-with-patterns");
+    let book = mdbook_linkcheck::Checker::builder()
+        .source_dir(&dir)
+        .build()
+        .unwrap()
+        .book()
+        .clone();
+
+    let selected = mdbook_linkcheck::selected_chapters(
+        &book,
+        &dir,
+        &dir,
+        &[String::from("README.md")],
+    )
+    .unwrap();
+
+    assert_eq!(selected, vec![PathBuf::from("README.md")]);
+    check_directory(&dir, selected).unwrap();
+}
+
+#[test]
+fn chapters_changed_in_git_can_link_to_the_rest_of_the_book() {
+    let dir = partially_broken_directory("changed-in-git");
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "Initial commit"]);
+    std::fs::write(dir.join("README.md"), "Read [the guide](docs/guide.md)\n")
+        .unwrap();
+    let book = mdbook_linkcheck::Checker::builder()
+        .source_dir(&dir)
+        .build()
+        .unwrap()
+        .book()
+        .clone();
+
+    let changed =
+        mdbook_linkcheck::changed_chapters(&book, &dir, "HEAD").unwrap();
+
+    assert_eq!(changed, vec![PathBuf::from("README.md")]);
+    check_directory(&dir, changed).unwrap();
+}
+
+#[test]
+fn files_checked_by_the_server_can_link_to_the_rest_of_the_book() {
+    let dir = partially_broken_directory("server-check");
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "check",
+        "params": { "files": ["README.md"] },
+    });
+    let mut output = Vec::new();
+
+    mdbook_linkcheck::serve(
+        &dir,
+        std::io::Cursor::new(format!("{}\n", request)),
+        &mut output,
+    )
+    .unwrap();
+
+    let response: Value = serde_json::from_slice(&output).unwrap();
+    let result = &response["result"];
+    assert_eq!(result["errors"], 0, "{:#}", response);
+    assert_eq!(result["diagnostics"], json!([]), "{:#}", response);
+}
+
 fn is_specific_error<E>(reason: &Reason) -> bool
 where
     E: std::error::Error + 'static,
@@ -198,11 +543,12 @@ where
     assert_eq!(left, right);
 }
 
+type AfterValidation = dyn Fn(&Files<String>, &ValidationOutcome, &Vec<FileId>);
+
 struct TestRun {
     config: Config,
     root: PathBuf,
-    after_validation:
-        Box<dyn Fn(&Files<String>, &ValidationOutcome, &Vec<FileId>)>,
+    after_validation: Box<AfterValidation>,
     validation_outcome: Cell<Option<ValidationOutcome>>,
 }
 
@@ -247,8 +593,7 @@ impl TestRun {
 
     fn initialise_logging(&self) {
         let _ = env_logger::builder()
-            .filter(Some("linkcheck"), log::LevelFilter::Debug)
-            .filter(Some("mdbook_linkcheck"), log::LevelFilter::Debug)
+            .parse_filters("linkcheck=debug,mdbook_linkcheck=debug")
             .is_test(true)
             .try_init();
     }
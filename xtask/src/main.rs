@@ -6,7 +6,7 @@ use std::{
     fs::File,
     io::{Seek, Write},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::Command,
 };
 use structopt::StructOpt;
 use zip::{write::FileOptions, ZipWriter};